@@ -18,6 +18,8 @@ struct Inner {
     arp: SyncMutex<HashMap<Ipv4Address, EthernetAddress>>,
     // send by udp to server
     tx: Sender<Vec<u8>>,
+    // ping responses, read by the same task as everything else
+    pong: Sender<Vec<u8>>,
 }
 
 #[derive(Debug, Clone)]
@@ -25,6 +27,7 @@ pub struct LanClient {
     relay_server: String,
     inner: Arc<Inner>,
     cidr: Ipv4Cidr,
+    pong: Receiver<Vec<u8>>,
 }
 
 // struct LanClientIntercepter {
@@ -83,6 +86,10 @@ impl LanClient {
         socket.send(&packet).await.unwrap();
     }
     async fn on_recv(inner: Arc<Inner>, buf: &[u8]) {
+        if ForwarderFrame::is_ping(buf) {
+            let _ = inner.pong.try_send(buf.to_vec());
+            return;
+        }
         if let Ok(p) = ForwarderFrame::parse(buf) {
             match p {
                 ForwarderFrame::Ipv4(pkt) => {
@@ -150,6 +157,7 @@ impl LanClient {
         let socket = UdpSocket::bind("0.0.0.0:0").await?;
         socket.connect(&relay_server).await?;
         let (tx, rx) = unbounded();
+        let (pong_sender, pong) = unbounded();
         let inner = Arc::new(Inner {
             socket,
             map_sender: SyncMutex::new(HashMap::new()),
@@ -158,12 +166,14 @@ impl LanClient {
             self_addr: SyncMutex::new(None),
             arp: SyncMutex::new(HashMap::new()),
             tx,
+            pong: pong_sender,
         });
         tokio::spawn(Self::process(inner.clone(), rx));
         Ok(LanClient {
             relay_server,
             inner,
             cidr,
+            pong,
         })
     }
     pub async fn ping(&self) -> io::Result<()> {
@@ -171,11 +181,11 @@ impl LanClient {
         let content = b"\x021234";
 
         socket.send(content).await?;
-        let mut buf = vec![0u8; 5];
-        let size = socket.recv(&mut buf).await?;
-        if size == 0 {
-            return Err(io::Error::new(io::ErrorKind::Other, "the server seems not working, or blocked by the firewall"));
-        }
+        let buf = self.pong
+            .recv()
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "the lan client stopped"))?;
+        let size = buf.len();
         if size != 5 {
             log::error!("ping response size: {} {:?}. this should be a bug on server", size, &buf[0..size]);
             return Err(io::Error::new(io::ErrorKind::Other, "wrong length of ping response"));
@@ -186,3 +196,64 @@ impl LanClient {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::time::timeout;
+
+    /// A relay answering pings with `reply`, and forwarding IPv4 frames back
+    /// to the sender.
+    async fn relay_server(reply: &'static [u8]) -> (String, Receiver<Vec<u8>>) {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = server.local_addr().unwrap().to_string();
+        let (sender, received) = unbounded();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 2048];
+            while let Ok((size, addr)) = server.recv_from(&mut buf).await {
+                let frame = &buf[..size];
+                if ForwarderFrame::is_ping(frame) {
+                    let _ = server.send_to(reply, addr).await;
+                } else {
+                    let _ = sender.try_send(frame.to_vec());
+                }
+            }
+        });
+        (addr, received)
+    }
+
+    fn cidr() -> Ipv4Cidr {
+        Ipv4Cidr::new(Ipv4Address([10, 13, 0, 0]), 16)
+    }
+
+    #[tokio::test]
+    async fn test_ping() {
+        let (relay, _) = relay_server(b"\x021234").await;
+        let client = LanClient::new(relay, cidr()).await.unwrap();
+        for _ in 0..3 {
+            timeout(Duration::from_secs(5), client.ping()).await.expect("timed out").unwrap();
+        }
+
+        let (relay, _) = relay_server(b"\x02123").await;
+        let client = LanClient::new(relay, cidr()).await.unwrap();
+        let r = timeout(Duration::from_secs(5), client.ping()).await.expect("timed out");
+        assert!(r.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_relay_ipv4() {
+        let (relay, received) = relay_server(b"").await;
+        let client = LanClient::new(relay, cidr()).await.unwrap();
+
+        let mut packet = vec![0x45u8; 20];
+        packet[12..16].copy_from_slice(&[10, 13, 0, 2]);
+        packet[16..20].copy_from_slice(&[10, 13, 0, 3]);
+        client.inner.tx.try_send(packet.clone()).unwrap();
+
+        let frame = timeout(Duration::from_secs(5), received.recv()).await.expect("timed out").unwrap();
+        match ForwarderFrame::parse(&frame).unwrap() {
+            ForwarderFrame::Ipv4(ipv4) => assert_eq!(ipv4.payload(), &packet[..]),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -66,6 +66,13 @@ pub enum ForwarderFrame<'a> {
     Info,
 }
 
+impl<'a> ForwarderFrame<'a> {
+    /// Whether `bytes` answers a ping, even a malformed one.
+    pub fn is_ping(bytes: &[u8]) -> bool {
+        bytes.first() == Some(&forwarder_type::PING)
+    }
+}
+
 impl<'a> Parser<'a> for ForwarderFrame<'a> {
     const MIN_LENGTH: usize = 1;
     fn do_parse(bytes: &'a [u8]) -> Result<ForwarderFrame> {
@@ -8,6 +8,8 @@ pub enum Error {
     IoError(#[from] std::io::Error),
     #[error("NoInterface")]
     NoInterface,
+    #[error("Could not open a packet capture library, tried:\n{}", .0.join("\n"))]
+    NoLibrary(Vec<String>),
     #[error("Timed out")]
     Timedout(#[from] tokio::time::error::Elapsed),
     #[error("Smoltcp error {0:?}")]
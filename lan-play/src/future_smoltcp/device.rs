@@ -2,7 +2,7 @@ use smoltcp::{
     phy::{DeviceCapabilities, RxToken, TxToken},
     time::Instant,
 };
-use futures::{Stream, Sink, StreamExt, stream::iter};
+use futures::{Stream, Sink, SinkExt, StreamExt};
 use std::{collections::VecDeque, io};
 
 const MAX_QUEUE_SIZE: usize = 100;
@@ -43,10 +43,16 @@ where
     pub async fn wait(&mut self) {
         self.temp = self.stream.next().await;
     }
+    /// Hands the packets of this reactor tick to the interface, flushing once
+    /// so that they can be sent as one batch.
     pub async fn send_queue(&mut self) -> io::Result<()> {
-        let stream = iter(self.send_queue.drain(..).map(|i| Ok(i)));
-        stream.forward(&mut self.stream).await?;
-        Ok(())
+        if self.send_queue.is_empty() {
+            return Ok(());
+        }
+        for packet in self.send_queue.drain(..) {
+            self.stream.feed(packet).await?;
+        }
+        self.stream.flush().await
     }
     fn get_next(&mut self) -> Option<Packet> {
         if let Some(t) = self.temp.take() {
@@ -3,8 +3,12 @@ mod reactor;
 mod socket;
 mod socketset;
 mod device;
+#[cfg(test)]
+mod test;
 
 pub use raw_udp::OwnedUdp;
+#[cfg(test)]
+pub(crate) use raw_udp::parse_udp_owned;
 use reactor::NetReactor;
 use smoltcp::{
     iface::{
@@ -13,11 +17,12 @@ use smoltcp::{
     },
     wire::{EthernetAddress, IpCidr, Ipv4Address},
 };
-pub use socket::{SocketHandle, TcpListener, TcpSocket, UdpSocket, SendHalf, RecvHalf};
+pub use socket::{SocketHandle, TcpConnector, TcpListener, TcpSocket, UdpSocket, SendHalf, RecvHalf};
 pub use socketset::BufferSize;
 use socketset::SocketSet;
 use std::collections::BTreeMap;
-use device::FutureDevice;
+pub use device::{Interface, Packet};
+pub(crate) use device::FutureDevice;
 use std::sync::Arc;
 
 // pub type Ethernet = SmoltcpEthernetInterface<'static, 'static, 'static, FutureDevice<PacketInterface>>;
@@ -67,4 +72,7 @@ impl Net {
     pub async fn udp_socket(&self) -> UdpSocket {
         UdpSocket::new(self.reactor.clone()).await
     }
+    pub fn tcp_connector(&self) -> TcpConnector {
+        TcpConnector::new(self.reactor.clone())
+    }
 }
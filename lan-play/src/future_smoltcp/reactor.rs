@@ -105,7 +105,8 @@ impl NetReactor {
         self.sources.lock().unwrap().remove(handle);
     }
     pub fn notify(&self) {
-        self.notify.notify_waiters();
+        // keep a permit if the reactor is busy sending, so the wakeup isn't lost
+        self.notify.notify_one();
     }
     pub async fn run<I>(&self, mut ethernet: smoltcp::iface::EthernetInterface<'static, 'static, 'static, FutureDevice<I>>)
     where
@@ -137,7 +138,9 @@ impl NetReactor {
             }
             let mut set = sockets.lock().unwrap();
             let end = Instant::now();
-            match ethernet.poll(set.as_set_mut(), end) {
+            let r = ethernet.poll(set.as_set_mut(), end);
+            set.prune();
+            match r {
                 Ok(true) => (),
                 // readiness not changed
                 Ok(false) | Err(smoltcp::Error::Dropped) => continue,
@@ -150,10 +153,7 @@ impl NetReactor {
             let sources = self.sources.lock().unwrap();
             for socket in set.as_set_mut().iter() {
                 let (readable, writable) = match socket {
-                    smoltcp::socket::Socket::Tcp(tcp) => (
-                        tcp.can_recv() || is_going_to_close(tcp.state()),
-                        tcp.can_send() || is_going_to_close(tcp.state()),
-                    ),
+                    smoltcp::socket::Socket::Tcp(tcp) => tcp_readiness(tcp),
                     smoltcp::socket::Socket::Raw(raw) => (raw.can_recv(), raw.can_send()),
                     _ => continue, // ignore other type
                 };
@@ -179,9 +179,14 @@ impl NetReactor {
     }
 }
 
-fn is_going_to_close(s: TcpState) -> bool {
-    match s {
-        TcpState::Closed | TcpState::Listen | TcpState::SynSent | TcpState::SynReceived | TcpState::Established => false,
-        _ => true,
+/// A TCP socket is readable when it has data or will never get more, and
+/// writable when it has room or can't be written anymore.
+fn tcp_readiness(tcp: &smoltcp::socket::TcpSocket) -> (bool, bool) {
+    match tcp.state() {
+        TcpState::Listen | TcpState::SynSent | TcpState::SynReceived => (false, false),
+        _ => (
+            tcp.can_recv() || !tcp.may_recv(),
+            tcp.can_send() || !tcp.may_send(),
+        ),
     }
 }
@@ -15,6 +15,7 @@ use std::{
     mem::replace,
     future::Future,
     net::SocketAddr,
+    time::Duration,
 };
 use tokio::io::{self, AsyncRead, AsyncWrite};
 use futures::{ready, Stream};
@@ -101,7 +102,10 @@ impl Drop for Base {
     fn drop(&mut self) {
         self.reactor.remove(&self.handle);
         let mut set = self.reactor.lock_set();
-        set.remove(self.handle);
+        // TCP sockets are closed gracefully and pruned by the reactor
+        set.release(self.handle);
+        drop(set);
+        self.reactor.notify();
     }
 }
 
@@ -113,12 +117,32 @@ pub struct TcpSocket {
     base: Base,
     local_addr: SocketAddr,
     peer_addr: SocketAddr,
+    fin_sent: bool,
 }
 
 pub struct UdpSocket {
     base: Base,
 }
 
+/// Opens TCP connections from the gateway side into the virtual network.
+#[derive(Clone)]
+pub struct TcpConnector {
+    reactor: Arc<NetReactor>,
+}
+
+impl TcpConnector {
+    pub(super) fn new(reactor: Arc<NetReactor>) -> TcpConnector {
+        TcpConnector {
+            reactor,
+        }
+    }
+    /// Connects to `remote` from `local`, which doesn't need to be one of
+    /// our addresses.
+    pub async fn connect(&self, local: SocketAddr, remote: SocketAddr) -> io::Result<TcpSocket> {
+        TcpSocket::connect(self.reactor.clone(), local, remote).await
+    }
+}
+
 fn map_err(e: smoltcp::Error) -> io::Error {
     io::Error::new(io::ErrorKind::Other, e.to_string())
 }
@@ -243,33 +267,78 @@ impl TcpSocket {
             base,
             local_addr,
             peer_addr,
+            fin_sent: false,
         }
     }
+    async fn connect(reactor: Arc<NetReactor>, local: SocketAddr, remote: SocketAddr) -> io::Result<TcpSocket> {
+        let base = Base::new(reactor, SocketSet::new_client_tcp_socket);
+        {
+            let mut set = base.lock_set();
+            let mut socket = set.get::<socket::TcpSocket>(base.handle);
+            socket.connect(remote, local).map_err(map_err)?;
+        }
+        base.reactor.notify();
+        base.writable(|socket: &mut SocketRef<socket::TcpSocket>| {
+            match socket.state() {
+                TcpState::SynSent | TcpState::SynReceived => None,
+                TcpState::Established => Some(Ok(())),
+                _ => Some(Err(io::ErrorKind::ConnectionRefused.into())),
+            }
+        }).await?;
+        Ok(TcpSocket {
+            base,
+            local_addr: local,
+            peer_addr: remote,
+            fin_sent: false,
+        })
+    }
     pub fn local_addr(&self) -> io::Result<SocketAddr> {
         Ok(self.local_addr)
     }
     pub fn peer_addr(&self) -> io::Result<SocketAddr> {
         Ok(self.peer_addr)
     }
+    /// Send keep-alive segments after `interval` of inactivity.
+    pub fn set_keep_alive(&self, interval: Option<Duration>) {
+        let mut set = self.base.lock_set();
+        let mut socket = set.get::<socket::TcpSocket>(self.base.handle);
+        socket.set_keep_alive(interval.map(Into::into));
+    }
+    /// Hold back ACKs for up to `delay`, `None` acknowledges every segment at once.
+    pub fn set_ack_delay(&self, delay: Option<Duration>) {
+        let mut set = self.base.lock_set();
+        let mut socket = set.get::<socket::TcpSocket>(self.base.handle);
+        socket.set_ack_delay(delay.map(Into::into));
+    }
+    /// Abort the connection if the peer doesn't respond within `duration`.
+    pub fn set_timeout(&self, duration: Option<Duration>) {
+        let mut set = self.base.lock_set();
+        let mut socket = set.get::<socket::TcpSocket>(self.base.handle);
+        socket.set_timeout(duration.map(Into::into));
+    }
+    /// Reads buffered data. Returns `Ok(0)` once the peer has sent FIN and
+    /// everything before it has been read.
     pub async fn recv(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         self.base.readable(|socket: &mut SocketRef<socket::TcpSocket>| {
-            if socket.state() != TcpState::Established {
-                socket.close();
-                return Some(Ok(0))
-            }
             if socket.can_recv() {
-                return Some(socket
+                let r = socket
                     .recv_slice(buf)
-                    .map_err(map_err));
+                    .map_err(map_err);
+                // window update
+                self.base.reactor.notify();
+                return Some(r);
+            }
+            if !socket.may_recv() {
+                return Some(Ok(0))
             }
             None
         }).await
     }
+    /// Writes data. Still allowed after the peer half-closed (`CloseWait`).
     pub async fn send(&mut self, data: &[u8]) -> io::Result<usize> {
         self.base.writable(|socket: &mut SocketRef<socket::TcpSocket>| {
-            if socket.state() != TcpState::Established {
-                socket.close();
-                return Some(Ok(0))
+            if !socket.may_send() {
+                return Some(Err(io::ErrorKind::BrokenPipe.into()))
             }
             if socket.can_send() {
                 let r = socket.send_slice(data)
@@ -280,11 +349,37 @@ impl TcpSocket {
             None
         }).await
     }
+    /// Sends FIN after the queued data and waits until the peer acknowledged it.
     pub async fn shutdown(&mut self) -> io::Result<()> {
+        let TcpSocket { base, fin_sent, .. } = self;
+        base.writable(|socket: &mut SocketRef<socket::TcpSocket>| {
+            // `poll_shutdown` builds this future again on every poll
+            if !*fin_sent {
+                socket.close();
+                base.reactor.notify();
+                *fin_sent = true;
+            }
+            if is_fin_acked(socket.state()) {
+                return Some(Ok(()))
+            }
+            None
+        }).await
+    }
+    /// Resets the connection immediately, discarding unsent data.
+    pub fn abort(&mut self) {
         let mut set = self.base.lock_set();
         let mut socket = set.get::<socket::TcpSocket>(self.base.handle);
-        socket.close();
-        Ok(())
+        socket.abort();
+        drop(socket);
+        drop(set);
+        self.base.reactor.notify();
+    }
+}
+
+fn is_fin_acked(s: TcpState) -> bool {
+    match s {
+        TcpState::FinWait2 | TcpState::TimeWait | TcpState::Closed => true,
+        _ => false,
     }
 }
 
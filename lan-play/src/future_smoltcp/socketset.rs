@@ -30,21 +30,34 @@ impl SocketSet {
     pub fn get<T: AnySocket<'static, 'static>>(&mut self, handle: SocketHandle) -> SocketRef<T> {
         self.set.get(handle)
     }
-    pub fn remove(&mut self, handle: SocketHandle) {
-        self.set.remove(handle);
+    /// Drops our reference to the socket. It is removed by `prune` once it
+    /// is safe to do so, i.e. after a TCP socket finished closing.
+    pub fn release(&mut self, handle: SocketHandle) {
+        self.set.release(handle);
+    }
+    pub fn prune(&mut self) {
+        self.set.prune();
     }
     pub fn new_tcp_socket(&mut self) -> SocketHandle {
         let handle = self.set.add(self.alloc_tcp_socket());
         handle
     }
+    /// A TCP socket to be connected by us rather than accepted.
+    pub fn new_client_tcp_socket(&mut self) -> SocketHandle {
+        let handle = self.set.add(self.alloc_tcp_buffers());
+        handle
+    }
     pub fn new_raw_socket(&mut self) -> SocketHandle {
         let handle = self.set.add(self.alloc_raw_socket());
         handle
     }
-    fn alloc_tcp_socket(&self) -> socket::TcpSocket<'static> {
+    fn alloc_tcp_buffers(&self) -> socket::TcpSocket<'static> {
         let rx_buffer = TcpSocketBuffer::new(vec![0; self.buffer_size.tcp_rx_size]);
         let tx_buffer = TcpSocketBuffer::new(vec![0; self.buffer_size.tcp_tx_size]);
-        let mut tcp = TcpSocket::new(rx_buffer, tx_buffer);
+        TcpSocket::new(rx_buffer, tx_buffer)
+    }
+    fn alloc_tcp_socket(&self) -> socket::TcpSocket<'static> {
+        let mut tcp = self.alloc_tcp_buffers();
         tcp.set_accept_all(true);
         tcp.listen(0).unwrap();
     
@@ -0,0 +1,89 @@
+use super::{BufferSize, Net};
+use crate::test_support::{pair, Console, VirtualPort};
+use smoltcp::{
+    socket::TcpState,
+    wire::{EthernetAddress, IpCidr, Ipv4Address},
+};
+use std::net::SocketAddr;
+use tokio::{prelude::*, time::{timeout, Duration}};
+
+const GATEWAY_MAC: EthernetAddress = EthernetAddress([0x02, 0, 0, 0, 0, 0x02]);
+const CONSOLE_MAC: EthernetAddress = EthernetAddress([0x02, 0, 0, 0, 0, 0x05]);
+const GATEWAY_IP: Ipv4Address = Ipv4Address([10, 13, 37, 2]);
+const CONSOLE_IP: Ipv4Address = Ipv4Address([10, 13, 37, 5]);
+
+fn new_net(interface: VirtualPort) -> Net {
+    Net::new(
+        GATEWAY_MAC,
+        vec![IpCidr::new(GATEWAY_IP.into(), 16)],
+        GATEWAY_IP,
+        interface,
+        1500,
+        BufferSize {
+            tcp_rx_size: 4096,
+            tcp_tx_size: 4096,
+        },
+    )
+}
+
+#[tokio::test]
+async fn test_half_close() {
+    let (gateway, console) = pair();
+    let net = new_net(gateway);
+    let mut listener = net.tcp_listener().await;
+    let mut console = Console::new(console, CONSOLE_MAC, IpCidr::new(CONSOLE_IP.into(), 16), GATEWAY_IP);
+
+    let server = tokio::spawn(async move {
+        let mut stcp = listener.accept().await.unwrap();
+        assert_eq!(stcp.local_addr().unwrap(), "1.2.3.4:80".parse::<SocketAddr>().unwrap());
+        let mut buf = Vec::new();
+        stcp.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"hello");
+        // the console only closed its sending side
+        stcp.write_all(b"world").await.unwrap();
+        stcp.shutdown().await.unwrap();
+    });
+
+    timeout(Duration::from_secs(5), async {
+        console.connect("1.2.3.4:80".parse().unwrap(), 49152);
+        console.poll_until(|tcp| tcp.may_send()).await;
+        console.with_tcp(|tcp| {
+            tcp.send_slice(b"hello").unwrap();
+            tcp.close();
+        });
+
+        let mut received = Vec::new();
+        console.poll_until(|tcp| {
+            let mut buf = [0u8; 64];
+            if tcp.can_recv() {
+                let size = tcp.recv_slice(&mut buf).unwrap();
+                received.extend_from_slice(&buf[..size]);
+            }
+            !tcp.may_recv()
+        }).await;
+        assert_eq!(received, b"world");
+        assert_eq!(console.with_tcp(|tcp| tcp.state()), TcpState::TimeWait);
+
+        server.await.unwrap();
+    }).await.expect("timed out");
+}
+
+#[tokio::test]
+async fn test_abort() {
+    let (gateway, console) = pair();
+    let net = new_net(gateway);
+    let mut listener = net.tcp_listener().await;
+    let mut console = Console::new(console, CONSOLE_MAC, IpCidr::new(CONSOLE_IP.into(), 16), GATEWAY_IP);
+
+    let server = tokio::spawn(async move {
+        let mut stcp = listener.accept().await.unwrap();
+        stcp.abort();
+    });
+
+    timeout(Duration::from_secs(5), async {
+        console.connect("1.2.3.4:80".parse().unwrap(), 49152);
+        console.poll_until(|tcp| tcp.may_send()).await;
+        server.await.unwrap();
+        console.poll_until(|tcp| tcp.state() == TcpState::Closed).await;
+    }).await.expect("timed out");
+}
@@ -0,0 +1,569 @@
+use super::divert::{ethernet_frame, Divert};
+use crate::future_smoltcp::{OwnedUdp, Packet};
+use async_channel::Sender;
+use smoltcp::wire::{
+    EthernetAddress, EthernetFrame, EthernetProtocol, IpProtocol, Ipv4Cidr, Ipv4Packet,
+    UdpPacket,
+};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SERVER_PORT: u16 = 67;
+const CLIENT_PORT: u16 = 68;
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+/// Fixed part of a DHCP message, up to the magic cookie
+const HEADER_LEN: usize = 236;
+/// How long an offered address is held for the client
+const OFFER_TIME: u64 = 60;
+/// How long an address a client declined (it found it in use) isn't offered
+const DECLINE_TIME: u64 = 10 * OFFER_TIME;
+
+const BOOTREQUEST: u8 = 1;
+const BOOTREPLY: u8 = 2;
+
+const DHCPDISCOVER: u8 = 1;
+const DHCPOFFER: u8 = 2;
+const DHCPREQUEST: u8 = 3;
+const DHCPDECLINE: u8 = 4;
+const DHCPACK: u8 = 5;
+const DHCPNAK: u8 = 6;
+const DHCPRELEASE: u8 = 7;
+
+const OPT_PAD: u8 = 0;
+const OPT_SUBNET_MASK: u8 = 1;
+const OPT_ROUTER: u8 = 3;
+const OPT_DNS: u8 = 6;
+const OPT_REQUESTED_IP: u8 = 50;
+const OPT_LEASE_TIME: u8 = 51;
+const OPT_MESSAGE_TYPE: u8 = 53;
+const OPT_SERVER_ID: u8 = 54;
+const OPT_VENDOR_CLASS: u8 = 60;
+const OPT_END: u8 = 255;
+
+/// Settings of the embedded DHCP server.
+#[derive(Debug, Clone)]
+pub struct DhcpConfig {
+    /// Our address, also handed out as router
+    pub server_ip: Ipv4Addr,
+    pub subnet: Ipv4Cidr,
+    pub pool: RangeInclusive<Ipv4Addr>,
+    pub dns: Ipv4Addr,
+    pub lease_time: Duration,
+    /// Only answer these clients. Together with `vendor_class`, matching
+    /// either is enough. Everyone is answered if both are unset.
+    pub allowed_macs: Vec<EthernetAddress>,
+    /// Only answer clients whose vendor class identifier starts with this
+    pub vendor_class: Option<String>,
+    /// Where leases are kept across restarts
+    pub state_file: Option<PathBuf>,
+}
+
+impl DhcpConfig {
+    /// Hands out the upper part of `subnet`, leaving the first 100
+    /// addresses for static configuration.
+    pub fn new(server_ip: Ipv4Addr, subnet: Ipv4Cidr) -> DhcpConfig {
+        let network = u32::from(Ipv4Addr::from(subnet.network().address()));
+        let broadcast = network | !netmask(subnet.prefix_len());
+        let start = (network + 100).min(broadcast.saturating_sub(1));
+        DhcpConfig {
+            server_ip,
+            subnet,
+            pool: Ipv4Addr::from(start)..=Ipv4Addr::from(broadcast.saturating_sub(1)),
+            dns: server_ip,
+            lease_time: Duration::from_secs(3600),
+            allowed_macs: Vec::new(),
+            vendor_class: None,
+            state_file: None,
+        }
+    }
+    /// Hands out `pool` instead, which has to lie in the subnet and leave
+    /// out our own address.
+    pub fn set_pool(&mut self, pool: RangeInclusive<Ipv4Addr>) -> io::Result<()> {
+        let in_subnet = |ip: &Ipv4Addr| self.subnet.contains_addr(&(*ip).into());
+        if !in_subnet(pool.start()) || !in_subnet(pool.end()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("DHCP range {}-{} is not in {}", pool.start(), pool.end(), self.subnet),
+            ));
+        }
+        if pool.contains(&self.server_ip) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("DHCP range {}-{} includes the gateway {}", pool.start(), pool.end(), self.server_ip),
+            ));
+        }
+        self.pool = pool;
+        Ok(())
+    }
+}
+
+fn netmask(prefix_len: u8) -> u32 {
+    match prefix_len {
+        0 => 0,
+        len => !0u32 << (32 - u32::from(len.min(32))),
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Parses `02:00:00:00:00:01` or `02-00-00-00-00-01`.
+pub fn parse_mac(s: &str) -> Result<EthernetAddress, String> {
+    let bytes = s
+        .split(|c| c == ':' || c == '-')
+        .map(|b| u8::from_str_radix(b, 16))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    if bytes.len() != 6 {
+        return Err(format!("invalid MAC address {}", s));
+    }
+    Ok(EthernetAddress::from_bytes(&bytes))
+}
+
+/// The parts of a client message we care about.
+#[derive(Debug)]
+struct Message {
+    xid: [u8; 4],
+    flags: [u8; 2],
+    ciaddr: Ipv4Addr,
+    chaddr: EthernetAddress,
+    message_type: u8,
+    requested_ip: Option<Ipv4Addr>,
+    server_id: Option<Ipv4Addr>,
+    vendor_class: Option<Vec<u8>>,
+}
+
+fn ip_at(b: &[u8]) -> Ipv4Addr {
+    Ipv4Addr::new(b[0], b[1], b[2], b[3])
+}
+
+impl Message {
+    fn parse(data: &[u8]) -> Option<Message> {
+        if data.len() < HEADER_LEN + 4 || data[0] != BOOTREQUEST || data[1] != 1 || data[2] != 6 {
+            return None;
+        }
+        if data[HEADER_LEN..HEADER_LEN + 4] != MAGIC_COOKIE {
+            return None;
+        }
+        let mut xid = [0u8; 4];
+        xid.copy_from_slice(&data[4..8]);
+        let mut message = Message {
+            xid,
+            flags: [data[10], data[11]],
+            ciaddr: ip_at(&data[12..16]),
+            chaddr: EthernetAddress::from_bytes(&data[28..34]),
+            message_type: 0,
+            requested_ip: None,
+            server_id: None,
+            vendor_class: None,
+        };
+        let mut options = &data[HEADER_LEN + 4..];
+        while let Some((&code, rest)) = options.split_first() {
+            match code {
+                OPT_PAD => {
+                    options = rest;
+                    continue;
+                }
+                OPT_END => break,
+                _ => {}
+            }
+            let (&len, rest) = rest.split_first()?;
+            let len = len as usize;
+            if rest.len() < len {
+                return None;
+            }
+            let value = &rest[..len];
+            match (code, len) {
+                (OPT_MESSAGE_TYPE, 1) => message.message_type = value[0],
+                (OPT_REQUESTED_IP, 4) => message.requested_ip = Some(ip_at(value)),
+                (OPT_SERVER_ID, 4) => message.server_id = Some(ip_at(value)),
+                (OPT_VENDOR_CLASS, _) => message.vendor_class = Some(value.to_vec()),
+                _ => {}
+            }
+            options = &rest[len..];
+        }
+        Some(message)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Lease {
+    ip: Ipv4Addr,
+    /// Unix time
+    expires: u64,
+}
+
+struct Leases {
+    by_mac: HashMap<EthernetAddress, Lease>,
+    /// Addresses clients found in use, until when they aren't offered
+    declined: HashMap<Ipv4Addr, u64>,
+}
+
+impl Leases {
+    fn load(path: &PathBuf) -> io::Result<Leases> {
+        let mut by_mac = HashMap::new();
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        for line in content.lines().filter(|l| !l.trim().is_empty()) {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let lease = match fields.as_slice() {
+                [mac, ip, expires] => parse_mac(mac).ok().and_then(|mac| {
+                    Some((mac, Lease { ip: ip.parse().ok()?, expires: expires.parse().ok()? }))
+                }),
+                _ => None,
+            };
+            match lease {
+                Some((mac, lease)) => {
+                    by_mac.insert(mac, lease);
+                }
+                None => log::warn!("dhcp: ignoring bad lease line {:?}", line),
+            }
+        }
+        Ok(Leases { by_mac, declined: HashMap::new() })
+    }
+    fn save(&self, path: &PathBuf, now: u64) -> io::Result<()> {
+        let content = self.by_mac
+            .iter()
+            .filter(|(_, lease)| lease.expires > now)
+            .map(|(mac, lease)| format!("{} {} {}\n", mac, lease.ip, lease.expires))
+            .collect::<String>();
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, content)?;
+        fs::rename(&tmp, path)
+    }
+}
+
+/// A DHCPv4 server for the virtual subnet, answering before smoltcp sees
+/// the (broadcast) requests.
+pub struct DhcpServer {
+    config: DhcpConfig,
+    leases: Mutex<Leases>,
+}
+
+impl DhcpServer {
+    pub fn new(config: DhcpConfig) -> io::Result<DhcpServer> {
+        let leases = match &config.state_file {
+            Some(path) => Leases::load(path)?,
+            None => Leases { by_mac: HashMap::new(), declined: HashMap::new() },
+        };
+        Ok(DhcpServer {
+            config,
+            leases: Mutex::new(leases),
+        })
+    }
+    fn allowed(&self, message: &Message) -> bool {
+        let config = &self.config;
+        if config.allowed_macs.is_empty() && config.vendor_class.is_none() {
+            return true;
+        }
+        let mac_ok = config.allowed_macs.contains(&message.chaddr);
+        let vendor_ok = match (&config.vendor_class, &message.vendor_class) {
+            (Some(prefix), Some(vendor)) => vendor.starts_with(prefix.as_bytes()),
+            _ => false,
+        };
+        mac_ok || vendor_ok
+    }
+    fn in_pool(&self, ip: Ipv4Addr) -> bool {
+        self.config.pool.contains(&ip) && ip != self.config.server_ip
+    }
+    fn is_free(&self, leases: &Leases, ip: Ipv4Addr, mac: &EthernetAddress, now: u64) -> bool {
+        self.in_pool(ip)
+            && leases.declined.get(&ip).map_or(true, |until| *until <= now)
+            && !leases.by_mac.iter().any(|(m, l)| l.ip == ip && m != mac && l.expires > now)
+    }
+    fn pick(&self, leases: &Leases, message: &Message, now: u64) -> Option<Ipv4Addr> {
+        let mac = &message.chaddr;
+        if let Some(lease) = leases.by_mac.get(mac) {
+            if self.in_pool(lease.ip) {
+                return Some(lease.ip);
+            }
+        }
+        if let Some(ip) = message.requested_ip {
+            if self.is_free(leases, ip, mac, now) {
+                return Some(ip);
+            }
+        }
+        let (start, end) = (u32::from(*self.config.pool.start()), u32::from(*self.config.pool.end()));
+        (start..=end).map(Ipv4Addr::from).find(|ip| self.is_free(leases, *ip, mac, now))
+    }
+    fn save(&self, leases: &Leases, now: u64) {
+        if let Some(path) = &self.config.state_file {
+            if let Err(e) = leases.save(path, now) {
+                log::error!("dhcp: failed to save leases to {:?}: {:?}", path, e);
+            }
+        }
+    }
+    /// Returns the reply and the address it should go to, if any.
+    fn handle(&self, data: &[u8], now: u64) -> Option<(Vec<u8>, Ipv4Addr, EthernetAddress)> {
+        let message = Message::parse(data)?;
+        if !self.allowed(&message) {
+            log::trace!("dhcp: ignoring {}", message.chaddr);
+            return None;
+        }
+        let mut leases = self.leases.lock().unwrap();
+        let mac = message.chaddr;
+        let (message_type, ip) = match message.message_type {
+            DHCPDISCOVER => {
+                let ip = self.pick(&leases, &message, now)?;
+                let expires = leases.by_mac.get(&mac).map(|l| l.expires).unwrap_or(0);
+                leases.by_mac.insert(mac, Lease { ip, expires: expires.max(now + OFFER_TIME) });
+                (DHCPOFFER, ip)
+            }
+            DHCPREQUEST => {
+                if message.server_id.map(|id| id != self.config.server_ip).unwrap_or(false) {
+                    // the client went with another server
+                    return None;
+                }
+                let ip = message.requested_ip.or_else(|| match message.ciaddr {
+                    ip if ip.is_unspecified() => None,
+                    ip => Some(ip),
+                })?;
+                if self.is_free(&leases, ip, &mac, now) {
+                    let expires = now + self.config.lease_time.as_secs();
+                    leases.by_mac.insert(mac, Lease { ip, expires });
+                    self.save(&leases, now);
+                    log::info!("dhcp: leased {} to {}", ip, mac);
+                    (DHCPACK, ip)
+                } else if message.server_id.is_some()
+                    || self.config.subnet.contains_addr(&ip.into())
+                {
+                    (DHCPNAK, Ipv4Addr::UNSPECIFIED)
+                } else {
+                    // not ours to refuse, another server may know it
+                    return None;
+                }
+            }
+            DHCPRELEASE | DHCPDECLINE => {
+                let lease = leases.by_mac.remove(&mac);
+                if message.message_type == DHCPDECLINE {
+                    if let Some(ip) = message.requested_ip.or_else(|| lease.map(|l| l.ip)) {
+                        log::warn!("dhcp: {} declined {}, it is in use", mac, ip);
+                        leases.declined.retain(|_, until| *until > now);
+                        leases.declined.insert(ip, now + DECLINE_TIME);
+                    }
+                }
+                if lease.is_some() {
+                    self.save(&leases, now);
+                }
+                return None;
+            }
+            _ => return None,
+        };
+        drop(leases);
+
+        let reply = self.reply(&message, message_type, ip);
+        // renewing clients already have their address, everyone else gets
+        // a broadcast
+        if message_type == DHCPACK && !message.ciaddr.is_unspecified() {
+            Some((reply, message.ciaddr, mac))
+        } else {
+            Some((reply, Ipv4Addr::BROADCAST, EthernetAddress::BROADCAST))
+        }
+    }
+    fn reply(&self, message: &Message, message_type: u8, yiaddr: Ipv4Addr) -> Vec<u8> {
+        let config = &self.config;
+        let mut buf = vec![0u8; HEADER_LEN];
+        buf[0] = BOOTREPLY;
+        buf[1] = 1;
+        buf[2] = 6;
+        buf[4..8].copy_from_slice(&message.xid);
+        buf[10..12].copy_from_slice(&message.flags);
+        if message_type != DHCPNAK {
+            buf[12..16].copy_from_slice(&message.ciaddr.octets());
+        }
+        buf[16..20].copy_from_slice(&yiaddr.octets());
+        buf[20..24].copy_from_slice(&config.server_ip.octets());
+        buf[28..34].copy_from_slice(message.chaddr.as_bytes());
+        buf.extend_from_slice(&MAGIC_COOKIE);
+
+        buf.extend_from_slice(&[OPT_MESSAGE_TYPE, 1, message_type]);
+        buf.extend_from_slice(&[OPT_SERVER_ID, 4]);
+        buf.extend_from_slice(&config.server_ip.octets());
+        if message_type != DHCPNAK {
+            let lease_time = config.lease_time.as_secs().min(u64::from(u32::MAX)) as u32;
+            buf.extend_from_slice(&[OPT_LEASE_TIME, 4]);
+            buf.extend_from_slice(&lease_time.to_be_bytes());
+            buf.extend_from_slice(&[OPT_SUBNET_MASK, 4]);
+            buf.extend_from_slice(&netmask(config.subnet.prefix_len()).to_be_bytes());
+            buf.extend_from_slice(&[OPT_ROUTER, 4]);
+            buf.extend_from_slice(&config.server_ip.octets());
+            buf.extend_from_slice(&[OPT_DNS, 4]);
+            buf.extend_from_slice(&config.dns.octets());
+        }
+        buf.push(OPT_END);
+        buf
+    }
+}
+
+/// The DHCP payload of a frame sent to a server, which is broadcast until
+/// the client has an address.
+fn dhcp_payload(frame: &[u8]) -> Option<&[u8]> {
+    let frame = EthernetFrame::new_checked(frame).ok()?;
+    if frame.ethertype() != EthernetProtocol::Ipv4 {
+        return None;
+    }
+    let ip = Ipv4Packet::new_checked(frame.payload()).ok()?;
+    if ip.protocol() != IpProtocol::Udp {
+        return None;
+    }
+    let udp = UdpPacket::new_checked(ip.payload()).ok()?;
+    if udp.dst_port() != SERVER_PORT {
+        return None;
+    }
+    Some(udp.payload())
+}
+
+impl Divert for DhcpServer {
+    fn divert(&self, frame: &[u8], mac: EthernetAddress, inject: &Sender<Packet>) -> bool {
+        let data = match dhcp_payload(frame) {
+            Some(data) => data,
+            None => return false,
+        };
+        if let Some((reply, dst_ip, dst_mac)) = self.handle(data, now()) {
+            let src = SocketAddr::new(self.config.server_ip.into(), SERVER_PORT);
+            let dst = SocketAddr::new(dst_ip.into(), CLIENT_PORT);
+            let packet = OwnedUdp::new(src, dst, reply).to_raw();
+            let _ = inject.try_send(ethernet_frame(mac, dst_mac, &packet));
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CLIENT: EthernetAddress = EthernetAddress([0x02, 0, 0, 0, 0, 0x05]);
+
+    fn config() -> DhcpConfig {
+        let server_ip = Ipv4Addr::new(10, 13, 37, 2);
+        DhcpConfig::new(server_ip, Ipv4Cidr::new(server_ip.into(), 16))
+    }
+
+    fn request(message_type: u8, requested_ip: Option<Ipv4Addr>, vendor_class: Option<&[u8]>) -> Vec<u8> {
+        let mut buf = vec![0u8; HEADER_LEN];
+        buf[0] = BOOTREQUEST;
+        buf[1] = 1;
+        buf[2] = 6;
+        buf[4..8].copy_from_slice(&[1, 2, 3, 4]);
+        buf[28..34].copy_from_slice(CLIENT.as_bytes());
+        buf.extend_from_slice(&MAGIC_COOKIE);
+        buf.extend_from_slice(&[OPT_MESSAGE_TYPE, 1, message_type]);
+        if let Some(ip) = requested_ip {
+            buf.extend_from_slice(&[OPT_REQUESTED_IP, 4]);
+            buf.extend_from_slice(&ip.octets());
+        }
+        if let Some(vendor) = vendor_class {
+            buf.extend_from_slice(&[OPT_VENDOR_CLASS, vendor.len() as u8]);
+            buf.extend_from_slice(vendor);
+        }
+        buf.push(OPT_END);
+        buf
+    }
+
+    /// Message type and offered address of a reply.
+    fn parse_reply(reply: &[u8]) -> (u8, Ipv4Addr) {
+        assert_eq!(reply[0], BOOTREPLY);
+        assert_eq!(&reply[4..8], &[1, 2, 3, 4]);
+        let options = &reply[HEADER_LEN + 4..];
+        assert_eq!(options[0], OPT_MESSAGE_TYPE);
+        (options[2], ip_at(&reply[16..20]))
+    }
+
+    #[test]
+    fn test_lease() {
+        let server = DhcpServer::new(config()).unwrap();
+        let (offer, _, _) = server.handle(&request(DHCPDISCOVER, None, None), 1000).unwrap();
+        let (message_type, ip) = parse_reply(&offer);
+        assert_eq!(message_type, DHCPOFFER);
+        assert_eq!(ip, Ipv4Addr::new(10, 13, 0, 100));
+
+        let (ack, dst, _) = server.handle(&request(DHCPREQUEST, Some(ip), None), 1000).unwrap();
+        assert_eq!(parse_reply(&ack), (DHCPACK, ip));
+        assert_eq!(dst, Ipv4Addr::BROADCAST);
+
+        // someone else doesn't get the same address
+        let mut other = request(DHCPREQUEST, Some(ip), None);
+        other[33] = 0x06;
+        let (nak, _, _) = server.handle(&other, 1000).unwrap();
+        assert_eq!(parse_reply(&nak).0, DHCPNAK);
+
+        // but outside the subnet we stay quiet
+        assert!(server.handle(&request(DHCPREQUEST, Some(Ipv4Addr::new(192, 168, 1, 5)), None), 1000).is_none());
+    }
+
+    #[test]
+    fn test_decline() {
+        let server = DhcpServer::new(config()).unwrap();
+        let (offer, _, _) = server.handle(&request(DHCPDISCOVER, None, None), 1000).unwrap();
+        let (_, declined) = parse_reply(&offer);
+        assert!(server.handle(&request(DHCPDECLINE, Some(declined), None), 1000).is_none());
+
+        // the next address is offered, even when the declined one is asked for
+        let (offer, _, _) = server.handle(&request(DHCPDISCOVER, Some(declined), None), 1001).unwrap();
+        assert_eq!(parse_reply(&offer), (DHCPOFFER, Ipv4Addr::new(10, 13, 0, 101)));
+
+        // until it's been a while
+        let server = DhcpServer::new(config()).unwrap();
+        server.handle(&request(DHCPDECLINE, Some(declined), None), 1000);
+        let (offer, _, _) = server.handle(&request(DHCPDISCOVER, None, None), 1000 + DECLINE_TIME).unwrap();
+        assert_eq!(parse_reply(&offer), (DHCPOFFER, declined));
+    }
+
+    #[test]
+    fn test_set_pool() {
+        let mut config = config();
+        assert!(config.set_pool(Ipv4Addr::new(10, 13, 0, 100)..=Ipv4Addr::new(10, 13, 0, 200)).is_ok());
+        assert_eq!(config.pool, Ipv4Addr::new(10, 13, 0, 100)..=Ipv4Addr::new(10, 13, 0, 200));
+        // outside the subnet
+        assert!(config.set_pool(Ipv4Addr::new(10, 13, 0, 100)..=Ipv4Addr::new(10, 14, 0, 1)).is_err());
+        assert!(config.set_pool(Ipv4Addr::new(192, 168, 0, 1)..=Ipv4Addr::new(192, 168, 0, 9)).is_err());
+        // the gateway
+        let err = config.set_pool(Ipv4Addr::new(10, 13, 37, 1)..=Ipv4Addr::new(10, 13, 37, 9)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(config.pool, Ipv4Addr::new(10, 13, 0, 100)..=Ipv4Addr::new(10, 13, 0, 200));
+    }
+
+    #[test]
+    fn test_allowed() {
+        let mut config = config();
+        config.vendor_class = Some("Nintendo".to_string());
+        let server = DhcpServer::new(config).unwrap();
+        assert!(server.handle(&request(DHCPDISCOVER, None, None), 1000).is_none());
+        assert!(server.handle(&request(DHCPDISCOVER, None, Some(b"Android")), 1000).is_none());
+        assert!(server.handle(&request(DHCPDISCOVER, None, Some(b"Nintendo Switch")), 1000).is_some());
+
+        let mut config = self::config();
+        config.allowed_macs = vec![CLIENT];
+        let server = DhcpServer::new(config).unwrap();
+        assert!(server.handle(&request(DHCPDISCOVER, None, None), 1000).is_some());
+    }
+
+    #[test]
+    fn test_persist() {
+        let path = std::env::temp_dir().join(format!("lan-play-dhcp-test-{}", std::process::id()));
+        let mut config = config();
+        config.state_file = Some(path.clone());
+        let requested = Ipv4Addr::new(10, 13, 1, 1);
+
+        let server = DhcpServer::new(config.clone()).unwrap();
+        let (ack, _, _) = server.handle(&request(DHCPREQUEST, Some(requested), None), now()).unwrap();
+        assert_eq!(parse_reply(&ack), (DHCPACK, requested));
+
+        // a restarted server offers the same address again
+        let server = DhcpServer::new(config).unwrap();
+        let (offer, _, _) = server.handle(&request(DHCPDISCOVER, None, None), now()).unwrap();
+        assert_eq!(parse_reply(&offer), (DHCPOFFER, requested));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,112 @@
+use crate::future_smoltcp::{Interface, Packet};
+use async_channel::{unbounded, Receiver, Sender};
+use futures::prelude::*;
+use smoltcp::wire::{EthernetAddress, EthernetFrame, EthernetProtocol};
+use std::io;
+use std::sync::Arc;
+use std::{pin::Pin, task::{Context, Poll}};
+
+const ETHERNET_HEADER_LEN: usize = 14;
+
+/// Something that handles some frames itself instead of smoltcp.
+pub trait Divert: Send + Sync {
+    /// Takes over `frame`, received by the gateway `mac`, if it is ours.
+    /// Frames for the console can be sent through `inject`.
+    fn divert(&self, frame: &[u8], mac: EthernetAddress, inject: &Sender<Packet>) -> bool;
+}
+
+/// Wraps an IPv4 packet in an Ethernet frame.
+pub(super) fn ethernet_frame(src: EthernetAddress, dst: EthernetAddress, packet: &[u8]) -> Packet {
+    let mut buf = vec![0u8; ETHERNET_HEADER_LEN + packet.len()];
+    {
+        let mut frame = EthernetFrame::new_unchecked(&mut buf[..]);
+        frame.set_src_addr(src);
+        frame.set_dst_addr(dst);
+        frame.set_ethertype(EthernetProtocol::Ipv4);
+    }
+    buf[ETHERNET_HEADER_LEN..].copy_from_slice(packet);
+    buf
+}
+
+/// An Ethernet interface whose frames are offered to `Divert` handlers
+/// before they reach smoltcp.
+pub struct DivertInterface<I> {
+    inner: I,
+    handlers: Vec<Arc<dyn Divert>>,
+    mac: EthernetAddress,
+    inject_tx: Sender<Packet>,
+    inject_rx: Receiver<Packet>,
+}
+
+impl<I> DivertInterface<I> {
+    pub(super) fn new(inner: I, handlers: Vec<Arc<dyn Divert>>, mac: EthernetAddress) -> DivertInterface<I> {
+        let (inject_tx, inject_rx) = unbounded();
+        DivertInterface {
+            inner,
+            handlers,
+            mac,
+            inject_tx,
+            inject_rx,
+        }
+    }
+}
+
+impl<I: Interface> Stream for DivertInterface<I> {
+    type Item = Packet;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        // smoltcp waits on this stream when idle, so this is where frames
+        // from the handlers get onto the wire
+        loop {
+            match Sink::poll_ready(Pin::new(&mut this.inner), cx) {
+                Poll::Ready(Ok(())) => {}
+                _ => break,
+            }
+            match Stream::poll_next(Pin::new(&mut this.inject_rx), cx) {
+                Poll::Ready(Some(frame)) => {
+                    if let Err(e) = Pin::new(&mut this.inner).start_send(frame) {
+                        log::warn!("divert: failed to send frame {:?}", e);
+                    }
+                }
+                _ => break,
+            }
+        }
+        let _ = Sink::poll_flush(Pin::new(&mut this.inner), cx);
+
+        loop {
+            match Stream::poll_next(Pin::new(&mut this.inner), cx) {
+                Poll::Ready(Some(frame)) => {
+                    let (mac, inject) = (this.mac, &this.inject_tx);
+                    if !this.handlers.iter().any(|h| h.divert(&frame, mac, inject)) {
+                        return Poll::Ready(Some(frame));
+                    }
+                }
+                other => return other,
+            }
+        }
+    }
+}
+
+impl<I: Interface> Sink<Packet> for DivertInterface<I> {
+    type Error = io::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Sink::poll_ready(Pin::new(&mut self.inner), cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Packet) -> Result<(), Self::Error> {
+        Pin::new(&mut self.inner).start_send(item)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Sink::poll_flush(Pin::new(&mut self.inner), cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Sink::poll_close(Pin::new(&mut self.inner), cx)
+    }
+}
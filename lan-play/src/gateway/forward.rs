@@ -0,0 +1,348 @@
+use crate::future_smoltcp::{OwnedUdp, SendHalf as UdpSendHalf, TcpConnector};
+use super::tcp::pipe;
+use drop_abort::{abortable, DropAbortHandle};
+use futures::future::{try_join_all, BoxFuture, FutureExt};
+use lru::LruCache;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex as SyncMutex, atomic::{AtomicUsize, Ordering}};
+use thiserror::Error;
+use tokio::{
+    net::{TcpListener as HostTcpListener, UdpSocket as HostUdpSocket},
+    sync::Mutex,
+};
+
+/// Virtual source ports handed out to outside peers.
+const FIRST_PORT: u16 = 49152;
+const MAX_SESSIONS: usize = 1024;
+
+#[derive(Debug, Error, PartialEq)]
+#[error("invalid port forward {0:?}, expected e.g. 0.0.0.0:45000/udp->10.13.37.5:45000")]
+pub struct ParseForwardError(String);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Protocol::Tcp => "tcp",
+            Protocol::Udp => "udp",
+        })
+    }
+}
+
+/// A host socket whose traffic is forwarded to a console.
+///
+/// Parsed from `[host:]0.0.0.0:45000/udp->10.13.37.5:45000`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortForward {
+    pub protocol: Protocol,
+    pub listen: SocketAddr,
+    pub target: SocketAddr,
+}
+
+impl FromStr for PortForward {
+    type Err = ParseForwardError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseForwardError(s.to_string());
+        let arrow = s.find("->").ok_or_else(invalid)?;
+        let (listen, target) = (s[..arrow].trim(), s[arrow + 2..].trim());
+        let listen = listen.strip_prefix("host:").unwrap_or(listen);
+        let slash = listen.rfind('/').ok_or_else(invalid)?;
+        let protocol = match &listen[slash + 1..] {
+            "tcp" => Protocol::Tcp,
+            "udp" => Protocol::Udp,
+            _ => return Err(invalid()),
+        };
+        Ok(PortForward {
+            protocol,
+            listen: listen[..slash].parse().map_err(|_| invalid())?,
+            target: target.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl fmt::Display for PortForward {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}->{}", self.listen, self.protocol, self.target)
+    }
+}
+
+/// Outside peers of forwards, each seen by the console as a port of the
+/// virtual source address.
+struct Sessions {
+    by_peer: LruCache<(usize, SocketAddr), u16>,
+    by_port: LruCache<u16, (usize, SocketAddr)>,
+    next_udp_port: u16,
+    tcp_ports: HashSet<u16>,
+    next_tcp_port: u16,
+}
+
+impl Sessions {
+    fn new() -> Sessions {
+        Sessions {
+            by_peer: LruCache::new(MAX_SESSIONS),
+            by_port: LruCache::new(MAX_SESSIONS),
+            next_udp_port: FIRST_PORT,
+            tcp_ports: HashSet::new(),
+            next_tcp_port: FIRST_PORT,
+        }
+    }
+    /// The port of the UDP peer, `None` when every port is taken.
+    fn port_for(&mut self, forward: usize, peer: SocketAddr) -> Option<u16> {
+        let key = (forward, peer);
+        if let Some(port) = self.by_peer.get(&key).copied() {
+            if self.by_port.get(&port) == Some(&key) {
+                return Some(port);
+            }
+        }
+        let by_port = &self.by_port;
+        let port = alloc_port(&mut self.next_udp_port, |port| by_port.contains(&port))?;
+        self.by_peer.put(key, port);
+        self.by_port.put(port, key);
+        Some(port)
+    }
+    /// A port for a TCP connection, until `release_tcp`.
+    fn alloc_tcp(&mut self) -> Option<u16> {
+        let tcp_ports = &self.tcp_ports;
+        let port = alloc_port(&mut self.next_tcp_port, |port| tcp_ports.contains(&port))?;
+        self.tcp_ports.insert(port);
+        Some(port)
+    }
+    fn release_tcp(&mut self, port: u16) {
+        self.tcp_ports.remove(&port);
+    }
+}
+
+/// The first port from `next` on that isn't in use, wrapping around.
+fn alloc_port(next: &mut u16, in_use: impl Fn(u16) -> bool) -> Option<u16> {
+    for _ in FIRST_PORT..=u16::MAX {
+        let port = *next;
+        *next = port.checked_add(1).unwrap_or(FIRST_PORT);
+        if !in_use(port) {
+            return Some(port);
+        }
+    }
+    None
+}
+
+/// A forward added with `ForwardGateway::add`, closed when dropped.
+pub struct ForwardHandle {
+    gateway: Arc<ForwardGateway>,
+    id: usize,
+    local_addr: SocketAddr,
+    _handle: DropAbortHandle,
+}
+
+impl ForwardHandle {
+    /// The host socket address, with the port picked when it was 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Drop for ForwardHandle {
+    fn drop(&mut self) {
+        self.gateway.udp_sockets.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Listens on host sockets and relays what arrives into the virtual
+/// network, coming from `source_ip`. Replies go back to the host socket.
+///
+/// Only the first attached network is used.
+pub struct ForwardGateway {
+    source_ip: Ipv4Addr,
+    forwards: Vec<PortForward>,
+    next_id: AtomicUsize,
+    connector: SyncMutex<Option<TcpConnector>>,
+    sender: SyncMutex<Option<Arc<Mutex<UdpSendHalf>>>>,
+    udp_sockets: SyncMutex<HashMap<usize, Arc<HostUdpSocket>>>,
+    sessions: SyncMutex<Sessions>,
+}
+
+impl ForwardGateway {
+    pub fn new(source_ip: Ipv4Addr, forwards: Vec<PortForward>) -> ForwardGateway {
+        ForwardGateway {
+            source_ip,
+            next_id: AtomicUsize::new(forwards.len()),
+            forwards,
+            connector: SyncMutex::new(None),
+            sender: SyncMutex::new(None),
+            udp_sockets: SyncMutex::new(HashMap::new()),
+            sessions: SyncMutex::new(Sessions::new()),
+        }
+    }
+    pub(super) fn attach_tcp(&self, connector: TcpConnector) {
+        self.connector.lock().unwrap().get_or_insert(connector);
+    }
+    pub(super) fn attach_udp(&self, sender: Arc<Mutex<UdpSendHalf>>) {
+        self.sender.lock().unwrap().get_or_insert(sender);
+    }
+    fn source(&self, port: u16) -> SocketAddr {
+        SocketAddr::new(IpAddr::V4(self.source_ip), port)
+    }
+    /// Binds every host socket and serves them until one fails.
+    pub async fn run(self: Arc<Self>) -> io::Result<()> {
+        let mut tasks = Vec::new();
+        for (id, forward) in self.forwards.iter().enumerate() {
+            log::info!("forwarding {}", forward);
+            let (_, task) = self.listen(id, forward).await?;
+            tasks.push(tokio::spawn(task));
+        }
+        for r in try_join_all(tasks).await? {
+            r?;
+        }
+        Ok(())
+    }
+    /// Starts forwarding `forward` until the returned handle is dropped.
+    pub async fn add(self: &Arc<Self>, forward: &PortForward) -> io::Result<ForwardHandle> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (local_addr, task) = self.listen(id, forward).await?;
+        log::info!("forwarding {} on {}", forward, local_addr);
+        let (task, _handle) = abortable(task);
+        tokio::spawn(task);
+        Ok(ForwardHandle {
+            gateway: self.clone(),
+            id,
+            local_addr,
+            _handle,
+        })
+    }
+    async fn listen(
+        self: &Arc<Self>,
+        id: usize,
+        forward: &PortForward,
+    ) -> io::Result<(SocketAddr, BoxFuture<'static, io::Result<()>>)> {
+        let target = forward.target;
+        match forward.protocol {
+            Protocol::Tcp => {
+                let listener = HostTcpListener::bind(forward.listen).await?;
+                Ok((listener.local_addr()?, self.clone().run_tcp(target, listener).boxed()))
+            }
+            Protocol::Udp => {
+                let socket = Arc::new(HostUdpSocket::bind(forward.listen).await?);
+                self.udp_sockets.lock().unwrap().insert(id, socket.clone());
+                Ok((socket.local_addr()?, self.clone().run_udp(id, target, socket).boxed()))
+            }
+        }
+    }
+    async fn run_tcp(self: Arc<Self>, target: SocketAddr, listener: HostTcpListener) -> io::Result<()> {
+        loop {
+            let (host, peer) = listener.accept().await?;
+            let connector = match self.connector.lock().unwrap().clone() {
+                Some(connector) => connector,
+                None => {
+                    log::warn!("tcp forward from {}: no network yet", peer);
+                    continue;
+                }
+            };
+            let port = match self.sessions.lock().unwrap().alloc_tcp() {
+                Some(port) => port,
+                None => {
+                    log::warn!("tcp forward from {}: no free port", peer);
+                    continue;
+                }
+            };
+            let gateway = self.clone();
+            tokio::spawn(async move {
+                match connector.connect(gateway.source(port), target).await {
+                    Ok(console) => {
+                        let r = pipe(host, console).await;
+                        log::trace!("tcp forward {} -x {} {:?}", peer, target, r);
+                    }
+                    Err(e) => log::debug!("tcp forward {} -> {} failed {:?}", peer, target, e),
+                }
+                gateway.sessions.lock().unwrap().release_tcp(port);
+            });
+        }
+    }
+    async fn run_udp(self: Arc<Self>, id: usize, target: SocketAddr, socket: Arc<HostUdpSocket>) -> io::Result<()> {
+        let mut buf = vec![0; 2048];
+        loop {
+            let (size, peer) = socket.recv_from(&mut buf).await?;
+            let sender = match self.sender.lock().unwrap().clone() {
+                Some(sender) => sender,
+                None => continue,
+            };
+            let port = match self.sessions.lock().unwrap().port_for(id, peer) {
+                Some(port) => port,
+                None => {
+                    log::warn!("udp forward from {}: no free port", peer);
+                    continue;
+                }
+            };
+            let udp = OwnedUdp::new(self.source(port), target, buf[..size].to_vec());
+            // one datagram that can't be sent doesn't stop the forward
+            if let Err(e) = sender.lock().await.send(&udp).await {
+                log::warn!("udp forward {} -> {} failed {:?}", peer, target, e);
+            }
+        }
+    }
+    /// Sends a console datagram back to the outside peer it answers.
+    /// Returns false if it isn't part of a forward.
+    pub(super) async fn on_udp(&self, udp: &OwnedUdp) -> io::Result<bool> {
+        let dst = udp.dst();
+        if dst.ip() != IpAddr::V4(self.source_ip) {
+            return Ok(false);
+        }
+        let session = self.sessions.lock().unwrap().by_port.get(&dst.port()).copied();
+        let (id, peer) = match session {
+            Some(session) => session,
+            None => return Ok(false),
+        };
+        let socket = self.udp_sockets.lock().unwrap().get(&id).cloned();
+        if let Some(socket) = socket {
+            socket.send_to(&udp.data, peer).await?;
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_forward() {
+        let forward: PortForward = "host:0.0.0.0:45000/udp -> 10.13.37.5:45000".parse().unwrap();
+        assert_eq!(forward, PortForward {
+            protocol: Protocol::Udp,
+            listen: "0.0.0.0:45000".parse().unwrap(),
+            target: "10.13.37.5:45000".parse().unwrap(),
+        });
+        assert_eq!(forward.to_string().parse::<PortForward>(), Ok(forward));
+        assert!("0.0.0.0:45000->10.13.37.5:45000".parse::<PortForward>().is_err());
+        assert!("0.0.0.0:45000/icmp->10.13.37.5:45000".parse::<PortForward>().is_err());
+    }
+
+    #[test]
+    fn test_alloc_port() {
+        let mut sessions = Sessions::new();
+        let peer = |port| SocketAddr::new(Ipv4Addr::LOCALHOST.into(), port);
+        sessions.next_udp_port = u16::MAX;
+        assert_eq!(sessions.port_for(0, peer(1)), Some(u16::MAX));
+        assert_eq!(sessions.port_for(0, peer(2)), Some(FIRST_PORT));
+        // wrapped around, the live sessions keep their ports
+        sessions.next_udp_port = u16::MAX;
+        assert_eq!(sessions.port_for(0, peer(3)), Some(FIRST_PORT + 1));
+        assert_eq!(sessions.port_for(0, peer(1)), Some(u16::MAX));
+
+        // TCP counts on its own
+        assert_eq!(sessions.alloc_tcp(), Some(FIRST_PORT));
+        assert_eq!(sessions.alloc_tcp(), Some(FIRST_PORT + 1));
+        sessions.next_tcp_port = FIRST_PORT;
+        assert_eq!(sessions.alloc_tcp(), Some(FIRST_PORT + 2));
+        sessions.release_tcp(FIRST_PORT);
+        sessions.next_tcp_port = FIRST_PORT;
+        assert_eq!(sessions.alloc_tcp(), Some(FIRST_PORT));
+    }
+}
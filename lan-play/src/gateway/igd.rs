@@ -0,0 +1,574 @@
+use super::divert::{ethernet_frame, Divert};
+use super::forward::{ForwardGateway, ForwardHandle, PortForward, Protocol};
+use super::natpmp;
+use crate::future_smoltcp::{OwnedUdp, Packet, SendHalf as UdpSendHalf, TcpSocket};
+use async_channel::Sender;
+use smoltcp::wire::{
+    EthernetAddress, EthernetFrame, EthernetProtocol, IpProtocol, Ipv4Address, Ipv4Packet,
+    UdpPacket,
+};
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::{io::AsyncWriteExt, sync::Mutex, time::sleep};
+
+const SSDP_ADDR: Ipv4Address = Ipv4Address([239, 255, 255, 250]);
+const SSDP_PORT: u16 = 1900;
+const NATPMP_PORT: u16 = 5351;
+const MAX_REQUEST: usize = 16 * 1024;
+const EXPIRE_INTERVAL: Duration = Duration::from_secs(10);
+
+const DEVICE_TYPE: &str = "urn:schemas-upnp-org:device:InternetGatewayDevice:1";
+const SERVICE_TYPE: &str = "urn:schemas-upnp-org:service:WANIPConnection:1";
+/// Search targets we answer, the first one stands for `ssdp:all`
+const SEARCH_TARGETS: &[&str] = &[
+    DEVICE_TYPE,
+    "upnp:rootdevice",
+    "urn:schemas-upnp-org:device:WANDevice:1",
+    "urn:schemas-upnp-org:device:WANConnectionDevice:1",
+    SERVICE_TYPE,
+];
+const CONTROL_PATH: &str = "/ctl/IPConn";
+const DESCRIPTION_PATH: &str = "/rootDesc.xml";
+
+/// Settings of the UPnP IGD and NAT-PMP/PCP responder.
+#[derive(Debug, Clone)]
+pub struct UpnpConfig {
+    /// Address consoles reach us at, the gateway IP
+    pub gateway_ip: Ipv4Addr,
+    /// Address reported to consoles as our public one
+    pub external_ip: Ipv4Addr,
+    /// Port of the device description and control endpoint
+    pub http_port: u16,
+    /// Longest lease handed out, also used for permanent UPnP mappings
+    pub max_lease: Duration,
+}
+
+impl UpnpConfig {
+    pub fn new(gateway_ip: Ipv4Addr, external_ip: Ipv4Addr) -> UpnpConfig {
+        UpnpConfig {
+            gateway_ip,
+            external_ip,
+            http_port: 5000,
+            max_lease: Duration::from_secs(3600),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(super) enum MappingError {
+    /// The external port belongs to another client
+    Conflict,
+    Io(io::Error),
+}
+
+struct Mapping {
+    internal: SocketAddr,
+    expires: Instant,
+    _handle: ForwardHandle,
+}
+
+/// Port mappings requested by consoles, each backed by a host socket of
+/// the forward gateway.
+pub(super) struct PortMappings {
+    forward: Arc<ForwardGateway>,
+    max_lease: Duration,
+    mappings: Mutex<HashMap<(Protocol, u16), Mapping>>,
+}
+
+impl PortMappings {
+    pub(super) fn new(forward: Arc<ForwardGateway>, max_lease: Duration) -> PortMappings {
+        PortMappings {
+            forward,
+            max_lease,
+            mappings: Mutex::new(HashMap::new()),
+        }
+    }
+    fn lease(&self, lease: Duration) -> Duration {
+        if lease == Duration::from_secs(0) {
+            self.max_lease
+        } else {
+            lease.min(self.max_lease)
+        }
+    }
+    /// Maps `external` on the host to `internal`, renewing an existing
+    /// mapping of the same client. Unless `exact`, another port is picked
+    /// when `external` is 0 or taken. Returns the port and the lease.
+    pub(super) async fn add(
+        &self,
+        protocol: Protocol,
+        external: u16,
+        internal: SocketAddr,
+        lease: Duration,
+        exact: bool,
+    ) -> Result<(u16, Duration), MappingError> {
+        let now = Instant::now();
+        let lease = self.lease(lease);
+        let mut mappings = self.mappings.lock().await;
+        mappings.retain(|_, m| m.expires > now);
+
+        let existing = match mappings.get(&(protocol, external)) {
+            Some(m) if m.internal == internal => Some(external),
+            Some(_) if exact => return Err(MappingError::Conflict),
+            _ if exact => None,
+            // NAT-PMP hands a client its previous mapping back
+            _ => mappings
+                .iter()
+                .find(|((p, _), m)| *p == protocol && m.internal == internal)
+                .map(|((_, port), _)| *port),
+        };
+        if let Some(port) = existing {
+            mappings.get_mut(&(protocol, port)).unwrap().expires = now + lease;
+            return Ok((port, lease));
+        }
+
+        let mut forward = PortForward {
+            protocol,
+            listen: SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), external),
+            target: internal,
+        };
+        if mappings.contains_key(&(protocol, external)) {
+            forward.listen.set_port(0);
+        }
+        let handle = match self.forward.add(&forward).await {
+            Ok(handle) => handle,
+            Err(e) if !exact && external != 0 => {
+                log::debug!("upnp: port {} taken {:?}, picking another", external, e);
+                forward.listen.set_port(0);
+                self.forward.add(&forward).await.map_err(MappingError::Io)?
+            }
+            Err(e) => return Err(MappingError::Io(e)),
+        };
+        let port = handle.local_addr().port();
+        mappings.insert((protocol, port), Mapping {
+            internal,
+            expires: now + lease,
+            _handle: handle,
+        });
+        Ok((port, lease))
+    }
+    /// Removes the mapping of `external` if it belongs to `client`.
+    pub(super) async fn remove(&self, protocol: Protocol, external: u16, client: IpAddr) -> bool {
+        let mut mappings = self.mappings.lock().await;
+        match mappings.get(&(protocol, external)) {
+            Some(m) if m.internal.ip() == client => mappings.remove(&(protocol, external)).is_some(),
+            _ => false,
+        }
+    }
+    /// Removes the mappings to `internal`, or every mapping of its host
+    /// when the port is 0.
+    pub(super) async fn remove_internal(&self, protocol: Protocol, internal: SocketAddr) {
+        let mut mappings = self.mappings.lock().await;
+        mappings.retain(|(p, _), m| {
+            *p != protocol || m.internal.ip() != internal.ip()
+                || (internal.port() != 0 && m.internal.port() != internal.port())
+        });
+    }
+    async fn expire(&self) {
+        let now = Instant::now();
+        self.mappings.lock().await.retain(|(protocol, port), m| {
+            let alive = m.expires > now;
+            if !alive {
+                log::debug!("upnp: {}/{} -> {} expired", port, protocol, m.internal);
+            }
+            alive
+        });
+    }
+}
+
+/// Answers SSDP searches, the IGD control protocol and NAT-PMP/PCP, so
+/// consoles can open ports on the host.
+pub struct IgdServer {
+    config: UpnpConfig,
+    mappings: PortMappings,
+    started: Instant,
+}
+
+impl IgdServer {
+    pub fn new(config: UpnpConfig, forward: Arc<ForwardGateway>) -> IgdServer {
+        IgdServer {
+            mappings: PortMappings::new(forward, config.max_lease),
+            config,
+            started: Instant::now(),
+        }
+    }
+    pub(super) fn http_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.config.gateway_ip.into(), self.config.http_port)
+    }
+    /// Drops expired mappings, forever.
+    pub async fn run(&self) {
+        loop {
+            sleep(EXPIRE_INTERVAL).await;
+            self.mappings.expire().await;
+        }
+    }
+    fn uuid(&self) -> String {
+        let ip = self.config.gateway_ip.octets();
+        format!("4c616e50-6c61-7900-0000-0000{:02x}{:02x}{:02x}{:02x}", ip[0], ip[1], ip[2], ip[3])
+    }
+    /// Answer to an SSDP M-SEARCH, if we match its search target.
+    fn search_reply(&self, request: &[u8]) -> Option<Vec<u8>> {
+        let request = std::str::from_utf8(request).ok()?;
+        if !request.starts_with("M-SEARCH ") {
+            return None;
+        }
+        let st = header(request, "ST")?;
+        let st = if st == "ssdp:all" {
+            SEARCH_TARGETS[0]
+        } else {
+            SEARCH_TARGETS.iter().find(|t| **t == st)?
+        };
+        let usn = if st == "upnp:rootdevice" || st.starts_with("urn:") {
+            format!("uuid:{}::{}", self.uuid(), st)
+        } else {
+            format!("uuid:{}", self.uuid())
+        };
+        Some(format!(
+            "HTTP/1.1 200 OK\r\n\
+            CACHE-CONTROL: max-age=120\r\n\
+            ST: {}\r\n\
+            USN: {}\r\n\
+            EXT:\r\n\
+            SERVER: lan-play UPnP/1.1 IGD/1.0\r\n\
+            LOCATION: http://{}{}\r\n\
+            \r\n",
+            st, usn, self.http_addr(), DESCRIPTION_PATH,
+        ).into_bytes())
+    }
+    fn description(&self) -> String {
+        format!(
+            r#"<?xml version="1.0"?>
+<root xmlns="urn:schemas-upnp-org:device-1-0">
+<specVersion><major>1</major><minor>0</minor></specVersion>
+<device>
+<deviceType>{}</deviceType>
+<friendlyName>lan-play</friendlyName>
+<manufacturer>lan-play</manufacturer>
+<modelName>lan-play</modelName>
+<UDN>uuid:{}</UDN>
+<deviceList>
+<device>
+<deviceType>urn:schemas-upnp-org:device:WANDevice:1</deviceType>
+<friendlyName>WANDevice</friendlyName>
+<manufacturer>lan-play</manufacturer>
+<modelName>lan-play</modelName>
+<UDN>uuid:{}-1</UDN>
+<deviceList>
+<device>
+<deviceType>urn:schemas-upnp-org:device:WANConnectionDevice:1</deviceType>
+<friendlyName>WANConnectionDevice</friendlyName>
+<manufacturer>lan-play</manufacturer>
+<modelName>lan-play</modelName>
+<UDN>uuid:{}-2</UDN>
+<serviceList>
+<service>
+<serviceType>{}</serviceType>
+<serviceId>urn:upnp-org:serviceId:WANIPConn1</serviceId>
+<SCPDURL>/WANIPCn.xml</SCPDURL>
+<controlURL>{}</controlURL>
+<eventSubURL>/evt/IPConn</eventSubURL>
+</service>
+</serviceList>
+</device>
+</deviceList>
+</device>
+</deviceList>
+</device>
+</root>
+"#,
+            DEVICE_TYPE, self.uuid(), self.uuid(), self.uuid(), SERVICE_TYPE, CONTROL_PATH,
+        )
+    }
+    /// Serves one HTTP request of a console.
+    pub(super) async fn serve(&self, mut socket: TcpSocket) -> io::Result<()> {
+        let peer = socket.peer_addr()?;
+        let request = match read_request(&mut socket).await? {
+            Some(request) => request,
+            None => return Ok(()),
+        };
+        let response = match (request.method.as_str(), request.path.as_str()) {
+            ("GET", DESCRIPTION_PATH) => http_response("200 OK", &self.description()),
+            ("POST", CONTROL_PATH) => match request.action() {
+                Some(action) => self.control(peer.ip(), action, &request.body).await,
+                None => soap_fault(401, "Invalid Action"),
+            },
+            _ => http_response("404 Not Found", ""),
+        };
+        socket.write_all(response.as_bytes()).await?;
+        socket.shutdown().await
+    }
+    /// Runs a SOAP action of the WANIPConnection service.
+    async fn control(&self, client: IpAddr, action: &str, body: &str) -> String {
+        log::debug!("upnp: {} from {}", action, client);
+        let protocol = match argument(body, "NewProtocol").map(|p| p.to_ascii_uppercase()) {
+            Some(p) if p == "TCP" => Some(Protocol::Tcp),
+            Some(p) if p == "UDP" => Some(Protocol::Udp),
+            _ => None,
+        };
+        let external = argument(body, "NewExternalPort").and_then(|p| p.parse::<u16>().ok());
+        match action {
+            "GetExternalIPAddress" => soap_response(action, &format!(
+                "<NewExternalIPAddress>{}</NewExternalIPAddress>", self.config.external_ip
+            )),
+            "GetStatusInfo" => soap_response(action, &format!(
+                "<NewConnectionStatus>Connected</NewConnectionStatus>\
+                <NewLastConnectionError>ERROR_NONE</NewLastConnectionError>\
+                <NewUptime>{}</NewUptime>",
+                self.started.elapsed().as_secs()
+            )),
+            "AddPortMapping" => {
+                let internal_port = argument(body, "NewInternalPort").and_then(|p| p.parse::<u16>().ok());
+                let internal_client = argument(body, "NewInternalClient").and_then(|c| c.parse::<IpAddr>().ok());
+                let lease = argument(body, "NewLeaseDuration").and_then(|l| l.parse::<u64>().ok()).unwrap_or(0);
+                let (protocol, external, internal_port, internal_client) =
+                    match (protocol, external, internal_port, internal_client) {
+                        (Some(p), Some(e), Some(i), Some(c)) if e != 0 && i != 0 => (p, e, i, c),
+                        _ => return soap_fault(402, "Invalid Args"),
+                    };
+                if internal_client != client {
+                    return soap_fault(606, "Action not authorized");
+                }
+                let internal = SocketAddr::new(internal_client, internal_port);
+                match self.mappings.add(protocol, external, internal, Duration::from_secs(lease), true).await {
+                    Ok(_) => soap_response(action, ""),
+                    Err(MappingError::Conflict) => soap_fault(718, "ConflictInMappingEntry"),
+                    Err(MappingError::Io(e)) => {
+                        log::warn!("upnp: failed to map {}/{}: {:?}", external, protocol, e);
+                        soap_fault(501, "Action Failed")
+                    }
+                }
+            }
+            "DeletePortMapping" => match (protocol, external) {
+                (Some(protocol), Some(external)) => {
+                    if self.mappings.remove(protocol, external, client).await {
+                        soap_response(action, "")
+                    } else {
+                        soap_fault(714, "NoSuchEntryInArray")
+                    }
+                }
+                _ => soap_fault(402, "Invalid Args"),
+            },
+            _ => soap_fault(401, "Invalid Action"),
+        }
+    }
+    /// Answers NAT-PMP and PCP requests. Returns false if `udp` isn't one.
+    pub(super) async fn on_udp(&self, udp: &OwnedUdp, sender: &Mutex<UdpSendHalf>) -> io::Result<bool> {
+        let (src, dst) = (udp.src(), udp.dst());
+        if dst != SocketAddr::new(self.config.gateway_ip.into(), NATPMP_PORT) {
+            return Ok(false);
+        }
+        let client = match src.ip() {
+            IpAddr::V4(ip) => ip,
+            IpAddr::V6(_) => return Ok(true),
+        };
+        let epoch = self.started.elapsed().as_secs() as u32;
+        let reply = natpmp::handle(&self.mappings, self.config.external_ip, epoch, client, &udp.data).await;
+        if let Some(reply) = reply {
+            sender.lock().await.send(&OwnedUdp::new(dst, src, reply)).await?;
+        }
+        Ok(true)
+    }
+}
+
+impl Divert for IgdServer {
+    fn divert(&self, frame: &[u8], mac: EthernetAddress, inject: &Sender<Packet>) -> bool {
+        let frame = match EthernetFrame::new_checked(frame) {
+            Ok(frame) if frame.ethertype() == EthernetProtocol::Ipv4 => frame,
+            _ => return false,
+        };
+        let ip = match Ipv4Packet::new_checked(frame.payload()) {
+            Ok(ip) if ip.dst_addr() == SSDP_ADDR && ip.protocol() == IpProtocol::Udp => ip,
+            _ => return false,
+        };
+        let udp = match UdpPacket::new_checked(ip.payload()) {
+            Ok(udp) if udp.dst_port() == SSDP_PORT => udp,
+            _ => return false,
+        };
+        if let Some(reply) = self.search_reply(udp.payload()) {
+            let src = SocketAddr::new(self.config.gateway_ip.into(), SSDP_PORT);
+            let dst = SocketAddr::new(Ipv4Addr::from(ip.src_addr()).into(), udp.src_port());
+            let packet = OwnedUdp::new(src, dst, reply).to_raw();
+            let _ = inject.try_send(ethernet_frame(mac, frame.src_addr(), &packet));
+        }
+        true
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    headers: String,
+    body: String,
+}
+
+impl Request {
+    /// Action name of a SOAPAction header like `"urn:...:WANIPConnection:1#AddPortMapping"`.
+    fn action(&self) -> Option<&str> {
+        let action = header(&self.headers, "SOAPAction")?.trim_matches('"');
+        let hash = action.rfind('#')?;
+        if &action[..hash] != SERVICE_TYPE {
+            return None;
+        }
+        Some(&action[hash + 1..])
+    }
+}
+
+async fn read_request(socket: &mut TcpSocket) -> io::Result<Option<Request>> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 2048];
+    let header_end = loop {
+        if let Some(pos) = find(&buf, b"\r\n\r\n") {
+            break pos + 4;
+        }
+        let size = socket.recv(&mut chunk).await?;
+        if size == 0 || buf.len() > MAX_REQUEST {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&chunk[..size]);
+    };
+    let head = String::from_utf8_lossy(&buf[..header_end]).into_owned();
+    let length = header(&head, "Content-Length").and_then(|l| l.parse::<usize>().ok()).unwrap_or(0);
+    if length > MAX_REQUEST {
+        return Ok(None);
+    }
+    while buf.len() < header_end + length {
+        let size = socket.recv(&mut chunk).await?;
+        if size == 0 {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&chunk[..size]);
+    }
+    let mut request_line = head.lines().next().unwrap_or("").split_whitespace();
+    Ok(Some(Request {
+        method: request_line.next().unwrap_or("").to_string(),
+        path: request_line.next().unwrap_or("").to_string(),
+        body: String::from_utf8_lossy(&buf[header_end..header_end + length]).into_owned(),
+        headers: head,
+    }))
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Value of the first header called `name` in an HTTP message.
+fn header<'a>(message: &'a str, name: &str) -> Option<&'a str> {
+    message.lines().skip(1).find_map(|line| {
+        let colon = line.find(':')?;
+        if line[..colon].trim().eq_ignore_ascii_case(name) {
+            Some(line[colon + 1..].trim())
+        } else {
+            None
+        }
+    })
+}
+
+/// Text of the `<name>` element of a SOAP body.
+fn argument<'a>(body: &'a str, name: &str) -> Option<&'a str> {
+    let open = format!("<{}>", name);
+    let start = body.find(&open)? + open.len();
+    let end = start + body[start..].find(&format!("</{}>", name))?;
+    Some(body[start..end].trim())
+}
+
+fn http_response(status: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\n\
+        Content-Type: text/xml; charset=\"utf-8\"\r\n\
+        Content-Length: {}\r\n\
+        Connection: close\r\n\
+        Server: lan-play UPnP/1.1 IGD/1.0\r\n\
+        \r\n{}",
+        status, body.len(), body,
+    )
+}
+
+fn soap_envelope(body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\"?>\r\n\
+        <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+        s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\">\
+        <s:Body>{}</s:Body></s:Envelope>\r\n",
+        body
+    )
+}
+
+fn soap_response(action: &str, arguments: &str) -> String {
+    http_response("200 OK", &soap_envelope(&format!(
+        "<u:{0}Response xmlns:u=\"{1}\">{2}</u:{0}Response>",
+        action, SERVICE_TYPE, arguments
+    )))
+}
+
+fn soap_fault(code: u16, description: &str) -> String {
+    http_response("500 Internal Server Error", &soap_envelope(&format!(
+        "<s:Fault><faultcode>s:Client</faultcode><faultstring>UPnPError</faultstring>\
+        <detail><UPnPError xmlns=\"urn:schemas-upnp-org:control-1-0\">\
+        <errorCode>{}</errorCode><errorDescription>{}</errorDescription>\
+        </UPnPError></detail></s:Fault>",
+        code, description
+    )))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn server() -> IgdServer {
+        let forward = Arc::new(ForwardGateway::new(Ipv4Addr::new(10, 13, 37, 2), Vec::new()));
+        IgdServer::new(UpnpConfig::new(Ipv4Addr::new(10, 13, 37, 2), Ipv4Addr::new(192, 168, 1, 2)), forward)
+    }
+
+    #[test]
+    fn test_search_reply() {
+        let server = server();
+        let search = |st: &str| server.search_reply(format!(
+            "M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nMAN: \"ssdp:discover\"\r\nMX: 2\r\nST: {}\r\n\r\n",
+            st,
+        ).as_bytes()).map(|r| String::from_utf8(r).unwrap());
+
+        let reply = search(DEVICE_TYPE).unwrap();
+        assert_eq!(header(&reply, "LOCATION"), Some("http://10.13.37.2:5000/rootDesc.xml"));
+        assert_eq!(header(&reply, "ST"), Some(DEVICE_TYPE));
+        assert_eq!(header(&search("ssdp:all").unwrap(), "ST"), Some(DEVICE_TYPE));
+        assert!(search("urn:schemas-upnp-org:device:MediaRenderer:1").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_control() {
+        let server = server();
+        let client: IpAddr = "10.13.37.5".parse().unwrap();
+        let add = |client: &str| format!(
+            "<u:AddPortMapping xmlns:u=\"{}\"><NewRemoteHost></NewRemoteHost>\
+            <NewExternalPort>0</NewExternalPort><NewProtocol>UDP</NewProtocol>\
+            <NewInternalPort>45000</NewInternalPort><NewInternalClient>{}</NewInternalClient>\
+            <NewEnabled>1</NewEnabled><NewLeaseDuration>0</NewLeaseDuration></u:AddPortMapping>",
+            SERVICE_TYPE, client,
+        );
+
+        let reply = server.control(client, "GetExternalIPAddress", "").await;
+        assert_eq!(argument(&reply, "NewExternalIPAddress"), Some("192.168.1.2"));
+
+        // port 0 is not allowed in IGD v1
+        assert_eq!(argument(&server.control(client, "AddPortMapping", &add("10.13.37.5")).await, "errorCode"), Some("402"));
+
+        let port = {
+            let socket = std::net::UdpSocket::bind("0.0.0.0:0").unwrap();
+            socket.local_addr().unwrap().port()
+        };
+        let add = add("10.13.37.5").replace("<NewExternalPort>0<", &format!("<NewExternalPort>{}<", port));
+        assert!(server.control(client, "AddPortMapping", &add).await.starts_with("HTTP/1.1 200"));
+        // someone else can't take it over, or remove it
+        let other: IpAddr = "10.13.37.6".parse().unwrap();
+        let add_other = add.replace("10.13.37.5", "10.13.37.6");
+        assert_eq!(argument(&server.control(other, "AddPortMapping", &add_other).await, "errorCode"), Some("718"));
+        let delete = format!(
+            "<NewRemoteHost></NewRemoteHost><NewExternalPort>{}</NewExternalPort><NewProtocol>UDP</NewProtocol>",
+            port,
+        );
+        assert_eq!(argument(&server.control(other, "DeletePortMapping", &delete).await, "errorCode"), Some("714"));
+        assert!(server.control(client, "DeletePortMapping", &delete).await.starts_with("HTTP/1.1 200"));
+    }
+}
@@ -1,29 +1,102 @@
+mod dhcp;
+mod divert;
+mod forward;
+mod igd;
+#[cfg(target_os = "linux")]
+mod nat;
+mod natpmp;
 mod timeout_stream;
 mod tcp;
+mod tcp_config;
 mod udp;
+mod udp_config;
+#[cfg(test)]
+mod test;
 
-use crate::future_smoltcp::{TcpListener, UdpSocket};
+use crate::future_smoltcp::{Interface, TcpConnector, TcpListener, UdpSocket};
 use crate::proxy::BoxedProxy;
 use std::io;
 use std::sync::Arc;
 use futures::future::try_join;
+use smoltcp::wire::EthernetAddress;
+use divert::{Divert, DivertInterface};
 use tcp::TcpGateway;
 use udp::UdpGateway;
 
+pub use dhcp::{parse_mac, DhcpConfig, DhcpServer};
+pub use forward::{ForwardGateway, PortForward};
+pub use igd::{IgdServer, UpnpConfig};
+#[cfg(target_os = "linux")]
+pub use nat::{NatConfig, NatGateway};
+pub use tcp_config::{TcpConfig, TcpOptions, TcpRule};
+pub use udp_config::{NatBehavior, UdpConfig};
+
 pub struct Gateway {
     tcp: TcpGateway,
     udp: UdpGateway,
+    dhcp: Option<Arc<DhcpServer>>,
+    igd: Option<Arc<IgdServer>>,
+    #[cfg(target_os = "linux")]
+    nat: Option<Arc<NatGateway>>,
+    forward: Option<Arc<ForwardGateway>>,
 }
 
 impl Gateway {
-    pub fn new(proxy: BoxedProxy) -> Gateway {
+    pub fn new(proxy: BoxedProxy, tcp_config: TcpConfig, udp_config: UdpConfig) -> Gateway {
         let proxy = Arc::new(proxy);
         Gateway {
-            tcp: TcpGateway::new(proxy.clone()),
-            udp: UdpGateway::new(proxy.clone()),
+            tcp: TcpGateway::new(proxy.clone(), tcp_config),
+            udp: UdpGateway::new(proxy.clone(), udp_config),
+            dhcp: None,
+            igd: None,
+            #[cfg(target_os = "linux")]
+            nat: None,
+            forward: None,
         }
     }
-    pub async fn process(&self, tcp: Vec<TcpListener>, udp: UdpSocket) -> io::Result<()> {
+    /// Serves the port forwards of `forward` on our network.
+    pub fn set_forward(&mut self, forward: Arc<ForwardGateway>) {
+        self.udp.set_forward(forward.clone());
+        self.forward = Some(forward);
+    }
+    pub fn forward(&self) -> Option<&Arc<ForwardGateway>> {
+        self.forward.as_ref()
+    }
+    /// Lets consoles open ports on the host through `igd`.
+    pub fn set_igd(&mut self, igd: Arc<IgdServer>) {
+        self.tcp.set_igd(igd.clone());
+        self.udp.set_igd(igd.clone());
+        self.igd = Some(igd);
+    }
+    /// Traffic leaving the subnet goes through `nat` instead of the proxy.
+    #[cfg(target_os = "linux")]
+    pub fn set_nat(&mut self, nat: Arc<NatGateway>) {
+        self.nat = Some(nat);
+    }
+    /// Answers DHCP requests from consoles with `dhcp`.
+    pub fn set_dhcp(&mut self, dhcp: Arc<DhcpServer>) {
+        self.dhcp = Some(dhcp);
+    }
+    /// Lets the DHCP server, SSDP and NAT see the frames of `interface`
+    /// before smoltcp does.
+    pub fn divert<I: Interface>(&self, mac: EthernetAddress, interface: I) -> DivertInterface<I> {
+        let mut handlers: Vec<Arc<dyn Divert>> = Vec::new();
+        if let Some(dhcp) = &self.dhcp {
+            handlers.push(dhcp.clone());
+        }
+        if let Some(igd) = &self.igd {
+            handlers.push(igd.clone());
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(nat) = &self.nat {
+            handlers.push(nat.clone());
+        }
+        DivertInterface::new(interface, handlers, mac)
+    }
+    pub async fn process(&self, tcp: Vec<TcpListener>, udp: UdpSocket, connector: TcpConnector) -> io::Result<()> {
+        if let Some(forward) = &self.forward {
+            forward.attach_tcp(connector);
+        }
         try_join(
             self.tcp.process(tcp),
             self.udp.process(udp),
@@ -0,0 +1,510 @@
+use super::divert::{ethernet_frame, Divert};
+use crate::future_smoltcp::{Interface, Packet};
+use async_channel::{unbounded, Receiver, Sender};
+use futures::{future::try_join, prelude::*};
+use lru::LruCache;
+use smoltcp::wire::{
+    EthernetAddress, EthernetFrame, EthernetProtocol, IpProtocol, Ipv4Address, Ipv4Cidr,
+    Ipv4Packet,
+};
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddrV4;
+use std::ops::RangeInclusive;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const ICMP_ECHO_REPLY: u8 = 0;
+const ICMP_ECHO_REQUEST: u8 = 8;
+/// Consoles answered on the virtual subnet, least recently seen ones are
+/// forgotten first
+const MAX_CONSOLES: usize = 256;
+
+/// Settings of the userspace NAT.
+#[derive(Debug, Clone)]
+pub struct NatConfig {
+    /// Source address of translated packets on the host side
+    pub external_ip: Ipv4Address,
+    /// Ports handed out to new mappings
+    pub port_range: RangeInclusive<u16>,
+    /// Idle time before a UDP or ICMP mapping expires
+    pub udp_timeout: Duration,
+    /// Idle time before a TCP mapping expires
+    pub tcp_timeout: Duration,
+}
+
+impl NatConfig {
+    pub fn new(external_ip: Ipv4Address) -> NatConfig {
+        NatConfig {
+            external_ip,
+            port_range: 1024..=65535,
+            udp_timeout: Duration::from_secs(120),
+            tcp_timeout: Duration::from_secs(7440),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Proto {
+    Tcp,
+    Udp,
+    Icmp,
+    Other(u8),
+}
+
+/// What the NAT needs to know about an IPv4 packet.
+#[derive(Debug)]
+struct Info {
+    proto: Proto,
+    src: Ipv4Address,
+    dst: Ipv4Address,
+    /// Source and destination ports, or the echo identifier twice for ICMP
+    ports: Option<(u16, u16)>,
+    ident: u16,
+    /// Not the first fragment, so there is no transport header
+    fragment: bool,
+    more_frags: bool,
+    header_len: usize,
+    total_len: usize,
+}
+
+fn word(b: &[u8]) -> u16 {
+    u16::from_be_bytes([b[0], b[1]])
+}
+
+fn parse(packet: &[u8]) -> Option<Info> {
+    let ip = Ipv4Packet::new_checked(packet).ok()?;
+    let header_len = ip.header_len() as usize;
+    let total_len = ip.total_len() as usize;
+    let payload = &packet[header_len..total_len];
+    let fragment = ip.frag_offset() != 0;
+    let proto = match ip.protocol() {
+        IpProtocol::Tcp => Proto::Tcp,
+        IpProtocol::Udp => Proto::Udp,
+        IpProtocol::Icmp => Proto::Icmp,
+        p => Proto::Other(p.into()),
+    };
+    let ports = match proto {
+        _ if fragment => None,
+        Proto::Tcp | Proto::Udp if payload.len() >= 4 => {
+            Some((word(&payload[0..2]), word(&payload[2..4])))
+        }
+        Proto::Icmp if payload.len() >= 8
+            && (payload[0] == ICMP_ECHO_REQUEST || payload[0] == ICMP_ECHO_REPLY) =>
+        {
+            let id = word(&payload[4..6]);
+            Some((id, id))
+        }
+        _ => None,
+    };
+    Some(Info {
+        proto,
+        src: ip.src_addr(),
+        dst: ip.dst_addr(),
+        ports,
+        ident: ip.ident(),
+        fragment,
+        more_frags: ip.more_frags(),
+        header_len,
+        total_len,
+    })
+}
+
+/// Updates a ones' complement checksum after `old` was replaced by `new` (RFC 1624).
+fn adjust_checksum(checksum: u16, old: &[u8], new: &[u8]) -> u16 {
+    let mut sum = u32::from(!checksum);
+    for (o, n) in old.chunks(2).zip(new.chunks(2)) {
+        sum += u32::from(!word(o)) + u32::from(word(n));
+    }
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Replaces the source (outbound) or destination (inbound) endpoint of a
+/// packet. Transport checksums are adjusted rather than recomputed, so the
+/// first fragment of a larger datagram is translated correctly too.
+fn rewrite(packet: &mut [u8], info: &Info, outbound: bool, addr: Ipv4Address, port: Option<u16>) {
+    let addr_offset = if outbound { 12 } else { 16 };
+    let mut old_addr = [0u8; 4];
+    old_addr.copy_from_slice(&packet[addr_offset..addr_offset + 4]);
+    packet[addr_offset..addr_offset + 4].copy_from_slice(addr.as_bytes());
+
+    let payload = &mut packet[info.header_len..info.total_len];
+    let (port_offset, checksum_offset, pseudo_header) = match info.proto {
+        _ if info.fragment => (0, 0, false),
+        Proto::Tcp => (if outbound { 0 } else { 2 }, 16, true),
+        Proto::Udp => (if outbound { 0 } else { 2 }, 6, true),
+        Proto::Icmp => (4, 2, false),
+        Proto::Other(_) => (0, 0, false),
+    };
+    if checksum_offset != 0 && payload.len() >= checksum_offset + 2 {
+        let mut checksum = word(&payload[checksum_offset..]);
+        // a zero UDP checksum means there is none
+        let skip = info.proto == Proto::Udp && checksum == 0;
+        if pseudo_header && !skip {
+            checksum = adjust_checksum(checksum, &old_addr, addr.as_bytes());
+        }
+        if let Some(port) = port {
+            let port = port.to_be_bytes();
+            if !skip {
+                checksum = adjust_checksum(checksum, &payload[port_offset..port_offset + 2], &port);
+            }
+            payload[port_offset..port_offset + 2].copy_from_slice(&port);
+        }
+        if info.proto == Proto::Udp && !skip && checksum == 0 {
+            checksum = 0xffff;
+        }
+        payload[checksum_offset..checksum_offset + 2].copy_from_slice(&checksum.to_be_bytes());
+    }
+
+    Ipv4Packet::new_unchecked(packet).fill_checksum();
+}
+
+#[derive(Debug)]
+struct Mapping {
+    internal: SocketAddrV4,
+    last_seen: Instant,
+}
+
+/// Endpoint-independent mapping and filtering: a console endpoint keeps the
+/// same external port whatever it talks to, and anyone may reach it through
+/// that port. The console's own port is kept when it's free.
+struct NatTable {
+    config: NatConfig,
+    by_internal: HashMap<(Proto, SocketAddrV4), u16>,
+    by_external: HashMap<(Proto, u16), Mapping>,
+    /// Protocols without ports are tracked by remote address, expiring like
+    /// UDP mappings
+    others: HashMap<(u8, Ipv4Address), (Ipv4Address, Instant)>,
+    /// Trailing fragments of inbound datagrams, by (remote address, IP ident)
+    fragments: LruCache<(Ipv4Address, u16), Ipv4Address>,
+    next_port: u16,
+}
+
+impl NatTable {
+    fn new(config: NatConfig) -> NatTable {
+        NatTable {
+            next_port: *config.port_range.start(),
+            config,
+            by_internal: HashMap::new(),
+            by_external: HashMap::new(),
+            others: HashMap::new(),
+            fragments: LruCache::new(64),
+        }
+    }
+    fn timeout(&self, proto: Proto) -> Duration {
+        match proto {
+            Proto::Tcp => self.config.tcp_timeout,
+            _ => self.config.udp_timeout,
+        }
+    }
+    fn expire(&mut self, now: Instant) {
+        let udp_timeout = self.config.udp_timeout;
+        let tcp_timeout = self.config.tcp_timeout;
+        let by_internal = &mut self.by_internal;
+        self.by_external.retain(|(proto, _), mapping| {
+            let timeout = if *proto == Proto::Tcp { tcp_timeout } else { udp_timeout };
+            let alive = now.duration_since(mapping.last_seen) <= timeout;
+            if !alive {
+                by_internal.remove(&(*proto, mapping.internal));
+            }
+            alive
+        });
+        self.others.retain(|_, (_, last_seen)| now.duration_since(*last_seen) <= udp_timeout);
+    }
+    fn allocate(&mut self, proto: Proto, preferred: u16) -> Option<u16> {
+        let range = self.config.port_range.clone();
+        if range.contains(&preferred) && !self.by_external.contains_key(&(proto, preferred)) {
+            return Some(preferred);
+        }
+        let len = u32::from(*range.end() - *range.start()) + 1;
+        for _ in 0..len {
+            let port = self.next_port;
+            self.next_port = if port >= *range.end() { *range.start() } else { port + 1 };
+            if !self.by_external.contains_key(&(proto, port)) {
+                return Some(port);
+            }
+        }
+        None
+    }
+    /// Returns the external port of `internal`, creating the mapping if needed.
+    fn map(&mut self, proto: Proto, internal: SocketAddrV4, now: Instant) -> Option<u16> {
+        if let Some(port) = self.by_internal.get(&(proto, internal)).copied() {
+            if let Some(mapping) = self.by_external.get_mut(&(proto, port)) {
+                mapping.last_seen = now;
+            }
+            return Some(port);
+        }
+        self.expire(now);
+        let port = match self.allocate(proto, internal.port()) {
+            Some(port) => port,
+            None => {
+                log::warn!("nat: no free {:?} port for {}", proto, internal);
+                return None;
+            }
+        };
+        log::trace!("nat: new {:?} mapping {} -> {}", proto, internal, port);
+        self.by_internal.insert((proto, internal), port);
+        self.by_external.insert((proto, port), Mapping { internal, last_seen: now });
+        Some(port)
+    }
+    fn lookup(&self, proto: Proto, port: u16, now: Instant) -> Option<SocketAddrV4> {
+        let mapping = self.by_external.get(&(proto, port))?;
+        if now.duration_since(mapping.last_seen) > self.timeout(proto) {
+            return None;
+        }
+        Some(mapping.internal)
+    }
+    /// Rewrites a packet from a console so it appears to come from the
+    /// external address.
+    fn translate_outbound(&mut self, packet: &mut Packet, now: Instant) -> Option<()> {
+        let info = parse(packet)?;
+        packet.truncate(info.total_len);
+        let port = match (info.proto, info.ports) {
+            (Proto::Other(p), _) => {
+                if self.others.insert((p, info.dst), (info.src, now)).is_none() {
+                    self.expire(now);
+                }
+                None
+            }
+            (proto, Some((src_port, _))) => {
+                Some(self.map(proto, SocketAddrV4::new(info.src.into(), src_port), now)?)
+            }
+            // the ports were rewritten in the first fragment
+            (_, None) if info.fragment => None,
+            _ => return None,
+        };
+        rewrite(packet, &info, true, self.config.external_ip, port);
+        Some(())
+    }
+    /// Rewrites a packet from the host back to the console it belongs to,
+    /// returning that console's address.
+    fn translate_inbound(&mut self, packet: &mut Packet, now: Instant) -> Option<Ipv4Address> {
+        let info = parse(packet)?;
+        if info.dst != self.config.external_ip {
+            return None;
+        }
+        packet.truncate(info.total_len);
+        let (internal, port) = match (info.proto, info.ports) {
+            (Proto::Other(p), _) => {
+                let (internal, last_seen) = *self.others.get(&(p, info.src))?;
+                if now.duration_since(last_seen) > self.config.udp_timeout {
+                    return None;
+                }
+                (internal, None)
+            }
+            (proto, Some((_, dst_port))) => {
+                let internal = self.lookup(proto, dst_port, now)?;
+                let addr = Ipv4Address::from(*internal.ip());
+                if info.more_frags {
+                    self.fragments.put((info.src, info.ident), addr);
+                }
+                (addr, Some(internal.port()))
+            }
+            (_, None) if info.fragment => (*self.fragments.get(&(info.src, info.ident))?, None),
+            _ => return None,
+        };
+        rewrite(packet, &info, false, internal, port);
+        Some(internal)
+    }
+}
+
+struct Console {
+    mac: EthernetAddress,
+    gateway_mac: EthernetAddress,
+    inject: Sender<Packet>,
+}
+
+/// Forwards traffic leaving the virtual subnet as raw IPv4 packets through
+/// a host interface (e.g. a TUN device), instead of terminating it in
+/// smoltcp and re-originating it through the proxy.
+pub struct NatGateway {
+    subnet: Ipv4Cidr,
+    table: Mutex<NatTable>,
+    consoles: Mutex<LruCache<Ipv4Address, Console>>,
+    to_host: Sender<Packet>,
+    from_consoles: Receiver<Packet>,
+}
+
+impl NatGateway {
+    pub fn new(config: NatConfig, subnet: Ipv4Cidr) -> NatGateway {
+        let (to_host, from_consoles) = unbounded();
+        NatGateway {
+            subnet,
+            table: Mutex::new(NatTable::new(config)),
+            consoles: Mutex::new(LruCache::new(MAX_CONSOLES)),
+            to_host,
+            from_consoles,
+        }
+    }
+    /// Exchanges IPv4 packets with the host until it goes away.
+    pub async fn run<H: Interface>(&self, host: H) -> io::Result<()> {
+        let (mut sink, mut stream) = host.split();
+        try_join(
+            async {
+                while let Ok(packet) = self.from_consoles.recv().await {
+                    sink.send(packet).await?;
+                }
+                Ok::<_, io::Error>(())
+            },
+            async {
+                while let Some(packet) = stream.next().await {
+                    self.inbound(packet);
+                }
+                Ok::<_, io::Error>(())
+            },
+        ).await?;
+        Ok(())
+    }
+    fn inbound(&self, mut packet: Packet) {
+        let translated = self.table.lock().unwrap().translate_inbound(&mut packet, Instant::now());
+        let internal = match translated {
+            Some(internal) => internal,
+            None => {
+                log::trace!("nat: dropped inbound packet");
+                return;
+            }
+        };
+        let mut consoles = self.consoles.lock().unwrap();
+        let console = match consoles.get(&internal) {
+            Some(console) => console,
+            None => return,
+        };
+        let _ = console.inject.try_send(ethernet_frame(console.gateway_mac, console.mac, &packet));
+    }
+}
+
+/// Takes over traffic leaving the subnet, everything else reaches smoltcp.
+impl Divert for NatGateway {
+    fn divert(&self, frame: &[u8], gateway_mac: EthernetAddress, inject: &Sender<Packet>) -> bool {
+        let frame = match EthernetFrame::new_checked(frame) {
+            Ok(frame) => frame,
+            Err(_) => return false,
+        };
+        if frame.ethertype() != EthernetProtocol::Ipv4 || frame.dst_addr() != gateway_mac {
+            return false;
+        }
+        let mut packet = frame.payload().to_vec();
+        let (src, dst) = match Ipv4Packet::new_checked(&packet[..]) {
+            Ok(ip) => (ip.src_addr(), ip.dst_addr()),
+            Err(_) => return false,
+        };
+        if !dst.is_unicast() || self.subnet.contains_addr(&dst) || !self.subnet.contains_addr(&src) {
+            return false;
+        }
+        self.consoles.lock().unwrap().put(src, Console {
+            mac: frame.src_addr(),
+            gateway_mac,
+            inject: inject.clone(),
+        });
+        let translated = self.table.lock().unwrap().translate_outbound(&mut packet, Instant::now());
+        match translated {
+            Some(()) => {
+                let _ = self.to_host.try_send(packet);
+            }
+            None => log::trace!("nat: dropped outbound packet from {}", src),
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::future_smoltcp::{parse_udp_owned, OwnedUdp};
+    use smoltcp::phy::ChecksumCapabilities;
+
+    const EXTERNAL: Ipv4Address = Ipv4Address([10, 13, 38, 2]);
+
+    fn udp(src: &str, dst: &str) -> Packet {
+        OwnedUdp::new(src.parse().unwrap(), dst.parse().unwrap(), b"hello".to_vec()).to_raw()
+    }
+
+    fn check(packet: &[u8]) -> OwnedUdp {
+        // verifies both the IP and the UDP checksum
+        parse_udp_owned(packet, &ChecksumCapabilities::default()).unwrap()
+    }
+
+    #[test]
+    fn test_endpoint_independent_mapping() {
+        let mut table = NatTable::new(NatConfig::new(EXTERNAL));
+        let now = Instant::now();
+
+        let mut a = udp("10.13.37.5:45000", "1.1.1.1:3478");
+        table.translate_outbound(&mut a, now).unwrap();
+        let a = check(&a);
+        assert_eq!(a.src(), "10.13.38.2:45000".parse().unwrap());
+        assert_eq!(a.dst(), "1.1.1.1:3478".parse().unwrap());
+
+        // same external port whatever the destination
+        let mut b = udp("10.13.37.5:45000", "2.2.2.2:3479");
+        table.translate_outbound(&mut b, now).unwrap();
+        assert_eq!(check(&b).src(), a.src());
+
+        // and anyone can come back through it
+        let mut reply = udp("3.3.3.3:1234", "10.13.38.2:45000");
+        assert_eq!(table.translate_inbound(&mut reply, now), Some(Ipv4Address([10, 13, 37, 5])));
+        assert_eq!(check(&reply).dst(), "10.13.37.5:45000".parse().unwrap());
+
+        let mut unknown = udp("3.3.3.3:1234", "10.13.38.2:45001");
+        assert_eq!(table.translate_inbound(&mut unknown, now), None);
+    }
+
+    #[test]
+    fn test_port_collision_and_expiry() {
+        let mut config = NatConfig::new(EXTERNAL);
+        config.port_range = 45000..=45001;
+        let mut table = NatTable::new(config);
+        let now = Instant::now();
+
+        let mut a = udp("10.13.37.5:45000", "1.1.1.1:3478");
+        let mut b = udp("10.13.37.6:45000", "1.1.1.1:3478");
+        let mut c = udp("10.13.37.7:45000", "1.1.1.1:3478");
+        table.translate_outbound(&mut a, now).unwrap();
+        table.translate_outbound(&mut b, now).unwrap();
+        assert_eq!(check(&b).src().port(), 45001);
+        // the range is exhausted
+        assert!(table.translate_outbound(&mut c, now).is_none());
+
+        let later = now + Duration::from_secs(121);
+        let mut c = udp("10.13.37.7:45000", "1.1.1.1:3478");
+        table.translate_outbound(&mut c, later).unwrap();
+        assert_eq!(check(&c).src().port(), 45000);
+    }
+
+    #[test]
+    fn test_other_protocol_expiry() {
+        let mut table = NatTable::new(NatConfig::new(EXTERNAL));
+        let now = Instant::now();
+        let gre = |src: &str, dst: &str| {
+            let mut packet = udp(src, dst);
+            let mut ip = Ipv4Packet::new_unchecked(&mut packet[..]);
+            ip.set_protocol(IpProtocol::Unknown(47));
+            ip.fill_checksum();
+            packet
+        };
+
+        let mut out = gre("10.13.37.5:0", "1.1.1.1:0");
+        table.translate_outbound(&mut out, now).unwrap();
+        let mut reply = gre("1.1.1.1:0", "10.13.38.2:0");
+        assert_eq!(table.translate_inbound(&mut reply.clone(), now), Some(Ipv4Address([10, 13, 37, 5])));
+
+        let later = now + Duration::from_secs(121);
+        assert_eq!(table.translate_inbound(&mut reply, later), None);
+        // forgotten once another remote shows up
+        let mut other = gre("10.13.37.6:0", "2.2.2.2:0");
+        table.translate_outbound(&mut other, later).unwrap();
+        assert_eq!(table.others.len(), 1);
+    }
+
+    #[test]
+    fn test_adjust_checksum() {
+        let mut packet = udp("10.13.37.5:45000", "1.1.1.1:3478");
+        let info = parse(&packet).unwrap();
+        rewrite(&mut packet, &info, true, Ipv4Address([192, 168, 1, 1]), Some(1));
+        let expected = udp("192.168.1.1:1", "1.1.1.1:3478");
+        assert_eq!(packet, expected);
+    }
+}
@@ -0,0 +1,238 @@
+//! NAT-PMP (RFC 6886) and the MAP and ANNOUNCE opcodes of PCP (RFC 6887).
+use super::forward::Protocol;
+use super::igd::{MappingError, PortMappings};
+use std::convert::TryInto;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+const NATPMP_VERSION: u8 = 0;
+const PCP_VERSION: u8 = 2;
+const RESPONSE: u8 = 0x80;
+
+const NATPMP_EXTERNAL_ADDRESS: u8 = 0;
+const NATPMP_MAP_UDP: u8 = 1;
+const NATPMP_MAP_TCP: u8 = 2;
+
+const NATPMP_SUCCESS: u16 = 0;
+const NATPMP_NOT_AUTHORIZED: u16 = 2;
+const NATPMP_NETWORK_FAILURE: u16 = 3;
+const NATPMP_UNSUPPORTED_OPCODE: u16 = 5;
+
+const PCP_ANNOUNCE: u8 = 0;
+const PCP_MAP: u8 = 1;
+const PCP_HEADER_LEN: usize = 24;
+const PCP_MAP_LEN: usize = 36;
+
+const PCP_SUCCESS: u8 = 0;
+const PCP_NOT_AUTHORIZED: u8 = 2;
+const PCP_MALFORMED_REQUEST: u8 = 3;
+const PCP_UNSUPP_OPCODE: u8 = 4;
+const PCP_NO_RESOURCES: u8 = 8;
+const PCP_UNSUPP_PROTOCOL: u8 = 9;
+const PCP_ADDRESS_MISMATCH: u8 = 12;
+
+/// Answer to a NAT-PMP or PCP request from `client`, if it deserves one.
+pub(super) async fn handle(
+    mappings: &PortMappings,
+    external_ip: Ipv4Addr,
+    epoch: u32,
+    client: Ipv4Addr,
+    request: &[u8],
+) -> Option<Vec<u8>> {
+    match request.first()? {
+        &NATPMP_VERSION => natpmp(mappings, external_ip, epoch, client, request).await,
+        &PCP_VERSION => pcp(mappings, external_ip, epoch, client, request).await,
+        _ => {
+            // tell the client the highest version we speak
+            let mut reply = vec![PCP_VERSION, RESPONSE | request.get(1)? & 0x7f, 0, 1];
+            reply.extend_from_slice(&[0; PCP_HEADER_LEN - 4]);
+            Some(reply)
+        }
+    }
+}
+
+async fn natpmp(
+    mappings: &PortMappings,
+    external_ip: Ipv4Addr,
+    epoch: u32,
+    client: Ipv4Addr,
+    request: &[u8],
+) -> Option<Vec<u8>> {
+    let opcode = *request.get(1)?;
+    if opcode & RESPONSE != 0 {
+        return None;
+    }
+    let header = |result: u16| {
+        let mut reply = vec![NATPMP_VERSION, RESPONSE | opcode];
+        reply.extend_from_slice(&result.to_be_bytes());
+        reply.extend_from_slice(&epoch.to_be_bytes());
+        reply
+    };
+    let protocol = match opcode {
+        NATPMP_EXTERNAL_ADDRESS => {
+            let mut reply = header(NATPMP_SUCCESS);
+            reply.extend_from_slice(&external_ip.octets());
+            return Some(reply);
+        }
+        NATPMP_MAP_UDP => Protocol::Udp,
+        NATPMP_MAP_TCP => Protocol::Tcp,
+        _ => return Some(header(NATPMP_UNSUPPORTED_OPCODE)),
+    };
+    if request.len() < 12 {
+        return None;
+    }
+    let internal_port = u16::from_be_bytes([request[4], request[5]]);
+    let external_port = u16::from_be_bytes([request[6], request[7]]);
+    let lifetime = u32::from_be_bytes(request[8..12].try_into().unwrap());
+    let internal = SocketAddr::new(client.into(), internal_port);
+
+    let (result, external_port, lifetime) = if lifetime == 0 {
+        mappings.remove_internal(protocol, internal).await;
+        (NATPMP_SUCCESS, 0, 0)
+    } else if internal_port == 0 {
+        (NATPMP_NOT_AUTHORIZED, 0, 0)
+    } else {
+        let lease = Duration::from_secs(lifetime.into());
+        match mappings.add(protocol, external_port, internal, lease, false).await {
+            Ok((port, lease)) => (NATPMP_SUCCESS, port, lease.as_secs() as u32),
+            Err(MappingError::Conflict) => (NATPMP_NOT_AUTHORIZED, 0, 0),
+            Err(MappingError::Io(e)) => {
+                log::warn!("nat-pmp: failed to map {:?}: {:?}", internal, e);
+                (NATPMP_NETWORK_FAILURE, 0, 0)
+            }
+        }
+    };
+    let mut reply = header(result);
+    reply.extend_from_slice(&internal_port.to_be_bytes());
+    reply.extend_from_slice(&external_port.to_be_bytes());
+    reply.extend_from_slice(&lifetime.to_be_bytes());
+    Some(reply)
+}
+
+async fn pcp(
+    mappings: &PortMappings,
+    external_ip: Ipv4Addr,
+    epoch: u32,
+    client: Ipv4Addr,
+    request: &[u8],
+) -> Option<Vec<u8>> {
+    if request.len() < PCP_HEADER_LEN || request[1] & RESPONSE != 0 {
+        return None;
+    }
+    let opcode = request[1];
+    let reply = |result: u8, lifetime: u32, payload: &[u8]| {
+        let mut reply = vec![PCP_VERSION, RESPONSE | opcode, 0, result];
+        reply.extend_from_slice(&lifetime.to_be_bytes());
+        reply.extend_from_slice(&epoch.to_be_bytes());
+        reply.extend_from_slice(&[0; 12]);
+        reply.extend_from_slice(payload);
+        Some(reply)
+    };
+    if request.len() > 1100 || request.len() % 4 != 0 {
+        return reply(PCP_MALFORMED_REQUEST, 0, &[]);
+    }
+    let client_ip: [u8; 16] = request[8..24].try_into().unwrap();
+    if Ipv6Addr::from(client_ip) != client.to_ipv6_mapped() {
+        return reply(PCP_ADDRESS_MISMATCH, 0, &[]);
+    }
+    match opcode {
+        PCP_ANNOUNCE => return reply(PCP_SUCCESS, 0, &[]),
+        PCP_MAP => {}
+        _ => return reply(PCP_UNSUPP_OPCODE, 0, &[]),
+    }
+    if request.len() < PCP_HEADER_LEN + PCP_MAP_LEN {
+        return reply(PCP_MALFORMED_REQUEST, 0, &[]);
+    }
+    let lifetime = u32::from_be_bytes(request[4..8].try_into().unwrap());
+    let mut payload = request[PCP_HEADER_LEN..PCP_HEADER_LEN + PCP_MAP_LEN].to_vec();
+    let protocol = match payload[12] {
+        6 => Protocol::Tcp,
+        17 => Protocol::Udp,
+        _ => return reply(PCP_UNSUPP_PROTOCOL, 0, &payload),
+    };
+    let internal_port = u16::from_be_bytes([payload[16], payload[17]]);
+    let external_port = u16::from_be_bytes([payload[18], payload[19]]);
+    let internal = SocketAddr::new(client.into(), internal_port);
+
+    let (result, external_port, lifetime) = if lifetime == 0 {
+        mappings.remove_internal(protocol, internal).await;
+        (PCP_SUCCESS, external_port, 0)
+    } else if internal_port == 0 {
+        (PCP_NOT_AUTHORIZED, 0, 0)
+    } else {
+        let lease = Duration::from_secs(lifetime.into());
+        match mappings.add(protocol, external_port, internal, lease, false).await {
+            Ok((port, lease)) => (PCP_SUCCESS, port, lease.as_secs() as u32),
+            Err(e) => {
+                log::warn!("pcp: failed to map {:?}: {:?}", internal, e);
+                (PCP_NO_RESOURCES, 0, 0)
+            }
+        }
+    };
+    payload[18..20].copy_from_slice(&external_port.to_be_bytes());
+    payload[20..36].copy_from_slice(&external_ip.to_ipv6_mapped().octets());
+    reply(result, lifetime, &payload)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gateway::ForwardGateway;
+    use std::sync::Arc;
+
+    const EXTERNAL: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 2);
+    const CLIENT: Ipv4Addr = Ipv4Addr::new(10, 13, 37, 5);
+
+    fn mappings() -> PortMappings {
+        let forward = Arc::new(ForwardGateway::new(Ipv4Addr::new(10, 13, 37, 2), Vec::new()));
+        PortMappings::new(forward, Duration::from_secs(3600))
+    }
+
+    #[tokio::test]
+    async fn test_natpmp() {
+        let mappings = mappings();
+        let reply = handle(&mappings, EXTERNAL, 7, CLIENT, &[0, 0]).await.unwrap();
+        assert_eq!(reply, [0, 128, 0, 0, 0, 0, 0, 7, 192, 168, 1, 2]);
+
+        // ask for UDP 45000 for a day, any external port
+        let request = [0, 1, 0, 0, 0xaf, 0xc8, 0, 0, 0, 1, 0x51, 0x80];
+        let reply = handle(&mappings, EXTERNAL, 7, CLIENT, &request).await.unwrap();
+        assert_eq!(&reply[..8], &[0, 129, 0, 0, 0, 0, 0, 7]);
+        assert_eq!(&reply[8..10], &[0xaf, 0xc8]);
+        let port = u16::from_be_bytes([reply[10], reply[11]]);
+        assert_ne!(port, 0);
+        // capped to the longest lease
+        assert_eq!(&reply[12..16], &3600u32.to_be_bytes());
+
+        // asking again gives the same mapping
+        let again = handle(&mappings, EXTERNAL, 7, CLIENT, &request).await.unwrap();
+        assert_eq!(again, reply);
+
+        let delete = [0, 1, 0, 0, 0xaf, 0xc8, 0, 0, 0, 0, 0, 0];
+        let reply = handle(&mappings, EXTERNAL, 7, CLIENT, &delete).await.unwrap();
+        assert_eq!(&reply[2..4], &[0, 0]);
+        assert!(!mappings.remove(Protocol::Udp, port, CLIENT.into()).await);
+    }
+
+    #[tokio::test]
+    async fn test_pcp_map() {
+        let mappings = mappings();
+        let mut request = vec![2, 1, 0, 0, 0, 0, 0x0e, 0x10];
+        request.extend_from_slice(&CLIENT.to_ipv6_mapped().octets());
+        request.extend_from_slice(&[7; 12]);
+        request.extend_from_slice(&[17, 0, 0, 0, 0xaf, 0xc8, 0, 0]);
+        request.extend_from_slice(&[0; 16]);
+
+        let reply = handle(&mappings, EXTERNAL, 7, CLIENT, &request).await.unwrap();
+        assert_eq!(reply.len(), PCP_HEADER_LEN + PCP_MAP_LEN);
+        assert_eq!(&reply[..4], &[2, 129, 0, PCP_SUCCESS]);
+        assert_eq!(&reply[4..8], &3600u32.to_be_bytes());
+        // nonce is echoed
+        assert_eq!(&reply[24..36], &[7; 12]);
+        assert_eq!(&reply[44..60], &EXTERNAL.to_ipv6_mapped().octets());
+
+        // the client address must be the sender's
+        let reply = handle(&mappings, EXTERNAL, 7, Ipv4Addr::new(10, 13, 37, 6), &request).await.unwrap();
+        assert_eq!(reply[3], PCP_ADDRESS_MISMATCH);
+    }
+}
@@ -1,29 +1,46 @@
 use crate::future_smoltcp::{TcpListener, TcpSocket};
-use crate::proxy::BoxedProxy;
-use tokio::{io::{copy, split}, time::Instant, prelude::*};
+use crate::proxy::{BoxedProxy, BoxedTcp, prelude::*};
+use tokio::{io::{copy, split}, time::{timeout, Instant}, prelude::*};
+use super::igd::IgdServer;
 use super::timeout_stream::TimeoutStream;
+use super::tcp_config::{non_zero, TcpConfig, TcpOptions};
 use std::io;
 use std::sync::Arc;
-use std::time::Duration;
 use futures::{future::try_join, stream::{StreamExt, select_all}};
 
-const TCP_TIMEOUT: Duration = Duration::from_secs(60);
-
 pub(super) struct TcpGateway {
-    proxy: Arc<BoxedProxy>
+    proxy: Arc<BoxedProxy>,
+    config: Arc<TcpConfig>,
+    igd: Option<Arc<IgdServer>>,
 }
 
 impl TcpGateway {
-    pub fn new(proxy: Arc<BoxedProxy>) -> TcpGateway {
+    pub fn new(proxy: Arc<BoxedProxy>, config: TcpConfig) -> TcpGateway {
         TcpGateway {
             proxy,
+            config: Arc::new(config),
+            igd: None,
         }
     }
+    pub fn set_igd(&mut self, igd: Arc<IgdServer>) {
+        self.igd = Some(igd);
+    }
     pub async fn process(&self, listener: Vec<TcpListener>) -> io::Result<()> {
         let mut listener = select_all(listener.into_iter().map(|i| i.incoming()));
         loop {
             let tcp = listener.next().await.ok_or(io::ErrorKind::NotFound)?;
             let (local_addr, peer_addr) = (tcp.local_addr(), tcp.peer_addr());
+            if let Some(igd) = &self.igd {
+                if local_addr.as_ref().ok() == Some(&igd.http_addr()) {
+                    let igd = igd.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = igd.serve(tcp).await {
+                            log::debug!("upnp serve {:?}", e);
+                        }
+                    });
+                    continue;
+                }
+            }
             if let Err(e) = self.on_tcp(tcp).await {
                 log::error!("on_tcp {:?}", e);
             }
@@ -32,25 +49,39 @@ impl TcpGateway {
     }
     async fn on_tcp(&self, stcp: TcpSocket) -> io::Result<()> {
         let proxy = self.proxy.clone();
+        let local_addr = stcp.local_addr()?;
+        let opts = self.config.options_for(&local_addr);
+        log::trace!("tcp options for {:?}: {:?}", local_addr, opts);
+
+        stcp.set_keep_alive(non_zero(opts.keepalive));
+        stcp.set_timeout(non_zero(opts.timeout));
+        if let Some(delay) = opts.ack_delay {
+            stcp.set_ack_delay(non_zero(Some(delay)));
+        }
 
         tokio::spawn(async move {
-            let (local_addr, peer_addr) = (stcp.local_addr(), stcp.peer_addr());
-            let ptcp = match proxy.new_tcp_timeout(stcp.local_addr()?).await {
+            let mut stcp = stcp;
+            let peer_addr = stcp.peer_addr();
+            let ptcp = match proxy.new_tcp_timeout(local_addr).await {
                 Ok(s) => s,
                 Err(e) => {
-                    log::error!("tcp connect to {:?} err {:?}", stcp.local_addr()?, e);
+                    log::error!("tcp connect to {:?} err {:?}", local_addr, e);
+                    // let the console see a refused connection instead of a hang
+                    stcp.abort();
                     return Err(e);
                 },
             };
+            apply_upstream_options(&ptcp, &opts);
 
             let start = Instant::now();
-            let ptcp = TimeoutStream::new(
-                ptcp,
-                TCP_TIMEOUT,
-            );
-            let r = pipe(stcp, ptcp).await;
+            let r = match non_zero(opts.max_lifetime) {
+                Some(lifetime) => timeout(lifetime, pipe_idle(stcp, ptcp, &opts))
+                    .await
+                    .unwrap_or_else(|_| Err(io::ErrorKind::TimedOut.into())),
+                None => pipe_idle(stcp, ptcp, &opts).await,
+            };
 
-            log::trace!("tcp {:?} -x {:?} {:?} {:?}", peer_addr?, local_addr?, r, start.elapsed());
+            log::trace!("tcp {:?} -x {:?} {:?} {:?}", peer_addr?, local_addr, r, start.elapsed());
 
             Ok::<(), io::Error>(())
         });
@@ -58,7 +89,27 @@ impl TcpGateway {
     }
 }
 
-async fn pipe<S1, S2>(s1: S1, s2: S2) -> io::Result<(u64, u64)>
+fn apply_upstream_options(ptcp: &BoxedTcp, opts: &TcpOptions) {
+    if let Some(nodelay) = opts.nodelay {
+        if let Err(e) = ptcp.set_nodelay(nodelay) {
+            log::warn!("failed to set nodelay on upstream: {:?}", e);
+        }
+    }
+    if let Some(keepalive) = opts.upstream_keepalive {
+        if let Err(e) = ptcp.set_keepalive(non_zero(Some(keepalive))) {
+            log::warn!("failed to set keepalive on upstream: {:?}", e);
+        }
+    }
+}
+
+async fn pipe_idle(stcp: TcpSocket, ptcp: BoxedTcp, opts: &TcpOptions) -> io::Result<(u64, u64)> {
+    match non_zero(opts.idle_timeout) {
+        Some(idle) => pipe(stcp, TimeoutStream::new(ptcp, idle)).await,
+        None => pipe(stcp, ptcp).await,
+    }
+}
+
+pub(super) async fn pipe<S1, S2>(s1: S1, s2: S2) -> io::Result<(u64, u64)>
 where
     S1: AsyncRead + AsyncWrite,
     S2: AsyncRead + AsyncWrite,
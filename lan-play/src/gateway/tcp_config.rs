@@ -0,0 +1,230 @@
+use smoltcp::wire::{Ipv4Address, Ipv4Cidr};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::time::Duration;
+use thiserror::Error;
+
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ParseError {
+    #[error("expected key=value, got {0:?}")]
+    MissingValue(String),
+    #[error("unknown key {0:?}")]
+    UnknownKey(String),
+    #[error("invalid value {1:?} for {0}")]
+    InvalidValue(String, String),
+}
+
+/// Tuning applied to a proxied TCP connection.
+///
+/// Every field is optional so a rule only overrides what it mentions.
+/// A zero duration disables the corresponding timer.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TcpOptions {
+    /// smoltcp keep-alive interval towards the console
+    pub keepalive: Option<Duration>,
+    /// smoltcp timeout before an unresponsive console is dropped
+    pub timeout: Option<Duration>,
+    /// How long smoltcp holds back an ACK to the console, hoping to send it with data
+    pub ack_delay: Option<Duration>,
+    /// Disable (`true`) or enable (`false`) Nagle's algorithm on the upstream socket
+    pub nodelay: Option<bool>,
+    /// Close the connection when neither side transfers data for this long
+    pub idle_timeout: Option<Duration>,
+    /// Close the connection after this long regardless of activity
+    pub max_lifetime: Option<Duration>,
+    /// OS keepalive idle time on the upstream socket
+    pub upstream_keepalive: Option<Duration>,
+}
+
+impl TcpOptions {
+    /// Fields set in `other` take precedence over ours.
+    pub fn merge(&self, other: &TcpOptions) -> TcpOptions {
+        TcpOptions {
+            keepalive: other.keepalive.or(self.keepalive),
+            timeout: other.timeout.or(self.timeout),
+            ack_delay: other.ack_delay.or(self.ack_delay),
+            nodelay: other.nodelay.or(self.nodelay),
+            idle_timeout: other.idle_timeout.or(self.idle_timeout),
+            max_lifetime: other.max_lifetime.or(self.max_lifetime),
+            upstream_keepalive: other.upstream_keepalive.or(self.upstream_keepalive),
+        }
+    }
+    fn set(&mut self, key: &str, value: &str) -> Result<(), ParseError> {
+        let invalid = || ParseError::InvalidValue(key.to_string(), value.to_string());
+        let secs = || value.parse::<u64>().map(Duration::from_secs).map_err(|_| invalid());
+        match key {
+            "keepalive" => self.keepalive = Some(secs()?),
+            "timeout" => self.timeout = Some(secs()?),
+            "ack-delay" => {
+                let millis = value.parse::<u64>().map_err(|_| invalid())?;
+                self.ack_delay = Some(Duration::from_millis(millis))
+            }
+            "nodelay" => self.nodelay = Some(value.parse().map_err(|_| invalid())?),
+            "idle-timeout" => self.idle_timeout = Some(secs()?),
+            "max-lifetime" => self.max_lifetime = Some(secs()?),
+            "upstream-keepalive" => self.upstream_keepalive = Some(secs()?),
+            _ => return Err(ParseError::UnknownKey(key.to_string())),
+        };
+        Ok(())
+    }
+}
+
+/// Options for connections matching a destination port range and/or network.
+///
+/// Parsed from `port=27015-27030,dst=1.2.3.0/24,idle-timeout=3600,...`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TcpRule {
+    pub port: Option<RangeInclusive<u16>>,
+    pub dst: Option<Ipv4Cidr>,
+    pub options: TcpOptions,
+}
+
+impl TcpRule {
+    pub fn matches(&self, addr: &SocketAddr) -> bool {
+        let port_ok = self.port.as_ref().map(|r| r.contains(&addr.port())).unwrap_or(true);
+        let dst_ok = match (&self.dst, addr.ip()) {
+            (None, _) => true,
+            (Some(cidr), IpAddr::V4(ip)) => cidr.contains_addr(&Ipv4Address::from(ip)),
+            (Some(_), IpAddr::V6(_)) => false,
+        };
+        port_ok && dst_ok
+    }
+}
+
+fn parse_port_range(s: &str) -> Option<RangeInclusive<u16>> {
+    match s.find('-') {
+        Some(pos) => Some(s[..pos].parse().ok()?..=s[pos + 1..].parse().ok()?),
+        None => {
+            let port = s.parse().ok()?;
+            Some(port..=port)
+        }
+    }
+}
+
+fn parse_cidr(s: &str) -> Option<Ipv4Cidr> {
+    let (addr, prefix_len) = match s.find('/') {
+        Some(pos) => (&s[..pos], s[pos + 1..].parse().ok()?),
+        None => (s, 32),
+    };
+    if prefix_len > 32 {
+        return None;
+    }
+    let addr: Ipv4Addr = addr.parse().ok()?;
+    Some(Ipv4Cidr::new(addr.into(), prefix_len))
+}
+
+fn split_pair(pair: &str) -> Result<(&str, &str), ParseError> {
+    let pos = pair.find('=').ok_or_else(|| ParseError::MissingValue(pair.to_string()))?;
+    Ok((pair[..pos].trim(), pair[pos + 1..].trim()))
+}
+
+impl FromStr for TcpOptions {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut options = TcpOptions::default();
+        for pair in s.split(',').filter(|p| !p.is_empty()) {
+            let (key, value) = split_pair(pair)?;
+            options.set(key, value)?;
+        }
+        Ok(options)
+    }
+}
+
+impl FromStr for TcpRule {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rule = TcpRule {
+            port: None,
+            dst: None,
+            options: TcpOptions::default(),
+        };
+        for pair in s.split(',').filter(|p| !p.is_empty()) {
+            let (key, value) = split_pair(pair)?;
+            let invalid = || ParseError::InvalidValue(key.to_string(), value.to_string());
+            match key {
+                "port" => rule.port = Some(parse_port_range(value).ok_or_else(invalid)?),
+                "dst" => rule.dst = Some(parse_cidr(value).ok_or_else(invalid)?),
+                _ => rule.options.set(key, value)?,
+            }
+        }
+        Ok(rule)
+    }
+}
+
+/// Global TCP defaults plus per-destination overrides. Later rules win.
+#[derive(Debug, Clone)]
+pub struct TcpConfig {
+    pub defaults: TcpOptions,
+    pub rules: Vec<TcpRule>,
+}
+
+impl Default for TcpConfig {
+    fn default() -> Self {
+        TcpConfig {
+            defaults: TcpOptions {
+                idle_timeout: Some(DEFAULT_IDLE_TIMEOUT),
+                ..Default::default()
+            },
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl TcpConfig {
+    pub fn options_for(&self, addr: &SocketAddr) -> TcpOptions {
+        self.rules
+            .iter()
+            .filter(|rule| rule.matches(addr))
+            .fold(self.defaults, |opts, rule| opts.merge(&rule.options))
+    }
+}
+
+/// Treat zero durations as "disabled".
+pub(super) fn non_zero(d: Option<Duration>) -> Option<Duration> {
+    d.filter(|d| *d != Duration::from_secs(0))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_rule() {
+        let rule: TcpRule =
+            "port=45000-45010,dst=10.0.0.0/8,idle-timeout=3600,nodelay=false,ack-delay=40"
+                .parse()
+                .unwrap();
+        assert_eq!(rule.port, Some(45000..=45010));
+        assert_eq!(rule.options.idle_timeout, Some(Duration::from_secs(3600)));
+        assert_eq!(rule.options.nodelay, Some(false));
+        assert_eq!(rule.options.ack_delay, Some(Duration::from_millis(40)));
+        assert!(rule.matches(&"10.1.2.3:45005".parse().unwrap()));
+        assert!(!rule.matches(&"10.1.2.3:80".parse().unwrap()));
+        assert!(!rule.matches(&"192.168.1.1:45005".parse().unwrap()));
+
+        assert_eq!("foo=1".parse::<TcpRule>(), Err(ParseError::UnknownKey("foo".into())));
+        assert!("port=abc".parse::<TcpRule>().is_err());
+    }
+
+    #[test]
+    fn test_options_for() {
+        let config = TcpConfig {
+            rules: vec![
+                "port=443,keepalive=30".parse().unwrap(),
+                "port=443,idle-timeout=0".parse().unwrap(),
+            ],
+            ..Default::default()
+        };
+        let opts = config.options_for(&"1.1.1.1:443".parse().unwrap());
+        assert_eq!(opts.keepalive, Some(Duration::from_secs(30)));
+        assert_eq!(non_zero(opts.idle_timeout), None);
+
+        let opts = config.options_for(&"1.1.1.1:80".parse().unwrap());
+        assert_eq!(opts, config.defaults);
+    }
+}
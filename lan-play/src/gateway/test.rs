@@ -0,0 +1,337 @@
+use super::{ForwardGateway, Gateway, NatBehavior, PortForward, TcpConfig, UdpConfig};
+use crate::future_smoltcp::{BufferSize, Net, OwnedUdp, TcpListener};
+use crate::proxy::{resolve, DirectProxy};
+use crate::test_support::{dns_server, Console, Impairment, VirtualPort, VirtualSwitch};
+use dns_parser::{rdata::A, Builder, Packet, QueryClass, QueryType, RData};
+use futures::future::join_all;
+use smoltcp::wire::{EthernetAddress, IpCidr, Ipv4Address};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use tokio::{
+    io::{copy, split, AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener as HostTcpListener, TcpStream as HostTcpStream, UdpSocket as HostUdpSocket},
+    time::{sleep, timeout, Duration},
+};
+
+const GATEWAY_MAC: EthernetAddress = EthernetAddress([0x02, 0, 0, 0, 0, 0x02]);
+const CONSOLE_MAC: EthernetAddress = EthernetAddress([0x02, 0, 0, 0, 0, 0x05]);
+const GATEWAY_IP: Ipv4Address = Ipv4Address([10, 13, 37, 2]);
+const CONSOLE_IP: Ipv4Address = Ipv4Address([10, 13, 37, 5]);
+
+async fn start_gateway(port: VirtualPort, gateway: Gateway) {
+    let net = Net::new(
+        GATEWAY_MAC,
+        vec![IpCidr::new(GATEWAY_IP.into(), 16)],
+        GATEWAY_IP,
+        port,
+        1500,
+        BufferSize {
+            tcp_rx_size: 8192,
+            tcp_tx_size: 8192,
+        },
+    );
+    let tcp: Vec<TcpListener> = join_all((0..4).map(|_| net.tcp_listener())).await;
+    let udp = net.udp_socket().await;
+    let connector = net.tcp_connector();
+    tokio::spawn(async move {
+        gateway.process(tcp, udp, connector).await
+    });
+}
+
+async fn setup(impairment: Impairment) -> Console<VirtualPort> {
+    setup_with(impairment, UdpConfig::default()).await
+}
+
+async fn setup_with(impairment: Impairment, udp_config: UdpConfig) -> Console<VirtualPort> {
+    let switch = VirtualSwitch::with_impairment(impairment);
+    let gateway = Gateway::new(DirectProxy::new(), TcpConfig::default(), udp_config);
+    start_gateway(switch.port(), gateway).await;
+    Console::new(switch.port(), CONSOLE_MAC, IpCidr::new(CONSOLE_IP.into(), 16), GATEWAY_IP)
+}
+
+async fn tcp_echo_server() -> SocketAddr {
+    let server = HostTcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = server.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (socket, _) = server.accept().await?;
+        let (mut reader, mut writer) = split(socket);
+        copy(&mut reader, &mut writer).await?;
+        Ok::<_, std::io::Error>(())
+    });
+    addr
+}
+
+async fn tcp_echo(console: &mut Console<VirtualPort>, data: &[u8]) -> Vec<u8> {
+    let target = tcp_echo_server().await;
+    console.connect(target, 49152);
+    console.poll_until(|tcp| tcp.may_send()).await;
+    console.with_tcp(|tcp| tcp.send_slice(data)).unwrap();
+
+    let mut received = Vec::new();
+    console.poll_until(|tcp| {
+        let mut buf = [0u8; 1024];
+        while tcp.can_recv() {
+            let size = tcp.recv_slice(&mut buf).unwrap();
+            received.extend_from_slice(&buf[..size]);
+        }
+        received.len() >= data.len()
+    }).await;
+    received
+}
+
+#[tokio::test]
+async fn test_tcp() {
+    let mut console = setup(Impairment::default()).await;
+    let received = timeout(Duration::from_secs(5), tcp_echo(&mut console, b"hello"))
+        .await
+        .expect("timed out");
+    assert_eq!(received, b"hello");
+}
+
+#[tokio::test]
+async fn test_tcp_impaired() {
+    let mut console = setup(Impairment {
+        loss: 0.1,
+        delay: Duration::from_millis(2),
+        reorder: 0.2,
+        ..Default::default()
+    }).await;
+    let data = (0..8000u32).map(|i| i as u8).collect::<Vec<_>>();
+    let received = timeout(Duration::from_secs(30), tcp_echo(&mut console, &data))
+        .await
+        .expect("timed out");
+    assert_eq!(received, data);
+}
+
+#[tokio::test]
+async fn test_udp() {
+    let mut console = setup(Impairment::default()).await;
+    let server = HostUdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let target = server.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut buf = [0u8; 1024];
+        while let Ok((size, addr)) = server.recv_from(&mut buf).await {
+            let _ = server.send_to(&buf[..size], addr).await;
+        }
+    });
+
+    let src: SocketAddr = "10.13.37.5:5000".parse().unwrap();
+    let query = OwnedUdp::new(src, target, b"ping".to_vec());
+    // the first datagrams may be lost while ARP resolves
+    let reply = timeout(Duration::from_secs(5), async {
+        loop {
+            console.send_udp(&query);
+            if let Ok(reply) = timeout(Duration::from_millis(100), console.poll_udp()).await {
+                return reply;
+            }
+        }
+    }).await.expect("timed out");
+
+    assert_eq!(reply.src(), target);
+    assert_eq!(reply.dst(), src);
+    assert_eq!(reply.data, b"ping");
+}
+
+#[tokio::test]
+async fn test_dns() {
+    let mut console = setup(Impairment::default()).await;
+    let server = dns_server(Ipv4Addr::new(1, 2, 3, 4)).await;
+
+    let mut builder = Builder::new_query(7, true);
+    builder.add_question("example.org", false, QueryType::A, QueryClass::IN);
+    let src: SocketAddr = "10.13.37.5:5353".parse().unwrap();
+    let query = OwnedUdp::new(src, server, builder.build().unwrap());
+    let reply = timeout(Duration::from_secs(5), async {
+        loop {
+            console.send_udp(&query);
+            if let Ok(reply) = timeout(Duration::from_millis(100), console.poll_udp()).await {
+                return reply;
+            }
+        }
+    }).await.expect("timed out");
+
+    assert_eq!(reply.src(), server);
+    let packet = Packet::parse(&reply.data).unwrap();
+    assert_eq!(packet.header.id, 7);
+    let answers = packet.answers.iter().filter_map(|a| match a.data {
+        RData::A(A(ip)) => Some(ip),
+        _ => None,
+    }).collect::<Vec<_>>();
+    assert_eq!(answers, vec![Ipv4Addr::new(1, 2, 3, 4)]);
+}
+
+#[tokio::test]
+async fn test_resolve() {
+    let server = dns_server(Ipv4Addr::new(1, 2, 3, 4)).await;
+    let answers = timeout(Duration::from_secs(5), resolve(&DirectProxy::new(), &server, "example.org"))
+        .await
+        .expect("timed out")
+        .unwrap();
+    assert_eq!(answers, vec![Ipv4Addr::new(1, 2, 3, 4)]);
+}
+
+/// Answers every datagram with the address it came from, like a STUN
+/// binding request.
+async fn stun_server() -> SocketAddr {
+    let server = HostUdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let local = server.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut buf = [0u8; 1024];
+        while let Ok((_, addr)) = server.recv_from(&mut buf).await {
+            let _ = server.send_to(addr.to_string().as_bytes(), addr).await;
+        }
+    });
+    local
+}
+
+/// Returns the address `server` sees for the console endpoint `src`.
+async fn binding_request(console: &mut Console<VirtualPort>, src: SocketAddr, server: SocketAddr) -> SocketAddr {
+    let query = OwnedUdp::new(src, server, b"binding".to_vec());
+    let reply = timeout(Duration::from_secs(5), async {
+        loop {
+            console.send_udp(&query);
+            if let Ok(reply) = timeout(Duration::from_millis(100), console.poll_udp()).await {
+                // skip answers to earlier requests
+                if reply.src() == server {
+                    return reply;
+                }
+            }
+        }
+    }).await.expect("timed out");
+    String::from_utf8(reply.data).unwrap().parse().unwrap()
+}
+
+/// Whether a datagram sent from `from` to `mapped` makes it to the console.
+async fn reaches(console: &mut Console<VirtualPort>, from: &str, mapped: SocketAddr) -> bool {
+    let socket = HostUdpSocket::bind(from).await.unwrap();
+    socket.send_to(b"probe", mapped).await.unwrap();
+    timeout(Duration::from_millis(300), async {
+        while console.poll_udp().await.data != b"probe" {}
+    }).await.is_ok()
+}
+
+/// Returns whether a new port on the same host and a different host can
+/// reach the mapping.
+async fn probe_nat_type(behavior: NatBehavior) -> (bool, bool) {
+    let mut console = setup_with(Impairment::default(), UdpConfig {
+        behavior,
+        ..Default::default()
+    }).await;
+    let src: SocketAddr = "10.13.37.5:5000".parse().unwrap();
+    let first = binding_request(&mut console, src, stun_server().await).await;
+    let second = binding_request(&mut console, src, stun_server().await).await;
+    // endpoint-independent mapping
+    assert_eq!(first, second);
+    (
+        reaches(&mut console, "127.0.0.1:0", first).await,
+        reaches(&mut console, "127.0.0.2:0", first).await,
+    )
+}
+
+// binding 127.0.0.2 only works out of the box on Linux
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_udp_nat_type() {
+    assert_eq!(probe_nat_type(NatBehavior::FullCone).await, (true, true));
+    assert_eq!(probe_nat_type(NatBehavior::AddressRestricted).await, (true, false));
+    assert_eq!(probe_nat_type(NatBehavior::PortRestricted).await, (false, false));
+}
+
+#[tokio::test]
+async fn test_udp_sticky_port() {
+    let mut console = setup_with(Impairment::default(), UdpConfig {
+        timeout: Duration::from_secs(1),
+        ..Default::default()
+    }).await;
+    let src: SocketAddr = "10.13.37.5:5000".parse().unwrap();
+    let first = binding_request(&mut console, src, stun_server().await).await;
+    sleep(Duration::from_millis(1500)).await;
+    // the mapping expired and is recreated on the same port
+    let second = binding_request(&mut console, src, stun_server().await).await;
+    assert_eq!(first, second);
+}
+
+async fn free_port() -> u16 {
+    HostUdpSocket::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().port()
+}
+
+/// Starts a gateway forwarding `forward` and returns the console behind it.
+async fn setup_forward(forward: &str) -> Console<VirtualPort> {
+    let switch = VirtualSwitch::new();
+    let mut gateway = Gateway::new(DirectProxy::new(), TcpConfig::default(), UdpConfig::default());
+    let forward = Arc::new(ForwardGateway::new(GATEWAY_IP.into(), vec![forward.parse::<PortForward>().unwrap()]));
+    gateway.set_forward(forward.clone());
+    tokio::spawn(forward.run());
+    start_gateway(switch.port(), gateway).await;
+    Console::new(switch.port(), CONSOLE_MAC, IpCidr::new(CONSOLE_IP.into(), 16), GATEWAY_IP)
+}
+
+#[tokio::test]
+async fn test_udp_forward() {
+    let port = free_port().await;
+    let mut console = setup_forward(&format!("127.0.0.1:{}/udp->10.13.37.5:45000", port)).await;
+    let listen: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+    let peer = HostUdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+    timeout(Duration::from_secs(5), async {
+        // resend until the forward is listening and ARP is resolved
+        let request = loop {
+            peer.send_to(b"hello", listen).await.unwrap();
+            if let Ok(udp) = timeout(Duration::from_millis(100), console.poll_udp()).await {
+                break udp;
+            }
+        };
+        assert_eq!(request.src().ip(), std::net::IpAddr::V4(GATEWAY_IP.into()));
+        assert_eq!(request.dst(), "10.13.37.5:45000".parse().unwrap());
+        assert_eq!(request.data, b"hello");
+
+        console.send_udp(&OwnedUdp::new(request.dst(), request.src(), b"world".to_vec()));
+        let mut buf = [0u8; 64];
+        let (size, from) = loop {
+            console.poll_until(|_| true).await;
+            if let Ok(r) = timeout(Duration::from_millis(10), peer.recv_from(&mut buf)).await {
+                break r.unwrap();
+            }
+        };
+        assert_eq!(from, listen);
+        assert_eq!(&buf[..size], b"world");
+    }).await.expect("timed out");
+}
+
+#[tokio::test]
+async fn test_tcp_forward() {
+    let port = free_port().await;
+    let mut console = setup_forward(&format!("127.0.0.1:{}/tcp->10.13.37.5:45000", port)).await;
+    console.with_tcp(|tcp| tcp.listen(45000)).unwrap();
+
+    let host = tokio::spawn(async move {
+        let mut stream = loop {
+            // the forward may not be listening yet
+            if let Ok(stream) = HostTcpStream::connect(("127.0.0.1", port)).await {
+                break stream;
+            }
+            sleep(Duration::from_millis(50)).await;
+        };
+        stream.write_all(b"hello").await.unwrap();
+        let mut buf = [0u8; 5];
+        stream.read_exact(&mut buf).await.unwrap();
+        buf
+    });
+
+    timeout(Duration::from_secs(5), async {
+        let mut received = Vec::new();
+        console.poll_until(|tcp| {
+            let mut buf = [0u8; 64];
+            if tcp.can_recv() {
+                let size = tcp.recv_slice(&mut buf).unwrap();
+                received.extend_from_slice(&buf[..size]);
+            }
+            received.len() >= 5
+        }).await;
+        assert_eq!(received, b"hello");
+        assert_eq!(console.with_tcp(|tcp| tcp.remote_endpoint().addr), GATEWAY_IP.into());
+        console.with_tcp(|tcp| tcp.send_slice(b"world")).unwrap();
+        console.poll_until(|tcp| tcp.send_queue() == 0).await;
+        assert_eq!(&host.await.unwrap(), b"world");
+    }).await.expect("timed out");
+}
@@ -1,29 +1,47 @@
 use crate::future_smoltcp::{OwnedUdp, UdpSocket, SendHalf as UdpSendHalf};
 use crate::proxy::{other, BoxedProxy, SendHalf, RecvHalf};
-use tokio::{spawn, sync::Mutex, time::Duration};
+use super::forward::ForwardGateway;
+use super::igd::IgdServer;
+use super::udp_config::{NatBehavior, UdpConfig};
+use tokio::{spawn, sync::Mutex};
 use drop_abort::{abortable, DropAbortHandle};
+use std::collections::HashSet;
 use std::io;
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex as SyncMutex};
 use futures::future::try_join;
 use lru::LruCache;
 use async_timeout::{VisitorTimeout, Visitor};
 use async_channel::{Sender, Receiver, unbounded};
 
-const UDP_TIMEOUT: Duration = Duration::from_secs(60);
-
 pub(super) struct UdpGateway {
     proxy: Arc<BoxedProxy>,
+    config: UdpConfig,
     cache: Mutex<LruCache<SocketAddr, UdpConnection>>,
+    /// Upstream address last used by each console endpoint, so a recreated
+    /// mapping gets the same port back
+    ports: Mutex<LruCache<SocketAddr, SocketAddr>>,
+    forward: Option<Arc<ForwardGateway>>,
+    igd: Option<Arc<IgdServer>>,
 }
 
 impl UdpGateway {
-    pub fn new(proxy: Arc<BoxedProxy>) -> UdpGateway {
+    pub fn new(proxy: Arc<BoxedProxy>, config: UdpConfig) -> UdpGateway {
         UdpGateway {
             proxy,
+            config,
             cache: Mutex::new(LruCache::new(100)),
+            ports: Mutex::new(LruCache::new(1000)),
+            forward: None,
+            igd: None,
         }
     }
+    pub fn set_forward(&mut self, forward: Arc<ForwardGateway>) {
+        self.forward = Some(forward);
+    }
+    pub fn set_igd(&mut self, igd: Arc<IgdServer>) {
+        self.igd = Some(igd);
+    }
     pub async fn process(&self, udp: UdpSocket) -> io::Result<()> {
         let (pop_tx, pop_rx) = unbounded();
         try_join(
@@ -35,8 +53,31 @@ impl UdpGateway {
     async fn process_udp(&self, udp: UdpSocket, pop_tx: Sender<SocketAddr>) -> io::Result<()> {
         let (tx, mut rx) = udp.split();
         let sender = Arc::new(Mutex::new(tx));
+        if let Some(forward) = &self.forward {
+            forward.attach_udp(sender.clone());
+        }
         loop {
             let udp = rx.recv().await?;
+            if let Some(igd) = &self.igd {
+                match igd.on_udp(&udp, &sender).await {
+                    Ok(true) => continue,
+                    Ok(false) => (),
+                    Err(e) => {
+                        log::error!("igd on_udp {:?}", e);
+                        continue;
+                    }
+                }
+            }
+            if let Some(forward) = &self.forward {
+                match forward.on_udp(&udp).await {
+                    Ok(true) => continue,
+                    Ok(false) => (),
+                    Err(e) => {
+                        log::error!("forward on_udp {:?}", e);
+                        continue;
+                    }
+                }
+            }
             if let Err(e) = self.on_udp(udp, sender.clone(), pop_tx.clone()).await {
                 log::error!("on_udp {:?}", e);
             }
@@ -56,13 +97,26 @@ impl UdpGateway {
         let mut cache = self.cache.lock().await;
         let src = udp.src();
         if !cache.contains(&src) {
-            let (timeout, visitor) = VisitorTimeout::new(UDP_TIMEOUT);
+            let (timeout, visitor) = VisitorTimeout::new(self.config.timeout);
             let pop_tx = pop_tx.clone();
             spawn(async move {
                 let _ = timeout.await;
                 pop_tx.send(src).await.unwrap();
             });
-            cache.put(src, UdpConnection::new(&self.proxy, sender, src, visitor).await?);
+            let mut ports = self.ports.lock().await;
+            let previous = ports.get(&src).copied();
+            let connection = UdpConnection::new(
+                &self.proxy,
+                sender,
+                src,
+                visitor,
+                self.config.behavior,
+                previous,
+            ).await?;
+            if let Ok(local_addr) = connection.sender.local_addr() {
+                ports.put(src, local_addr);
+            }
+            cache.put(src, connection);
             log::trace!("new udp from {:?}", src);
         }
         let connection = cache.get_mut(&src).unwrap();
@@ -71,9 +125,27 @@ impl UdpGateway {
     }
 }
 
+/// Remote endpoints the console has sent to, used to filter replies.
+struct Filter {
+    behavior: NatBehavior,
+    peers: SyncMutex<HashSet<SocketAddr>>,
+}
+
+impl Filter {
+    fn allows(&self, addr: &SocketAddr) -> bool {
+        let peers = self.peers.lock().unwrap();
+        match self.behavior {
+            NatBehavior::FullCone => true,
+            NatBehavior::AddressRestricted => peers.iter().any(|p| p.ip() == addr.ip()),
+            NatBehavior::PortRestricted => peers.contains(addr),
+        }
+    }
+}
+
 pub(super) struct UdpConnection {
     sender: SendHalf,
     visitor: Visitor,
+    filter: Arc<Filter>,
     _handle: DropAbortHandle,
 }
 
@@ -84,14 +156,19 @@ impl Drop for UdpConnection {
 }
 
 impl UdpConnection {
-    pub(super) async fn run(
+    async fn run(
         mut rx: RecvHalf,
         sender: Arc<Mutex<UdpSendHalf>>,
         src: SocketAddr,
+        filter: Arc<Filter>,
     ) -> io::Result<()> {
         loop {
             let mut buf = vec![0; 2048];
             let (size, addr) = rx.recv_from(&mut buf).await?;
+            if !filter.allows(&addr) {
+                log::trace!("udp from {:?} to {:?} filtered", addr, src);
+                continue;
+            }
             buf.truncate(size);
             let data = OwnedUdp::new(addr, src, buf);
             sender
@@ -102,26 +179,49 @@ impl UdpConnection {
                 .map_err(other)?;
         }
     }
+    /// Binds the upstream socket to `previous` when given, falling back to
+    /// any port if it's taken.
     pub(super) async fn new(
         proxy: &Arc<BoxedProxy>,
         sender: Arc<Mutex<UdpSendHalf>>,
         src: SocketAddr,
         visitor: Visitor,
+        behavior: NatBehavior,
+        previous: Option<SocketAddr>,
     ) -> io::Result<UdpConnection> {
         let proxy = proxy.clone();
-        let (tx, rx) = proxy.new_udp_timeout("0.0.0.0:0".parse().unwrap()).await?.split();
+        let any = "0.0.0.0:0".parse().unwrap();
+        let udp = match previous {
+            Some(addr) => match proxy.new_udp_timeout(addr).await {
+                Ok(udp) => udp,
+                Err(e) => {
+                    log::debug!("can't reuse {:?} for {:?}: {:?}", addr, src, e);
+                    proxy.new_udp_timeout(any).await?
+                }
+            },
+            None => proxy.new_udp_timeout(any).await?,
+        };
+        let (tx, rx) = udp.split();
+        let filter = Arc::new(Filter {
+            behavior,
+            peers: SyncMutex::new(HashSet::new()),
+        });
         let (fut, _handle) = abortable(
-            UdpConnection::run(rx, sender, src)
+            UdpConnection::run(rx, sender, src, filter.clone())
         );
         tokio::spawn(fut);
         Ok(UdpConnection {
             sender: tx,
             visitor,
+            filter,
             _handle
         })
     }
     pub(super) async fn send_to(&mut self, buf: &[u8], addr: &SocketAddr) -> io::Result<usize> {
         self.visitor.visit();
+        if self.filter.behavior != NatBehavior::FullCone {
+            self.filter.peers.lock().unwrap().insert(*addr);
+        }
         self.sender.send_to(buf, addr).await
     }
 }
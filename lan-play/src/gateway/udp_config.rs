@@ -0,0 +1,84 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Kept at the previous 60 seconds for compatibility, below the two minutes
+/// RFC 4787 recommends.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Error, PartialEq)]
+#[error("unknown NAT behavior {0:?}, expected full-cone, address-restricted or port-restricted")]
+pub struct ParseNatBehaviorError(String);
+
+/// Which upstream datagrams are let back to the console.
+///
+/// Mapping is always endpoint-independent: one upstream socket per console
+/// endpoint, whatever the destination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NatBehavior {
+    /// Anyone may send to the mapped port
+    FullCone,
+    /// Only hosts the console has sent to
+    AddressRestricted,
+    /// Only host and port pairs the console has sent to
+    PortRestricted,
+}
+
+impl Default for NatBehavior {
+    fn default() -> Self {
+        NatBehavior::FullCone
+    }
+}
+
+impl FromStr for NatBehavior {
+    type Err = ParseNatBehaviorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "full-cone" => Ok(NatBehavior::FullCone),
+            "address-restricted" => Ok(NatBehavior::AddressRestricted),
+            "port-restricted" => Ok(NatBehavior::PortRestricted),
+            _ => Err(ParseNatBehaviorError(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for NatBehavior {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            NatBehavior::FullCone => "full-cone",
+            NatBehavior::AddressRestricted => "address-restricted",
+            NatBehavior::PortRestricted => "port-restricted",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UdpConfig {
+    pub behavior: NatBehavior,
+    /// A mapping without outbound traffic for this long is dropped
+    pub timeout: Duration,
+}
+
+impl Default for UdpConfig {
+    fn default() -> Self {
+        UdpConfig {
+            behavior: NatBehavior::default(),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_behavior() {
+        for behavior in &[NatBehavior::FullCone, NatBehavior::AddressRestricted, NatBehavior::PortRestricted] {
+            assert_eq!(behavior.to_string().parse::<NatBehavior>(), Ok(*behavior));
+        }
+        assert!("symmetric".parse::<NatBehavior>().is_err());
+    }
+}
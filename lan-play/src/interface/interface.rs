@@ -1,21 +1,80 @@
+use super::link::{Egress, LinkAdapter, LINK_GATEWAY_MAC};
+use super::list::InterfaceReport;
+use super::stats::Counters;
 use super::{Error, ErrorWithDesc};
-use crate::interface_info::{get_interface_info, InterfaceInfo};
+use crate::interface_info::{self, get_interface_info, InterfaceInfo};
 use async_channel::{unbounded, Receiver, Sender};
 use rawsock::traits::{DynamicInterface, Library};
-use rawsock::InterfaceDescription;
+use rawsock::{
+    DataLink, Endpoint, Filter, InterfaceDescription, InterfaceFlags, InterfaceOptions, Protocol,
+};
 use smoltcp::wire::{EthernetAddress, Ipv4Cidr};
 use std::ffi::CString;
+use std::net::{IpAddr, Ipv4Addr};
 use std::sync::Arc;
 use std::thread;
+use std::time::Duration;
 use futures::{Stream, Sink};
+#[cfg(unix)]
+use futures::StreamExt;
 use std::{pin::Pin, task::{Context, Poll}, io};
 
 pub type Packet = Vec<u8>;
 type Interface = Arc<dyn DynamicInterface<'static> + 'static>;
 
+/// Most packets handed to the interface in one batch
+const MAX_BURST: usize = 64;
+
+/// Takes `first` and the packets queued behind it, adapted to the link.
+fn egress_burst(
+    link: &LinkAdapter,
+    first: Packet,
+    packet_receiver: &Receiver<Packet>,
+    reply_sender: &Sender<Packet>,
+) -> Vec<Packet> {
+    let mut burst = Vec::new();
+    let mut next = Some(first);
+    while let Some(data) = next {
+        match link.egress(&data) {
+            Egress::Send(data) => burst.push(data),
+            Egress::Reply(reply) => {
+                let _ = reply_sender.try_send(reply);
+            }
+            Egress::Drop => {}
+        }
+        next = if burst.len() < MAX_BURST {
+            packet_receiver.try_recv().ok()
+        } else {
+            None
+        };
+    }
+    burst
+}
+
+/// What the capture task of an asynchronous interface woke up for.
+#[cfg(unix)]
+enum AsyncEvent {
+    Received(Option<Result<rawsock::OwnedPacket, rawsock::Error>>),
+    Flushed(Result<(), rawsock::Error>),
+}
+
 pub struct PacketInterface {
     sink: Sender<Packet>,
     stream: Receiver<Packet>,
+    counters: Arc<Counters>,
+}
+
+impl PacketInterface {
+    pub(super) fn new(sink: Sender<Packet>, stream: Receiver<Packet>) -> PacketInterface {
+        PacketInterface {
+            sink,
+            stream,
+            counters: Default::default(),
+        }
+    }
+    pub fn counters(&self) -> &Arc<Counters> {
+        &self.counters
+    }
 }
 
 impl Stream for PacketInterface {
@@ -50,11 +109,58 @@ impl Sink<Packet> for PacketInterface {
     }
 }
 
+/// Keeps a network stack going while the capture under it is closed and
+/// opened again: the stack uses the `PacketInterface` returned by `new`, and
+/// each capture is plugged in with `attach`.
+pub struct Reattachable {
+    to_stack: Sender<Packet>,
+    from_stack: Receiver<Packet>,
+}
+
+impl Reattachable {
+    pub fn new(counters: Arc<Counters>) -> (Reattachable, PacketInterface) {
+        let (to_stack, stream) = unbounded();
+        let (sink, from_stack) = unbounded();
+        let reattachable = Reattachable {
+            to_stack,
+            from_stack,
+        };
+        (reattachable, PacketInterface { sink, stream, counters })
+    }
+    /// Carries packets between the stack and `capture`, until the capture
+    /// stops or the future is dropped.
+    pub async fn attach(&self, capture: PacketInterface) {
+        // frames queued while nothing was attached are stale
+        while self.from_stack.try_recv().is_ok() {}
+        let PacketInterface { sink, stream, .. } = capture;
+        let ingress = async {
+            while let Ok(packet) = stream.recv().await {
+                if self.to_stack.send(packet).await.is_err() {
+                    break;
+                }
+            }
+        };
+        let egress = async {
+            while let Ok(packet) = self.from_stack.recv().await {
+                if sink.send(packet).await.is_err() {
+                    break;
+                }
+            }
+        };
+        futures::pin_mut!(ingress, egress);
+        futures::future::select(ingress, egress).await;
+    }
+}
+
 pub struct RawsockInterface {
     pub desc: InterfaceDescription,
     mac: EthernetAddress,
     data_link: rawsock::DataLink,
-    interface: Arc<dyn DynamicInterface<'static>>,
+    info: Option<InterfaceInfo>,
+    link: LinkAdapter,
+    interface: Box<dyn DynamicInterface<'static>>,
+    counters: Arc<Counters>,
+    stats_interval: Option<Duration>,
 }
 
 impl std::fmt::Debug for RawsockInterface {
@@ -73,30 +179,40 @@ impl RawsockInterface {
         desc: &mut InterfaceDescription,
     ) -> Result<RawsockInterface, Error> {
         let name = &desc.name;
-        let mut interface = slf.lib.open_interface_arc(name)?;
-        Arc::get_mut(&mut interface)
-            .unwrap()
-            .set_filter_cstr(&slf.filter)?;
+        let mut interface = slf.lib.open_interface_with_options(name, &slf.options)?;
+        interface.set_filter_cstr(&slf.filter)?;
 
         let data_link = interface.data_link();
-        if let rawsock::DataLink::Ethernet = data_link {
-        } else {
+        if !LinkAdapter::is_supported(data_link) {
             return Err(Error::WrongDataLink(data_link));
         }
-        let InterfaceInfo {
-            ethernet_address: mac,
-            name: _,
-            description,
-        } = get_interface_info(name)?;
-        if let Some(description) = description {
-            desc.description = description;
-        }
+        // the system knows more about the link than the library
+        let info = get_interface_info(name).ok();
+        let mac = match desc.hardware_address {
+            Some(mac) => EthernetAddress(mac),
+            // frames are rebuilt for other links, any address does
+            None if data_link != rawsock::DataLink::Ethernet => LINK_GATEWAY_MAC,
+            None => {
+                let mac = info.as_ref().and_then(|info| info.ethernet_address);
+                let mac = mac.ok_or(interface_info::Error::NotFound)?;
+                if let Some(description) = info.as_ref().and_then(|info| info.description.clone()) {
+                    desc.description = description;
+                }
+                mac
+            }
+        };
 
+        let counters = Arc::new(Counters::default());
+        counters.set_capturing(true);
         Ok(RawsockInterface {
             data_link,
+            link: LinkAdapter::new(data_link, mac),
             desc: desc.clone(),
             mac,
+            info,
             interface,
+            counters,
+            stats_interval: None,
         })
     }
     pub fn name(&self) -> &String {
@@ -108,40 +224,202 @@ impl RawsockInterface {
     pub fn data_link(&self) -> rawsock::DataLink {
         self.data_link
     }
+    /// Whether the interface captures several others, like the `any` device of Linux.
+    pub fn is_cooked(&self) -> bool {
+        LinkAdapter::is_cooked(self.data_link)
+    }
+    /// What the system tells about the link, if it knows the interface.
+    pub fn info(&self) -> Option<&InterfaceInfo> {
+        self.info.as_ref()
+    }
+    pub fn counters(&self) -> &Arc<Counters> {
+        &self.counters
+    }
+    /// Counts into `counters`, kept from an earlier capture on the interface.
+    pub fn set_counters(&mut self, counters: Arc<Counters>) {
+        counters.reopened();
+        counters.set_capturing(true);
+        self.counters = counters;
+    }
+    /// Asks the capture library for its statistics every `interval` once
+    /// started.
+    pub fn sample_stats(&mut self, interval: Duration) {
+        self.stats_interval = Some(interval);
+    }
     pub fn start(
         self,
     ) -> PacketInterface {
-        let interface = self.interface;
+        let RawsockInterface { interface, link, counters, stats_interval, .. } = self;
+        #[cfg(unix)]
+        let interface = match rawsock::AsyncInterface::new(interface) {
+            Ok(interface) => return Self::start_async(interface, link, counters, stats_interval),
+            Err((e, interface)) => {
+                log::debug!("Reading the interface from a thread: {:?}", e);
+                interface
+            }
+        };
+        let interface: Interface = Arc::from(interface);
         let (packet_sender, stream) = unbounded();
         let (sink, packet_receiver) = unbounded();
 
-        Self::start_thread(interface.clone(), packet_sender);
-        tokio::spawn(Self::run(interface, packet_receiver));
+        Self::start_thread(interface.clone(), link.clone(), packet_sender.clone(), counters.clone());
+        tokio::spawn(Self::run(interface, link, packet_receiver, packet_sender, counters.clone(), stats_interval));
+
+        PacketInterface {
+            sink,
+            stream,
+            counters,
+        }
+    }
+    /// Reads and writes the interface from the reactor, without a thread.
+    #[cfg(unix)]
+    fn start_async(
+        mut interface: rawsock::AsyncInterface<'static>,
+        link: LinkAdapter,
+        counters: Arc<Counters>,
+        stats_interval: Option<Duration>,
+    ) -> PacketInterface {
+        let (packet_sender, stream) = unbounded();
+        let (sink, packet_receiver) = unbounded::<Packet>();
+
+        let task_counters = counters.clone();
+        tokio::spawn(async move {
+            let counters = task_counters;
+            let mut ticks = tokio::time::interval(stats_interval.unwrap_or(Duration::from_secs(1)));
+            // frames fed to the interface are written while capture goes on
+            let mut flushing = false;
+            // one task owns the interface, so that its statistics can be read
+            loop {
+                let event = futures::future::poll_fn(|cx| {
+                    if flushing {
+                        if let Poll::Ready(r) = Sink::<Packet>::poll_flush(Pin::new(&mut interface), cx) {
+                            return Poll::Ready(AsyncEvent::Flushed(r));
+                        }
+                    }
+                    interface.poll_next_unpin(cx).map(AsyncEvent::Received)
+                });
+                tokio::select! {
+                    event = event => match event {
+                        AsyncEvent::Flushed(r) => {
+                            flushing = false;
+                            if let Err(e) = r {
+                                log::error!("Failed when sending packets {:?}", e);
+                            }
+                        }
+                        AsyncEvent::Received(Some(Ok(packet))) => {
+                            let frame = match link.ingress(&packet) {
+                                Some(frame) => frame,
+                                None => continue,
+                            };
+                            match packet_sender.try_send(frame) {
+                                Ok(()) => counters.queued(),
+                                Err(err) => log::warn!("recv error: {:?}", err),
+                            }
+                        }
+                        AsyncEvent::Received(Some(Err(err))) => {
+                            log::warn!("receive {:?}", err);
+                            counters.set_capturing(false);
+                            break;
+                        }
+                        AsyncEvent::Received(None) => {
+                            counters.set_capturing(false);
+                            break;
+                        }
+                    },
+                    first = packet_receiver.recv() => {
+                        let first = match first {
+                            Ok(first) => first,
+                            Err(_) => break,
+                        };
+                        let burst = egress_burst(&link, first, &packet_receiver, &packet_sender);
+                        if burst.is_empty() {
+                            continue;
+                        }
+                        counters.injected(burst.len());
+                        // queued here, the receive arm writes them as the socket takes them
+                        for frame in burst {
+                            if let Err(e) = Pin::new(&mut interface).start_send(frame) {
+                                log::error!("Failed when sending packets {:?}", e);
+                            }
+                        }
+                        flushing = true;
+                    }
+                    _ = ticks.tick(), if stats_interval.is_some() => {
+                        match interface.get_ref().stats() {
+                            Ok(stats) => counters.set_capture(stats),
+                            Err(e) => log::debug!("stats {:?}", e),
+                        }
+                    }
+                }
+            }
+            log::debug!("interface task exit");
+        });
 
         PacketInterface {
             sink,
             stream,
+            counters,
         }
     }
-    async fn run(interface: Interface, packet_receiver: Receiver<Packet>) {
-        while let Ok(data) = packet_receiver.recv().await {
-            if let Err(e) = interface.send(&data) {
-                log::error!("Failed when sending packet {:?}", e);
+    async fn run(
+        interface: Interface,
+        link: LinkAdapter,
+        packet_receiver: Receiver<Packet>,
+        reply_sender: Sender<Packet>,
+        counters: Arc<Counters>,
+        stats_interval: Option<Duration>,
+    ) {
+        let mut ticks = tokio::time::interval(stats_interval.unwrap_or(Duration::from_secs(1)));
+        loop {
+            tokio::select! {
+                first = packet_receiver.recv() => {
+                    let first = match first {
+                        Ok(first) => first,
+                        Err(_) => {
+                            // nobody reads the packets anymore, stop the receiving thread
+                            interface.break_loop();
+                            break;
+                        }
+                    };
+                    let burst = egress_burst(&link, first, &packet_receiver, &reply_sender);
+                    let batch: Vec<&[u8]> = burst.iter().map(|p| &p[..]).collect();
+                    if batch.is_empty() {
+                        continue;
+                    }
+                    counters.injected(batch.len());
+                    if let Err(e) = interface.send_batch(&batch) {
+                        log::error!("Failed when sending packets {:?}", e);
+                    }
+                }
+                _ = ticks.tick(), if stats_interval.is_some() => {
+                    match interface.stats() {
+                        Ok(stats) => counters.set_capture(stats),
+                        Err(e) => log::debug!("stats {:?}", e),
+                    }
+                }
             }
         }
     }
     fn start_thread(
         interface: Interface,
+        link: LinkAdapter,
         packet_sender: Sender<Packet>,
+        counters: Arc<Counters>,
     ) {
         thread::spawn(move || {
-            let r = interface.loop_infinite_dyn(&|packet| {
-                if let Err(err) = packet_sender.try_send(packet.to_vec()) {
-                    log::warn!("recv error: {:?}", err);
+            let r = interface.loop_infinite_dyn(&mut |packet| {
+                let frame = match link.ingress(packet) {
+                    Some(frame) => frame,
+                    None => return,
+                };
+                match packet_sender.try_send(frame) {
+                    Ok(()) => counters.queued(),
+                    Err(err) => log::warn!("recv error: {:?}", err),
                 }
             });
             if !r.is_ok() {
                 log::warn!("loop_infinite {:?}", r);
+                counters.set_capturing(false);
             }
             log::debug!("recv thread exit");
         });
@@ -152,39 +430,110 @@ pub struct RawsockInterfaceSet {
     lib: &'static Box<dyn Library>,
     all_interf: Vec<rawsock::InterfaceDescription>,
     filter: CString,
+    ip: Ipv4Cidr,
+    options: InterfaceOptions,
 }
 impl RawsockInterfaceSet {
     pub fn new(
         lib: &'static Box<dyn Library>,
         ip: Ipv4Cidr,
+        dhcp: bool,
+        options: InterfaceOptions,
+        capture_filter: Option<&str>,
     ) -> Result<RawsockInterfaceSet, rawsock::Error> {
-        let all_interf = lib.all_interfaces()?;
-        let filter = format!("net {}", ip.network());
+        let network = ip.network();
+        let mut subnet = Filter::net(network.address().0.into(), network.prefix_len());
+        if dhcp {
+            // DHCP clients have no address yet
+            subnet = subnet.or(Filter::Port(Some(Protocol::Udp), Endpoint::Dst, 67));
+        }
+        let filter = match capture_filter {
+            // the user's filter may use more of the pcap syntax than Filter parses
+            Some(user) => format!("({}) and ({})", subnet, user),
+            None => subnet.to_string(),
+        };
+        lib.compile_filter(&filter, DataLink::Ethernet)?;
         log::debug!("filter: {}", filter);
+        let all_interf = lib.all_interfaces()?;
         Ok(RawsockInterfaceSet {
             lib,
             all_interf,
             filter: CString::new(filter)?,
+            ip,
+            options,
         })
     }
-    pub fn open_all_interface(&self) -> (Vec<RawsockInterface>, Vec<ErrorWithDesc>) {
-        let all_interf = self.all_interf.clone();
-        let (opened, errored): (Vec<_>, _) = all_interf
-            .into_iter()
-            .map(|i| self.open_interface(i))
-            .partition(Result::is_ok);
-        (
-            opened.into_iter().map(Result::unwrap).collect::<Vec<_>>(),
-            errored
-                .into_iter()
-                .map(Result::unwrap_err)
-                .collect::<Vec<_>>(),
-        )
-    }
-    fn open_interface(
+    /// The gateway address, interfaces on its subnet are next to the consoles.
+    pub fn console_ip(&self) -> IpAddr {
+        Ipv4Addr::from(self.ip.address()).into()
+    }
+    /// Opens every interface once to tell which ones can be used, and why
+    /// the others can't.
+    pub fn list_interfaces(&self) -> Vec<InterfaceReport> {
+        self.all_interf
+            .iter()
+            .map(|desc| {
+                let mut report = InterfaceReport::new(desc, desc.is_on_subnet_of(self.console_ip()));
+                if desc.flags.contains(InterfaceFlags::LOOPBACK) {
+                    report.error = Some("the loopback interface is only used when passed as --netif".to_string());
+                    return report;
+                }
+                match self.open_interface(desc.clone()) {
+                    Ok(interface) => report.opened(&interface),
+                    Err(ErrorWithDesc(err, _)) => report.failed(&err),
+                }
+                report
+            })
+            .collect()
+    }
+    /// Lists the interfaces again, some may have come or gone since the set
+    /// was made.
+    pub fn current_interfaces(&self) -> Result<Vec<InterfaceDescription>, rawsock::Error> {
+        self.lib.all_interfaces()
+    }
+    pub fn open_interface(
         &self,
         mut desc: InterfaceDescription,
     ) -> Result<RawsockInterface, ErrorWithDesc> {
         RawsockInterface::new(self, &mut desc).map_err(|err| ErrorWithDesc(err, desc))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn capture() -> (PacketInterface, Sender<Packet>, Receiver<Packet>) {
+        let (to_capture, stream) = unbounded();
+        let (sink, from_capture) = unbounded();
+        (PacketInterface::new(sink, stream), to_capture, from_capture)
+    }
+
+    #[tokio::test]
+    async fn test_reattach() {
+        let (reattachable, stack) = Reattachable::new(Default::default());
+        let reattachable = Arc::new(reattachable);
+
+        let (first, received, sent) = capture();
+        let plugged = reattachable.clone();
+        let task = tokio::spawn(async move { plugged.attach(first).await });
+        received.send(vec![1]).await.unwrap();
+        assert_eq!(stack.stream.recv().await.unwrap(), vec![1]);
+        stack.sink.send(vec![2]).await.unwrap();
+        assert_eq!(sent.recv().await.unwrap(), vec![2]);
+
+        // the capture stops, the stack stays
+        drop(received);
+        task.await.unwrap();
+        stack.sink.send(vec![3]).await.unwrap();
+
+        let (second, received, sent) = capture();
+        let plugged = reattachable.clone();
+        tokio::spawn(async move { plugged.attach(second).await });
+        received.send(vec![4]).await.unwrap();
+        assert_eq!(stack.stream.recv().await.unwrap(), vec![4]);
+        stack.sink.send(vec![5]).await.unwrap();
+        // what was sent while nothing was attached is dropped
+        assert_eq!(sent.recv().await.unwrap(), vec![5]);
+    }
+}
@@ -0,0 +1,165 @@
+use crate::error::{Error, Result};
+use rawsock::traits::Library;
+use std::fmt;
+use std::str::FromStr;
+
+/// Packet capture library picked on the command line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CaptureLibrary {
+    /// wpcap, then pcap, then native sockets where there are any
+    Auto,
+    Pcap,
+    Wpcap,
+    Pfring,
+    /// AF_PACKET sockets, Linux only
+    Native,
+}
+
+#[derive(Debug)]
+pub struct ParseCaptureLibraryError(String);
+
+impl fmt::Display for ParseCaptureLibraryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid capture library {}, expected auto, pcap, wpcap, pfring or native",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseCaptureLibraryError {}
+
+impl FromStr for CaptureLibrary {
+    type Err = ParseCaptureLibraryError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(CaptureLibrary::Auto),
+            "pcap" => Ok(CaptureLibrary::Pcap),
+            "wpcap" => Ok(CaptureLibrary::Wpcap),
+            "pfring" => Ok(CaptureLibrary::Pfring),
+            "native" => Ok(CaptureLibrary::Native),
+            _ => Err(ParseCaptureLibraryError(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for CaptureLibrary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CaptureLibrary::Auto => "auto",
+            CaptureLibrary::Pcap => "pcap",
+            CaptureLibrary::Wpcap => "wpcap",
+            CaptureLibrary::Pfring => "pfring",
+            CaptureLibrary::Native => "native",
+        })
+    }
+}
+
+/// The message of `err` followed by the causes it doesn't already tell.
+fn error_chain(err: &dyn std::error::Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        let cause = err.to_string();
+        if !message.contains(&cause) {
+            message = format!("{}: {}", message, cause);
+        }
+        source = err.source();
+    }
+    message
+}
+
+/// Opens library `L` from `path`, or from its default paths, noting why
+/// each path failed in `tried`.
+fn open_paths<L>(name: &str, path: Option<&str>, tried: &mut Vec<String>) -> Option<Box<dyn Library>>
+where
+    L: Library + 'static,
+{
+    let paths = match path {
+        Some(path) => vec![path],
+        None => L::default_paths().to_vec(),
+    };
+    for path in paths {
+        match L::open(path) {
+            Ok(lib) => return Some(Box::new(lib)),
+            Err(err) => tried.push(format!("{} {}: {}", name, path, error_chain(&err))),
+        }
+    }
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn open_native(path: Option<&str>, tried: &mut Vec<String>) -> Option<Box<dyn Library>> {
+    if let Some(path) = path {
+        tried.push(format!("native {}: AF_PACKET sockets are not loaded from a path", path));
+        return None;
+    }
+    Some(Box::new(rawsock::af_packet::Library::with_ring(
+        rawsock::af_packet::RingOptions::default(),
+    )))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_native(_path: Option<&str>, tried: &mut Vec<String>) -> Option<Box<dyn Library>> {
+    tried.push("native: AF_PACKET sockets are only available on Linux".to_string());
+    None
+}
+
+/// Opens the capture library `kind`, from `path` if given.
+///
+/// The error lists every path that was tried and why it failed.
+pub fn open_library(kind: CaptureLibrary, path: Option<&str>) -> Result<Box<dyn Library>> {
+    use rawsock::{pcap, pfring, wpcap};
+
+    let mut tried = Vec::new();
+    let lib = match kind {
+        CaptureLibrary::Auto => {
+            let lib = open_paths::<wpcap::Library>("wpcap", path, &mut tried)
+                .or_else(|| open_paths::<pcap::Library>("pcap", path, &mut tried));
+            match lib {
+                // no libpcap, e.g. on OpenWrt
+                None if path.is_none() => open_native(None, &mut tried),
+                lib => lib,
+            }
+        }
+        CaptureLibrary::Pcap => open_paths::<pcap::Library>("pcap", path, &mut tried),
+        CaptureLibrary::Wpcap => open_paths::<wpcap::Library>("wpcap", path, &mut tried),
+        CaptureLibrary::Pfring => open_paths::<pfring::Library>("pfring", path, &mut tried),
+        CaptureLibrary::Native => open_native(path, &mut tried),
+    };
+    lib.ok_or(Error::NoLibrary(tried))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        for name in &["auto", "pcap", "wpcap", "pfring", "native"] {
+            let kind: CaptureLibrary = name.parse().unwrap();
+            assert_eq!(&kind.to_string(), name);
+        }
+        assert!("libpcap".parse::<CaptureLibrary>().is_err());
+    }
+
+    #[test]
+    fn test_lists_tried_paths() {
+        let path = "/nonexistent/libpcap.so";
+        let tried = match open_library(CaptureLibrary::Auto, Some(path)) {
+            Err(Error::NoLibrary(tried)) => tried,
+            other => panic!("unexpected result {:?}", other.map(|lib| lib.version())),
+        };
+        assert_eq!(tried.len(), 2);
+        assert!(tried[0].starts_with("wpcap /nonexistent/libpcap.so: "));
+        assert!(tried[1].starts_with("pcap /nonexistent/libpcap.so: "));
+        assert!(tried[1].contains("Could not open library"));
+
+        let err = open_library(CaptureLibrary::Pfring, Some(path)).err().unwrap();
+        let message = err.to_string();
+        assert!(message.starts_with("Could not open a packet capture library, tried:\n"));
+        assert!(message.contains("pfring /nonexistent/libpcap.so: "));
+    }
+}
@@ -0,0 +1,314 @@
+use super::Packet;
+use rawsock::DataLink;
+use smoltcp::wire::{EthernetAddress, EthernetFrame, EthernetProtocol};
+
+const ETHERNET_HEADER_LEN: usize = 14;
+const SLL_HEADER_LEN: usize = 16;
+const SLL2_HEADER_LEN: usize = 20;
+const LOOPBACK_HEADER_LEN: usize = 4;
+// sll_pkttype of packets sent by the host itself
+const PACKET_OUTGOING: u8 = 4;
+const ARP_LEN: usize = 28;
+
+/// MAC of the gateway on links that have no MAC addresses.
+pub const LINK_GATEWAY_MAC: EthernetAddress = EthernetAddress([0x02, 0x4c, 0x50, 0, 0, 1]);
+
+/// What to do with a frame the gateway sends.
+#[derive(Debug, PartialEq)]
+pub enum Egress {
+    /// Send the packet on the interface.
+    Send(Packet),
+    /// Answer the gateway with this frame, e.g. ARP replies on links without ARP.
+    Reply(Packet),
+    /// The frame can't be sent on this link.
+    Drop,
+}
+
+/// Translates between the link of an interface and the Ethernet frames of the gateway.
+///
+/// Peers on links without MAC addresses get a synthetic one made from their IP address,
+/// and ARP requests for them are answered here.
+#[derive(Debug, Clone)]
+pub struct LinkAdapter {
+    data_link: DataLink,
+    mac: EthernetAddress,
+}
+
+impl LinkAdapter {
+    /// Whether packets of `data_link` can be turned into Ethernet frames.
+    pub fn is_supported(data_link: DataLink) -> bool {
+        match data_link {
+            DataLink::Ethernet
+            | DataLink::RawIp
+            | DataLink::LinuxSll
+            | DataLink::LinuxSll2
+            | DataLink::Null
+            | DataLink::Loop => true,
+            _ => false,
+        }
+    }
+
+    /// Whether `data_link` is a cooked capture, which can't be sent on.
+    pub fn is_cooked(data_link: DataLink) -> bool {
+        match data_link {
+            DataLink::LinuxSll | DataLink::LinuxSll2 => true,
+            _ => false,
+        }
+    }
+
+    /// `mac` is the address of the gateway, which frames from the link are sent to.
+    pub fn new(data_link: DataLink, mac: EthernetAddress) -> LinkAdapter {
+        LinkAdapter { data_link, mac }
+    }
+
+    /// Turns a packet received on the link into an Ethernet frame.
+    pub fn ingress(&self, packet: &[u8]) -> Option<Packet> {
+        let (src, ethertype, payload) = match self.data_link {
+            DataLink::Ethernet => return Some(packet.to_vec()),
+            DataLink::RawIp => {
+                let ethertype = ip_ethertype(packet)?;
+                (None, ethertype, packet)
+            }
+            DataLink::Null | DataLink::Loop => {
+                // the address family has a different value on every system, trust the packet
+                let payload = packet.get(LOOPBACK_HEADER_LEN..)?;
+                (None, ip_ethertype(payload)?, payload)
+            }
+            DataLink::LinuxSll => {
+                let header = packet.get(..SLL_HEADER_LEN)?;
+                if header[1] == PACKET_OUTGOING {
+                    return None;
+                }
+                let src = link_address(header[5], &header[6..12]);
+                let ethertype = u16::from_be_bytes([header[14], header[15]]);
+                (src, ethertype.into(), &packet[SLL_HEADER_LEN..])
+            }
+            DataLink::LinuxSll2 => {
+                let header = packet.get(..SLL2_HEADER_LEN)?;
+                if header[10] == PACKET_OUTGOING {
+                    return None;
+                }
+                let src = link_address(header[11], &header[12..18]);
+                let ethertype = u16::from_be_bytes([header[0], header[1]]);
+                (src, ethertype.into(), &packet[SLL2_HEADER_LEN..])
+            }
+            _ => return None,
+        };
+        let src = match src {
+            Some(src) => src,
+            None => synthetic_mac(source_ip(ethertype, payload)?),
+        };
+        let mut buf = vec![0u8; ETHERNET_HEADER_LEN + payload.len()];
+        {
+            let mut frame = EthernetFrame::new_unchecked(&mut buf[..]);
+            frame.set_src_addr(src);
+            frame.set_dst_addr(self.mac);
+            frame.set_ethertype(ethertype);
+        }
+        buf[ETHERNET_HEADER_LEN..].copy_from_slice(payload);
+        Some(buf)
+    }
+
+    /// Turns a frame of the gateway into a packet for the link.
+    pub fn egress(&self, frame: &[u8]) -> Egress {
+        if let DataLink::Ethernet = self.data_link {
+            return Egress::Send(frame.to_vec());
+        }
+        let frame = match EthernetFrame::new_checked(frame) {
+            Ok(frame) => frame,
+            Err(_) => return Egress::Drop,
+        };
+        let payload = frame.payload();
+        match frame.ethertype() {
+            EthernetProtocol::Arp => match arp_reply(payload) {
+                Some(reply) => Egress::Reply(reply),
+                None => Egress::Drop,
+            },
+            EthernetProtocol::Ipv4 | EthernetProtocol::Ipv6 => {
+                let ipv6 = frame.ethertype() == EthernetProtocol::Ipv6;
+                match self.data_link {
+                    DataLink::RawIp => Egress::Send(payload.to_vec()),
+                    DataLink::Null => {
+                        Egress::Send(with_family(address_family(ipv6).to_ne_bytes(), payload))
+                    }
+                    DataLink::Loop => {
+                        Egress::Send(with_family(address_family(ipv6).to_be_bytes(), payload))
+                    }
+                    // cooked captures can't be written to
+                    _ => Egress::Drop,
+                }
+            }
+            _ => Egress::Drop,
+        }
+    }
+}
+
+fn ip_ethertype(packet: &[u8]) -> Option<EthernetProtocol> {
+    match packet.first()? >> 4 {
+        4 => Some(EthernetProtocol::Ipv4),
+        6 => Some(EthernetProtocol::Ipv6),
+        _ => None,
+    }
+}
+
+fn link_address(len: u8, addr: &[u8]) -> Option<EthernetAddress> {
+    if len == 6 {
+        Some(EthernetAddress::from_bytes(addr))
+    } else {
+        None
+    }
+}
+
+/// The last four bytes of the source address of an IP packet.
+fn source_ip(ethertype: EthernetProtocol, payload: &[u8]) -> Option<[u8; 4]> {
+    let range = match ethertype {
+        EthernetProtocol::Ipv4 => 12..16,
+        EthernetProtocol::Ipv6 => 20..24,
+        _ => return None,
+    };
+    let mut ip = [0u8; 4];
+    ip.copy_from_slice(payload.get(range)?);
+    Some(ip)
+}
+
+/// Locally administered MAC standing for a peer of a link without MAC addresses.
+fn synthetic_mac(ip: [u8; 4]) -> EthernetAddress {
+    EthernetAddress([0x02, 0x00, ip[0], ip[1], ip[2], ip[3]])
+}
+
+/// Answers an ARP request with the synthetic MAC of the requested address.
+fn arp_reply(arp: &[u8]) -> Option<Packet> {
+    let arp = arp.get(..ARP_LEN)?;
+    // Ethernet/IPv4 requests only
+    if arp[..8] != [0, 1, 0x08, 0x00, 6, 4, 0, 1] {
+        return None;
+    }
+    let sender_mac = EthernetAddress::from_bytes(&arp[8..14]);
+    let (sender_ip, target_ip) = (&arp[14..18], &arp[24..28]);
+    let mut target = [0u8; 4];
+    target.copy_from_slice(target_ip);
+    let target_mac = synthetic_mac(target);
+
+    let mut buf = vec![0u8; ETHERNET_HEADER_LEN + ARP_LEN];
+    {
+        let mut frame = EthernetFrame::new_unchecked(&mut buf[..]);
+        frame.set_src_addr(target_mac);
+        frame.set_dst_addr(sender_mac);
+        frame.set_ethertype(EthernetProtocol::Arp);
+    }
+    let reply = &mut buf[ETHERNET_HEADER_LEN..];
+    reply[..6].copy_from_slice(&arp[..6]);
+    reply[6..8].copy_from_slice(&[0, 2]);
+    reply[8..14].copy_from_slice(target_mac.as_bytes());
+    reply[14..18].copy_from_slice(target_ip);
+    reply[18..24].copy_from_slice(sender_mac.as_bytes());
+    reply[24..28].copy_from_slice(sender_ip);
+    Some(buf)
+}
+
+/// Value of AF_INET or AF_INET6 in loopback headers, which differs between systems.
+fn address_family(ipv6: bool) -> u32 {
+    if !ipv6 {
+        2
+    } else if cfg!(any(target_os = "macos", target_os = "ios")) {
+        30
+    } else if cfg!(target_os = "freebsd") {
+        28
+    } else {
+        24
+    }
+}
+
+fn with_family(family: [u8; 4], payload: &[u8]) -> Packet {
+    let mut buf = Vec::with_capacity(LOOPBACK_HEADER_LEN + payload.len());
+    buf.extend_from_slice(&family);
+    buf.extend_from_slice(payload);
+    buf
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const GATEWAY: EthernetAddress = EthernetAddress([0x02, 0, 0, 0, 0, 0xfe]);
+
+    fn ipv4_packet() -> Vec<u8> {
+        let mut packet = vec![0u8; 28];
+        packet[0] = 0x45;
+        packet[12..16].copy_from_slice(&[10, 13, 0, 2]);
+        packet[16..20].copy_from_slice(&[10, 13, 37, 1]);
+        packet
+    }
+
+    #[test]
+    fn test_raw_ip() {
+        let adapter = LinkAdapter::new(DataLink::RawIp, GATEWAY);
+        let packet = ipv4_packet();
+        let frame = adapter.ingress(&packet).unwrap();
+        {
+            let frame = EthernetFrame::new_checked(&frame[..]).unwrap();
+            assert_eq!(frame.src_addr(), EthernetAddress([0x02, 0, 10, 13, 0, 2]));
+            assert_eq!(frame.dst_addr(), GATEWAY);
+            assert_eq!(frame.ethertype(), EthernetProtocol::Ipv4);
+            assert_eq!(frame.payload(), &packet[..]);
+        }
+        assert_eq!(adapter.egress(&frame), Egress::Send(packet));
+        assert_eq!(adapter.ingress(&[0x10, 0]), None);
+    }
+
+    #[test]
+    fn test_linux_sll() {
+        let adapter = LinkAdapter::new(DataLink::LinuxSll, GATEWAY);
+        let mut packet = vec![0, 0, 0, 1, 0, 6, 0x02, 1, 2, 3, 4, 5, 0, 0, 0x08, 0x00];
+        packet.extend_from_slice(&ipv4_packet());
+        let frame = adapter.ingress(&packet).unwrap();
+        {
+            let frame = EthernetFrame::new_checked(&frame[..]).unwrap();
+            assert_eq!(frame.src_addr(), EthernetAddress([0x02, 1, 2, 3, 4, 5]));
+            assert_eq!(frame.payload(), &packet[SLL_HEADER_LEN..]);
+        }
+        assert_eq!(adapter.egress(&frame), Egress::Drop);
+
+        // our own packets come back on the any interface
+        packet[1] = PACKET_OUTGOING;
+        assert_eq!(adapter.ingress(&packet), None);
+    }
+
+    #[test]
+    fn test_loop() {
+        let adapter = LinkAdapter::new(DataLink::Loop, GATEWAY);
+        let mut packet = vec![0, 0, 0, 2];
+        packet.extend_from_slice(&ipv4_packet());
+        let frame = adapter.ingress(&packet).unwrap();
+        assert_eq!(adapter.egress(&frame), Egress::Send(packet));
+    }
+
+    #[test]
+    fn test_arp_reply() {
+        let adapter = LinkAdapter::new(DataLink::RawIp, GATEWAY);
+        let mut request = vec![0u8; ETHERNET_HEADER_LEN + ARP_LEN];
+        {
+            let mut frame = EthernetFrame::new_unchecked(&mut request[..]);
+            frame.set_src_addr(GATEWAY);
+            frame.set_dst_addr(EthernetAddress::BROADCAST);
+            frame.set_ethertype(EthernetProtocol::Arp);
+        }
+        let arp = &mut request[ETHERNET_HEADER_LEN..];
+        arp[..8].copy_from_slice(&[0, 1, 0x08, 0x00, 6, 4, 0, 1]);
+        arp[8..14].copy_from_slice(GATEWAY.as_bytes());
+        arp[14..18].copy_from_slice(&[10, 13, 37, 1]);
+        arp[24..28].copy_from_slice(&[10, 13, 0, 2]);
+
+        let reply = match adapter.egress(&request) {
+            Egress::Reply(reply) => reply,
+            other => panic!("unexpected {:?}", other),
+        };
+        let frame = EthernetFrame::new_checked(&reply[..]).unwrap();
+        assert_eq!(frame.dst_addr(), GATEWAY);
+        let arp = frame.payload();
+        assert_eq!(&arp[6..8], &[0, 2]);
+        assert_eq!(&arp[8..14], &[0x02, 0, 10, 13, 0, 2]);
+        assert_eq!(&arp[14..18], &[10, 13, 0, 2]);
+        assert_eq!(&arp[24..28], &[10, 13, 37, 1]);
+    }
+}
@@ -0,0 +1,228 @@
+use super::{Error, RawsockInterface};
+use crate::interface_info::{get_interface_info, InterfaceInfo, OperState};
+use rawsock::{DataLink, InterfaceAddress, InterfaceDescription};
+use smoltcp::wire::EthernetAddress;
+use std::fmt;
+use std::net::IpAddr;
+
+/// What `lan-play list` tells about one interface.
+#[derive(Debug, Clone)]
+pub struct InterfaceReport {
+    pub name: String,
+    pub description: String,
+    pub mac: Option<EthernetAddress>,
+    pub data_link: Option<DataLink>,
+    pub addresses: Vec<InterfaceAddress>,
+    pub on_console_subnet: bool,
+    pub mtu: Option<u32>,
+    pub oper_state: Option<OperState>,
+    pub driver: Option<String>,
+    pub wireless: bool,
+    /// Why lan-play can't use the interface
+    pub error: Option<String>,
+}
+
+impl InterfaceReport {
+    pub(super) fn new(desc: &InterfaceDescription, on_console_subnet: bool) -> InterfaceReport {
+        InterfaceReport {
+            name: desc.name.clone(),
+            description: desc.description.clone(),
+            mac: desc.hardware_address.map(EthernetAddress),
+            data_link: None,
+            addresses: desc.addresses.clone(),
+            on_console_subnet,
+            mtu: None,
+            oper_state: None,
+            driver: None,
+            wireless: false,
+            error: None,
+        }
+    }
+    pub(super) fn opened(&mut self, interface: &RawsockInterface) {
+        self.description = interface.desc.description.clone();
+        self.mac = Some(*interface.mac());
+        self.data_link = Some(interface.data_link());
+        if let Some(info) = interface.info() {
+            self.set_info(info);
+        }
+    }
+    pub(super) fn failed(&mut self, err: &Error) {
+        self.error = Some(err.to_string());
+        if let Ok(info) = get_interface_info(&self.name) {
+            if self.mac.is_none() {
+                self.mac = info.ethernet_address;
+            }
+            self.set_info(&info);
+        }
+    }
+    fn set_info(&mut self, info: &InterfaceInfo) {
+        self.mtu = info.mtu;
+        self.oper_state = info.oper_state;
+        self.driver = info.driver.clone();
+        self.wireless = info.wireless;
+    }
+    pub fn is_opened(&self) -> bool {
+        self.error.is_none()
+    }
+    pub fn to_json(&self) -> String {
+        let string_or_null = |s: Option<String>| {
+            s.map(|s| json_string(&s)).unwrap_or_else(|| "null".to_string())
+        };
+        let addresses = self
+            .addresses
+            .iter()
+            .map(|a| json_string(&format_address(a)))
+            .collect::<Vec<_>>();
+        format!(
+            "{{\"name\":{},\"description\":{},\"mac\":{},\"data_link\":{},\"addresses\":[{}],\
+             \"on_console_subnet\":{},\"mtu\":{},\"oper_state\":{},\"driver\":{},\"wireless\":{},\
+             \"opened\":{},\"error\":{}}}",
+            json_string(&self.name),
+            json_string(&self.description),
+            string_or_null(self.mac.map(|mac| mac.to_string())),
+            string_or_null(self.data_link.map(|data_link| data_link.to_string())),
+            addresses.join(","),
+            self.on_console_subnet,
+            self.mtu.map(|mtu| mtu.to_string()).unwrap_or_else(|| "null".to_string()),
+            string_or_null(self.oper_state.map(|state| state.to_string())),
+            string_or_null(self.driver.clone()),
+            self.wireless,
+            self.is_opened(),
+            string_or_null(self.error.clone()),
+        )
+    }
+}
+
+impl fmt::Display for InterfaceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unknown = || "unknown".to_string();
+        writeln!(f, "{}: {}", self.name, self.description)?;
+        writeln!(
+            f,
+            "    mac {}, data link {}",
+            self.mac.map(|mac| mac.to_string()).unwrap_or_else(unknown),
+            self.data_link.map(|data_link| data_link.to_string()).unwrap_or_else(unknown),
+        )?;
+        writeln!(
+            f,
+            "    mtu {}, link {}, driver {}{}",
+            self.mtu.map(|mtu| mtu.to_string()).unwrap_or_else(unknown),
+            self.oper_state.map(|state| state.to_string()).unwrap_or_else(unknown),
+            self.driver.clone().unwrap_or_else(unknown),
+            if self.wireless { ", wireless" } else { "" },
+        )?;
+        if self.addresses.is_empty() {
+            writeln!(f, "    no addresses")?;
+        } else {
+            let addresses = self.addresses.iter().map(format_address).collect::<Vec<_>>();
+            writeln!(f, "    addresses {}", addresses.join(", "))?;
+        }
+        match &self.error {
+            None if self.on_console_subnet => write!(f, "    opened, on the consoles' subnet"),
+            None => write!(f, "    opened"),
+            Some(error) => write!(f, "    not usable: {}", error),
+        }
+    }
+}
+
+/// The reports as a JSON array.
+pub fn reports_to_json(reports: &[InterfaceReport]) -> String {
+    let reports = reports.iter().map(InterfaceReport::to_json).collect::<Vec<_>>();
+    format!("[{}]", reports.join(","))
+}
+
+/// `address/prefix`, or the bare address without a netmask.
+fn format_address(address: &InterfaceAddress) -> String {
+    let prefix = match address.netmask {
+        Some(IpAddr::V4(mask)) => u32::from(mask).count_ones(),
+        Some(IpAddr::V6(mask)) => u128::from(mask).count_ones(),
+        None => return address.address.to_string(),
+    };
+    format!("{}/{}", address.address, prefix)
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn report() -> InterfaceReport {
+        InterfaceReport {
+            name: "eth0".to_string(),
+            description: "Card \"1\"\\".to_string(),
+            mac: Some(EthernetAddress([2, 0, 0, 0, 0, 1])),
+            data_link: Some(DataLink::Ethernet),
+            addresses: vec![InterfaceAddress {
+                address: "10.13.0.1".parse().unwrap(),
+                netmask: Some("255.255.0.0".parse().unwrap()),
+                broadcast: None,
+                destination: None,
+            }],
+            on_console_subnet: true,
+            mtu: Some(1500),
+            oper_state: Some(OperState::Up),
+            driver: Some("e1000e".to_string()),
+            wireless: false,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_text() {
+        let mut report = report();
+        assert_eq!(
+            report.to_string(),
+            "eth0: Card \"1\"\\\n    mac 02-00-00-00-00-01, data link ethernet\n    \
+             mtu 1500, link up, driver e1000e\n    \
+             addresses 10.13.0.1/16\n    opened, on the consoles' subnet"
+        );
+        report.addresses.clear();
+        report.data_link = None;
+        report.mtu = None;
+        report.oper_state = None;
+        report.driver = None;
+        report.wireless = true;
+        report.error = Some("WrongDataLink Other".to_string());
+        assert!(report.to_string().ends_with(
+            "data link unknown\n    mtu unknown, link unknown, driver unknown, wireless\n    \
+             no addresses\n    not usable: WrongDataLink Other"
+        ));
+    }
+
+    #[test]
+    fn test_json() {
+        let mut failed = report();
+        failed.mac = None;
+        failed.oper_state = Some(OperState::Down);
+        failed.driver = None;
+        failed.error = Some("line\nbreak".to_string());
+        assert_eq!(
+            reports_to_json(&[report(), failed]),
+            "[{\"name\":\"eth0\",\"description\":\"Card \\\"1\\\"\\\\\",\"mac\":\"02-00-00-00-00-01\",\
+             \"data_link\":\"ethernet\",\"addresses\":[\"10.13.0.1/16\"],\"on_console_subnet\":true,\
+             \"mtu\":1500,\"oper_state\":\"up\",\"driver\":\"e1000e\",\"wireless\":false,\
+             \"opened\":true,\"error\":null},\
+             {\"name\":\"eth0\",\"description\":\"Card \\\"1\\\"\\\\\",\"mac\":null,\
+             \"data_link\":\"ethernet\",\"addresses\":[\"10.13.0.1/16\"],\"on_console_subnet\":true,\
+             \"mtu\":1500,\"oper_state\":\"down\",\"driver\":null,\"wireless\":false,\
+             \"opened\":false,\"error\":\"line\\nbreak\"}]"
+        );
+    }
+}
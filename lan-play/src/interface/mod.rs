@@ -1,5 +1,22 @@
 mod error;
 mod interface;
+mod library;
+mod link;
+mod list;
+mod rescan;
+mod stats;
+#[cfg(target_os = "linux")]
+mod tap;
+#[cfg(target_os = "linux")]
+mod watch;
 
 pub use error::{Error, ErrorWithDesc};
-pub use interface::{RawsockInterface, RawsockInterfaceSet, Packet, PacketInterface};
+pub use library::{open_library, CaptureLibrary};
+pub use interface::{RawsockInterface, RawsockInterfaceSet, Packet, PacketInterface, Reattachable};
+pub use list::reports_to_json;
+pub use rescan::{link_state, plan_rescan, settle_rescan, Captured, Closing, LinkState};
+pub use stats::{collect_stats, CounterList, Counters};
+#[cfg(target_os = "linux")]
+pub use tap::{TapInterface, TunInterface};
+#[cfg(target_os = "linux")]
+pub use watch::watch_links;
@@ -0,0 +1,244 @@
+use rawsock::{ConnectionStatus, InterfaceDescription, InterfaceFlags};
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+/// Flags and connection of a listed interface. An interface that couldn't be
+/// used is only tried again once they change.
+pub type LinkState = (InterfaceFlags, ConnectionStatus);
+
+pub fn link_state(desc: &InterfaceDescription) -> LinkState {
+    (desc.flags, desc.connection)
+}
+
+/// What a rescan needs to know about an interface captured on.
+#[derive(Debug, Clone, Copy)]
+pub struct Captured {
+    pub on_console_subnet: bool,
+    pub capturing: bool,
+}
+
+/// Why an interface captured on is closed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Closing {
+    Gone,
+    StoppedCapturing,
+}
+
+/// What to do with the interfaces after listing them.
+#[derive(Debug, Default, PartialEq)]
+pub struct Plan {
+    /// Interfaces captured on to close
+    pub close: Vec<(String, Closing)>,
+    /// Interfaces to open
+    pub open: Vec<String>,
+    /// Whether an interface still captured on is on the consoles' subnet
+    pub on_console_subnet: bool,
+}
+
+/// Which of the opened interfaces to keep.
+#[derive(Debug, PartialEq)]
+pub struct Settled {
+    pub keep: Vec<String>,
+    /// The interfaces captured on before are closed, the consoles are on
+    /// another one
+    pub close_captured: bool,
+}
+
+/// Decides what to close and what to open from the listed interfaces
+/// `all_interf`, those `captured` on and those that `failed` before.
+///
+/// Failed interfaces that are no longer listed are forgotten, so that they
+/// are tried again when they come back.
+pub fn plan_rescan(
+    all_interf: &[InterfaceDescription],
+    captured: &HashMap<String, Captured>,
+    failed: &mut HashMap<String, LinkState>,
+    netif: Option<&str>,
+    console_ip: IpAddr,
+) -> Plan {
+    let listed = |name: &str| all_interf.iter().any(|desc| desc.name == name);
+    failed.retain(|name, _| listed(name));
+
+    let mut plan = Plan::default();
+    for (name, state) in captured {
+        if !listed(name) {
+            plan.close.push((name.clone(), Closing::Gone));
+        } else if !state.capturing {
+            plan.close.push((name.clone(), Closing::StoppedCapturing));
+        } else {
+            plan.on_console_subnet |= state.on_console_subnet;
+        }
+    }
+    plan.close.sort_by(|a, b| a.0.cmp(&b.0));
+
+    for desc in all_interf {
+        let kept = captured.get(&desc.name).map_or(false, |state| state.capturing);
+        // consoles are only behind the loopback interface when it is asked for
+        if kept
+            || (desc.flags.contains(InterfaceFlags::LOOPBACK) && netif.is_none())
+            || netif.map_or(false, |netif| netif != desc.name)
+            || (plan.on_console_subnet && !desc.is_on_subnet_of(console_ip))
+            || failed.get(&desc.name) == Some(&link_state(desc))
+        {
+            continue;
+        }
+        plan.open.push(desc.name.clone());
+    }
+    plan
+}
+
+/// Once the interfaces of `plan` are `opened`: an interface with an address
+/// next to the consoles is the one they use, the others are closed.
+pub fn settle_rescan(
+    plan: &Plan,
+    opened: &[&InterfaceDescription],
+    netif: Option<&str>,
+    console_ip: IpAddr,
+) -> Settled {
+    let names = |on_subnet_only: bool| {
+        opened
+            .iter()
+            .filter(|desc| !on_subnet_only || desc.is_on_subnet_of(console_ip))
+            .map(|desc| desc.name.clone())
+            .collect()
+    };
+    if netif.is_some() || !opened.iter().any(|desc| desc.is_on_subnet_of(console_ip)) {
+        return Settled {
+            keep: names(false),
+            close_captured: false,
+        };
+    }
+    Settled {
+        keep: names(true),
+        close_captured: !plan.on_console_subnet,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rawsock::InterfaceAddress;
+
+    const CONSOLE_IP: &str = "10.13.37.1";
+
+    fn desc(name: &str, address: Option<&str>) -> InterfaceDescription {
+        InterfaceDescription {
+            name: name.to_string(),
+            description: String::new(),
+            addresses: address
+                .map(|address| InterfaceAddress {
+                    address: address.parse().unwrap(),
+                    netmask: Some("255.255.0.0".parse().unwrap()),
+                    broadcast: None,
+                    destination: None,
+                })
+                .into_iter()
+                .collect(),
+            hardware_address: None,
+            flags: InterfaceFlags::UP | InterfaceFlags::RUNNING,
+            connection: ConnectionStatus::Connected,
+        }
+    }
+
+    fn capturing(names: &[(&str, bool, bool)]) -> HashMap<String, Captured> {
+        names
+            .iter()
+            .map(|(name, on_console_subnet, capturing)| {
+                let state = Captured {
+                    on_console_subnet: *on_console_subnet,
+                    capturing: *capturing,
+                };
+                (name.to_string(), state)
+            })
+            .collect()
+    }
+
+    fn plan_for(
+        all_interf: &[InterfaceDescription],
+        captured: &HashMap<String, Captured>,
+        failed: &mut HashMap<String, LinkState>,
+    ) -> Plan {
+        plan_rescan(all_interf, captured, failed, None, CONSOLE_IP.parse().unwrap())
+    }
+
+    #[test]
+    fn test_gone() {
+        let all_interf = [desc("eth0", None)];
+        let captured = capturing(&[("eth0", false, true), ("usb0", false, true)]);
+        let plan = plan_for(&all_interf, &captured, &mut HashMap::new());
+        assert_eq!(plan.close, vec![("usb0".to_string(), Closing::Gone)]);
+        assert!(plan.open.is_empty());
+    }
+
+    #[test]
+    fn test_stopped_capturing() {
+        let all_interf = [desc("eth0", None), desc("wlan0", None)];
+        let captured = capturing(&[("eth0", false, false), ("wlan0", false, true)]);
+        let plan = plan_for(&all_interf, &captured, &mut HashMap::new());
+        assert_eq!(plan.close, vec![("eth0".to_string(), Closing::StoppedCapturing)]);
+        assert_eq!(plan.open, vec!["eth0".to_string()]);
+    }
+
+    #[test]
+    fn test_came_back() {
+        let connected = desc("eth0", None);
+        let mut unplugged = connected.clone();
+        unplugged.flags = InterfaceFlags::UP;
+        unplugged.connection = ConnectionStatus::Disconnected;
+        let mut failed = HashMap::new();
+        failed.insert("eth0".to_string(), link_state(&unplugged));
+        let captured = HashMap::new();
+
+        // it failed in this state, it isn't opened again
+        assert!(plan_for(&[unplugged.clone()], &captured, &mut failed).open.is_empty());
+        // the link state changed
+        assert_eq!(plan_for(&[connected], &captured, &mut failed).open, vec!["eth0".to_string()]);
+        // it went away and came back
+        assert!(plan_for(&[], &captured, &mut failed).open.is_empty());
+        assert!(failed.is_empty());
+        assert_eq!(plan_for(&[unplugged], &captured, &mut failed).open, vec!["eth0".to_string()]);
+    }
+
+    #[test]
+    fn test_console_subnet_appears() {
+        let console_ip = CONSOLE_IP.parse().unwrap();
+        let eth0 = desc("eth0", Some("10.13.0.5"));
+        let all_interf = [desc("wlan0", Some("192.168.1.5")), eth0.clone()];
+        let captured = capturing(&[("wlan0", false, true)]);
+        let plan = plan_for(&all_interf, &captured, &mut HashMap::new());
+        assert!(plan.close.is_empty());
+        assert_eq!(plan.open, vec!["eth0".to_string()]);
+        assert_eq!(
+            settle_rescan(&plan, &[&eth0], None, console_ip),
+            Settled {
+                keep: vec!["eth0".to_string()],
+                close_captured: true,
+            }
+        );
+        // not when the interface is asked for
+        assert!(!settle_rescan(&plan, &[&eth0], Some("eth0"), console_ip).close_captured);
+
+        // once captured on, the other interfaces aren't opened
+        let captured = capturing(&[("eth0", true, true)]);
+        let all_interf = [desc("wlan0", Some("192.168.1.5")), eth0, desc("usb0", None)];
+        let plan = plan_for(&all_interf, &captured, &mut HashMap::new());
+        assert!(plan.open.is_empty());
+    }
+
+    #[test]
+    fn test_loopback_and_netif() {
+        let mut lo = desc("lo", Some("127.0.0.1"));
+        lo.flags |= InterfaceFlags::LOOPBACK;
+        let all_interf = [lo, desc("eth0", None)];
+        let captured = HashMap::new();
+        assert_eq!(plan_for(&all_interf, &captured, &mut HashMap::new()).open, vec!["eth0".to_string()]);
+        let plan = plan_rescan(
+            &all_interf,
+            &captured,
+            &mut HashMap::new(),
+            Some("lo"),
+            CONSOLE_IP.parse().unwrap(),
+        );
+        assert_eq!(plan.open, vec!["lo".to_string()]);
+    }
+}
@@ -0,0 +1,190 @@
+use rawsock::Stats;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Interfaces whose counters are logged, growing as interfaces are attached.
+pub type CounterList = Arc<Mutex<Vec<(String, Arc<Counters>)>>>;
+
+/// Packets of one interface counted by lan-play, next to the last
+/// statistics of the capture library.
+#[derive(Debug, Default)]
+pub struct Counters {
+    capture: Mutex<Option<Stats>>,
+    /// Capture statistics of the handles closed before, the library counts
+    /// from zero again on every handle
+    closed: Mutex<Option<Stats>>,
+    capturing: AtomicBool,
+    queued: AtomicU64,
+    filtered: AtomicU64,
+    injected: AtomicU64,
+}
+
+impl Counters {
+    /// A captured frame was handed to the gateway.
+    pub fn queued(&self) {
+        self.queued.fetch_add(1, Ordering::Relaxed);
+    }
+    /// A frame was dropped by `filter_bad_packet`.
+    pub fn filtered(&self) {
+        self.filtered.fetch_add(1, Ordering::Relaxed);
+    }
+    /// `count` frames of the gateway were handed to the capture library.
+    pub fn injected(&self, count: usize) {
+        self.injected.fetch_add(count as u64, Ordering::Relaxed);
+    }
+    /// Whether packets are still captured, false once capturing failed.
+    pub fn is_capturing(&self) -> bool {
+        self.capturing.load(Ordering::Relaxed)
+    }
+    pub fn set_capturing(&self, capturing: bool) {
+        self.capturing.store(capturing, Ordering::Relaxed);
+    }
+    pub fn set_capture(&self, stats: Stats) {
+        *self.capture.lock().unwrap() = Some(stats);
+    }
+    /// The interface is captured on through a new handle, whose statistics
+    /// are added to the last ones of the previous handle.
+    pub fn reopened(&self) {
+        if let Some(last) = self.capture.lock().unwrap().take() {
+            let mut closed = self.closed.lock().unwrap();
+            *closed = Some(add_stats(closed.unwrap_or_default(), last));
+        }
+    }
+    pub fn snapshot(&self) -> Snapshot {
+        let capture = match (*self.closed.lock().unwrap(), *self.capture.lock().unwrap()) {
+            (Some(closed), Some(capture)) => Some(add_stats(closed, capture)),
+            (closed, capture) => closed.or(capture),
+        };
+        Snapshot {
+            capture,
+            queued: self.queued.load(Ordering::Relaxed),
+            filtered: self.filtered.load(Ordering::Relaxed),
+            injected: self.injected.load(Ordering::Relaxed),
+        }
+    }
+}
+
+fn add_stats(a: Stats, b: Stats) -> Stats {
+    Stats {
+        received: a.received + b.received,
+        dropped: a.dropped + b.dropped,
+        if_dropped: a.if_dropped + b.if_dropped,
+        sent: a.sent + b.sent,
+        send_errors: a.send_errors + b.send_errors,
+        shunted: a.shunted + b.shunted,
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Snapshot {
+    /// `None` until the capture library was asked
+    pub capture: Option<Stats>,
+    pub queued: u64,
+    pub filtered: u64,
+    pub injected: u64,
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let capture = self.capture.unwrap_or_default();
+        write!(
+            f,
+            "captured {} (dropped {} by the capture, {} by the interface), \
+             queued {}, filtered {}, injected {} (sent {}, {} errors)",
+            capture.received,
+            capture.dropped,
+            capture.if_dropped,
+            self.queued,
+            self.filtered,
+            self.injected,
+            capture.sent,
+            capture.send_errors,
+        )?;
+        if capture.shunted > 0 {
+            write!(f, ", shunted {}", capture.shunted)?;
+        }
+        Ok(())
+    }
+}
+
+/// Logs the counters of the interfaces every `interval`, until nobody else
+/// holds the list.
+pub async fn collect_stats(interfaces: CounterList, interval: Duration) {
+    let mut ticks = tokio::time::interval(interval);
+    // the first tick is immediate, nothing was counted yet
+    ticks.tick().await;
+    loop {
+        ticks.tick().await;
+        if Arc::strong_count(&interfaces) == 1 {
+            break;
+        }
+        for (name, counters) in interfaces.lock().unwrap().iter() {
+            log::info!("{}: {}", name, counters.snapshot());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_snapshot() {
+        let counters = Counters::default();
+        counters.queued();
+        counters.queued();
+        counters.filtered();
+        counters.injected(3);
+        assert_eq!(
+            counters.snapshot().to_string(),
+            "captured 0 (dropped 0 by the capture, 0 by the interface), \
+             queued 2, filtered 1, injected 3 (sent 0, 0 errors)"
+        );
+
+        counters.set_capture(Stats {
+            received: 10,
+            dropped: 4,
+            if_dropped: 1,
+            sent: 2,
+            send_errors: 1,
+            shunted: 5,
+        });
+        let snapshot = counters.snapshot();
+        assert_eq!(snapshot.capture.unwrap().received, 10);
+        assert_eq!(
+            snapshot.to_string(),
+            "captured 10 (dropped 4 by the capture, 1 by the interface), \
+             queued 2, filtered 1, injected 3 (sent 2, 1 errors), shunted 5"
+        );
+    }
+
+    #[test]
+    fn test_reopened() {
+        let counters = Counters::default();
+        counters.reopened();
+        assert_eq!(counters.snapshot().capture, None);
+
+        let stats = Stats {
+            received: 10,
+            sent: 2,
+            ..Stats::default()
+        };
+        counters.set_capture(stats);
+        counters.queued();
+        counters.reopened();
+        // nothing counted on the new handle yet
+        assert_eq!(counters.snapshot().capture.unwrap().received, 10);
+
+        counters.set_capture(Stats {
+            received: 3,
+            ..Stats::default()
+        });
+        counters.queued();
+        let snapshot = counters.snapshot();
+        assert_eq!(snapshot.capture.unwrap().received, 13);
+        assert_eq!(snapshot.capture.unwrap().sent, 2);
+        assert_eq!(snapshot.queued, 2);
+    }
+}
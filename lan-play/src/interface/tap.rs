@@ -0,0 +1,226 @@
+use super::{Packet, PacketInterface};
+use async_channel::{unbounded, Receiver, Sender};
+use nix::libc::{c_char, c_int, c_short, IFF_UP, IFNAMSIZ};
+use nix::sys::socket::{socket, AddressFamily, SockFlag, SockType};
+use nix::unistd::close;
+use smoltcp::wire::EthernetAddress;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::sync::Arc;
+use std::thread;
+
+const IFF_TUN: c_short = 0x0001;
+const IFF_TAP: c_short = 0x0002;
+const IFF_NO_PI: c_short = 0x1000;
+const TUNSETIFF: u8 = 202;
+
+#[repr(C)]
+struct IfReq {
+    name: [c_char; IFNAMSIZ],
+    flags: c_short,
+    _pad: [u8; 22],
+}
+
+impl IfReq {
+    fn new(name: &str) -> io::Result<IfReq> {
+        if name.len() >= IFNAMSIZ {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "interface name too long"));
+        }
+        let mut req = IfReq {
+            name: [0; IFNAMSIZ],
+            flags: 0,
+            _pad: [0; 22],
+        };
+        for (dst, src) in req.name.iter_mut().zip(name.bytes()) {
+            *dst = src as c_char;
+        }
+        Ok(req)
+    }
+    /// The request for TUNSETIFF, without the packet information header.
+    fn device(name: &str, flags: c_short) -> io::Result<IfReq> {
+        let mut req = IfReq::new(name)?;
+        req.flags = flags | IFF_NO_PI;
+        Ok(req)
+    }
+    fn name(&self) -> String {
+        let bytes = self.name.iter().take_while(|c| **c != 0).map(|c| *c as u8).collect::<Vec<_>>();
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+}
+
+nix::ioctl_write_ptr_bad!(tun_set_iff, nix::request_code_write!(b'T', TUNSETIFF, std::mem::size_of::<c_int>()), IfReq);
+nix::ioctl_readwrite_bad!(get_if_flags, nix::libc::SIOCGIFFLAGS, IfReq);
+nix::ioctl_write_ptr_bad!(set_if_flags, nix::libc::SIOCSIFFLAGS, IfReq);
+
+fn nix_to_io(e: nix::Error) -> io::Error {
+    match e.as_errno() {
+        Some(errno) => io::Error::from_raw_os_error(errno as i32),
+        None => io::Error::new(io::ErrorKind::Other, e),
+    }
+}
+
+/// Sets the link of `name` administratively up.
+fn set_link_up(name: &str) -> io::Result<()> {
+    let fd = socket(AddressFamily::Inet, SockType::Datagram, SockFlag::SOCK_CLOEXEC, None)
+        .map_err(nix_to_io)?;
+    let mut req = IfReq::new(name)?;
+    let r = unsafe {
+        get_if_flags(fd, &mut req).and_then(|_| {
+            req.flags |= IFF_UP as c_short;
+            set_if_flags(fd, &req)
+        })
+    };
+    let _ = close(fd);
+    r.map(|_| ()).map_err(nix_to_io)
+}
+
+/// Creates (or attaches to) a tun/tap device and brings it up.
+/// An empty name lets the kernel pick one.
+fn open_device(name: &str, flags: c_short) -> io::Result<(File, String)> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/net/tun")?;
+    let req = IfReq::device(name, flags)?;
+    unsafe { tun_set_iff(file.as_raw_fd(), &req) }.map_err(nix_to_io)?;
+    let name = req.name();
+    set_link_up(&name)?;
+    Ok((file, name))
+}
+
+fn start_device(file: File) -> PacketInterface {
+    let file = Arc::new(file);
+    let (packet_sender, stream) = unbounded();
+    let (sink, packet_receiver) = unbounded();
+
+    start_thread(file.clone(), packet_sender);
+    start_write_thread(file, packet_receiver);
+
+    PacketInterface::new(sink, stream)
+}
+
+/// Writes block, so they don't run on the runtime either.
+fn start_write_thread(file: Arc<File>, packet_receiver: Receiver<Packet>) {
+    thread::spawn(move || {
+        while let Ok(data) = packet_receiver.recv_blocking() {
+            if let Err(e) = (&*file).write(&data) {
+                log::error!("Failed when writing to tun/tap {:?}", e);
+            }
+        }
+        log::debug!("tun/tap write thread exit");
+    });
+}
+
+fn start_thread(file: Arc<File>, packet_sender: Sender<Packet>) {
+    thread::spawn(move || {
+        let mut buf = vec![0u8; 65536];
+        loop {
+            match (&*file).read(&mut buf) {
+                Ok(size) => {
+                    if let Err(err) = packet_sender.try_send(buf[..size].to_vec()) {
+                        log::warn!("recv error: {:?}", err);
+                        break;
+                    }
+                }
+                Err(e) => {
+                    log::warn!("tun/tap read {:?}", e);
+                    break;
+                }
+            }
+        }
+        log::debug!("tun/tap thread exit");
+    });
+}
+
+/// A Linux TAP device. The kernel side behaves like a NIC the host can
+/// bridge, and we act as the machine plugged into it.
+pub struct TapInterface {
+    name: String,
+    mac: EthernetAddress,
+    file: File,
+}
+
+impl TapInterface {
+    /// Creates (or attaches to) the TAP device `name` and brings it up.
+    /// An empty name lets the kernel pick one, e.g. `tap0`.
+    pub fn open(name: &str, mac: EthernetAddress) -> io::Result<TapInterface> {
+        let (file, name) = open_device(name, IFF_TAP)?;
+        Ok(TapInterface {
+            name,
+            mac,
+            file,
+        })
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn mac(&self) -> &EthernetAddress {
+        &self.mac
+    }
+    pub fn start(self) -> PacketInterface {
+        start_device(self.file)
+    }
+}
+
+/// A Linux TUN device carrying bare IPv4 packets, used as the host side of
+/// NAT mode. Addresses and routes are left to the administrator.
+pub struct TunInterface {
+    name: String,
+    file: File,
+}
+
+impl TunInterface {
+    /// Creates (or attaches to) the TUN device `name` and brings it up.
+    /// An empty name lets the kernel pick one, e.g. `tun0`.
+    pub fn open(name: &str) -> io::Result<TunInterface> {
+        let (file, name) = open_device(name, IFF_TUN)?;
+        Ok(TunInterface {
+            name,
+            file,
+        })
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn start(self) -> PacketInterface {
+        start_device(self.file)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ifreq_layout() {
+        // struct ifreq: the name followed by a union of at most 24 bytes
+        assert_eq!(std::mem::size_of::<IfReq>(), IFNAMSIZ + 24);
+    }
+
+    #[test]
+    fn test_ifreq_name() {
+        let req = IfReq::new("tap0").unwrap();
+        assert_eq!(req.name(), "tap0");
+        assert_eq!(req.flags, 0);
+        assert_eq!(req.name[4], 0);
+
+        // the kernel picks the name
+        assert_eq!(IfReq::new("").unwrap().name(), "");
+
+        // room is left for the terminating nul
+        let longest = "a".repeat(IFNAMSIZ - 1);
+        assert_eq!(IfReq::new(&longest).unwrap().name(), longest);
+        let err = IfReq::new(&"a".repeat(IFNAMSIZ)).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_ifreq_device_flags() {
+        let tap = IfReq::device("tap0", IFF_TAP).unwrap();
+        assert_eq!(tap.flags, 0x1002);
+        assert_eq!(tap.name(), "tap0");
+        let tun = IfReq::device("", IFF_TUN).unwrap();
+        assert_eq!(tun.flags, 0x1001);
+    }
+}
@@ -0,0 +1,43 @@
+use async_channel::Sender;
+use nix::errno::Errno;
+use nix::libc::{RTMGRP_IPV4_IFADDR, RTMGRP_IPV6_IFADDR, RTMGRP_LINK};
+use nix::sys::socket::{bind, recv, socket, AddressFamily, MsgFlags, NetlinkAddr, SockAddr, SockFlag, SockType};
+use nix::unistd::close;
+use std::thread;
+
+/// Notifies `changes` whenever a link or an address is added, removed or
+/// changed, until the receiver is dropped. `changes` is dropped when watching
+/// fails, which closes the channel if it was the only sender.
+///
+/// The messages aren't parsed, the interfaces are listed again instead.
+pub fn watch_links(changes: Sender<()>) -> nix::Result<()> {
+    // protocol 0 is NETLINK_ROUTE
+    let fd = socket(AddressFamily::Netlink, SockType::Raw, SockFlag::SOCK_CLOEXEC, None)?;
+    let groups = (RTMGRP_LINK | RTMGRP_IPV4_IFADDR | RTMGRP_IPV6_IFADDR) as u32;
+    if let Err(e) = bind(fd, &SockAddr::Netlink(NetlinkAddr::new(0, groups))) {
+        let _ = close(fd);
+        return Err(e);
+    }
+
+    thread::spawn(move || {
+        let mut buf = [0u8; 8192];
+        loop {
+            match recv(fd, &mut buf, MsgFlags::empty()) {
+                Ok(_) => {}
+                Err(nix::Error::Sys(Errno::EINTR)) => continue,
+                // ENOBUFS: messages were lost, which is still a change
+                Err(nix::Error::Sys(Errno::ENOBUFS)) => {}
+                Err(e) => {
+                    log::warn!("netlink: {}", e);
+                    break;
+                }
+            }
+            if changes.try_send(()).is_err() {
+                break;
+            }
+        }
+        let _ = close(fd);
+        log::debug!("netlink thread exit");
+    });
+    Ok(())
+}
@@ -1,6 +1,8 @@
 #![allow(dead_code)]
 
+use rawsock::InterfaceAddress;
 use smoltcp::wire::EthernetAddress;
+use std::fmt;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -11,22 +13,73 @@ pub enum Error {
     FailedToCallSystem,
 }
 
+#[derive(Debug, Clone, Default)]
 pub struct InterfaceInfo {
-    pub ethernet_address: EthernetAddress,
+    /// `None` for links without MAC addresses, e.g. TUN devices
+    pub ethernet_address: Option<EthernetAddress>,
     pub name: String,
     pub description: Option<String>,
+    pub addresses: Vec<InterfaceAddress>,
+    pub mtu: Option<u32>,
+    pub oper_state: Option<OperState>,
+    /// Kernel driver of the network card
+    pub driver: Option<String>,
+    pub wireless: bool,
 }
 
-impl Default for InterfaceInfo {
-    fn default() -> Self {
-        InterfaceInfo {
-            ethernet_address: EthernetAddress::BROADCAST,
-            name: "".to_string(),
-            description: None,
+impl InterfaceInfo {
+    /// Whether the link is known to be down, e.g. without a cable.
+    pub fn is_down(&self) -> bool {
+        match self.oper_state {
+            Some(OperState::Down)
+            | Some(OperState::LowerLayerDown)
+            | Some(OperState::NotPresent) => true,
+            _ => false,
         }
     }
 }
 
+/// Operational state of a link, as in RFC 2863.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OperState {
+    Unknown,
+    NotPresent,
+    Down,
+    LowerLayerDown,
+    Testing,
+    Dormant,
+    Up,
+}
+
+impl OperState {
+    /// Parses the names used by `/sys/class/net/*/operstate`.
+    pub fn from_name(name: &str) -> OperState {
+        match name {
+            "notpresent" => OperState::NotPresent,
+            "down" => OperState::Down,
+            "lowerlayerdown" => OperState::LowerLayerDown,
+            "testing" => OperState::Testing,
+            "dormant" => OperState::Dormant,
+            "up" => OperState::Up,
+            _ => OperState::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for OperState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OperState::Unknown => "unknown",
+            OperState::NotPresent => "not present",
+            OperState::Down => "down",
+            OperState::LowerLayerDown => "lower layer down",
+            OperState::Testing => "testing",
+            OperState::Dormant => "dormant",
+            OperState::Up => "up",
+        })
+    }
+}
+
 cfg_if! {
     if #[cfg(windows)] {
         mod windows;
@@ -36,3 +89,6 @@ cfg_if! {
         pub use unix::get_interface_info;
     }
 }
+
+#[cfg(target_os = "linux")]
+mod sysfs;
@@ -0,0 +1,112 @@
+use super::{InterfaceInfo, OperState};
+use crate::gateway::parse_mac;
+use std::fs;
+use std::path::Path;
+
+pub const SYSFS_NET: &str = "/sys/class/net";
+
+/// Fills `info` from `root/<name>`, laid out like `/sys/class/net/<name>`.
+///
+/// Returns whether the interface is there.
+pub fn read_sysfs(root: &Path, info: &mut InterfaceInfo) -> bool {
+    let dir = root.join(&info.name);
+    if !dir.is_dir() {
+        return false;
+    }
+    let read = |file: &str| {
+        fs::read_to_string(dir.join(file))
+            .ok()
+            .map(|s| s.trim().to_string())
+    };
+
+    if info.ethernet_address.is_none() {
+        // empty for TUN devices, all zero for loopback and some tunnels
+        info.ethernet_address = read("address")
+            .and_then(|mac| parse_mac(&mac).ok())
+            .filter(|mac| mac.0 != [0; 6]);
+    }
+    info.mtu = read("mtu").and_then(|mtu| mtu.parse().ok());
+    info.oper_state = read("operstate").map(|state| OperState::from_name(&state));
+    info.driver = fs::read_link(dir.join("device/driver"))
+        .ok()
+        .and_then(|driver| driver.file_name().map(|name| name.to_string_lossy().into_owned()));
+    info.wireless = dir.join("wireless").exists() || dir.join("phy80211").exists();
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use smoltcp::wire::EthernetAddress;
+    use std::path::PathBuf;
+
+    fn fake_sysfs() -> PathBuf {
+        let root = std::env::temp_dir().join(format!("lan-play-sysfs-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let eth0 = root.join("eth0");
+        fs::create_dir_all(eth0.join("device")).unwrap();
+        fs::write(eth0.join("address"), "02:00:00:00:00:01\n").unwrap();
+        fs::write(eth0.join("mtu"), "1500\n").unwrap();
+        fs::write(eth0.join("operstate"), "down\n").unwrap();
+        let driver = root.join("drivers/e1000e");
+        fs::create_dir_all(&driver).unwrap();
+        std::os::unix::fs::symlink(&driver, eth0.join("device/driver")).unwrap();
+
+        let wlan0 = root.join("wlan0");
+        fs::create_dir_all(wlan0.join("wireless")).unwrap();
+        fs::write(wlan0.join("operstate"), "up\n").unwrap();
+
+        let tun0 = root.join("tun0");
+        fs::create_dir_all(&tun0).unwrap();
+        fs::write(tun0.join("address"), "\n").unwrap();
+        fs::write(tun0.join("mtu"), "1400\n").unwrap();
+        fs::write(tun0.join("operstate"), "unknown\n").unwrap();
+
+        let lo = root.join("lo");
+        fs::create_dir_all(&lo).unwrap();
+        fs::write(lo.join("address"), "00:00:00:00:00:00\n").unwrap();
+        root
+    }
+
+    fn info(root: &Path, name: &str) -> Option<InterfaceInfo> {
+        let mut info = InterfaceInfo {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        if read_sysfs(root, &mut info) {
+            Some(info)
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn test_read_sysfs() {
+        let root = fake_sysfs();
+
+        let eth0 = info(&root, "eth0").unwrap();
+        assert_eq!(eth0.ethernet_address, Some(EthernetAddress([2, 0, 0, 0, 0, 1])));
+        assert_eq!(eth0.mtu, Some(1500));
+        assert_eq!(eth0.oper_state, Some(OperState::Down));
+        assert!(eth0.is_down());
+        assert_eq!(eth0.driver.as_deref(), Some("e1000e"));
+        assert!(!eth0.wireless);
+
+        let wlan0 = info(&root, "wlan0").unwrap();
+        assert!(wlan0.wireless);
+        assert!(!wlan0.is_down());
+        assert_eq!((wlan0.mtu, wlan0.driver), (None, None));
+
+        let tun0 = info(&root, "tun0").unwrap();
+        assert_eq!(tun0.ethernet_address, None);
+        assert_eq!(tun0.mtu, Some(1400));
+        assert_eq!(tun0.oper_state, Some(OperState::Unknown));
+        assert!(!tun0.is_down());
+
+        let lo = info(&root, "lo").unwrap();
+        assert_eq!(lo.ethernet_address, None);
+
+        assert!(info(&root, "eth1").is_none());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
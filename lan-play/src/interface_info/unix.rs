@@ -1,6 +1,7 @@
 extern crate nix;
 
 use super::{Error, InterfaceInfo};
+use rawsock::InterfaceAddress;
 use smoltcp::wire::EthernetAddress;
 
 impl From<nix::Error> for Error {
@@ -12,18 +13,54 @@ impl From<nix::Error> for Error {
 pub fn get_interface_info(name: &str) -> Result<InterfaceInfo, Error> {
     extern crate nix;
     use nix::{ifaddrs::getifaddrs, sys::socket::SockAddr};
+    let ip = |addr: Option<SockAddr>| match addr {
+        Some(SockAddr::Inet(addr)) => Some(addr.ip().to_std()),
+        _ => None,
+    };
+
+    let mut info = InterfaceInfo {
+        name: name.into(),
+        ..Default::default()
+    };
+    let mut found = false;
     let addrs = getifaddrs()?;
     for ifaddr in addrs {
         if ifaddr.interface_name != name {
             continue;
         }
-        if let Some(SockAddr::Link(link)) = ifaddr.address {
-            return Ok(InterfaceInfo {
-                ethernet_address: EthernetAddress(link.addr()),
-                name: name.into(),
-                description: None,
-            });
+        found = true;
+        match ifaddr.address {
+            Some(SockAddr::Link(link)) => info.ethernet_address = link_mac(&link),
+            Some(SockAddr::Inet(addr)) => info.addresses.push(InterfaceAddress {
+                address: addr.ip().to_std(),
+                netmask: ip(ifaddr.netmask),
+                broadcast: ip(ifaddr.broadcast),
+                destination: ip(ifaddr.destination),
+            }),
+            _ => {}
         }
     }
-    Err(Error::NotFound)
+    #[cfg(target_os = "linux")]
+    {
+        use super::sysfs::{read_sysfs, SYSFS_NET};
+        found |= read_sysfs(std::path::Path::new(SYSFS_NET), &mut info);
+    }
+    if found {
+        Ok(info)
+    } else {
+        Err(Error::NotFound)
+    }
+}
+
+/// The MAC address of a link, `None` for links without one, e.g. TUN devices
+/// which report an empty address.
+fn link_mac(link: &nix::sys::socket::LinkAddr) -> Option<EthernetAddress> {
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    let len = link.halen();
+    #[cfg(not(any(target_os = "android", target_os = "linux")))]
+    let len = link.alen();
+    if len != 6 {
+        return None;
+    }
+    Some(EthernetAddress(link.addr())).filter(|mac| mac.0 != [0; 6])
 }
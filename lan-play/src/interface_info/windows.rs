@@ -58,7 +58,7 @@ pub fn get_interface_info(name: &str) -> Result<InterfaceInfo, Error> {
                             if guid == intf_guid {
                                 if row.dw_phys_addr_len == 6 {
                                     info.ethernet_address =
-                                        EthernetAddress::from_bytes(&row.b_phys_addr[0..6]);
+                                        Some(EthernetAddress::from_bytes(&row.b_phys_addr[0..6]));
                                 } else {
                                     continue;
                                 }
@@ -69,6 +69,7 @@ pub fn get_interface_info(name: &str) -> Result<InterfaceInfo, Error> {
                                         info.description = Some(desc);
                                     }
                                 }
+                                info.mtu = Some(row.dw_mtu);
                                 return Ok(info);
                             }
                         }
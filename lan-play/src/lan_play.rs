@@ -1,13 +1,32 @@
 use crate::error::{Error, Result};
-use crate::future_smoltcp::{Net, TcpListener, BufferSize};
-use crate::gateway::Gateway;
+use crate::future_smoltcp::{Net, TcpListener, BufferSize, Interface};
+use crate::gateway::{DhcpConfig, DhcpServer, ForwardGateway, Gateway, IgdServer, PortForward, TcpConfig, UdpConfig, UpnpConfig};
+#[cfg(target_os = "linux")]
+use crate::gateway::{NatConfig, NatGateway};
 use crate::proxy::BoxedProxy;
-use crate::interface::{ErrorWithDesc, RawsockInterface, RawsockInterfaceSet};
+use crate::interface::{collect_stats, link_state, plan_rescan, settle_rescan, Captured, Closing, CounterList, Counters, ErrorWithDesc, LinkState, PacketInterface, RawsockInterface, RawsockInterfaceSet, Reattachable};
+#[cfg(target_os = "linux")]
+use crate::interface::{watch_links, TapInterface};
 use crate::client::LanClient;
-use futures::{future::{join_all, ready}, stream::StreamExt};
-use smoltcp::wire::{Ipv4Address, Ipv4Cidr, EthernetFrame, EthernetProtocol, Ipv4Packet};
+use async_channel::unbounded;
+use drop_abort::{abortable, DropAbortHandle};
+use futures::{future::{join_all, ready, FutureExt, LocalBoxFuture}, stream::{FuturesUnordered, StreamExt}};
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::time::Duration;
+use smoltcp::wire::{Ipv4Address, Ipv4Cidr, EthernetAddress, EthernetFrame, EthernetProtocol, Ipv4Packet};
 
 const BACKLOG: usize = 10;
+/// MTU when neither the user nor the link tells one
+const DEFAULT_MTU: usize = 1400;
+/// How often the interfaces are listed again when the system can't tell
+/// when they change
+const RESCAN_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A capture or a network stack, resolving to its interface name and id once
+/// stopped.
+type Task<'a> = LocalBoxFuture<'a, (String, usize)>;
 
 fn filter_bad_packet(packet: &[u8]) -> Result<()> {
     let packet = EthernetFrame::new_checked(packet)?;
@@ -26,80 +45,373 @@ fn filter_bad_packet(packet: &[u8]) -> Result<()> {
     Ok(())
 }
 
+/// An interface lan-play captures on.
+struct Running {
+    /// Tells this capture from a later one on the same interface
+    id: usize,
+    on_console_subnet: bool,
+    link_state: LinkState,
+    _handle: DropAbortHandle,
+}
+
+/// The network stack of an interface. It is kept while the interface is
+/// closed and opened again, so that the consoles' connections go on.
+struct Network {
+    id: usize,
+    mac: EthernetAddress,
+    mtu: usize,
+    reattachable: Arc<Reattachable>,
+    _handle: DropAbortHandle,
+}
+
+/// The interfaces captured on, by name.
+#[derive(Default)]
+struct Attached {
+    running: HashMap<String, Running>,
+    networks: HashMap<String, Network>,
+    /// Kept when an interface goes, so that its counts go on when it's back
+    counters: HashMap<String, Arc<Counters>>,
+    counter_list: Option<CounterList>,
+    /// Interfaces that couldn't be opened or used, tried again once their
+    /// link state changes
+    failed: HashMap<String, LinkState>,
+    next_id: usize,
+}
+
 pub struct LanPlay {
     gateway: Gateway,
     ipv4cidr: Ipv4Cidr,
     gateway_ip: Ipv4Address,
-    mtu: usize,
+    mtu: Option<usize>,
     buffer_size: BufferSize,
+    stats_interval: Option<Duration>,
 }
 
 impl LanPlay {
-    pub fn new(proxy: BoxedProxy, ipv4cidr: Ipv4Cidr, gateway_ip: Ipv4Address, mtu: usize, buffer_size: BufferSize) -> LanPlay {
+    pub fn new(proxy: BoxedProxy, ipv4cidr: Ipv4Cidr, gateway_ip: Ipv4Address, mtu: Option<usize>, buffer_size: BufferSize, tcp_config: TcpConfig, udp_config: UdpConfig) -> LanPlay {
         LanPlay {
-            gateway: Gateway::new(proxy),
+            gateway: Gateway::new(proxy, tcp_config, udp_config),
             ipv4cidr,
             gateway_ip,
             mtu,
             buffer_size,
+            stats_interval: None,
         }
     }
-    pub async fn start(&mut self, set: &RawsockInterfaceSet, netif: Option<String>, _client: Option<LanClient>) -> Result<()> {
-        let (mut opened, errored) = set.open_all_interface();
-
-        for ErrorWithDesc(err, desc) in errored {
-            log::debug!(
-                "Err: Interface {:?} ({:?}) err {:?}",
-                desc.name,
-                desc.description,
-                err
-            );
+    /// Logs the packet counters of the interfaces every `interval`.
+    pub fn set_stats_interval(&mut self, interval: Duration) {
+        self.stats_interval = Some(interval);
+    }
+    /// Switches to NAT mode, exchanging translated packets with `host`.
+    #[cfg(target_os = "linux")]
+    pub fn set_nat<H>(&mut self, config: NatConfig, host: H)
+    where
+        H: Interface + Send + 'static,
+    {
+        let nat = Arc::new(NatGateway::new(config, self.ipv4cidr));
+        self.gateway.set_nat(nat.clone());
+        tokio::spawn(async move {
+            if let Err(err) = nat.run(host).await {
+                log::error!("nat::run failed {:?}", err);
+            }
+        });
+    }
+    /// Hands out addresses to consoles from `config`.
+    pub fn set_dhcp(&mut self, config: DhcpConfig) -> Result<()> {
+        log::info!("DHCP server on, pool {}-{}", config.pool.start(), config.pool.end());
+        self.gateway.set_dhcp(Arc::new(DhcpServer::new(config)?));
+        Ok(())
+    }
+    /// Answers UPnP IGD and NAT-PMP/PCP, mapping ports with the forward
+    /// gateway.
+    pub fn set_upnp(&mut self, config: UpnpConfig) {
+        if self.gateway.forward().is_none() {
+            self.set_forwards(self.gateway_ip.into(), Vec::new());
         }
+        let forward = self.gateway.forward().unwrap().clone();
+        log::info!("UPnP IGD and NAT-PMP on, external address {}", config.external_ip);
+        let igd = Arc::new(IgdServer::new(config, forward));
+        self.gateway.set_igd(igd.clone());
+        tokio::spawn(async move { igd.run().await });
+    }
+    /// Serves `forwards` from host sockets, appearing to consoles as `source_ip`.
+    pub fn set_forwards(&mut self, source_ip: Ipv4Addr, forwards: Vec<PortForward>) {
+        let forward = Arc::new(ForwardGateway::new(source_ip, forwards));
+        self.gateway.set_forward(forward.clone());
+        tokio::spawn(async move {
+            if let Err(err) = forward.run().await {
+                log::error!("forward::run failed {:?}", err);
+            }
+        });
+    }
+    /// Captures on the interfaces the consoles may be behind.
+    ///
+    /// Interfaces that go away or stop capturing are closed, and opened again
+    /// when they come back. The network stack of an interface is kept
+    /// meanwhile, so the consoles' TCP connections survive, unless the
+    /// interface comes back with another MAC address or MTU.
+    /// Only returns when no interface could be opened at first.
+    pub async fn start(&mut self, set: &RawsockInterfaceSet, netif: Option<String>, _client: Option<LanClient>) -> Result<()> {
+        let this = &*self;
+        // the watcher holds the only sender, `changes` closes once it stops
+        let (notify, changes) = unbounded();
+        #[cfg(target_os = "linux")]
+        let mut poll = match watch_links(notify) {
+            Ok(()) => false,
+            Err(e) => {
+                log::debug!("Can't watch links, listing interfaces every {:?}: {}", RESCAN_INTERVAL, e);
+                true
+            }
+        };
+        #[cfg(not(target_os = "linux"))]
+        let mut poll = {
+            drop(notify);
+            true
+        };
 
-        if let Some(netif) = netif {
-            opened = opened.into_iter().filter(|i| i.name() == &netif).collect();
+        let mut attached = Attached::default();
+        if let Some(interval) = this.stats_interval {
+            let counter_list = CounterList::default();
+            tokio::spawn(collect_stats(counter_list.clone(), interval));
+            attached.counter_list = Some(counter_list);
         }
 
-        if opened.len() == 0 {
+        let opened = this.rescan(set, netif.as_deref(), &mut attached);
+        if opened.is_empty() {
             return Err(Error::NoInterface);
         }
+        let mut tasks = FuturesUnordered::new();
+        for interface in opened {
+            this.attach(set, &mut attached, &mut tasks, interface);
+        }
 
-        for interface in &opened {
-            log::info!(
-                "Interface {} ({}) opened, mac: {}, data link: {}",
-                interface.name(),
-                interface.desc.description,
-                interface.mac(),
-                interface.data_link()
-            );
+        let mut ticks = tokio::time::interval(RESCAN_INTERVAL);
+        // the first tick is immediate, the interfaces were just listed
+        ticks.tick().await;
+        loop {
+            tokio::select! {
+                Some((name, id)) = tasks.next(), if !tasks.is_empty() => {
+                    if attached.running.get(&name).map(|running| running.id) == Some(id) {
+                        log::warn!("Interface {} stopped", name);
+                        let running = attached.running.remove(&name).unwrap();
+                        attached.failed.insert(name, running.link_state);
+                    } else if attached.networks.get(&name).map(|network| network.id) == Some(id) {
+                        log::error!("The network stack of interface {} stopped", name);
+                        attached.networks.remove(&name);
+                    }
+                    continue;
+                }
+                _ = ticks.tick(), if poll => {}
+                changed = changes.recv(), if !poll => {
+                    if changed.is_err() {
+                        log::warn!("Stopped watching links, listing interfaces every {:?}", RESCAN_INTERVAL);
+                        poll = true;
+                    }
+                    // one listing covers every change so far
+                    while changes.try_recv().is_ok() {}
+                }
+            }
+            for interface in this.rescan(set, netif.as_deref(), &mut attached) {
+                this.attach(set, &mut attached, &mut tasks, interface);
+            }
         }
+    }
+    /// Lists the interfaces, forgets those that are gone or stopped capturing
+    /// and opens the ones the consoles may be behind.
+    fn rescan(&self, set: &RawsockInterfaceSet, netif: Option<&str>, attached: &mut Attached) -> Vec<RawsockInterface> {
+        let all_interf = match set.current_interfaces() {
+            Ok(all_interf) => all_interf,
+            Err(e) => {
+                log::warn!("Failed to list interfaces {:?}", e);
+                return Vec::new();
+            }
+        };
 
-        let futures = opened
-            .into_iter()
-            .map(|interface| {
-                self.process_interface(interface)
+        let captured = attached
+            .running
+            .iter()
+            .map(|(name, running)| {
+                let state = Captured {
+                    on_console_subnet: running.on_console_subnet,
+                    capturing: attached.counters[name].is_capturing(),
+                };
+                (name.clone(), state)
             })
-            .collect::<Vec<_>>();
-        join_all(futures).await;
+            .collect::<HashMap<_, _>>();
+        let console_ip = set.console_ip();
+        let plan = plan_rescan(&all_interf, &captured, &mut attached.failed, netif, console_ip);
+        for (name, closing) in &plan.close {
+            match closing {
+                Closing::Gone => log::warn!("Interface {} is gone, waiting for it to come back", name),
+                Closing::StoppedCapturing => log::warn!("Interface {} stopped capturing, opening it again", name),
+            }
+            attached.running.remove(name);
+        }
+
+        let mut opened = Vec::new();
+        for desc in all_interf.into_iter().filter(|desc| plan.open.contains(&desc.name)) {
+            let state = link_state(&desc);
+            match set.open_interface(desc) {
+                // it captures the other interfaces again and can't send the replies
+                Ok(interface) if netif.is_none() && interface.is_cooked() => {
+                    log::debug!("Interface {} is only used when passed as --netif", interface.name());
+                    attached.failed.insert(interface.name().clone(), state);
+                }
+                Ok(interface) => {
+                    attached.failed.remove(interface.name());
+                    opened.push(interface);
+                }
+                Err(ErrorWithDesc(err, desc)) => {
+                    log::debug!(
+                        "Err: Interface {:?} ({:?}) err {:?}",
+                        desc.name,
+                        desc.description,
+                        err
+                    );
+                    attached.failed.insert(desc.name, state);
+                }
+            }
+        }
 
+        let descs = opened.iter().map(|interface| &interface.desc).collect::<Vec<_>>();
+        let settled = settle_rescan(&plan, &descs, netif, console_ip);
+        if settled.close_captured {
+            for name in attached.running.keys() {
+                log::info!("Interface {} closed, the consoles are on another one", name);
+            }
+            attached.running.clear();
+        }
+        opened.retain(|interface| settled.keep.contains(interface.name()));
+        opened
+    }
+    /// Starts capturing on `interface`, until its handle in `attached` is
+    /// dropped, into the network stack kept for it. The capture, and the
+    /// stack when it is new, are pushed to `tasks`.
+    fn attach<'a>(
+        &'a self,
+        set: &RawsockInterfaceSet,
+        attached: &mut Attached,
+        tasks: &mut FuturesUnordered<Task<'a>>,
+        mut interface: RawsockInterface,
+    ) {
+        let name = interface.name().clone();
+        log::info!(
+            "Interface {} ({}) opened, mac: {}, data link: {}",
+            name,
+            interface.desc.description,
+            interface.mac(),
+            interface.data_link()
+        );
+        if let Some(info) = interface.info().filter(|info| info.is_down()) {
+            log::warn!(
+                "Interface {} is {}, consoles can't be reached through it",
+                name,
+                info.oper_state.unwrap()
+            );
+        }
+
+        match attached.counters.get(&name) {
+            Some(counters) => {
+                log::debug!("Interface {} counts on from its last capture", name);
+                interface.set_counters(counters.clone());
+            }
+            None => {
+                let counters = interface.counters().clone();
+                if let Some(counter_list) = &attached.counter_list {
+                    counter_list.lock().unwrap().push((name.clone(), counters.clone()));
+                }
+                attached.counters.insert(name.clone(), counters);
+            }
+        }
+        if let Some(interval) = self.stats_interval {
+            interface.sample_stats(interval);
+        }
+
+        let mac = interface.mac().to_owned();
+        let link_mtu = interface.info().and_then(|info| info.mtu).map(|mtu| mtu as usize);
+        let mtu = self.mtu.or(link_mtu).unwrap_or(DEFAULT_MTU);
+        log::debug!("Interface {} MTU {}", name, mtu);
+        let kept = attached
+            .networks
+            .get(&name)
+            .map_or(false, |network| network.mac == mac && network.mtu == mtu);
+        if !kept {
+            if attached.networks.contains_key(&name) {
+                log::info!("Interface {} came back with another MAC address or MTU, its connections are closed", name);
+            }
+            let (reattachable, stack) = Reattachable::new(attached.counters[&name].clone());
+            let id = attached.next_id;
+            attached.next_id += 1;
+            let (task, _handle) = abortable(self.process_stream(mac, mtu, stack));
+            attached.networks.insert(name.clone(), Network {
+                id,
+                mac,
+                mtu,
+                reattachable: Arc::new(reattachable),
+                _handle,
+            });
+            let name = name.clone();
+            tasks.push(async move {
+                let _ = task.await;
+                (name, id)
+            }.boxed_local());
+        }
+        let reattachable = attached.networks[&name].reattachable.clone();
+
+        let id = attached.next_id;
+        attached.next_id += 1;
+        let on_console_subnet = interface.desc.is_on_subnet_of(set.console_ip());
+        let link_state = link_state(&interface.desc);
+        let (task, _handle) = abortable(async move { reattachable.attach(interface.start()).await });
+        attached.running.insert(name.clone(), Running {
+            id,
+            on_console_subnet,
+            link_state,
+            _handle,
+        });
+        tasks.push(async move {
+            let _ = task.await;
+            (name, id)
+        }.boxed_local());
+    }
+    #[cfg(target_os = "linux")]
+    pub async fn start_tap(&mut self, tap: TapInterface) -> Result<()> {
+        log::info!("TAP interface {} opened, mac: {}", tap.name(), tap.mac());
+        let mac = tap.mac().to_owned();
+        let mtu = self.mtu.unwrap_or(DEFAULT_MTU);
+        self.process_stream(mac, mtu, tap.start()).await;
         Ok(())
     }
-    async fn process_interface(&self, interf: RawsockInterface) {
-        let mac = interf.mac().to_owned();
-        let stream = interf.start();
+    async fn process_stream(&self, mac: EthernetAddress, mtu: usize, stream: PacketInterface) {
         // TODO: add lan_client
-        let stream = stream.filter(|p| ready(filter_bad_packet(p).is_ok()));
+        // DHCP and SSDP are broadcast, so they are diverted before filtering
+        let counters = stream.counters().clone();
+        let stream = self.gateway
+            .divert(mac, stream)
+            .filter(move |p| {
+                let ok = filter_bad_packet(p).is_ok();
+                if !ok {
+                    counters.filtered();
+                }
+                ready(ok)
+            });
+        self.process_net(mac, mtu, stream).await;
+    }
+    async fn process_net<I>(&self, mac: EthernetAddress, mtu: usize, stream: I)
+    where
+        I: Interface + Send + 'static,
+    {
         let net = Net::new(
             mac.clone(),
             vec![self.ipv4cidr.into()],
             self.gateway_ip,
             stream,
-            self.mtu,
+            mtu,
             self.buffer_size,
         );
         let tcp: Vec<TcpListener> = join_all((0..BACKLOG).map(|_| net.tcp_listener())).await;
         let udp = net.udp_socket().await;
-        if let Err(err) = self.gateway.process(tcp, udp).await {
+        if let Err(err) = self.gateway.process(tcp, udp, net.tcp_connector()).await {
             log::error!("gateway::process failed {:?}", err);
         }
     }
@@ -15,17 +15,28 @@ mod interface_info;
 mod lan_play;
 mod proxy;
 mod interface;
+#[cfg(test)]
+mod test_support;
 
 use client::LanClient;
 use error::Result;
 use lan_play::LanPlay;
 use proxy::{DirectProxy, Auth, BoxedProxy};
 use rawsock::traits::Library;
-use interface::RawsockInterfaceSet;
+use rawsock::{Direction, InterfaceOptions, TimestampPrecision};
+use interface::{open_library, reports_to_json, CaptureLibrary, RawsockInterfaceSet};
+#[cfg(target_os = "linux")]
+use interface::{TapInterface, TunInterface};
+use smoltcp::wire::EthernetAddress;
 use smoltcp::wire::Ipv4Cidr;
 use std::net::Ipv4Addr;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
 use url::Url;
 use future_smoltcp::BufferSize;
+#[cfg(target_os = "linux")]
+use gateway::NatConfig;
+use gateway::{parse_mac, DhcpConfig, NatBehavior, PortForward, TcpConfig, TcpOptions, TcpRule, UdpConfig, UpnpConfig};
 use tokio::{time::{Instant, Duration, timeout, sleep}, prelude::*};
 
 #[cfg(feature = "logging-allocator")]
@@ -53,6 +64,12 @@ enum Subcommand {
         #[structopt(short)]
         times: Option<u64>,
     },
+    /// List the capture interfaces and whether lan-play can use them
+    List {
+        /// Print a JSON array instead of text
+        #[structopt(long)]
+        json: bool,
+    },
 }
 
 /// Lan play
@@ -62,9 +79,9 @@ struct Opt {
     #[structopt(short, long, parse(try_from_str = str::parse), default_value = "10.13.37.2")]
     gateway_ip: Ipv4Addr,
 
-    /// MTU
-    #[structopt(long, default_value = "1400")]
-    mtu: usize,
+    /// MTU, by default the one of the interface, or 1400
+    #[structopt(long)]
+    mtu: Option<usize>,
 
     /// Buffer size for each TCP connection in bytes
     #[structopt(long, default_value = "131072")]
@@ -74,10 +91,166 @@ struct Opt {
     #[structopt(long, default_value = "16")]
     prefix_len: u8,
 
+    /// Keep-alive interval in seconds towards the console, 0 to disable
+    #[structopt(long)]
+    tcp_keepalive: Option<u64>,
+
+    /// Seconds before an unresponsive console connection is dropped, 0 to disable
+    #[structopt(long)]
+    tcp_timeout: Option<u64>,
+
+    /// Milliseconds smoltcp may delay an ACK to the console, 0 to acknowledge at once
+    #[structopt(long)]
+    tcp_ack_delay: Option<u64>,
+
+    /// Disable (true) or enable (false) Nagle's algorithm on upstream connections
+    #[structopt(long)]
+    tcp_nodelay: Option<bool>,
+
+    /// Seconds without traffic before a TCP connection is closed, 0 to disable
+    #[structopt(long, default_value = "60")]
+    tcp_idle_timeout: u64,
+
+    /// Maximum lifetime of a TCP connection in seconds
+    #[structopt(long)]
+    tcp_max_lifetime: Option<u64>,
+
+    /// Keepalive idle time in seconds on upstream connections
+    #[structopt(long)]
+    tcp_upstream_keepalive: Option<u64>,
+
+    /// Per-destination TCP options e.g. port=45000-45010,dst=1.2.3.0/24,idle-timeout=3600
+    #[structopt(long = "tcp-rule", number_of_values = 1)]
+    tcp_rules: Vec<TcpRule>,
+
+    /// Which replies reach the console: full-cone, address-restricted or port-restricted
+    #[structopt(long, default_value = "full-cone")]
+    udp_nat: NatBehavior,
+
+    /// Seconds without outbound traffic before a UDP mapping is dropped.
+    /// RFC 4787 recommends at least 120, the default is kept for compatibility
+    #[structopt(long, default_value = "60")]
+    udp_timeout: u64,
+
+    /// Forward a host port to a console e.g. 0.0.0.0:45000/udp->10.13.37.5:45000
+    #[structopt(long = "forward", number_of_values = 1)]
+    forwards: Vec<PortForward>,
+
+    /// Virtual address forwarded connections come from, defaults to the gateway IP
+    #[structopt(long, parse(try_from_str = str::parse))]
+    forward_source: Option<Ipv4Addr>,
+
+    /// Hand out addresses in the subnet to consoles, with the gateway as router and DNS
+    #[structopt(long)]
+    dhcp: bool,
+
+    /// Addresses handed out by DHCP e.g. 10.13.0.100-10.13.0.200, defaults to
+    /// everything above the first 100
+    #[structopt(long, parse(try_from_str = parse_range))]
+    dhcp_range: Option<RangeInclusive<Ipv4Addr>>,
+
+    /// DHCP lease time in seconds
+    #[structopt(long, default_value = "3600")]
+    dhcp_lease_time: u64,
+
+    /// Only answer DHCP from this MAC, so another DHCP server on the LAN keeps
+    /// serving everyone else
+    #[structopt(long = "dhcp-mac", number_of_values = 1, parse(try_from_str = parse_mac))]
+    dhcp_macs: Vec<EthernetAddress>,
+
+    /// Only answer DHCP from clients whose vendor class starts with this
+    #[structopt(long)]
+    dhcp_vendor_class: Option<String>,
+
+    /// File keeping DHCP leases across restarts
+    #[structopt(long, parse(from_os_str))]
+    dhcp_state: Option<PathBuf>,
+
+    /// Let consoles open ports on this host with UPnP IGD and NAT-PMP/PCP
+    #[structopt(long)]
+    upnp: bool,
+
+    /// Address reported to consoles as the public one, defaults to this host's
+    #[structopt(long, parse(try_from_str = str::parse))]
+    upnp_external_ip: Option<Ipv4Addr>,
+
+    /// Longest UPnP/NAT-PMP lease in seconds
+    #[structopt(long, default_value = "3600")]
+    upnp_max_lease: u64,
+
     /// Network interface
     #[structopt(short = "i", long, env = "LP_NETIF")]
     netif: Option<String>,
 
+    /// Maximum number of bytes captured from every packet
+    #[structopt(long, default_value = "65536")]
+    snaplen: u32,
+
+    /// Don't put the network interface into promiscuous mode
+    #[structopt(long)]
+    no_promisc: bool,
+
+    /// Size of the kernel capture buffer in bytes, the library default if not set
+    #[structopt(long)]
+    capture_buffer_size: Option<u32>,
+
+    /// Hand captured packets over as soon as they arrive
+    #[structopt(long)]
+    immediate: bool,
+
+    /// Timestamp captured packets with nanoseconds instead of microseconds
+    #[structopt(long)]
+    timestamp_nano: bool,
+
+    /// Source of packet timestamps by pcap name, e.g. "host" or "adapter"
+    #[structopt(long)]
+    timestamp_type: Option<String>,
+
+    /// Only capture received packets, leaving out the ones lan-play sends
+    #[structopt(long)]
+    capture_in_only: bool,
+
+    /// pcap filter expression that captured packets must match as well,
+    /// e.g. "not ether src 02:00:00:00:00:01"
+    #[structopt(long)]
+    capture_filter: Option<String>,
+
+    /// Log packet counters of each interface every this many seconds, to tell
+    /// drops in the kernel or the capture library from drops in lan-play
+    #[structopt(long)]
+    stats_interval: Option<u64>,
+
+    /// Packet capture library: auto, pcap, wpcap, pfring or native (AF_PACKET
+    /// sockets on Linux)
+    #[structopt(long, default_value = "auto")]
+    capture_library: CaptureLibrary,
+
+    /// Load the capture library from this file instead of the usual places
+    #[structopt(long)]
+    capture_library_path: Option<String>,
+
+    /// Create a TAP device with this name and use it instead of pcap capture.
+    /// Can't be combined with a relay server
+    #[cfg(target_os = "linux")]
+    #[structopt(long, env = "LP_TAP", conflicts_with_all = &["netif", "relay"])]
+    tap: Option<String>,
+
+    /// MAC address of the gateway on the TAP device e.g. 02:00:00:00:00:01
+    #[cfg(target_os = "linux")]
+    #[structopt(long, parse(try_from_str = parse_mac))]
+    tap_mac: Option<EthernetAddress>,
+
+    /// NAT mode: forward traffic as raw IP through a TUN device with this name
+    /// instead of proxying it
+    #[cfg(target_os = "linux")]
+    #[structopt(long, env = "LP_NAT_TUN")]
+    nat_tun: Option<String>,
+
+    /// Source address of NAT mode packets, routed by the host through the TUN device
+    #[cfg(target_os = "linux")]
+    #[structopt(long, parse(try_from_str = str::parse), default_value = "10.13.38.2")]
+    nat_ip: Ipv4Addr,
+
     /// Proxy setting e.g. socks5://localhost:1080
     #[structopt(short, long, parse(try_from_str = Url::parse), env = "LP_PROXY")]
     proxy: Option<Url>,
@@ -91,19 +264,18 @@ struct Opt {
     subcommand: Option<Subcommand>,
 }
 
-lazy_static! {
-    static ref RAWSOCK_LIB: Box<dyn Library> = {
-        let lib = open_best_library().expect("Can't open any library");
-        log::info!("Library opened, version is {}", lib.version());
-        lib
+/// Opens the capture library for the rest of the run.
+fn open_rawsock_lib(opt: &Opt) -> Result<&'static Box<dyn Library>> {
+    let lib = match open_library(opt.capture_library, opt.capture_library_path.as_deref()) {
+        Ok(lib) => lib,
+        Err(err) => {
+            log::error!("{}", err);
+            return Err(err);
+        }
     };
-}
-
-fn open_best_library() -> Result<Box<dyn Library>> {
-    if let Ok(l) = rawsock::wpcap::Library::open_default_paths() {
-        return Ok(Box::new(l));
-    }
-    Ok(Box::new(rawsock::pcap::Library::open_default_paths()?))
+    log::info!("Library opened, version is {}", lib.version());
+    // interfaces borrow the library until the process exits
+    Ok(Box::leak(Box::new(lib)))
 }
 
 fn url_into_addr_auth(url: &Url) -> Option<(String, Option<Auth>)> {
@@ -154,6 +326,63 @@ fn parse_proxy(proxy: &Option<Url>) -> BoxedProxy {
     }
 }
 
+/// A locally administered MAC derived from the gateway IP, so it's stable
+/// across restarts and won't clash with the kernel side of the TAP.
+#[cfg(target_os = "linux")]
+fn tap_mac(gateway_ip: Ipv4Addr) -> EthernetAddress {
+    let ip = gateway_ip.octets();
+    EthernetAddress([0x02, 0x4c, ip[0], ip[1], ip[2], ip[3]])
+}
+
+fn parse_range(s: &str) -> std::result::Result<RangeInclusive<Ipv4Addr>, String> {
+    let dash = s.find('-').ok_or_else(|| format!("invalid range {}, expected e.g. 10.13.0.100-10.13.0.200", s))?;
+    let start: Ipv4Addr = s[..dash].trim().parse().map_err(|e| format!("{}", e))?;
+    let end: Ipv4Addr = s[dash + 1..].trim().parse().map_err(|e| format!("{}", e))?;
+    if start > end {
+        return Err(format!("empty range {}", s));
+    }
+    Ok(start..=end)
+}
+
+fn dhcp_config(opt: &Opt, ipv4cidr: Ipv4Cidr) -> Result<DhcpConfig> {
+    let mut config = DhcpConfig::new(opt.gateway_ip, ipv4cidr);
+    if let Some(range) = &opt.dhcp_range {
+        config.set_pool(range.clone())?;
+    }
+    config.lease_time = Duration::from_secs(opt.dhcp_lease_time);
+    config.allowed_macs = opt.dhcp_macs.clone();
+    config.vendor_class = opt.dhcp_vendor_class.clone();
+    config.state_file = opt.dhcp_state.clone();
+    Ok(config)
+}
+
+/// The address this host reaches the internet from, found without sending
+/// anything.
+fn local_ip() -> Ipv4Addr {
+    let ip = std::net::UdpSocket::bind("0.0.0.0:0")
+        .and_then(|s| s.connect("8.8.8.8:53").and_then(|_| s.local_addr()));
+    match ip {
+        Ok(std::net::SocketAddr::V4(addr)) => *addr.ip(),
+        _ => Ipv4Addr::UNSPECIFIED,
+    }
+}
+
+fn tcp_config(opt: &Opt) -> TcpConfig {
+    let secs = |s: Option<u64>| s.map(Duration::from_secs);
+    TcpConfig {
+        defaults: TcpOptions {
+            keepalive: secs(opt.tcp_keepalive),
+            timeout: secs(opt.tcp_timeout),
+            ack_delay: opt.tcp_ack_delay.map(Duration::from_millis),
+            nodelay: opt.tcp_nodelay,
+            idle_timeout: secs(Some(opt.tcp_idle_timeout)),
+            max_lifetime: secs(opt.tcp_max_lifetime),
+            upstream_keepalive: secs(opt.tcp_upstream_keepalive),
+        },
+        rules: opt.tcp_rules.clone(),
+    }
+}
+
 async fn run(opt: Opt) -> Result<()> {
     let ipv4cidr = Ipv4Cidr::new(opt.gateway_ip.into(), opt.prefix_len);
     let gateway_ip = opt.gateway_ip.into();
@@ -163,9 +392,7 @@ async fn run(opt: Opt) -> Result<()> {
         None => None,
     };
     let tcp_half = opt.tcp_buffer_size / 2;
-
-    let set = RawsockInterfaceSet::new(&RAWSOCK_LIB, ipv4cidr)
-        .expect("Could not open any packet capturing library");
+    let tcp_config = tcp_config(&opt);
 
     let mut lp = LanPlay::new(
         proxy,
@@ -176,20 +403,101 @@ async fn run(opt: Opt) -> Result<()> {
             tcp_rx_size: tcp_half,
             tcp_tx_size: tcp_half,
         },
+        tcp_config,
+        UdpConfig {
+            behavior: opt.udp_nat,
+            timeout: Duration::from_secs(opt.udp_timeout),
+        },
     );
 
+    if opt.dhcp {
+        lp.set_dhcp(dhcp_config(&opt, ipv4cidr)?)?;
+    }
+
+    if !opt.forwards.is_empty() || opt.upnp {
+        let source = opt.forward_source.unwrap_or(opt.gateway_ip);
+        lp.set_forwards(source, opt.forwards.clone());
+    }
+
+    if opt.upnp {
+        let external_ip = opt.upnp_external_ip.unwrap_or_else(local_ip);
+        let mut config = UpnpConfig::new(opt.gateway_ip, external_ip);
+        config.max_lease = Duration::from_secs(opt.upnp_max_lease);
+        lp.set_upnp(config);
+    }
+
+    #[cfg(target_os = "linux")]
+    if let Some(name) = &opt.nat_tun {
+        let tun = TunInterface::open(name)?;
+        log::info!("NAT mode via TUN interface {}, source address {}", tun.name(), opt.nat_ip);
+        lp.set_nat(NatConfig::new(opt.nat_ip.into()), tun.start());
+    }
+
+    #[cfg(target_os = "linux")]
+    if let Some(name) = opt.tap {
+        let mac = opt.tap_mac.unwrap_or_else(|| tap_mac(opt.gateway_ip));
+        let tap = TapInterface::open(&name, mac)?;
+        return lp.start_tap(tap).await;
+    }
+
+    if let Some(secs) = opt.stats_interval.filter(|secs| *secs > 0) {
+        lp.set_stats_interval(Duration::from_secs(secs));
+    }
+    let set = rawsock_interface_set(&opt, ipv4cidr)?;
+
     lp.start(&set, opt.netif, client).await?;
 
     Ok(())
 }
 
+/// Opens the capture library and lists its interfaces, with the capture
+/// settings of `opt`.
+fn rawsock_interface_set(opt: &Opt, ipv4cidr: Ipv4Cidr) -> Result<RawsockInterfaceSet> {
+    let mut capture = InterfaceOptions::new()
+        .snaplen(opt.snaplen)
+        .promiscuous(!opt.no_promisc);
+    capture.buffer_size = opt.capture_buffer_size;
+    capture.immediate |= opt.immediate;
+    if opt.timestamp_nano {
+        capture.timestamp_precision = TimestampPrecision::Nano;
+    }
+    capture.timestamp_type = opt.timestamp_type.clone();
+    if opt.capture_in_only {
+        capture.direction = Direction::In;
+    }
+    let filter = opt.capture_filter.as_deref();
+    let lib = open_rawsock_lib(opt)?;
+    match RawsockInterfaceSet::new(lib, ipv4cidr, opt.dhcp, capture, filter) {
+        Err(rawsock::Error::InvalidFilter(err)) => {
+            log::error!("Invalid capture filter: {}", err);
+            Err(rawsock::Error::InvalidFilter(err).into())
+        }
+        set => Ok(set?),
+    }
+}
+
+fn list(opt: &Opt, json: bool) -> Result<()> {
+    let ipv4cidr = Ipv4Cidr::new(opt.gateway_ip.into(), opt.prefix_len);
+    let reports = rawsock_interface_set(opt, ipv4cidr)?.list_interfaces();
+    if json {
+        println!("{}", reports_to_json(&reports));
+    } else {
+        for report in &reports {
+            println!("{}\n", report);
+        }
+    }
+    Ok(())
+}
+
 async fn ping(relay: &str, times: &Option<u64>) -> Result<()> {
     let client = LanClient::new(relay.to_string(), Ipv4Cidr::new(Ipv4Addr::UNSPECIFIED.into(), 0)).await?;
     let times = times.unwrap_or(4);
 
     for i in 0..times {
         let start = Instant::now();
-        timeout(Duration::from_secs(1), client.ping()).await??;
+        timeout(Duration::from_secs(1), client.ping())
+            .await
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "no ping response, the server seems not working, or blocked by the firewall"))??;
         println!("ping responsed in {:?} (#{})", start.elapsed(), i + 1);
         if i + 1 != times {
             sleep(Duration::from_secs(1)).await;
@@ -244,6 +552,7 @@ async fn async_main() -> Result<()> {
     match &opt.subcommand {
         Some(Subcommand::Ping { relay, times  }) => ping(relay, times).await,
         Some(Subcommand::Check { proxy }) => check(proxy).await,
+        Some(Subcommand::List { json }) => list(&opt, *json),
         None => run(opt).await,
     }
 }
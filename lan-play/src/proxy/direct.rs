@@ -1,9 +1,39 @@
-use super::{traits, BoxedProxy, BoxedTcp, BoxedUdp, SocketAddr, prelude::*};
+use super::{other, traits, BoxedProxy, BoxedTcp, BoxedUdp, SocketAddr, prelude::*};
 use tokio::{io, net::{TcpStream, UdpSocket}};
 use std::task::{Context, Poll};
+use std::time::Duration;
 use futures::ready;
 
-impl traits::Tcp for TcpStream {}
+impl traits::Tcp for TcpStream {
+    fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        TcpStream::set_nodelay(self, nodelay)
+    }
+    fn set_keepalive(&self, keepalive: Option<Duration>) -> io::Result<()> {
+        set_tcp_keepalive(self, keepalive)
+    }
+}
+
+#[cfg(unix)]
+fn set_tcp_keepalive(stream: &TcpStream, keepalive: Option<Duration>) -> io::Result<()> {
+    use nix::sys::socket::{setsockopt, sockopt};
+    use std::os::unix::io::AsRawFd;
+
+    let fd = stream.as_raw_fd();
+    setsockopt(fd, sockopt::KeepAlive, &keepalive.is_some()).map_err(other)?;
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    if let Some(idle) = keepalive {
+        setsockopt(fd, sockopt::TcpKeepIdle, &(idle.as_secs().max(1) as u32)).map_err(other)?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_tcp_keepalive(_stream: &TcpStream, keepalive: Option<Duration>) -> io::Result<()> {
+    match keepalive {
+        Some(_) => Err(other("TCP keepalive is not supported on this platform")),
+        None => Ok(()),
+    }
+}
 
 impl traits::Udp for UdpSocket {
     fn poll_send_to(self: &mut Self, cx: &mut Context<'_>, buf: &[u8], target: &SocketAddr) -> Poll<io::Result<usize>> {
@@ -14,6 +44,9 @@ impl traits::Udp for UdpSocket {
         let addr = ready!(UdpSocket::poll_recv_from(&self, cx, &mut buf))?;
         Poll::Ready(Ok((buf.filled().len(), addr)))
     }
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        UdpSocket::local_addr(self)
+    }
 }
 
 pub struct DirectProxy {}
@@ -39,6 +39,14 @@ impl BoxedProxy {
 }
 
 pub trait Tcp: AsyncRead + AsyncWrite {
+    /// Enable or disable Nagle's algorithm on the upstream socket, if there is one.
+    fn set_nodelay(&self, _nodelay: bool) -> io::Result<()> {
+        Ok(())
+    }
+    /// Enable TCP keepalive probes on the upstream socket, if there is one.
+    fn set_keepalive(&self, _keepalive: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
     fn boxed(self) -> BoxedTcp
     where
         Self: Sized + Unpin + Send + Sync + 'static,
@@ -50,6 +58,10 @@ pub trait Tcp: AsyncRead + AsyncWrite {
 pub trait Udp {
     fn poll_send_to(self: &mut Self, cx: &mut Context<'_>, buf: &[u8], target: &SocketAddr) -> Poll<io::Result<usize>>;
     fn poll_recv_from(self: &mut Self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<(usize, SocketAddr)>>;
+    /// Local address of the upstream socket, if there is one.
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Err(io::Error::new(io::ErrorKind::Other, "local address is not available"))
+    }
     fn boxed(self) -> BoxedUdp
     where
         Self: Sized + Unpin + Send + Sync + 'static,
@@ -67,6 +79,9 @@ impl BoxedUdp {
     pub async fn recv_from(&mut self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
         poll_fn(|cx| self.0.poll_recv_from(cx, buf)).await
     }
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.0.local_addr()
+    }
     pub fn split(self) -> (SendHalf, RecvHalf) {
         let inner = Arc::new(SyncMutex::new(self));
         (SendHalf {
@@ -85,6 +100,9 @@ pub struct RecvHalf {
 }
 
 impl SendHalf {
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.lock().unwrap().local_addr()
+    }
     pub async fn send_to(&mut self, buf: &[u8], addr: &SocketAddr) -> io::Result<usize> {
         poll_fn(|cx| {
             let mut inner = self.inner.lock().unwrap();
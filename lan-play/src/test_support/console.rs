@@ -0,0 +1,96 @@
+use crate::future_smoltcp::{parse_udp_owned, FutureDevice, Interface, OwnedUdp};
+use smoltcp::{
+    iface::{EthernetInterface, EthernetInterfaceBuilder, NeighborCache, Routes},
+    phy::ChecksumCapabilities,
+    socket::{
+        RawPacketMetadata, RawSocket, RawSocketBuffer, SocketHandle, SocketSet, TcpSocket,
+        TcpSocketBuffer,
+    },
+    time::Instant,
+    wire::{EthernetAddress, IpCidr, IpEndpoint, IpProtocol, IpVersion, Ipv4Address},
+};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use tokio::time::{timeout, Duration};
+
+/// A bare smoltcp stack playing the console, driven by hand from a test.
+///
+/// It owns one TCP socket and one raw UDP socket, which is all the gateway
+/// tests need.
+pub struct Console<I: Interface + 'static> {
+    iface: EthernetInterface<'static, 'static, 'static, FutureDevice<I>>,
+    sockets: SocketSet<'static, 'static, 'static>,
+    tcp: SocketHandle,
+    udp: SocketHandle,
+}
+
+impl<I: Interface + 'static> Console<I> {
+    pub fn new(interface: I, mac: EthernetAddress, ip: IpCidr, gateway_ip: Ipv4Address) -> Console<I> {
+        let device = FutureDevice::new(interface, 1500);
+        let mut routes = Routes::new(BTreeMap::new());
+        routes.add_default_ipv4_route(gateway_ip).unwrap();
+        let iface = EthernetInterfaceBuilder::new(device)
+            .ethernet_addr(mac)
+            .ip_addrs(vec![ip])
+            .neighbor_cache(NeighborCache::new(BTreeMap::new()))
+            .routes(routes)
+            .finalize();
+        let mut sockets = SocketSet::new(vec![]);
+        let tcp = sockets.add(TcpSocket::new(
+            TcpSocketBuffer::new(vec![0; 16384]),
+            TcpSocketBuffer::new(vec![0; 16384]),
+        ));
+        let udp = sockets.add(RawSocket::new(
+            IpVersion::Ipv4,
+            IpProtocol::Udp,
+            RawSocketBuffer::new(vec![RawPacketMetadata::EMPTY; 32], vec![0; 8192]),
+            RawSocketBuffer::new(vec![RawPacketMetadata::EMPTY; 32], vec![0; 8192]),
+        ));
+        Console { iface, sockets, tcp, udp }
+    }
+    pub fn with_tcp<R>(&mut self, f: impl FnOnce(&mut TcpSocket<'static>) -> R) -> R {
+        let mut socket = self.sockets.get::<TcpSocket>(self.tcp);
+        f(&mut socket)
+    }
+    pub fn connect(&mut self, remote: SocketAddr, local_port: u16) {
+        let remote: IpEndpoint = remote.into();
+        self.with_tcp(|tcp| tcp.connect(remote, local_port)).unwrap();
+    }
+    pub fn send_udp(&mut self, udp: &OwnedUdp) {
+        let mut socket = self.sockets.get::<RawSocket>(self.udp);
+        socket.send_slice(&udp.to_raw()).unwrap();
+    }
+    pub fn recv_udp(&mut self) -> Option<OwnedUdp> {
+        let mut socket = self.sockets.get::<RawSocket>(self.udp);
+        let packet = socket.recv().ok()?;
+        parse_udp_owned(packet, &ChecksumCapabilities::default()).ok()
+    }
+    /// Runs the stack until `f` returns true for the TCP socket.
+    pub async fn poll_until(&mut self, mut f: impl FnMut(&mut TcpSocket<'static>) -> bool) {
+        loop {
+            let _ = self.iface.poll(&mut self.sockets, Instant::now());
+            self.iface.device_mut().send_queue().await.unwrap();
+            if self.with_tcp(&mut f) {
+                return;
+            }
+            self.wait().await;
+        }
+    }
+    /// Runs the stack until a UDP datagram arrives.
+    pub async fn poll_udp(&mut self) -> OwnedUdp {
+        loop {
+            let _ = self.iface.poll(&mut self.sockets, Instant::now());
+            self.iface.device_mut().send_queue().await.unwrap();
+            if let Some(udp) = self.recv_udp() {
+                return udp;
+            }
+            self.wait().await;
+        }
+    }
+    async fn wait(&mut self) {
+        let device = self.iface.device_mut();
+        if device.need_wait() {
+            let _ = timeout(Duration::from_millis(10), device.wait()).await;
+        }
+    }
+}
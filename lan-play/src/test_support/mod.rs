@@ -0,0 +1,10 @@
+//! In-process network plumbing for tests: a virtual Ethernet switch and a
+//! smoltcp "console" that can talk to a `Net` without root or a real NIC.
+
+mod console;
+mod dns;
+mod virtual_ethernet;
+
+pub use console::Console;
+pub use dns::dns_server;
+pub use virtual_ethernet::{pair, Impairment, VirtualPort, VirtualSwitch};
@@ -0,0 +1,210 @@
+use crate::future_smoltcp::Packet;
+use async_channel::{unbounded, Receiver, Sender};
+use futures::{Stream, Sink};
+use smoltcp::wire::{EthernetAddress, EthernetFrame};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::{pin::Pin, task::{Context, Poll}, io};
+use tokio::time::{sleep, Duration};
+
+/// Network conditions applied to every delivered frame.
+#[derive(Debug, Clone, Copy)]
+pub struct Impairment {
+    /// Probability (0.0 - 1.0) that a frame is dropped
+    pub loss: f64,
+    /// Fixed delay before a frame is delivered
+    pub delay: Duration,
+    /// Probability (0.0 - 1.0) that a frame is held back and overtaken by later ones
+    pub reorder: f64,
+    /// Seed of the random generator, so failures are reproducible
+    pub seed: u64,
+}
+
+impl Default for Impairment {
+    fn default() -> Self {
+        Impairment {
+            loss: 0.0,
+            delay: Duration::from_millis(0),
+            reorder: 0.0,
+            seed: 0x5eed,
+        }
+    }
+}
+
+/// xorshift64*, good enough to pick which frames to mess with.
+struct Rng(u64);
+
+impl Rng {
+    fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        let r = self.0.wrapping_mul(0x2545_f491_4f6c_dd1d);
+        (r >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+struct Inner {
+    ports: Vec<Sender<Packet>>,
+    macs: HashMap<EthernetAddress, usize>,
+    impairment: Impairment,
+    rng: Rng,
+}
+
+/// A learning Ethernet switch living in memory.
+///
+/// Frames to a known MAC go to its port, everything else is flooded.
+#[derive(Clone)]
+pub struct VirtualSwitch {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl VirtualSwitch {
+    pub fn new() -> VirtualSwitch {
+        VirtualSwitch::with_impairment(Impairment::default())
+    }
+    pub fn with_impairment(impairment: Impairment) -> VirtualSwitch {
+        VirtualSwitch {
+            inner: Arc::new(Mutex::new(Inner {
+                ports: Vec::new(),
+                macs: HashMap::new(),
+                impairment,
+                rng: Rng(impairment.seed.max(1)),
+            })),
+        }
+    }
+    pub fn set_impairment(&self, impairment: Impairment) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.impairment = impairment;
+        inner.rng = Rng(impairment.seed.max(1));
+    }
+    /// Plugs a new cable into the switch.
+    pub fn port(&self) -> VirtualPort {
+        let (tx, rx) = unbounded();
+        let mut inner = self.inner.lock().unwrap();
+        inner.ports.push(tx);
+        VirtualPort {
+            id: inner.ports.len() - 1,
+            switch: self.clone(),
+            rx,
+        }
+    }
+    fn forward(&self, from: usize, packet: Packet) {
+        let mut inner = self.inner.lock().unwrap();
+        let frame = match EthernetFrame::new_checked(&packet) {
+            Ok(frame) => frame,
+            Err(_) => return,
+        };
+        inner.macs.insert(frame.src_addr(), from);
+        let targets = match inner.macs.get(&frame.dst_addr()) {
+            Some(port) if frame.dst_addr().is_unicast() => vec![*port],
+            _ => (0..inner.ports.len()).filter(|p| *p != from).collect(),
+        };
+        for port in targets {
+            let impairment = inner.impairment;
+            if inner.rng.next_f64() < impairment.loss {
+                continue;
+            }
+            let mut delay = impairment.delay;
+            if inner.rng.next_f64() < impairment.reorder {
+                delay += impairment.delay + Duration::from_millis(1);
+            }
+            let tx = inner.ports[port].clone();
+            let packet = packet.clone();
+            if delay == Duration::from_millis(0) {
+                let _ = tx.try_send(packet);
+            } else {
+                tokio::spawn(async move {
+                    sleep(delay).await;
+                    let _ = tx.try_send(packet);
+                });
+            }
+        }
+    }
+}
+
+/// One port of a `VirtualSwitch`, usable as a `future_smoltcp` interface.
+pub struct VirtualPort {
+    id: usize,
+    switch: VirtualSwitch,
+    rx: Receiver<Packet>,
+}
+
+/// Two ports connected by an ideal cable.
+pub fn pair() -> (VirtualPort, VirtualPort) {
+    let switch = VirtualSwitch::new();
+    (switch.port(), switch.port())
+}
+
+impl Stream for VirtualPort {
+    type Item = Packet;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        Stream::poll_next(Pin::new(&mut self.rx), cx)
+    }
+}
+
+impl Sink<Packet> for VirtualPort {
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Packet) -> Result<(), Self::Error> {
+        self.switch.forward(self.id, item);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::{SinkExt, StreamExt};
+
+    fn frame(src: u8, dst: EthernetAddress) -> Packet {
+        let mut buf = vec![0u8; 64];
+        let mut frame = EthernetFrame::new_unchecked(&mut buf);
+        frame.set_src_addr(EthernetAddress([0x02, 0, 0, 0, 0, src]));
+        frame.set_dst_addr(dst);
+        buf
+    }
+
+    #[tokio::test]
+    async fn test_learning_switch() {
+        let switch = VirtualSwitch::new();
+        let (mut a, mut b, mut c) = (switch.port(), switch.port(), switch.port());
+
+        // unknown destination is flooded
+        a.send(frame(1, EthernetAddress::BROADCAST)).await.unwrap();
+        assert!(b.next().await.is_some());
+        assert!(c.next().await.is_some());
+
+        // a's MAC is learned, so the reply only reaches a
+        b.send(frame(2, EthernetAddress([0x02, 0, 0, 0, 0, 1]))).await.unwrap();
+        assert!(a.next().await.is_some());
+        assert!(c.rx.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_loss() {
+        let switch = VirtualSwitch::with_impairment(Impairment {
+            loss: 1.0,
+            ..Default::default()
+        });
+        let (mut a, b) = (switch.port(), switch.port());
+        a.send(frame(1, EthernetAddress::BROADCAST)).await.unwrap();
+        assert!(b.rx.is_empty());
+    }
+}
@@ -0,0 +1,76 @@
+extern crate crossbeam_utils;
+/**
+    Compares receiving through AF_PACKET copies with receiving through the memory-mapped ring.
+
+    Needs a veth pair and CAP_NET_RAW:
+
+    ip link add veth0 type veth peer name veth1
+    ip link set veth0 up && ip link set veth1 up
+    cargo run --release --example af_packet_bench -- veth0 veth1 1000000
+*/
+extern crate rawsock;
+
+#[cfg(target_os = "linux")]
+fn main() {
+    use crossbeam_utils::thread;
+    use rawsock::af_packet::{Library, RingOptions};
+    use rawsock::traits::{DynamicInterface, StaticInterface};
+    use std::env::args;
+    use std::time::Instant;
+
+    let args: Vec<String> = args().collect();
+    let tx_name = args.get(1).map(String::as_str).unwrap_or("veth0");
+    let rx_name = args.get(2).map(String::as_str).unwrap_or("veth1");
+    let count: u64 = args.get(3).and_then(|c| c.parse().ok()).unwrap_or(1_000_000);
+
+    //broadcast frame with the local experimental ether type
+    let mut frame = vec![0xffu8; 6];
+    frame.extend_from_slice(&[0x02, 0, 0, 0, 0, 1, 0x88, 0xb5]);
+    frame.resize(64, 0);
+
+    for (mode, lib) in &[
+        ("copy", Library::default()),
+        ("ring", Library::with_ring(RingOptions::default())),
+    ] {
+        let sender = lib
+            .open_interface(tx_name)
+            .expect("Could not open sending interface");
+        let receiver = lib
+            .open_interface(rx_name)
+            .expect("Could not open receiving interface");
+        let start = Instant::now();
+        let mut received = 0u64;
+        thread::scope(|s| {
+            s.spawn(|_| {
+                for _ in 0..count {
+                    // the send ring may be full, just try again
+                    while sender.send(&frame).is_err() {}
+                }
+                receiver.break_loop();
+            });
+            receiver
+                .loop_infinite(|packet| {
+                    if packet.len() == frame.len() {
+                        received += 1;
+                    }
+                })
+                .expect("Error when running receiving loop");
+        })
+        .unwrap();
+        let elapsed = start.elapsed().as_secs_f64();
+        println!(
+            "{}: received {} of {} packets in {:.2}s, {:.0} packets/s, {} dropped",
+            mode,
+            received,
+            count,
+            elapsed,
+            received as f64 / elapsed,
+            receiver.stats().map(|s| s.dropped).unwrap_or(0)
+        );
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    println!("AF_PACKET sockets are only available on Linux");
+}
@@ -11,6 +11,10 @@ fn print_interfaces(interfs: Vec<InterfaceDescription>) {
     println!("Found interfaces:");
     for (num, interf) in interfs.iter().enumerate() {
         println!("{}: {}, {}", num, interf.name, interf.description);
+        println!("   flags: {:?}, {:?}", interf.flags, interf.connection);
+        for addr in &interf.addresses {
+            println!("   address: {}", addr.address);
+        }
     }
 }
 
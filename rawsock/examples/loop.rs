@@ -52,7 +52,7 @@ fn dynamic_loop() {
 
     let mut count: usize = 0;
     interf
-        .loop_infinite_dyn(&|packet| {
+        .loop_infinite_dyn(&mut |packet| {
             count += 1;
             println!("Received packet: {:?}", packet);
             if count >= 5 {
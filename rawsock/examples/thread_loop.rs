@@ -38,7 +38,7 @@ fn dynamic_loop() {
     thread::scope(|s| {
         s.spawn(|_| {
             interf
-                .loop_infinite_dyn(&|packet| {
+                .loop_infinite_dyn(&mut |packet| {
                     println!("Received packet: {}", packet);
                 })
                 .expect("Error when running receiving loop");
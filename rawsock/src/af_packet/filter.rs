@@ -0,0 +1,402 @@
+use super::structs::SockFilter;
+use crate::{DataLink, Endpoint, Error, Filter, Protocol};
+
+/**
+Parses a compiled BPF program in the format printed by `tcpdump -ddd`.
+
+The first number is the count of instructions, followed by one instruction per line:
+`code jt jf k`, all in decimal.
+
+# Example
+
+```
+use rawsock::af_packet::parse_program;
+
+//accept everything
+let program = parse_program("1\n6 0 0 262144\n").unwrap();
+assert_eq!(program.len(), 1);
+```
+*/
+pub fn parse_program(text: &str) -> Result<Vec<SockFilter>, Error> {
+    let invalid = |what: &str| Error::LibraryError(format!("Invalid BPF program: {}", what));
+    let mut lines = text.lines().map(str::trim).filter(|l| !l.is_empty());
+    let count: usize = lines
+        .next()
+        .and_then(|l| l.parse().ok())
+        .ok_or_else(|| invalid("missing instruction count"))?;
+    let program = lines
+        .map(|line| {
+            let fields: Vec<u32> = line
+                .split_whitespace()
+                .map(|f| f.parse().map_err(|_| invalid(line)))
+                .collect::<Result<_, _>>()?;
+            match fields[..] {
+                [code, jt, jf, k] if code <= 0xffff && jt <= 0xff && jf <= 0xff => Ok(SockFilter {
+                    code: code as u16,
+                    jt: jt as u8,
+                    jf: jf as u8,
+                    k,
+                }),
+                _ => Err(invalid(line)),
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if program.len() != count || count == 0 || count > 0xffff {
+        return Err(invalid("wrong instruction count"));
+    }
+    Ok(program)
+}
+
+///Accepts the whole packet.
+const RET_ACCEPT: u32 = 262_144;
+//most instructions the kernel accepts in one program
+const MAX_INSTRUCTIONS: usize = 4096;
+
+const LD_W_ABS: u16 = 0x20;
+const LD_H_ABS: u16 = 0x28;
+const LD_B_ABS: u16 = 0x30;
+const LD_H_IND: u16 = 0x48;
+const LDX_B_MSH: u16 = 0xb1;
+const ALU_AND_K: u16 = 0x54;
+const JMP_JA: u16 = 0x05;
+const JMP_JEQ_K: u16 = 0x15;
+const JMP_JSET_K: u16 = 0x45;
+const RET_K: u16 = 0x06;
+
+const ETHERTYPE_IP: u16 = 0x0800;
+const ETHERTYPE_ARP: u16 = 0x0806;
+const ETHERTYPE_RARP: u16 = 0x8035;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+
+///Single comparison that a filter is made of.
+enum Test {
+    ///The value loaded from an offset, masked if a mask is given, equals `value`.
+    Equal {
+        load: u16,
+        offset: u32,
+        mask: Option<u32>,
+        value: u32,
+    },
+    ///Any of the bits are set in the value loaded from an offset.
+    AnySet { load: u16, offset: u32, bits: u32 },
+    ///The 16-bit value at an offset of the payload of the IPv4 header at `header` equals `value`.
+    Payload { header: u32, offset: u32, value: u32 },
+}
+
+enum Expr {
+    Const(bool),
+    Test(Test),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+fn and(a: Expr, b: Expr) -> Expr {
+    Expr::And(Box::new(a), Box::new(b))
+}
+
+fn or(a: Expr, b: Expr) -> Expr {
+    Expr::Or(Box::new(a), Box::new(b))
+}
+
+fn equal(load: u16, offset: u32, value: u32) -> Expr {
+    Expr::Test(Test::Equal {
+        load,
+        offset,
+        mask: None,
+        value,
+    })
+}
+
+///Combines the tests of the source and destination side as the endpoint asks.
+fn sides(endpoint: Endpoint, src: Expr, dst: Expr) -> Expr {
+    match endpoint {
+        Endpoint::SrcOrDst => or(src, dst),
+        Endpoint::Src => src,
+        Endpoint::Dst => dst,
+    }
+}
+
+///Turns filters into tests on the packets of one data link.
+struct Lowering {
+    ethernet: bool,
+    //offset of the network layer
+    nl: u32,
+}
+
+impl Lowering {
+    fn ether_type(&self, ether_type: u16) -> Expr {
+        if self.ethernet {
+            return equal(LD_H_ABS, 12, u32::from(ether_type));
+        }
+        // raw IP packets only tell their version
+        let version = match ether_type {
+            ETHERTYPE_IP => 0x40,
+            ETHERTYPE_IPV6 => 0x60,
+            _ => return Expr::Const(false),
+        };
+        Expr::Test(Test::Equal {
+            load: LD_B_ABS,
+            offset: 0,
+            mask: Some(0xf0),
+            value: version,
+        })
+    }
+
+    fn ip_protocol(&self, number: u32) -> Expr {
+        and(self.ether_type(ETHERTYPE_IP), equal(LD_B_ABS, self.nl + 9, number))
+    }
+
+    ///IPv4 and ARP packets whose addresses are in the network.
+    fn net(&self, endpoint: Endpoint, address: u32, len: u8) -> Result<Expr, Error> {
+        if len > 32 {
+            return Err(Error::LibraryError(format!("Invalid prefix length {}", len)));
+        }
+        let mask = if len == 0 { 0 } else { u32::max_value() << (32 - len) };
+        let test = |offset| {
+            if mask == 0 {
+                return Expr::Const(true);
+            }
+            Expr::Test(Test::Equal {
+                load: LD_W_ABS,
+                offset,
+                mask: if mask == u32::max_value() { None } else { Some(mask) },
+                value: address & mask,
+            })
+        };
+        let ip = sides(endpoint, test(self.nl + 12), test(self.nl + 16));
+        // sender and target protocol addresses of ARP
+        let arp = sides(endpoint, test(self.nl + 14), test(self.nl + 24));
+        Ok(or(
+            and(self.ether_type(ETHERTYPE_IP), ip),
+            and(self.ether_type(ETHERTYPE_ARP), arp),
+        ))
+    }
+
+    fn port(&self, proto: Option<Protocol>, endpoint: Endpoint, port: u16) -> Result<Expr, Error> {
+        let proto = match proto {
+            None => or(self.ip_protocol(6), self.ip_protocol(17)),
+            Some(Protocol::Tcp) => self.ip_protocol(6),
+            Some(Protocol::Udp) => self.ip_protocol(17),
+            Some(other) => {
+                return Err(Error::LibraryError(format!("{} has no ports", other)));
+            }
+        };
+        // only the first fragment has the ports
+        let first_fragment = Expr::Not(Box::new(Expr::Test(Test::AnySet {
+            load: LD_H_ABS,
+            offset: self.nl + 6,
+            bits: 0x1fff,
+        })));
+        let port = |offset| {
+            Expr::Test(Test::Payload {
+                header: self.nl,
+                offset,
+                value: u32::from(port),
+            })
+        };
+        Ok(and(proto, and(first_fragment, sides(endpoint, port(0), port(2)))))
+    }
+
+    fn ether_host(&self, endpoint: Endpoint, mac: &[u8; 6]) -> Expr {
+        if !self.ethernet {
+            return Expr::Const(false);
+        }
+        let at = |offset| {
+            and(
+                equal(LD_W_ABS, offset + 2, u32::from_be_bytes([mac[2], mac[3], mac[4], mac[5]])),
+                equal(LD_H_ABS, offset, u32::from(u16::from_be_bytes([mac[0], mac[1]]))),
+            )
+        };
+        sides(endpoint, at(6), at(0))
+    }
+
+    fn lower(&self, filter: &Filter) -> Result<Expr, Error> {
+        Ok(match filter {
+            Filter::Protocol(Protocol::Ip) => self.ether_type(ETHERTYPE_IP),
+            Filter::Protocol(Protocol::Ip6) => self.ether_type(ETHERTYPE_IPV6),
+            Filter::Protocol(Protocol::Arp) => self.ether_type(ETHERTYPE_ARP),
+            Filter::Protocol(Protocol::Rarp) => self.ether_type(ETHERTYPE_RARP),
+            Filter::Protocol(Protocol::Icmp) => self.ip_protocol(1),
+            Filter::Protocol(Protocol::Tcp) => self.ip_protocol(6),
+            Filter::Protocol(Protocol::Udp) => self.ip_protocol(17),
+            Filter::EtherType(ether_type) => self.ether_type(*ether_type),
+            Filter::EtherHost(endpoint, mac) => self.ether_host(*endpoint, mac),
+            Filter::Host(endpoint, address) => self.net(*endpoint, u32::from(*address), 32)?,
+            Filter::Net(endpoint, address, len) => self.net(*endpoint, u32::from(*address), *len)?,
+            Filter::Port(proto, endpoint, port) => self.port(*proto, *endpoint, *port)?,
+            Filter::Not(filter) => Expr::Not(Box::new(self.lower(filter)?)),
+            Filter::And(a, b) => and(self.lower(a)?, self.lower(b)?),
+            Filter::Or(a, b) => or(self.lower(a)?, self.lower(b)?),
+        })
+    }
+}
+
+enum Jump {
+    None,
+    Always(usize),
+    Cond(usize, usize),
+}
+
+///Emits instructions that jump to labels, resolved once the program is complete.
+struct Codegen {
+    code: Vec<(SockFilter, Jump)>,
+    labels: Vec<usize>,
+}
+
+impl Codegen {
+    fn label(&mut self) -> usize {
+        self.labels.push(usize::max_value());
+        self.labels.len() - 1
+    }
+
+    ///Makes the label point at the next instruction.
+    fn place(&mut self, label: usize) {
+        self.labels[label] = self.code.len();
+    }
+
+    fn emit(&mut self, code: u16, k: u32, jump: Jump) {
+        self.code.push((SockFilter { code, jt: 0, jf: 0, k }, jump));
+    }
+
+    ///Emits `expr`, which goes on at `t` if the packet matches and at `f` otherwise.
+    fn expr(&mut self, expr: &Expr, t: usize, f: usize) {
+        match expr {
+            Expr::Const(true) => self.emit(JMP_JA, 0, Jump::Always(t)),
+            Expr::Const(false) => self.emit(JMP_JA, 0, Jump::Always(f)),
+            Expr::Not(expr) => self.expr(expr, f, t),
+            Expr::And(a, b) => {
+                let next = self.label();
+                self.expr(a, next, f);
+                self.place(next);
+                self.expr(b, t, f);
+            }
+            Expr::Or(a, b) => {
+                let next = self.label();
+                self.expr(a, t, next);
+                self.place(next);
+                self.expr(b, t, f);
+            }
+            Expr::Test(Test::Equal {
+                load,
+                offset,
+                mask,
+                value,
+            }) => {
+                self.emit(*load, *offset, Jump::None);
+                if let Some(mask) = mask {
+                    self.emit(ALU_AND_K, *mask, Jump::None);
+                }
+                self.emit(JMP_JEQ_K, *value, Jump::Cond(t, f));
+            }
+            Expr::Test(Test::AnySet { load, offset, bits }) => {
+                self.emit(*load, *offset, Jump::None);
+                self.emit(JMP_JSET_K, *bits, Jump::Cond(t, f));
+            }
+            Expr::Test(Test::Payload {
+                header,
+                offset,
+                value,
+            }) => {
+                // length of the IPv4 header, then the value behind it
+                self.emit(LDX_B_MSH, *header, Jump::None);
+                self.emit(LD_H_IND, header + offset, Jump::None);
+                self.emit(JMP_JEQ_K, *value, Jump::Cond(t, f));
+            }
+        }
+    }
+
+    fn finish(self) -> Result<Vec<SockFilter>, Error> {
+        if self.code.len() > MAX_INSTRUCTIONS {
+            return Err(Error::LibraryError("Filter needs too many instructions".into()));
+        }
+        let labels = self.labels;
+        self.code
+            .into_iter()
+            .enumerate()
+            .map(|(i, (mut insn, jump))| {
+                let offset = |label: usize| labels[label] - (i + 1);
+                match jump {
+                    Jump::None => (),
+                    Jump::Always(label) => insn.k = offset(label) as u32,
+                    Jump::Cond(t, f) => {
+                        let (t, f) = (offset(t), offset(f));
+                        if t > 0xff || f > 0xff {
+                            return Err(Error::LibraryError("Filter jumps too far".into()));
+                        }
+                        insn.jt = t as u8;
+                        insn.jf = f as u8;
+                    }
+                }
+                Ok(insn)
+            })
+            .collect()
+    }
+}
+
+/**
+Compiles a filter into a BPF program for sockets of the given data link.
+
+Only Ethernet and raw IP links are supported. Primitives that cannot match on a link, such as
+hardware addresses on raw IP, never match.
+
+# Example
+
+```
+use rawsock::af_packet::compile_program;
+use rawsock::{DataLink, Filter, Protocol};
+
+let program = compile_program(&Filter::Protocol(Protocol::Arp), DataLink::Ethernet).unwrap();
+assert_eq!(program.len(), 4);
+```
+*/
+pub fn compile_program(filter: &Filter, data_link: DataLink) -> Result<Vec<SockFilter>, Error> {
+    let lowering = match data_link {
+        DataLink::Ethernet => Lowering {
+            ethernet: true,
+            nl: 14,
+        },
+        DataLink::RawIp => Lowering {
+            ethernet: false,
+            nl: 0,
+        },
+        other => {
+            return Err(Error::LibraryError(format!(
+                "Filters cannot be compiled for {} links",
+                other
+            )))
+        }
+    };
+    let expr = lowering.lower(filter)?;
+    let mut codegen = Codegen {
+        code: Vec::new(),
+        labels: Vec::new(),
+    };
+    let (accept, reject) = (codegen.label(), codegen.label());
+    codegen.expr(&expr, accept, reject);
+    codegen.place(accept);
+    codegen.emit(RET_K, RET_ACCEPT, Jump::None);
+    codegen.place(reject);
+    codegen.emit(RET_K, 0, Jump::None);
+    codegen.finish()
+}
+
+/**
+Turns the text given to `set_filter()` into a program.
+
+The text is either a program printed by `tcpdump -ddd`, or a filter expression. An empty text
+accepts every packet.
+*/
+pub(super) fn program_from_text(text: &str, data_link: DataLink) -> Result<Vec<SockFilter>, Error> {
+    let text = text.trim();
+    match text.chars().next() {
+        None => Ok(vec![SockFilter {
+            code: RET_K,
+            jt: 0,
+            jf: 0,
+            k: RET_ACCEPT,
+        }]),
+        Some(c) if c.is_ascii_digit() => parse_program(text),
+        Some(_) => compile_program(&text.parse::<Filter>()?, data_link),
+    }
+}
@@ -0,0 +1,434 @@
+use super::filter::program_from_text;
+use super::ring::{Ring, RingOptions};
+use super::structs::*;
+use crate::utils::{last_errno, send_error, string_from_errno};
+use crate::common::SendCounters;
+use crate::{traits, BorrowedPacket, DataLink, Direction, Error, InterfaceOptions, Stats};
+use libc::{c_int, c_void, socklen_t};
+use std::ffi::{CStr, CString};
+use std::mem::{size_of, zeroed};
+use std::ptr::null;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+use time::Timespec;
+
+///AF_PACKET version of interface.
+pub struct Interface {
+    fd: c_int,
+    datalink: DataLink,
+    nonblock: bool,
+    read_timeout: Duration,
+    buffer: Vec<u8>,
+    break_loop: AtomicBool,
+    received: AtomicU64,
+    dropped: AtomicU64,
+    send_counters: SendCounters,
+    ring: Option<Ring>,
+}
+
+///Receives one packet into `buffer`, returning its captured length.
+fn recv(fd: c_int, buffer: &mut [u8], flags: c_int) -> Result<usize, Error> {
+    let len = unsafe { libc::recv(fd, buffer.as_mut_ptr() as *mut c_void, buffer.len(), flags) };
+    if len >= 0 {
+        return Ok((len as usize).min(buffer.len()));
+    }
+    match std::io::Error::last_os_error().raw_os_error() {
+        Some(libc::EAGAIN) | Some(libc::EINTR) => Err(Error::Timeout),
+        _ => Err(Error::ReceivingPacket(string_from_errno())),
+    }
+}
+
+pub(super) fn setsockopt<T>(fd: c_int, level: c_int, name: c_int, value: &T) -> Result<(), Error> {
+    if 0 == unsafe {
+        libc::setsockopt(
+            fd,
+            level,
+            name,
+            value as *const T as *const c_void,
+            size_of::<T>() as socklen_t,
+        )
+    } {
+        Ok(())
+    } else {
+        Err(Error::LibraryError(string_from_errno()))
+    }
+}
+
+///Waits until the socket is readable, returning false on timeout.
+fn wait(fd: c_int, timeout: Duration) -> bool {
+    let mut pollfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let ms = timeout.as_millis().min(c_int::max_value() as u128) as c_int;
+    unsafe { libc::poll(&mut pollfd, 1, ms) > 0 }
+}
+
+impl Interface {
+    pub fn new(name: &str) -> Result<Self, Error> {
+        Self::open(name, &InterfaceOptions::default(), None)
+    }
+
+    /**
+    Opens the interface with the given options, and with memory-mapped rings if `ring` is set.
+
+    The snapshot length only applies to copied packets, packets in a ring are cut to its frames.
+    Capturing only sent packets is not supported, the socket can only be told to leave them out.
+    */
+    pub fn open(
+        name: &str,
+        options: &InterfaceOptions,
+        ring: Option<&RingOptions>,
+    ) -> Result<Self, Error> {
+        if options.direction == Direction::Out {
+            return Err(Error::OpeningInterface(
+                "capturing only sent packets is not supported".into(),
+            ));
+        }
+        let cname = CString::new(name)?;
+        let ifindex = unsafe { libc::if_nametoindex(cname.as_ptr()) };
+        if ifindex == 0 {
+            return Err(Error::OpeningInterface(format!(
+                "{}: no such interface",
+                name
+            )));
+        }
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_PACKET,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                c_int::from(ETH_P_ALL.to_be()),
+            )
+        };
+        if fd < 0 {
+            return Err(Error::OpeningInterface(string_from_errno()));
+        }
+        let mut ret = Interface {
+            fd,
+            datalink: DataLink::Other,
+            nonblock: false,
+            read_timeout: options.read_timeout,
+            buffer: vec![0; options.snaplen as usize],
+            break_loop: AtomicBool::new(false),
+            received: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            send_counters: SendCounters::default(),
+            ring: None,
+        };
+
+        let mut addr: libc::sockaddr_ll = unsafe { zeroed() };
+        addr.sll_family = libc::AF_PACKET as u16;
+        addr.sll_protocol = ETH_P_ALL.to_be();
+        addr.sll_ifindex = ifindex as c_int;
+        if 0 != unsafe {
+            libc::bind(
+                fd,
+                &addr as *const libc::sockaddr_ll as *const libc::sockaddr,
+                size_of::<libc::sockaddr_ll>() as socklen_t,
+            )
+        } {
+            return Err(Error::OpeningInterface(string_from_errno()));
+        }
+
+        if options.promiscuous {
+            let mreq = PacketMreq {
+                mr_ifindex: ifindex as c_int,
+                mr_type: PACKET_MR_PROMISC,
+                mr_alen: 0,
+                mr_address: [0; 8],
+            };
+            setsockopt(fd, SOL_PACKET, PACKET_ADD_MEMBERSHIP, &mreq)
+                .map_err(|_| Error::OpeningInterface(string_from_errno()))?;
+        }
+        if let Some(size) = options.buffer_size {
+            let size = size.min(c_int::max_value() as u32) as c_int;
+            setsockopt(fd, libc::SOL_SOCKET, libc::SO_RCVBUF, &size)
+                .map_err(|_| Error::OpeningInterface(string_from_errno()))?;
+        }
+        if options.direction == Direction::In {
+            let on: c_int = 1;
+            setsockopt(fd, SOL_PACKET, PACKET_IGNORE_OUTGOING, &on)
+                .map_err(|_| Error::OpeningInterface(string_from_errno()))?;
+        }
+
+        ret.datalink = match ret.hardware_type(&cname)? {
+            // the loopback device has fake Ethernet headers
+            ARPHRD_ETHER | ARPHRD_LOOPBACK => DataLink::Ethernet,
+            // tun devices and the like
+            ARPHRD_NONE | ARPHRD_PPP | ARPHRD_RAWIP => DataLink::RawIp,
+            // wireless interfaces in monitor mode
+            ARPHRD_IEEE80211_RADIOTAP => DataLink::Ieee80211Radio,
+            _ => DataLink::Other,
+        };
+        if let Some(options) = ring {
+            ret.ring = Some(Ring::new(ret.fd, options)?);
+        }
+        Ok(ret)
+    }
+
+    /**
+    Opens the interface with memory-mapped rings.
+
+    Packets are then received without copying them out of the kernel, and sent through the send
+    ring when it has frames.
+    */
+    pub fn with_ring(name: &str, options: &RingOptions) -> Result<Self, Error> {
+        Self::open(name, &InterfaceOptions::default(), Some(options))
+    }
+
+    ///Whether packets go through memory-mapped rings.
+    pub fn has_ring(&self) -> bool {
+        self.ring.is_some()
+    }
+
+    ///Attaches a compiled BPF program to the socket.
+    pub fn set_bpf_program(&mut self, program: &[SockFilter]) -> Result<(), Error> {
+        let prog = SockFprog {
+            len: program.len() as u16,
+            filter: program.as_ptr(),
+        };
+        setsockopt(self.fd, libc::SOL_SOCKET, SO_ATTACH_FILTER, &prog)?;
+        // packets queued before the filter was attached did not go through it
+        match &mut self.ring {
+            Some(ring) => ring.rx.get_mut().unwrap().drain(),
+            None => while recv(self.fd, &mut self.buffer, libc::MSG_DONTWAIT).is_ok() {},
+        }
+        Ok(())
+    }
+
+    fn hardware_type(&self, name: &CStr) -> Result<u16, Error> {
+        let mut req: IfReq = unsafe { zeroed() };
+        for (dst, src) in req
+            .ifr_name
+            .iter_mut()
+            .zip(name.to_bytes().iter().take(IFNAMSIZ - 1))
+        {
+            *dst = *src as _;
+        }
+        if 0 != unsafe { libc::ioctl(self.fd, SIOCGIFHWADDR as _, &mut req) } {
+            return Err(Error::OpeningInterface(string_from_errno()));
+        }
+        Ok(req.ifr_hwaddr.sa_family)
+    }
+
+    fn last_timestamp(&self) -> Timespec {
+        let mut ts: KernelTimespec = unsafe { zeroed() };
+        if 0 == unsafe { libc::ioctl(self.fd, SIOCGSTAMPNS as _, &mut ts) } {
+            Timespec::new(ts.tv_sec as i64, ts.tv_nsec as i32)
+        } else {
+            time::get_time()
+        }
+    }
+
+    fn receive_ring(&mut self, timeout: Duration) -> Result<BorrowedPacket<'_>, Error> {
+        let rx = match &mut self.ring {
+            Some(ring) => ring.rx.get_mut().unwrap(),
+            None => return Err(Error::ReceivingPacket("The interface has no ring".into())),
+        };
+        if !rx.ready() && !wait(self.fd, timeout) {
+            return Err(Error::Timeout);
+        }
+        let packet = rx.next().ok_or(Error::Timeout)?;
+        self.received.fetch_add(1, Ordering::Relaxed);
+        Ok(packet)
+    }
+
+    fn receive_wait(&mut self, timeout: Duration) -> Result<BorrowedPacket<'_>, Error> {
+        if self.ring.is_some() {
+            return self.receive_ring(timeout);
+        }
+        if !wait(self.fd, timeout) {
+            return Err(Error::Timeout);
+        }
+        let len = recv(self.fd, &mut self.buffer, libc::MSG_DONTWAIT)?;
+        self.received.fetch_add(1, Ordering::Relaxed);
+        let when = self.last_timestamp();
+        Ok(BorrowedPacket::new(when, &self.buffer[..len]))
+    }
+}
+
+impl Drop for Interface {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+impl<'a> traits::DynamicInterface<'a> for Interface {
+    fn send(&self, packet: &[u8]) -> Result<(), Error> {
+        if let Some(tx) = self.ring.as_ref().and_then(|ring| ring.tx.as_ref()) {
+            let result = tx.lock().unwrap().send(self.fd, packet);
+            return self.send_counters.count(1, result);
+        }
+        let flags = if self.nonblock { libc::MSG_DONTWAIT } else { 0 };
+        let len = unsafe { libc::send(self.fd, packet.as_ptr() as *const c_void, packet.len(), flags) };
+        let result = if len < 0 {
+            Err(send_error(last_errno()))
+        } else {
+            Ok(())
+        };
+        self.send_counters.count(1, result)
+    }
+
+    ///Fills the send ring when there is one, otherwise hands the packets over with sendmmsg().
+    fn send_batch(&self, packets: &[&[u8]]) -> Result<(), Error> {
+        if let Some(tx) = self.ring.as_ref().and_then(|ring| ring.tx.as_ref()) {
+            let result = tx.lock().unwrap().send_batch(self.fd, packets);
+            return self.send_counters.count(packets.len(), result);
+        }
+        let mut iovecs: Vec<libc::iovec> = packets
+            .iter()
+            .map(|p| libc::iovec {
+                iov_base: p.as_ptr() as *mut c_void,
+                iov_len: p.len(),
+            })
+            .collect();
+        let mut msgs: Vec<libc::mmsghdr> = iovecs
+            .iter_mut()
+            .map(|iov| {
+                let mut msg: libc::mmsghdr = unsafe { zeroed() };
+                msg.msg_hdr.msg_iov = iov;
+                msg.msg_hdr.msg_iovlen = 1;
+                msg
+            })
+            .collect();
+        let mut sent = 0;
+        while sent < msgs.len() {
+            let rest = &mut msgs[sent..];
+            let count = unsafe { libc::sendmmsg(self.fd, rest.as_mut_ptr(), rest.len() as _, 0) };
+            if count < 0 {
+                if std::io::Error::last_os_error().raw_os_error() == Some(libc::EINTR) {
+                    continue;
+                }
+                self.send_counters.add(sent, msgs.len() - sent);
+                return Err(Error::SendingPacket(string_from_errno()));
+            }
+            sent += count as usize;
+        }
+        self.send_counters.add(sent, 0);
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<BorrowedPacket<'_>, Error> {
+        let timeout = if self.nonblock {
+            Duration::from_secs(0)
+        } else {
+            self.read_timeout
+        };
+        self.receive_wait(timeout)
+    }
+
+    fn receive_timeout(&mut self, timeout: Duration) -> Result<BorrowedPacket<'_>, Error> {
+        self.receive_wait(timeout)
+    }
+
+    fn set_nonblock(&mut self, nonblock: bool) -> Result<(), Error> {
+        self.nonblock = nonblock;
+        Ok(())
+    }
+
+    fn selectable_fd(&self) -> Option<c_int> {
+        Some(self.fd)
+    }
+
+    fn flush(&self) {
+        //packets are sent right away - ignore
+    }
+
+    fn data_link(&self) -> DataLink {
+        self.datalink
+    }
+
+    fn stats(&self) -> Result<Stats, Error> {
+        // the kernel resets its counters on every read
+        let mut stats: TPacketStats = unsafe { zeroed() };
+        let mut len = size_of::<TPacketStats>() as socklen_t;
+        if 0 != unsafe {
+            libc::getsockopt(
+                self.fd,
+                SOL_PACKET,
+                PACKET_STATISTICS,
+                &mut stats as *mut TPacketStats as *mut c_void,
+                &mut len,
+            )
+        } {
+            return Err(Error::LibraryError(string_from_errno()));
+        }
+        self.dropped
+            .fetch_add(u64::from(stats.tp_drops), Ordering::Relaxed);
+        Ok(self.send_counters.fill(Stats {
+            received: self.received.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            ..Stats::default()
+        }))
+    }
+
+    fn break_loop(&self) {
+        self.break_loop.store(true, Ordering::SeqCst)
+    }
+
+    fn loop_infinite_dyn(&self, callback: &mut dyn FnMut(&BorrowedPacket)) -> Result<(), Error> {
+        traits::StaticInterface::loop_infinite(self, callback)
+    }
+
+    fn set_filter_cstr(&mut self, filter: &CStr) -> Result<(), Error> {
+        let program = program_from_text(&filter.to_string_lossy(), self.datalink)?;
+        self.set_bpf_program(&program)
+    }
+
+    fn remove_filter(&mut self) -> Result<(), Error> {
+        if 0 == unsafe { libc::setsockopt(self.fd, libc::SOL_SOCKET, SO_DETACH_FILTER, null(), 0) }
+        {
+            return Ok(());
+        }
+        match std::io::Error::last_os_error().raw_os_error() {
+            // there was no filter
+            Some(libc::ENOENT) => Ok(()),
+            _ => Err(Error::LibraryError(string_from_errno())),
+        }
+    }
+}
+
+impl<'a> traits::StaticInterface<'a> for Interface {
+    fn loop_infinite<F>(&self, mut callback: F) -> Result<(), Error>
+    where
+        F: FnMut(&BorrowedPacket),
+    {
+        if let Some(ring) = &self.ring {
+            let mut rx = ring.rx.lock().unwrap();
+            while !self.break_loop.load(Ordering::SeqCst) {
+                if !rx.ready() && !wait(self.fd, Duration::from_millis(100)) {
+                    continue;
+                }
+                if let Some(packet) = rx.next() {
+                    self.received.fetch_add(1, Ordering::Relaxed);
+                    callback(&packet);
+                }
+                // the kernel gets the block back as soon as its last packet was handled
+                rx.finish_block();
+            }
+            self.break_loop.store(false, Ordering::SeqCst);
+            return Ok(());
+        }
+        // loops run on a shared interface, so they need their own buffer
+        let mut buffer = vec![0u8; self.buffer.len()];
+        while !self.break_loop.load(Ordering::SeqCst) {
+            // wake up now and then to notice break_loop()
+            if !wait(self.fd, Duration::from_millis(100)) {
+                continue;
+            }
+            let len = match recv(self.fd, &mut buffer, libc::MSG_DONTWAIT) {
+                Ok(len) => len,
+                Err(Error::Timeout) => continue,
+                Err(e) => return Err(e),
+            };
+            self.received.fetch_add(1, Ordering::Relaxed);
+            callback(&BorrowedPacket::new(self.last_timestamp(), &buffer[..len]));
+        }
+        // a break that came before the loop started ends it too, clear it once handled
+        self.break_loop.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+}
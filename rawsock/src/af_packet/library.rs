@@ -0,0 +1,181 @@
+use super::filter::program_from_text;
+use super::interface::Interface;
+use super::ring::RingOptions;
+use super::structs::{if_freenameindex, if_nameindex};
+use crate::utils::{cstr_to_string, ip_from_sockaddr, mac_from_sockaddr, string_from_errno};
+use crate::{
+    traits, ConnectionStatus, DataLink, Error, InterfaceAddress, InterfaceDescription,
+    InterfaceFlags, InterfaceOptions, LibraryVersion,
+};
+use libc::{c_int, c_void};
+use std::mem::zeroed;
+use std::path::Path;
+use std::ptr::null_mut;
+use std::sync::Arc;
+
+///AF_PACKET sockets of the running kernel, used like a library.
+#[derive(Default)]
+pub struct Library {
+    ring: Option<RingOptions>,
+}
+
+impl traits::Library for Library {
+    ///There is nothing to load, the sockets are always available on Linux.
+    fn open_default_paths() -> Result<Self, Error> {
+        Ok(Library::default())
+    }
+
+    fn default_paths() -> &'static [&'static str]
+    where
+        Self: Sized,
+    {
+        &[]
+    }
+
+    fn open(_path: &str) -> Result<Self, Error> {
+        Ok(Library::default())
+    }
+
+    fn open_interface<'a>(
+        &'a self,
+        name: &str,
+    ) -> Result<Box<dyn traits::DynamicInterface<'a> + 'a>, Error> {
+        let interf = self.open_interface(name)?;
+        Ok(Box::new(interf) as Box<dyn traits::DynamicInterface>)
+    }
+
+    fn open_interface_arc<'a>(
+        &'a self,
+        name: &str,
+    ) -> Result<Arc<dyn traits::DynamicInterface<'a> + 'a>, Error> {
+        let interf = self.open_interface(name)?;
+        Ok(Arc::new(interf) as Arc<dyn traits::DynamicInterface>)
+    }
+
+    fn open_interface_with_options<'a>(
+        &'a self,
+        name: &str,
+        options: &InterfaceOptions,
+    ) -> Result<Box<dyn traits::DynamicInterface<'a> + 'a>, Error> {
+        let interf = self.open_interface_with_options(name, options)?;
+        Ok(Box::new(interf) as Box<dyn traits::DynamicInterface>)
+    }
+
+    fn open_interface_arc_with_options<'a>(
+        &'a self,
+        name: &str,
+        options: &InterfaceOptions,
+    ) -> Result<Arc<dyn traits::DynamicInterface<'a> + 'a>, Error> {
+        let interf = self.open_interface_with_options(name, options)?;
+        Ok(Arc::new(interf) as Arc<dyn traits::DynamicInterface>)
+    }
+
+    fn compile_filter(&self, filter: &str, data_link: DataLink) -> Result<(), Error> {
+        program_from_text(filter, data_link).map(|_| ())
+    }
+
+    fn all_interfaces(&self) -> Result<Vec<InterfaceDescription>, Error> {
+        let list = unsafe { if_nameindex() };
+        if list.is_null() {
+            return Err(Error::GettingDeviceDescriptionList(string_from_errno()));
+        }
+        let mut interfs = Vec::new();
+        let mut entry = list;
+        unsafe {
+            while (*entry).if_index != 0 {
+                interfs.push(InterfaceDescription {
+                    name: cstr_to_string((*entry).if_name),
+                    ..Default::default()
+                });
+                entry = entry.add(1);
+            }
+            if_freenameindex(list);
+        }
+        add_addresses(&mut interfs);
+        Ok(interfs)
+    }
+
+    fn version(&self) -> LibraryVersion {
+        let mut name: libc::utsname = unsafe { zeroed() };
+        let release = if 0 == unsafe { libc::uname(&mut name) } {
+            cstr_to_string(name.release.as_ptr())
+        } else {
+            String::new()
+        };
+        LibraryVersion::AfPacket(release)
+    }
+}
+
+impl Library {
+    ///Library whose interfaces receive and send through memory-mapped rings.
+    pub fn with_ring(options: RingOptions) -> Self {
+        Library {
+            ring: Some(options),
+        }
+    }
+
+    pub fn open_interface(&self, name: &str) -> Result<Interface, Error> {
+        self.open_interface_with_options(name, &InterfaceOptions::default())
+    }
+
+    pub fn open_interface_with_options(
+        &self,
+        name: &str,
+        options: &InterfaceOptions,
+    ) -> Result<Interface, Error> {
+        Interface::open(name, options, self.ring.as_ref())
+    }
+}
+
+///Fills in flags and addresses from getifaddrs(), which lists every address of every interface.
+fn add_addresses(interfs: &mut [InterfaceDescription]) {
+    let mut list: *mut libc::ifaddrs = null_mut();
+    if 0 != unsafe { libc::getifaddrs(&mut list) } {
+        return;
+    }
+    let mut curr = list;
+    while !curr.is_null() {
+        let entry = unsafe { &*curr };
+        curr = entry.ifa_next;
+        let name = cstr_to_string(entry.ifa_name);
+        let interf = match interfs.iter_mut().find(|i| i.name == name) {
+            Some(interf) => interf,
+            None => continue,
+        };
+        let flags = entry.ifa_flags as c_int;
+        interf.flags = InterfaceFlags::empty();
+        interf.flags.set(InterfaceFlags::UP, flags & libc::IFF_UP != 0);
+        interf
+            .flags
+            .set(InterfaceFlags::RUNNING, flags & libc::IFF_RUNNING != 0);
+        interf
+            .flags
+            .set(InterfaceFlags::LOOPBACK, flags & libc::IFF_LOOPBACK != 0);
+        interf.flags.set(
+            InterfaceFlags::WIRELESS,
+            Path::new("/sys/class/net").join(&name).join("wireless").exists(),
+        );
+        interf.connection = if flags & libc::IFF_LOOPBACK != 0 {
+            ConnectionStatus::NotApplicable
+        } else if flags & libc::IFF_RUNNING != 0 {
+            ConnectionStatus::Connected
+        } else {
+            ConnectionStatus::Disconnected
+        };
+
+        let addr = entry.ifa_addr as *const c_void;
+        if let Some(address) = ip_from_sockaddr(addr) {
+            // the same field holds either address, depending on the flags
+            let other = ip_from_sockaddr(entry.ifa_ifu as *const c_void);
+            interf.addresses.push(InterfaceAddress {
+                address,
+                netmask: ip_from_sockaddr(entry.ifa_netmask as *const c_void),
+                broadcast: other.filter(|_| flags & libc::IFF_BROADCAST != 0),
+                destination: other.filter(|_| flags & libc::IFF_POINTOPOINT != 0),
+            });
+        } else if let Some(mac) = mac_from_sockaddr(addr) {
+            interf.hardware_address = Some(mac);
+        }
+    }
+    unsafe { libc::freeifaddrs(list) };
+}
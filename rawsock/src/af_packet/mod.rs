@@ -0,0 +1,26 @@
+/*!
+Native Linux packet capture through `AF_PACKET` sockets, without any shared library.
+
+Useful on stripped systems (such as OpenWrt images) where no pcap library is installed.
+Opening interfaces requires the `CAP_NET_RAW` capability.
+
+`set_filter()` compiles the filter expressions that `Filter` parses, a subset of the pcap syntax,
+into BPF programs of its own. Programs compiled elsewhere can be given in the format printed by
+`tcpdump -ddd`.
+
+With `Library::with_ring()` packets go through TPACKET_V3 memory-mapped rings instead of a copy
+per packet: received packets borrow the ring memory, and each block goes back to the kernel once
+its packets were handled.
+*/
+
+mod filter;
+mod interface;
+mod library;
+mod ring;
+mod structs;
+
+pub use self::filter::{compile_program, parse_program};
+pub use self::interface::Interface;
+pub use self::library::Library;
+pub use self::ring::RingOptions;
+pub use self::structs::SockFilter;
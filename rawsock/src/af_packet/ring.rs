@@ -0,0 +1,338 @@
+use super::interface::setsockopt;
+use super::structs::*;
+use crate::utils::string_from_errno;
+use crate::{BorrowedPacket, Error};
+use libc::{c_int, c_void};
+use std::mem::size_of;
+use std::ptr::{copy_nonoverlapping, null, null_mut};
+use std::slice::from_raw_parts;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use time::Timespec;
+
+//TPACKET_ALIGN(sizeof(struct tpacket3_hdr)), where the kernel expects sent data
+const TX_DATA_OFFSET: usize = (size_of::<TPacket3Hdr>() + 15) & !15;
+
+/**
+Sizes of the memory-mapped rings of an interface.
+
+Received packets are borrowed straight from the receive ring. The kernel fills blocks of packets
+and hands each block over when it is full or when `block_timeout` passes.
+*/
+#[derive(Debug, Clone)]
+pub struct RingOptions {
+    ///Size of a receive block, a multiple of the page size.
+    pub block_size: u32,
+    ///Number of receive blocks.
+    pub block_count: u32,
+    ///Time after which a block that is not full is handed over anyway.
+    pub block_timeout: Duration,
+    ///Size of a send frame, which limits the size of sent packets.
+    pub tx_frame_size: u32,
+    ///Number of send frames, 0 to send without a ring.
+    pub tx_frame_count: u32,
+}
+
+impl Default for RingOptions {
+    fn default() -> Self {
+        RingOptions {
+            block_size: 1 << 18,
+            block_count: 8,
+            block_timeout: Duration::from_millis(10),
+            tx_frame_size: 1 << 11,
+            tx_frame_count: 256,
+        }
+    }
+}
+
+fn status(ptr: *mut u32) -> &'static AtomicU32 {
+    unsafe { &*(ptr as *const AtomicU32) }
+}
+
+///Receive side of the ring: blocks of packets handed over by the kernel in turn.
+pub struct RxRing {
+    map: *mut u8,
+    block_size: usize,
+    block_count: usize,
+    //block being read and whether the kernel handed it over
+    block: usize,
+    held: bool,
+    remaining: u32,
+    offset: usize,
+}
+
+unsafe impl Send for RxRing {}
+
+impl RxRing {
+    fn desc(&self, block: usize) -> *mut TPacketBlockDesc {
+        unsafe { self.map.add(block * self.block_size) as *mut TPacketBlockDesc }
+    }
+
+    fn user_owned(&self, block: usize) -> bool {
+        let desc = self.desc(block);
+        let status = status(unsafe { &mut (*desc).block_status });
+        status.load(Ordering::Acquire) & TP_STATUS_USER != 0
+    }
+
+    ///Whether next() has a packet to return.
+    pub fn ready(&self) -> bool {
+        self.remaining > 0 || self.user_owned(self.next_block())
+    }
+
+    fn next_block(&self) -> usize {
+        if self.held {
+            (self.block + 1) % self.block_count
+        } else {
+            self.block
+        }
+    }
+
+    ///Next received packet, if the kernel has handed one over.
+    ///
+    /// The block of the packet goes back to the kernel once all its packets are read.
+    pub fn next(&mut self) -> Option<BorrowedPacket<'_>> {
+        while self.remaining == 0 {
+            self.release();
+            if !self.user_owned(self.block) {
+                return None;
+            }
+            let desc = unsafe { &*self.desc(self.block) };
+            self.held = true;
+            self.remaining = desc.num_pkts;
+            self.offset = desc.offset_to_first_pkt as usize;
+        }
+        unsafe {
+            let start = (self.desc(self.block) as *const u8).add(self.offset);
+            let hdr = &*(start as *const TPacket3Hdr);
+            self.remaining -= 1;
+            self.offset += hdr.tp_next_offset as usize;
+            let data = from_raw_parts(start.add(hdr.tp_mac as usize), hdr.tp_snaplen as usize);
+            let when = Timespec::new(i64::from(hdr.tp_sec), hdr.tp_nsec as i32);
+            Some(BorrowedPacket::new(when, data))
+        }
+    }
+
+    ///Gives the current block back to the kernel if all its packets were read.
+    pub fn finish_block(&mut self) {
+        if self.remaining == 0 {
+            self.release()
+        }
+    }
+
+    ///Gives back all blocks that were handed over, with their packets unread.
+    pub fn drain(&mut self) {
+        self.remaining = 0;
+        self.release();
+        while self.user_owned(self.block) {
+            self.held = true;
+            self.release();
+        }
+    }
+
+    fn release(&mut self) {
+        if self.held {
+            let desc = self.desc(self.block);
+            status(unsafe { &mut (*desc).block_status }).store(TP_STATUS_KERNEL, Ordering::Release);
+            self.held = false;
+            self.block = (self.block + 1) % self.block_count;
+        }
+    }
+}
+
+///Send side of the ring: frames filled in turn and sent by the kernel.
+pub struct TxRing {
+    map: *mut u8,
+    block_size: usize,
+    frame_size: usize,
+    frames_per_block: usize,
+    frame_count: usize,
+    frame: usize,
+}
+
+unsafe impl Send for TxRing {}
+
+impl TxRing {
+    fn hdr(&self, frame: usize) -> *mut TPacket3Hdr {
+        let offset =
+            frame / self.frames_per_block * self.block_size + frame % self.frames_per_block * self.frame_size;
+        unsafe { self.map.add(offset) as *mut TPacket3Hdr }
+    }
+
+    fn busy(&self, frame: usize) -> bool {
+        let status = status(unsafe { &mut (*self.hdr(frame)).tp_status });
+        status.load(Ordering::Acquire) & (TP_STATUS_SEND_REQUEST | TP_STATUS_SENDING) != 0
+    }
+
+    ///Queues the packet in the next frame and asks the kernel to send it.
+    pub fn send(&mut self, fd: c_int, packet: &[u8]) -> Result<(), Error> {
+        self.queue(fd, packet)?;
+        kick(fd, libc::MSG_DONTWAIT)
+    }
+
+    ///Queues all packets, then asks the kernel once to send them.
+    pub fn send_batch(&mut self, fd: c_int, packets: &[&[u8]]) -> Result<(), Error> {
+        for packet in packets {
+            self.queue(fd, packet)?;
+        }
+        kick(fd, libc::MSG_DONTWAIT)
+    }
+
+    fn queue(&mut self, fd: c_int, packet: &[u8]) -> Result<(), Error> {
+        if packet.len() > self.frame_size - TX_DATA_OFFSET {
+            return Err(Error::SendingPacket(format!(
+                "Packet of {} bytes does not fit in a send frame",
+                packet.len()
+            )));
+        }
+        if self.busy(self.frame) {
+            // the ring is full, wait until the kernel sent what is queued
+            kick(fd, 0)?;
+            if self.busy(self.frame) {
+                // sent again once the socket is writable
+                return Err(Error::WouldBlock);
+            }
+        }
+        unsafe {
+            let hdr = self.hdr(self.frame);
+            copy_nonoverlapping(
+                packet.as_ptr(),
+                (hdr as *mut u8).add(TX_DATA_OFFSET),
+                packet.len(),
+            );
+            (*hdr).tp_next_offset = 0;
+            (*hdr).tp_len = packet.len() as u32;
+            (*hdr).tp_snaplen = packet.len() as u32;
+            status(&mut (*hdr).tp_status).store(TP_STATUS_SEND_REQUEST, Ordering::Release);
+        }
+        self.frame = (self.frame + 1) % self.frame_count;
+        Ok(())
+    }
+}
+
+///Makes the kernel send the frames queued in the send ring.
+fn kick(fd: c_int, flags: c_int) -> Result<(), Error> {
+    if unsafe { libc::send(fd, null(), 0, flags) } >= 0 {
+        return Ok(());
+    }
+    match std::io::Error::last_os_error().raw_os_error() {
+        Some(libc::EAGAIN) | Some(libc::ENOBUFS) | Some(libc::EINTR) => Ok(()),
+        _ => Err(Error::SendingPacket(string_from_errno())),
+    }
+}
+
+///Memory-mapped TPACKET_V3 rings of a socket.
+pub struct Ring {
+    map: *mut u8,
+    map_len: usize,
+    pub rx: Mutex<RxRing>,
+    pub tx: Option<Mutex<TxRing>>,
+}
+
+unsafe impl Send for Ring {}
+unsafe impl Sync for Ring {}
+
+impl Ring {
+    pub fn new(fd: c_int, options: &RingOptions) -> Result<Self, Error> {
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as u32;
+        let block_size = options.block_size;
+        let frame_size = options.tx_frame_size;
+        if block_size == 0 || block_size % page_size != 0 || options.block_count == 0 {
+            return Err(Error::LibraryError(format!(
+                "Ring blocks must be a multiple of {} bytes",
+                page_size
+            )));
+        }
+        if options.tx_frame_count > 0
+            && (frame_size % 16 != 0 || (frame_size as usize) <= TX_DATA_OFFSET || frame_size > block_size)
+        {
+            return Err(Error::LibraryError(format!(
+                "Invalid send frame size {}",
+                frame_size
+            )));
+        }
+
+        setsockopt(fd, SOL_PACKET, PACKET_VERSION, &TPACKET_V3)?;
+        let rx_req = TPacketReq3 {
+            tp_block_size: block_size,
+            tp_block_nr: options.block_count,
+            // frames are only used by older versions but must be consistent
+            tp_frame_size: 1 << 11,
+            tp_frame_nr: block_size / (1 << 11) * options.block_count,
+            tp_retire_blk_tov: options.block_timeout.as_millis() as u32,
+            tp_sizeof_priv: 0,
+            tp_feature_req_word: 0,
+        };
+        setsockopt(fd, SOL_PACKET, PACKET_RX_RING, &rx_req)?;
+        let rx_len = block_size as usize * options.block_count as usize;
+
+        let frames_per_block = (block_size / frame_size.max(1)) as usize;
+        let tx_blocks = if options.tx_frame_count > 0 {
+            (options.tx_frame_count as usize + frames_per_block - 1) / frames_per_block
+        } else {
+            0
+        };
+        if tx_blocks > 0 {
+            let tx_req = TPacketReq3 {
+                tp_block_size: block_size,
+                tp_block_nr: tx_blocks as u32,
+                tp_frame_size: frame_size,
+                tp_frame_nr: (tx_blocks * frames_per_block) as u32,
+                tp_retire_blk_tov: 0,
+                tp_sizeof_priv: 0,
+                tp_feature_req_word: 0,
+            };
+            setsockopt(fd, SOL_PACKET, PACKET_TX_RING, &tx_req)?;
+        }
+        let map_len = rx_len + tx_blocks * block_size as usize;
+
+        let map = unsafe {
+            libc::mmap(
+                null_mut(),
+                map_len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd,
+                0,
+            )
+        };
+        if map == libc::MAP_FAILED {
+            return Err(Error::LibraryError(string_from_errno()));
+        }
+        let map = map as *mut u8;
+        let tx = if tx_blocks > 0 {
+            Some(Mutex::new(TxRing {
+                map: unsafe { map.add(rx_len) },
+                block_size: block_size as usize,
+                frame_size: frame_size as usize,
+                frames_per_block,
+                frame_count: tx_blocks * frames_per_block,
+                frame: 0,
+            }))
+        } else {
+            None
+        };
+        Ok(Ring {
+            map,
+            map_len,
+            rx: Mutex::new(RxRing {
+                map,
+                block_size: block_size as usize,
+                block_count: options.block_count as usize,
+                block: 0,
+                held: false,
+                remaining: 0,
+                offset: 0,
+            }),
+            tx,
+        })
+    }
+}
+
+impl Drop for Ring {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.map as *mut c_void, self.map_len);
+        }
+    }
+}
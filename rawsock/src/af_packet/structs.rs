@@ -0,0 +1,136 @@
+#![allow(dead_code)]
+
+use libc::{c_char, c_int, c_long, c_uchar, c_uint, c_ushort, sockaddr};
+
+pub const ETH_P_ALL: u16 = 0x0003;
+pub const SOL_PACKET: c_int = 263;
+pub const PACKET_ADD_MEMBERSHIP: c_int = 1;
+pub const PACKET_MR_PROMISC: c_ushort = 1;
+pub const PACKET_STATISTICS: c_int = 6;
+pub const PACKET_IGNORE_OUTGOING: c_int = 23;
+pub const SO_ATTACH_FILTER: c_int = 26;
+pub const SO_DETACH_FILTER: c_int = 27;
+pub const SIOCGSTAMPNS: c_int = 0x8907;
+pub const SIOCGIFHWADDR: c_int = 0x8927;
+pub const IFNAMSIZ: usize = 16;
+pub const PACKET_RX_RING: c_int = 5;
+pub const PACKET_VERSION: c_int = 10;
+pub const PACKET_TX_RING: c_int = 13;
+pub const TPACKET_V3: c_int = 2;
+pub const TP_STATUS_KERNEL: u32 = 0;
+pub const TP_STATUS_USER: u32 = 1;
+pub const TP_STATUS_AVAILABLE: u32 = 0;
+pub const TP_STATUS_SEND_REQUEST: u32 = 1;
+pub const TP_STATUS_SENDING: u32 = 2;
+pub const TP_STATUS_WRONG_FORMAT: u32 = 4;
+pub const ARPHRD_ETHER: c_ushort = 1;
+pub const ARPHRD_PPP: c_ushort = 512;
+pub const ARPHRD_RAWIP: c_ushort = 519;
+pub const ARPHRD_LOOPBACK: c_ushort = 772;
+pub const ARPHRD_IEEE80211_RADIOTAP: c_ushort = 803;
+pub const ARPHRD_NONE: c_ushort = 0xfffe;
+
+///One instruction of a classic BPF program, equivalent of struct sock_filter.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SockFilter {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
+///Equivalent of struct sock_fprog
+#[repr(C)]
+pub struct SockFprog {
+    pub len: c_ushort,
+    pub filter: *const SockFilter,
+}
+
+///Equivalent of struct packet_mreq
+#[repr(C)]
+pub struct PacketMreq {
+    pub mr_ifindex: c_int,
+    pub mr_type: c_ushort,
+    pub mr_alen: c_ushort,
+    pub mr_address: [c_uchar; 8],
+}
+
+///Equivalent of struct tpacket_stats
+#[repr(C)]
+pub struct TPacketStats {
+    pub tp_packets: c_uint,
+    pub tp_drops: c_uint,
+}
+
+///Equivalent of struct tpacket_req3
+#[repr(C)]
+pub struct TPacketReq3 {
+    pub tp_block_size: c_uint,
+    pub tp_block_nr: c_uint,
+    pub tp_frame_size: c_uint,
+    pub tp_frame_nr: c_uint,
+    pub tp_retire_blk_tov: c_uint,
+    pub tp_sizeof_priv: c_uint,
+    pub tp_feature_req_word: c_uint,
+}
+
+///Equivalent of struct tpacket_block_desc with its struct tpacket_hdr_v1
+#[repr(C)]
+pub struct TPacketBlockDesc {
+    pub version: u32,
+    pub offset_to_priv: u32,
+    pub block_status: u32,
+    pub num_pkts: u32,
+    pub offset_to_first_pkt: u32,
+    pub blk_len: u32,
+    pub seq_num: u64,
+    pub ts_first_pkt: [u32; 2],
+    pub ts_last_pkt: [u32; 2],
+}
+
+///Equivalent of struct tpacket3_hdr
+#[repr(C)]
+pub struct TPacket3Hdr {
+    pub tp_next_offset: u32,
+    pub tp_sec: u32,
+    pub tp_nsec: u32,
+    pub tp_snaplen: u32,
+    pub tp_len: u32,
+    pub tp_status: u32,
+    pub tp_mac: u16,
+    pub tp_net: u16,
+    pub hv1_rxhash: u32,
+    pub hv1_vlan_tci: u32,
+    pub hv1_vlan_tpid: u16,
+    pub hv1_padding: u16,
+    pub tp_padding: [u8; 8],
+}
+
+///Equivalent of struct if_nameindex
+#[repr(C)]
+pub struct IfNameIndex {
+    pub if_index: c_uint,
+    pub if_name: *const c_char,
+}
+
+///Equivalent of struct ifreq, as used by SIOCGIFHWADDR.
+#[repr(C)]
+pub struct IfReq {
+    pub ifr_name: [c_char; IFNAMSIZ],
+    pub ifr_hwaddr: sockaddr,
+    //the rest of the union
+    pub _pad: [u8; 8],
+}
+
+///Timestamp returned by SIOCGSTAMPNS (the original version with long fields).
+#[repr(C)]
+pub struct KernelTimespec {
+    pub tv_sec: c_long,
+    pub tv_nsec: c_long,
+}
+
+extern "C" {
+    pub fn if_nameindex() -> *const IfNameIndex;
+    pub fn if_freenameindex(ptr: *const IfNameIndex);
+}
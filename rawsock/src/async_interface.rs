@@ -0,0 +1,158 @@
+/*!
+Async access to an interface, driven by the tokio reactor instead of a thread blocked in a loop.
+
+Available on unix with the `async-tokio` feature.
+*/
+
+use crate::traits::DynamicInterface;
+use crate::{Error, OwnedPacket};
+use futures_core::Stream;
+use futures_sink::Sink;
+use libc::c_int;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::unix::AsyncFd;
+
+struct SelectableFd(c_int);
+
+impl AsRawFd for SelectableFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
+    }
+}
+
+/**
+Stream of received packets and sink of packets to send.
+
+The interface is switched to non-blocking mode and only read when its selectable file descriptor
+is readable. Use it with the `StreamExt` and `SinkExt` helpers of the futures crate.
+
+Packets fed into the sink are kept until it is flushed, and then sent one by one whenever the
+file descriptor is writable, so that a full send buffer never blocks the task.
+
+# Example
+
+```no_run
+# async fn run() -> Result<(), rawsock::Error> {
+use rawsock::AsyncInterface;
+
+let lib = rawsock::open_best_library()?;
+let interf = lib.open_interface("eth0")?;
+let interf = AsyncInterface::new(interf).map_err(|(err, _interf)| err)?;
+println!("Interface opened, data link: {}", interf.get_ref().data_link());
+# Ok(())
+# }
+```
+*/
+pub struct AsyncInterface<'a> {
+    // deregistered before the interface closes the descriptor
+    fd: AsyncFd<SelectableFd>,
+    interface: Box<dyn DynamicInterface<'a> + 'a>,
+    pending: Vec<Vec<u8>>,
+    ///Packets of `pending` already sent
+    sent: usize,
+}
+
+impl<'a> AsyncInterface<'a> {
+    ///Registers `interface` with the reactor of the current tokio runtime.
+    ///
+    /// Fails if the library offers no selectable file descriptor for it. The interface is then
+    /// handed back unchanged, so that it can still be used from a thread.
+    pub fn new(
+        mut interface: Box<dyn DynamicInterface<'a> + 'a>,
+    ) -> Result<Self, (Error, Box<dyn DynamicInterface<'a> + 'a>)> {
+        let fd = match interface.selectable_fd() {
+            Some(fd) => fd,
+            None => {
+                let err = Error::LibraryError("Interface has no selectable file descriptor".into());
+                return Err((err, interface));
+            }
+        };
+        if let Err(err) = interface.set_nonblock(true) {
+            return Err((err, interface));
+        }
+        let fd = match AsyncFd::new(SelectableFd(fd)) {
+            Ok(fd) => fd,
+            Err(e) => {
+                let _ = interface.set_nonblock(false);
+                return Err((Error::LibraryError(e.to_string()), interface));
+            }
+        };
+        Ok(AsyncInterface {
+            fd,
+            interface,
+            pending: Vec::new(),
+            sent: 0,
+        })
+    }
+
+    ///The wrapped interface.
+    pub fn get_ref(&self) -> &(dyn DynamicInterface<'a> + 'a) {
+        &*self.interface
+    }
+}
+
+impl<'a> Stream for AsyncInterface<'a> {
+    type Item = Result<OwnedPacket, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            let mut guard = match this.fd.poll_read_ready(cx) {
+                Poll::Ready(Ok(guard)) => guard,
+                Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(Error::ReceivingPacket(e.to_string())))),
+                Poll::Pending => return Poll::Pending,
+            };
+            match this.interface.receive() {
+                Ok(packet) => return Poll::Ready(Some(Ok(packet.into_owned()))),
+                // drained, wait for the next edge
+                Err(Error::Timeout) => guard.clear_ready(),
+                Err(Error::EndOfFile) => return Poll::Ready(None),
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            }
+        }
+    }
+}
+
+impl<'a, P: AsRef<[u8]>> Sink<P> for AsyncInterface<'a> {
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, packet: P) -> Result<(), Self::Error> {
+        self.get_mut().pending.push(packet.as_ref().to_vec());
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        while this.sent < this.pending.len() {
+            let mut guard = match this.fd.poll_write_ready(cx) {
+                Poll::Ready(Ok(guard)) => guard,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(Error::SendingPacket(e.to_string()))),
+                Poll::Pending => return Poll::Pending,
+            };
+            match this.interface.send(&this.pending[this.sent]) {
+                Ok(()) => this.sent += 1,
+                // the send buffer filled up, wait until it drains
+                Err(Error::WouldBlock) => guard.clear_ready(),
+                Err(e) => {
+                    this.pending.clear();
+                    this.sent = 0;
+                    return Poll::Ready(Err(e));
+                }
+            }
+        }
+        this.pending.clear();
+        this.sent = 0;
+        this.interface.flush();
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Sink::<P>::poll_flush(self, cx)
+    }
+}
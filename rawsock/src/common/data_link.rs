@@ -1,18 +1,71 @@
 use std::fmt::{Display, Error as FmtError, Formatter};
 
 ///Kind of data link - protocol used below the surface.
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum DataLink {
     Ethernet,
+    ///IPv4 or IPv6 packets without any link header, e.g. on tun and ppp interfaces.
     RawIp,
+    ///Linux cooked capture, used for the `any` interface.
+    LinuxSll,
+    ///Linux cooked capture v2, which also tells the interface of every packet.
+    LinuxSll2,
+    ///BSD loopback, with the address family in host byte order.
+    Null,
+    ///OpenBSD loopback, with the address family in network byte order.
+    Loop,
+    ///802.11 frames preceded by a radiotap header.
+    Ieee80211Radio,
     Other,
 }
 
+impl DataLink {
+    ///Translates a pcap link-layer header type (DLT_*).
+    pub fn from_dlt(dlt: i32) -> DataLink {
+        match dlt {
+            0 => DataLink::Null,
+            1 => DataLink::Ethernet,
+            #[cfg(not(target_os = "openbsd"))]
+            12 => DataLink::RawIp,
+            // OpenBSD has its own DLT_LOOP and DLT_RAW
+            #[cfg(target_os = "openbsd")]
+            12 => DataLink::Loop,
+            #[cfg(target_os = "openbsd")]
+            14 => DataLink::RawIp,
+            108 => DataLink::Loop,
+            113 => DataLink::LinuxSll,
+            127 => DataLink::Ieee80211Radio,
+            276 => DataLink::LinuxSll2,
+            _ => DataLink::Other,
+        }
+    }
+
+    ///Matching pcap link-layer header type (DLT_*), if there is one.
+    pub fn dlt(self) -> Option<i32> {
+        match self {
+            DataLink::Null => Some(0),
+            DataLink::Ethernet => Some(1),
+            DataLink::RawIp if cfg!(target_os = "openbsd") => Some(14),
+            DataLink::RawIp => Some(12),
+            DataLink::Loop => Some(108),
+            DataLink::LinuxSll => Some(113),
+            DataLink::Ieee80211Radio => Some(127),
+            DataLink::LinuxSll2 => Some(276),
+            DataLink::Other => None,
+        }
+    }
+}
+
 impl Display for DataLink {
     fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
         match self {
             DataLink::Ethernet => write!(f, "ethernet"),
             DataLink::RawIp => write!(f, "raw IP"),
+            DataLink::LinuxSll => write!(f, "Linux cooked"),
+            DataLink::LinuxSll2 => write!(f, "Linux cooked v2"),
+            DataLink::Null => write!(f, "BSD loopback"),
+            DataLink::Loop => write!(f, "OpenBSD loopback"),
+            DataLink::Ieee80211Radio => write!(f, "802.11 radiotap"),
             DataLink::Other => write!(f, "other"),
         }
     }
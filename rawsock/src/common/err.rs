@@ -1,8 +1,10 @@
 use dlopen::Error as DlopenError;
 use std::convert::From;
 use std::error::Error as ErrorTrait;
+use super::filter::FilterError;
 use std::ffi::NulError;
 use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::Error as IoError;
 
 /// Error enumeration returned by this crate.
 #[derive(Debug)]
@@ -15,12 +17,25 @@ pub enum Error {
     OpeningInterface(String),
     ///Receiving raw packet failed.
     ReceivingPacket(String),
+    ///No packet arrived before the timeout, or none was ready in non-blocking mode.
+    Timeout,
+    ///There are no more packets to read from a capture file.
+    EndOfFile,
     ///Sending raw packet failed.
     SendingPacket(String),
+    ///The packet could not be sent right away in non-blocking mode, try again once the
+    /// interface is writable.
+    WouldBlock,
     ///Obtaining device description list failed.
     GettingDeviceDescriptionList(String),
     ///No paths were provided by the user
     NoPathsProvided,
+    ///Reading or writing a capture file failed.
+    Io(IoError),
+    ///A capture file is damaged or uses a format that is not supported.
+    CaptureFormat(String),
+    ///A filter expression is wrong.
+    InvalidFilter(FilterError),
     LibraryError(String),
 }
 
@@ -31,15 +46,29 @@ impl Display for Error {
             Error::NullCharacter(ref err) => err.fmt(f),
             Error::OpeningInterface(ref txt) => f.write_str(txt),
             Error::ReceivingPacket(ref txt) => f.write_str(txt),
+            Error::Timeout => f.write_str("Timed out waiting for a packet."),
+            Error::EndOfFile => f.write_str("No more packets in the capture file."),
             Error::SendingPacket(ref txt) => f.write_str(txt),
+            Error::WouldBlock => f.write_str("Sending the packet would block."),
             Error::GettingDeviceDescriptionList(ref txt) => f.write_str(txt),
             Error::NoPathsProvided => f.write_str("No library paths were provided."),
+            Error::Io(ref err) => err.fmt(f),
+            Error::CaptureFormat(ref txt) => f.write_str(txt),
+            Error::InvalidFilter(ref err) => err.fmt(f),
             Error::LibraryError(ref txt) => f.write_str(txt),
         }
     }
 }
 
 impl ErrorTrait for Error {
+    fn source(&self) -> Option<&(dyn ErrorTrait + 'static)> {
+        match *self {
+            Error::DllError(ref err) => Some(err),
+            Error::NullCharacter(ref err) => Some(err),
+            Error::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
 }
 
 impl From<::dlopen::Error> for Error {
@@ -53,3 +82,15 @@ impl From<NulError> for Error {
         Error::NullCharacter(err)
     }
 }
+
+impl From<FilterError> for Error {
+    fn from(err: FilterError) -> Error {
+        Error::InvalidFilter(err)
+    }
+}
+
+impl From<IoError> for Error {
+    fn from(err: IoError) -> Error {
+        Error::Io(err)
+    }
+}
@@ -0,0 +1,446 @@
+use std::error::Error as ErrorTrait;
+use std::fmt::{Display, Error as FmtError, Formatter};
+use std::net::Ipv4Addr;
+use std::ops::Not;
+use std::str::FromStr;
+
+///Which side of a packet a filter primitive looks at.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    SrcOrDst,
+    Src,
+    Dst,
+}
+
+///Protocols that filters can match by name.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Protocol {
+    Ip,
+    Ip6,
+    Arp,
+    Rarp,
+    Icmp,
+    Tcp,
+    Udp,
+}
+
+/**
+Packet filter that is written out in the pcap filter syntax.
+
+Filters can be built from their parts or parsed from an expression. Parsing covers the subset of
+the pcap syntax that the variants express, and tells where an expression went wrong.
+
+# Example
+
+```
+use rawsock::{Endpoint, Filter, Protocol};
+
+let filter = Filter::net("10.13.0.0".parse().unwrap(), 16)
+    .or(Filter::Port(Some(Protocol::Udp), Endpoint::Dst, 67));
+assert_eq!(filter.to_string(), "net 10.13.0.0/16 or udp dst port 67");
+assert_eq!("net 10.13.0.0/16 or udp dst port 67".parse::<Filter>().unwrap(), filter);
+
+let err = "udp dst prot 67".parse::<Filter>().unwrap_err();
+assert_eq!(err.position(), 8);
+```
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Filter {
+    Protocol(Protocol),
+    ///Ethernet frames with the given EtherType.
+    EtherType(u16),
+    ///Ethernet frames from or to the hardware address.
+    EtherHost(Endpoint, [u8; 6]),
+    ///IPv4 and ARP packets from or to the address.
+    Host(Endpoint, Ipv4Addr),
+    ///IPv4 and ARP packets from or to the network, given by its address and prefix length.
+    Net(Endpoint, Ipv4Addr, u8),
+    ///TCP or UDP packets from or to the port, only one of the protocols if it is given.
+    Port(Option<Protocol>, Endpoint, u16),
+    Not(Box<Filter>),
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+}
+
+impl Filter {
+    pub fn host(address: Ipv4Addr) -> Filter {
+        Filter::Host(Endpoint::SrcOrDst, address)
+    }
+
+    pub fn net(address: Ipv4Addr, prefix_len: u8) -> Filter {
+        Filter::Net(Endpoint::SrcOrDst, address, prefix_len)
+    }
+
+    pub fn port(port: u16) -> Filter {
+        Filter::Port(None, Endpoint::SrcOrDst, port)
+    }
+
+    pub fn ether_host(mac: [u8; 6]) -> Filter {
+        Filter::EtherHost(Endpoint::SrcOrDst, mac)
+    }
+
+    ///Matches packets that match both filters.
+    pub fn and(self, other: Filter) -> Filter {
+        Filter::And(Box::new(self), Box::new(other))
+    }
+
+    ///Matches packets that match any of the filters.
+    pub fn or(self, other: Filter) -> Filter {
+        Filter::Or(Box::new(self), Box::new(other))
+    }
+}
+
+impl Not for Filter {
+    type Output = Filter;
+
+    fn not(self) -> Filter {
+        Filter::Not(Box::new(self))
+    }
+}
+
+impl Display for Protocol {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        f.write_str(match self {
+            Protocol::Ip => "ip",
+            Protocol::Ip6 => "ip6",
+            Protocol::Arp => "arp",
+            Protocol::Rarp => "rarp",
+            Protocol::Icmp => "icmp",
+            Protocol::Tcp => "tcp",
+            Protocol::Udp => "udp",
+        })
+    }
+}
+
+impl Display for Endpoint {
+    ///Writes the qualifier with a trailing space, nothing for both sides.
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        f.write_str(match self {
+            Endpoint::SrcOrDst => "",
+            Endpoint::Src => "src ",
+            Endpoint::Dst => "dst ",
+        })
+    }
+}
+
+///Writes an operand of `and`, `or` and `not`, in parentheses if it is made of several parts.
+struct Operand<'a>(&'a Filter);
+
+impl<'a> Display for Operand<'a> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        match self.0 {
+            Filter::And(..) | Filter::Or(..) => write!(f, "({})", self.0),
+            other => write!(f, "{}", other),
+        }
+    }
+}
+
+impl Display for Filter {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        match self {
+            Filter::Protocol(proto) => write!(f, "{}", proto),
+            Filter::EtherType(ether_type) => write!(f, "ether proto {}", ether_type),
+            Filter::EtherHost(endpoint, mac) => write!(
+                f,
+                "ether {}host {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+                endpoint, mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]
+            ),
+            Filter::Host(endpoint, address) => write!(f, "{}host {}", endpoint, address),
+            Filter::Net(endpoint, address, len) => write!(f, "{}net {}/{}", endpoint, address, len),
+            Filter::Port(proto, endpoint, port) => {
+                if let Some(proto) = proto {
+                    write!(f, "{} ", proto)?;
+                }
+                write!(f, "{}port {}", endpoint, port)
+            }
+            Filter::Not(filter) => write!(f, "not {}", Operand(filter)),
+            Filter::And(a, b) => write!(f, "{} and {}", Operand(a), Operand(b)),
+            Filter::Or(a, b) => write!(f, "{} or {}", Operand(a), Operand(b)),
+        }
+    }
+}
+
+///Filter expression that could not be parsed, with the place where it went wrong.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterError {
+    expression: String,
+    position: usize,
+    message: String,
+}
+
+impl FilterError {
+    pub fn new(expression: &str, position: usize, message: &str) -> Self {
+        FilterError {
+            expression: expression.into(),
+            position,
+            message: message.into(),
+        }
+    }
+
+    ///Byte offset of the part of the expression that is wrong.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl Display for FilterError {
+    ///Writes the message, then the expression with a caret under the wrong part.
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        let column = self.expression[..self.position].chars().count();
+        write!(
+            f,
+            "{} at position {}\n{}\n{}^",
+            self.message,
+            self.position,
+            self.expression,
+            " ".repeat(column)
+        )
+    }
+}
+
+impl ErrorTrait for FilterError {}
+
+impl FromStr for Filter {
+    type Err = FilterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            text: s,
+            tokens: tokenize(s),
+            next: 0,
+        };
+        let filter = parser.or()?;
+        match parser.peek() {
+            None => Ok(filter),
+            Some(_) => Err(parser.error("expected \"and\" or \"or\"")),
+        }
+    }
+}
+
+///Splits an expression into words, parentheses and the `!`, `&&` and `||` operators,
+/// each with its byte offset.
+fn tokenize(text: &str) -> Vec<(usize, &str)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let single = match c {
+            '(' | ')' | '!' => Some(1),
+            '&' | '|' if chars.peek().map(|&(_, n)| n) == Some(c) => Some(2),
+            _ => None,
+        };
+        if c.is_whitespace() || single.is_some() {
+            if let Some(s) = start.take() {
+                tokens.push((s, &text[s..i]));
+            }
+        }
+        if let Some(len) = single {
+            tokens.push((i, &text[i..i + len]));
+            if len == 2 {
+                chars.next();
+            }
+        } else if !c.is_whitespace() && start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(s) = start {
+        tokens.push((s, &text[s..]));
+    }
+    tokens
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    tokens: Vec<(usize, &'a str)>,
+    next: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.next).map(|&(_, t)| t)
+    }
+
+    fn take(&mut self) -> Option<&'a str> {
+        let token = self.peek();
+        self.next += 1;
+        token
+    }
+
+    ///Error located at the next token, or at the end of the expression.
+    fn error(&self, message: &str) -> FilterError {
+        let position = match self.tokens.get(self.next) {
+            Some(&(position, _)) => position,
+            None => self.text.len(),
+        };
+        FilterError::new(self.text, position, message)
+    }
+
+    ///Error located at the token that was just taken.
+    fn error_before(&mut self, message: &str) -> FilterError {
+        self.next -= 1;
+        self.error(message)
+    }
+
+    fn or(&mut self) -> Result<Filter, FilterError> {
+        let mut filter = self.and()?;
+        while let Some("or") | Some("||") = self.peek() {
+            self.take();
+            filter = filter.or(self.and()?);
+        }
+        Ok(filter)
+    }
+
+    fn and(&mut self) -> Result<Filter, FilterError> {
+        let mut filter = self.not()?;
+        while let Some("and") | Some("&&") = self.peek() {
+            self.take();
+            filter = filter.and(self.not()?);
+        }
+        Ok(filter)
+    }
+
+    fn not(&mut self) -> Result<Filter, FilterError> {
+        match self.peek() {
+            Some("not") | Some("!") => {
+                self.take();
+                Ok(!self.not()?)
+            }
+            Some("(") => {
+                self.take();
+                let filter = self.or()?;
+                match self.take() {
+                    Some(")") => Ok(filter),
+                    Some(_) => Err(self.error_before("expected \")\"")),
+                    None => Err(self.error("expected \")\"")),
+                }
+            }
+            _ => self.primitive(),
+        }
+    }
+
+    fn primitive(&mut self) -> Result<Filter, FilterError> {
+        let proto = match self.peek() {
+            Some("ether") => {
+                self.take();
+                return self.ether();
+            }
+            Some("ip") => Protocol::Ip,
+            Some("ip6") => Protocol::Ip6,
+            Some("arp") => Protocol::Arp,
+            Some("rarp") => Protocol::Rarp,
+            Some("icmp") => Protocol::Icmp,
+            Some("tcp") => Protocol::Tcp,
+            Some("udp") => Protocol::Udp,
+            _ => return self.qualified(None),
+        };
+        self.take();
+        let has_port = proto == Protocol::Tcp || proto == Protocol::Udp;
+        match self.peek() {
+            Some("src") | Some("dst") | Some("port") if has_port => self.qualified(Some(proto)),
+            _ => Ok(Filter::Protocol(proto)),
+        }
+    }
+
+    fn endpoint(&mut self) -> Endpoint {
+        let endpoint = match self.peek() {
+            Some("src") => Endpoint::Src,
+            Some("dst") => Endpoint::Dst,
+            _ => return Endpoint::SrcOrDst,
+        };
+        self.take();
+        endpoint
+    }
+
+    ///`host`, `net` and `port` primitives, with their qualifiers.
+    fn qualified(&mut self, proto: Option<Protocol>) -> Result<Filter, FilterError> {
+        let endpoint = self.endpoint();
+        match self.take() {
+            Some("port") => Ok(Filter::Port(proto, endpoint, self.number("expected a port number")?)),
+            Some("host") if proto.is_none() => Ok(Filter::Host(endpoint, self.address()?)),
+            Some("net") if proto.is_none() => self.net(endpoint),
+            Some(_) if proto.is_some() => Err(self.error_before("expected \"port\"")),
+            Some(_) => Err(self.error_before(
+                "expected \"host\", \"net\", \"port\", \"ether\" or a protocol",
+            )),
+            None if proto.is_some() => Err(self.error("expected \"port\"")),
+            None => Err(self.error("expected a filter primitive")),
+        }
+    }
+
+    fn ether(&mut self) -> Result<Filter, FilterError> {
+        let endpoint = self.endpoint();
+        match self.peek() {
+            Some("proto") if endpoint == Endpoint::SrcOrDst => {
+                self.take();
+                return Ok(Filter::EtherType(self.number("expected an EtherType")?));
+            }
+            Some("host") => {
+                self.take();
+            }
+            _ => (),
+        }
+        let mac = self.take().and_then(|token| {
+            let bytes: Vec<u8> = token
+                .split(|c| c == ':' || c == '-')
+                .map(|b| u8::from_str_radix(b, 16).ok())
+                .collect::<Option<_>>()?;
+            match bytes[..] {
+                [a, b, c, d, e, f] => Some([a, b, c, d, e, f]),
+                _ => None,
+            }
+        });
+        match mac {
+            Some(mac) => Ok(Filter::EtherHost(endpoint, mac)),
+            None if self.next > self.tokens.len() => Err(self.error("expected a hardware address")),
+            None => Err(self.error_before("expected a hardware address")),
+        }
+    }
+
+    fn number(&mut self, message: &str) -> Result<u16, FilterError> {
+        let number = self.take().and_then(|token| {
+            if token.starts_with("0x") {
+                u16::from_str_radix(&token[2..], 16).ok()
+            } else {
+                token.parse().ok()
+            }
+        });
+        match number {
+            Some(number) => Ok(number),
+            None if self.next > self.tokens.len() => Err(self.error(message)),
+            None => Err(self.error_before(message)),
+        }
+    }
+
+    fn address(&mut self) -> Result<Ipv4Addr, FilterError> {
+        match self.take().map(str::parse) {
+            Some(Ok(address)) => Ok(address),
+            Some(Err(_)) => Err(self.error_before("expected an IPv4 address")),
+            None => Err(self.error("expected an IPv4 address")),
+        }
+    }
+
+    fn net(&mut self, endpoint: Endpoint) -> Result<Filter, FilterError> {
+        let token = match self.peek() {
+            Some(token) => token,
+            None => return Err(self.error("expected a network such as 10.0.0.0/8")),
+        };
+        let (address, len) = match token.find('/') {
+            Some(slash) => (&token[..slash], token[slash + 1..].parse().ok().filter(|&l| l <= 32)),
+            None => (token, Some(32)),
+        };
+        let (address, len) = match (address.parse::<Ipv4Addr>(), len) {
+            (Ok(address), Some(len)) => (address, len),
+            _ => return Err(self.error("expected a network such as 10.0.0.0/8")),
+        };
+        let mask = if len == 0 { 0 } else { u32::max_value() << (32 - len) };
+        if u32::from(address) & !mask != 0 {
+            return Err(self.error("non-network bits set in the network address"));
+        }
+        self.take();
+        Ok(Filter::Net(endpoint, address, len))
+    }
+}
@@ -1,12 +1,80 @@
 use std::fmt::{Display, Error as FmtError, Formatter};
+use std::net::IpAddr;
+
+bitflags! {
+    ///State of an interface, equivalent of the PCAP_IF_* flags.
+    #[derive(Default)]
+    pub struct InterfaceFlags: u32 {
+        const LOOPBACK = 0x1;
+        const UP = 0x2;
+        const RUNNING = 0x4;
+        const WIRELESS = 0x8;
+    }
+}
+
+///Whether an interface is connected to a network, for interfaces that can tell.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    Unknown,
+    Connected,
+    Disconnected,
+    ///The interface has no notion of connection, e.g. loopback.
+    NotApplicable,
+}
+
+impl Default for ConnectionStatus {
+    fn default() -> Self {
+        ConnectionStatus::Unknown
+    }
+}
+
+///IPv4 or IPv6 address assigned to an interface.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct InterfaceAddress {
+    pub address: IpAddr,
+    pub netmask: Option<IpAddr>,
+    pub broadcast: Option<IpAddr>,
+    ///Other end of a point-to-point link.
+    pub destination: Option<IpAddr>,
+}
+
+impl InterfaceAddress {
+    ///Checks if the address is on the same subnet as `ip`, according to the netmask.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.address, self.netmask, ip) {
+            (IpAddr::V4(addr), Some(IpAddr::V4(mask)), IpAddr::V4(ip)) => {
+                let mask = u32::from(mask);
+                u32::from(addr) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(addr), Some(IpAddr::V6(mask)), IpAddr::V6(ip)) => {
+                let mask = u128::from(mask);
+                u128::from(addr) & mask == u128::from(ip) & mask
+            }
+            (addr, _, ip) => addr == ip,
+        }
+    }
+}
 
 /// Describes a network interface.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct InterfaceDescription {
     ///Interface name that can be used as an argument for open_interface() function.
     pub name: String,
     /// Human friendly interface description.
     pub description: String,
+    ///IP addresses of the interface, if the library reports them.
+    pub addresses: Vec<InterfaceAddress>,
+    ///MAC address of the interface, if the library reports it.
+    pub hardware_address: Option<[u8; 6]>,
+    pub flags: InterfaceFlags,
+    pub connection: ConnectionStatus,
+}
+
+impl InterfaceDescription {
+    ///Checks if one of the addresses of the interface is on the same subnet as `ip`.
+    pub fn is_on_subnet_of(&self, ip: IpAddr) -> bool {
+        self.addresses.iter().any(|a| a.contains(ip))
+    }
 }
 
 impl Display for InterfaceDescription {
@@ -6,6 +6,8 @@ pub enum LibraryVersion {
     PCap(String),
     WPCap(String),
     PFRing(String),
+    File(String),
+    AfPacket(String),
 }
 
 impl Display for LibraryVersion {
@@ -14,6 +16,8 @@ impl Display for LibraryVersion {
             LibraryVersion::PCap(ver) => write!(f, "pcap {}", ver),
             LibraryVersion::WPCap(ver) => write!(f, "wpcap {}", ver),
             LibraryVersion::PFRing(ver) => write!(f, "pfring {}", ver),
+            LibraryVersion::AfPacket(ver) => write!(f, "AF_PACKET on Linux {}", ver),
+            LibraryVersion::File(ver) => write!(f, "rawsock capture files {}", ver),
         }
     }
 }
@@ -1,8 +1,11 @@
 mod data_link;
 mod err;
+mod filter;
 mod interf_desc;
 mod lib_version;
+mod options;
 mod packet;
+mod stats;
 
 use crate::traits::Library;
 use crate::{pcap, pfring, wpcap};
@@ -10,13 +13,21 @@ use std::sync::Arc;
 
 pub use self::data_link::DataLink;
 pub use self::err::Error;
-pub use self::interf_desc::InterfaceDescription;
+pub use self::filter::{Endpoint, Filter, FilterError, Protocol};
+pub use self::interf_desc::{
+    ConnectionStatus, InterfaceAddress, InterfaceDescription, InterfaceFlags,
+};
 pub use self::lib_version::LibraryVersion;
+pub use self::options::{Direction, InterfaceOptions, TimestampPrecision};
 pub use self::packet::{BorrowedPacket, OwnedPacket, Packet};
+pub(crate) use self::stats::SendCounters;
+pub use self::stats::Stats;
 
 /**
 Opens optimal library available on the platform.
 
+On Linux, AF_PACKET sockets are used when none of the shared libraries can be loaded.
+
 # Example
 
 ```no_run
@@ -39,6 +50,10 @@ pub fn open_best_library() -> Result<Box<dyn Library>, Error> {
     }
     match pcap::Library::open_default_paths() {
         Ok(l) => Ok(Box::new(l)),
+        // no library is installed, fall back to the sockets of the kernel
+        #[cfg(target_os = "linux")]
+        Err(_) => Ok(Box::new(crate::af_packet::Library::default())),
+        #[cfg(not(target_os = "linux"))]
         Err(e) => Err(e),
     }
 }
@@ -53,15 +68,10 @@ pub fn open_best_library_arc() -> Result<Arc<dyn Library>, Error> {
     }
     match pcap::Library::open_default_paths() {
         Ok(l) => Ok(Arc::new(l)),
+        // no library is installed, fall back to the sockets of the kernel
+        #[cfg(target_os = "linux")]
+        Err(_) => Ok(Arc::new(crate::af_packet::Library::default())),
+        #[cfg(not(target_os = "linux"))]
         Err(e) => Err(e),
     }
 }
-
-///Provides library statistics
-#[derive(Copy, Clone, Debug)]
-pub struct Stats {
-    /// Received frames
-    pub received: u64,
-    ///Dropped frames
-    pub dropped: u64,
-}
@@ -0,0 +1,122 @@
+use std::time::Duration;
+
+///Which packets of an interface are captured.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Direction {
+    ///Both received and sent packets.
+    InOut,
+    ///Only received packets, which leaves out packets sent through the interface.
+    In,
+    ///Only sent packets.
+    Out,
+}
+
+///Resolution of packet timestamps.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TimestampPrecision {
+    Micro,
+    Nano,
+}
+
+/**
+Parameters of an interface, used when it is opened.
+
+Libraries ignore parameters they have no equivalent of. The defaults are those of pcap,
+pfring keeps its own (1500 bytes, no promiscuous mode) when opened without options.
+
+# Example
+
+```no_run
+extern crate rawsock;
+use rawsock::traits::Library;
+use rawsock::{open_best_library, Direction, InterfaceOptions};
+
+fn main() {
+    let lib = open_best_library().expect("Could not open any library");
+    let options = InterfaceOptions::new()
+        .snaplen(9216)
+        .buffer_size(16 << 20)
+        .direction(Direction::In);
+    let interf = lib
+        .open_interface_with_options("eth0", &options)
+        .expect("Could not open interface");
+}
+```
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterfaceOptions {
+    ///Maximum number of bytes captured from every packet.
+    pub snaplen: u32,
+    pub promiscuous: bool,
+    ///Size of the kernel buffer for received packets, the library default if not set.
+    pub buffer_size: Option<u32>,
+    ///Time the library may wait to gather packets before handing them over.
+    pub read_timeout: Duration,
+    ///Hands packets over as soon as they arrive, without waiting for the read timeout.
+    pub immediate: bool,
+    pub timestamp_precision: TimestampPrecision,
+    ///Source of timestamps by pcap name, such as "host" or "adapter".
+    pub timestamp_type: Option<String>,
+    pub direction: Direction,
+}
+
+impl Default for InterfaceOptions {
+    fn default() -> Self {
+        InterfaceOptions {
+            snaplen: 65536,
+            promiscuous: true,
+            buffer_size: None,
+            read_timeout: Duration::from_millis(1000),
+            immediate: cfg!(feature = "immediate_mode"),
+            timestamp_precision: TimestampPrecision::Micro,
+            timestamp_type: None,
+            direction: Direction::InOut,
+        }
+    }
+}
+
+impl InterfaceOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn snaplen(mut self, snaplen: u32) -> Self {
+        self.snaplen = snaplen;
+        self
+    }
+
+    pub fn promiscuous(mut self, promiscuous: bool) -> Self {
+        self.promiscuous = promiscuous;
+        self
+    }
+
+    pub fn buffer_size(mut self, buffer_size: u32) -> Self {
+        self.buffer_size = Some(buffer_size);
+        self
+    }
+
+    pub fn read_timeout(mut self, read_timeout: Duration) -> Self {
+        self.read_timeout = read_timeout;
+        self
+    }
+
+    pub fn immediate(mut self, immediate: bool) -> Self {
+        self.immediate = immediate;
+        self
+    }
+
+    pub fn timestamp_precision(mut self, precision: TimestampPrecision) -> Self {
+        self.timestamp_precision = precision;
+        self
+    }
+
+    pub fn timestamp_type(mut self, timestamp_type: &str) -> Self {
+        self.timestamp_type = Some(timestamp_type.into());
+        self
+    }
+
+    pub fn direction(mut self, direction: Direction) -> Self {
+        self.direction = direction;
+        self
+    }
+}
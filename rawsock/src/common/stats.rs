@@ -0,0 +1,60 @@
+use crate::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/**
+Provides library statistics
+
+Counters start when the interface is opened. Libraries fill in what they can tell, the other
+fields stay 0. Frames lost in the kernel or the library are counted in `dropped`, frames the
+network card or its driver already lost in `if_dropped`.
+*/
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Received frames
+    pub received: u64,
+    ///Frames dropped by the kernel or the library, mostly because the buffer was full
+    pub dropped: u64,
+    ///Frames dropped by the network interface or its driver
+    pub if_dropped: u64,
+    ///Frames sent through this interface
+    pub sent: u64,
+    ///Frames that could not be sent
+    pub send_errors: u64,
+    ///Frames shunted by pf_ring rules (pfring only)
+    pub shunted: u64,
+}
+
+///Counts sent frames for the libraries that don't report them.
+#[derive(Debug, Default)]
+pub(crate) struct SendCounters {
+    sent: AtomicU64,
+    errors: AtomicU64,
+}
+
+impl SendCounters {
+    ///Adds `sent` frames that went out and `errors` that didn't.
+    pub fn add(&self, sent: usize, errors: usize) {
+        self.sent.fetch_add(sent as u64, Ordering::Relaxed);
+        self.errors.fetch_add(errors as u64, Ordering::Relaxed);
+    }
+
+    ///Counts `count` frames as sent or as errors, depending on `result`, and passes it on.
+    /// Frames that would block are sent again later, so they are not counted.
+    pub fn count(&self, count: usize, result: Result<(), Error>) -> Result<(), Error> {
+        match result {
+            Ok(()) => self.add(count, 0),
+            Err(Error::WouldBlock) => {}
+            Err(_) => self.add(0, count),
+        }
+        result
+    }
+
+    ///Copies the counters to `stats`.
+    pub fn fill(&self, stats: Stats) -> Stats {
+        Stats {
+            sent: self.sent.load(Ordering::Relaxed),
+            send_errors: self.errors.load(Ordering::Relaxed),
+            ..stats
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use super::CaptureReader;
+use crate::common::SendCounters;
+use crate::traits::PacketDumper;
+use crate::{traits, BorrowedPacket, DataLink, Error, Stats};
+use std::ffi::CStr;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+///Capture file opened as an interface.
+///
+/// Received packets are replayed from the file. Sent packets are written to the output set with
+/// set_output(), or refused if there is none.
+pub struct Interface {
+    reader: Mutex<CaptureReader<Box<dyn Read + Send>>>,
+    data_link: DataLink,
+    output: Mutex<Option<Box<dyn PacketDumper>>>,
+    received: AtomicU64,
+    send_counters: SendCounters,
+    break_loop: AtomicBool,
+}
+
+impl Interface {
+    ///Opens a pcap or pcapng file.
+    pub fn open(path: &str) -> Result<Self, Error> {
+        let file =
+            File::open(path).map_err(|e| Error::OpeningInterface(format!("{}: {}", path, e)))?;
+        Self::from_reader(BufReader::new(file))
+    }
+
+    ///Replays capture data from any reader.
+    pub fn from_reader<R: Read + Send + 'static>(reader: R) -> Result<Self, Error> {
+        let reader = CaptureReader::new(Box::new(reader) as Box<dyn Read + Send>)?;
+        Ok(Interface {
+            data_link: reader.data_link(),
+            reader: Mutex::new(reader),
+            output: Mutex::new(None),
+            received: AtomicU64::new(0),
+            send_counters: SendCounters::default(),
+            break_loop: AtomicBool::new(false),
+        })
+    }
+
+    ///Writes packets sent through this interface to `output`, stamped with the current time.
+    pub fn set_output(&mut self, output: Box<dyn PacketDumper>) {
+        *self.output.get_mut().unwrap() = Some(output);
+    }
+
+    fn replay<F>(&self, mut callback: F) -> Result<(), Error>
+    where
+        F: FnMut(&BorrowedPacket),
+    {
+        let mut reader = self.reader.lock().unwrap();
+        while !self.break_loop.load(Ordering::SeqCst) {
+            match reader.next_packet() {
+                Ok(packet) => {
+                    self.received.fetch_add(1, Ordering::Relaxed);
+                    callback(&packet)
+                }
+                Err(Error::EndOfFile) => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+        // a break that came before the loop started ends it too, clear it once handled
+        self.break_loop.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+impl<'a> traits::DynamicInterface<'a> for Interface {
+    fn send(&self, packet: &[u8]) -> Result<(), Error> {
+        let result = match &mut *self.output.lock().unwrap() {
+            Some(output) => output.dump(&BorrowedPacket::new(time::get_time(), packet)),
+            None => Err(Error::SendingPacket(
+                "Cannot send packets to a capture file".into(),
+            )),
+        };
+        self.send_counters.count(1, result)
+    }
+
+    fn receive(&mut self) -> Result<BorrowedPacket<'_>, Error> {
+        let packet = self.reader.get_mut().unwrap().next_packet()?;
+        self.received.fetch_add(1, Ordering::Relaxed);
+        Ok(packet)
+    }
+
+    fn receive_timeout(&mut self, _timeout: Duration) -> Result<BorrowedPacket<'_>, Error> {
+        // reading a file never blocks
+        traits::DynamicInterface::receive(self)
+    }
+
+    fn set_nonblock(&mut self, _nonblock: bool) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn flush(&self) {
+        if let Some(output) = &mut *self.output.lock().unwrap() {
+            let _ = output.flush();
+        }
+    }
+
+    fn data_link(&self) -> DataLink {
+        self.data_link
+    }
+
+    fn stats(&self) -> Result<Stats, Error> {
+        Ok(self.send_counters.fill(Stats {
+            received: self.received.load(Ordering::Relaxed),
+            ..Stats::default()
+        }))
+    }
+
+    fn break_loop(&self) {
+        self.break_loop.store(true, Ordering::SeqCst)
+    }
+
+    fn loop_infinite_dyn(&self, callback: &mut dyn FnMut(&BorrowedPacket)) -> Result<(), Error> {
+        self.replay(callback)
+    }
+
+    fn set_filter_cstr(&mut self, _filter: &CStr) -> Result<(), Error> {
+        Err(Error::LibraryError(
+            "Capture files cannot be filtered".into(),
+        ))
+    }
+
+    fn remove_filter(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'a> traits::StaticInterface<'a> for Interface {
+    fn loop_infinite<F>(&self, callback: F) -> Result<(), Error>
+    where
+        F: FnMut(&BorrowedPacket),
+    {
+        self.replay(callback)
+    }
+}
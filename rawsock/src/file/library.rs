@@ -0,0 +1,89 @@
+use super::{Interface, PcapNgWriter, PcapWriter};
+use crate::{traits, DataLink, Error, InterfaceDescription, InterfaceOptions, LibraryVersion};
+use std::fs::File;
+use std::io::BufWriter;
+use std::sync::Arc;
+
+///Capture files used like a library. Interface names are paths to the files.
+///
+/// Dumpers write pcapng when the path ends with `.pcapng`, classic pcap otherwise.
+pub struct Library;
+
+impl traits::Library for Library {
+    fn open_default_paths() -> Result<Self, Error> {
+        Ok(Library)
+    }
+
+    fn default_paths() -> &'static [&'static str]
+    where
+        Self: Sized,
+    {
+        &[]
+    }
+
+    fn open(_path: &str) -> Result<Self, Error> {
+        Ok(Library)
+    }
+
+    fn open_interface<'a>(
+        &'a self,
+        name: &str,
+    ) -> Result<Box<dyn traits::DynamicInterface<'a> + 'a>, Error> {
+        Ok(Box::new(Interface::open(name)?) as Box<dyn traits::DynamicInterface>)
+    }
+
+    fn open_interface_arc<'a>(
+        &'a self,
+        name: &str,
+    ) -> Result<Arc<dyn traits::DynamicInterface<'a> + 'a>, Error> {
+        Ok(Arc::new(Interface::open(name)?) as Arc<dyn traits::DynamicInterface>)
+    }
+
+    ///Capture files have nothing to configure, the options are ignored.
+    fn open_interface_with_options<'a>(
+        &'a self,
+        name: &str,
+        _options: &InterfaceOptions,
+    ) -> Result<Box<dyn traits::DynamicInterface<'a> + 'a>, Error> {
+        Ok(Box::new(Interface::open(name)?) as Box<dyn traits::DynamicInterface>)
+    }
+
+    fn open_interface_arc_with_options<'a>(
+        &'a self,
+        name: &str,
+        _options: &InterfaceOptions,
+    ) -> Result<Arc<dyn traits::DynamicInterface<'a> + 'a>, Error> {
+        Ok(Arc::new(Interface::open(name)?) as Arc<dyn traits::DynamicInterface>)
+    }
+
+    fn open_offline<'a>(
+        &'a self,
+        path: &str,
+    ) -> Result<Box<dyn traits::DynamicInterface<'a> + 'a>, Error> {
+        traits::Library::open_interface(self, path)
+    }
+
+    fn open_dumper<'a>(
+        &'a self,
+        path: &str,
+        data_link: DataLink,
+    ) -> Result<Box<dyn traits::PacketDumper + 'a>, Error> {
+        let file = BufWriter::new(File::create(path)?);
+        if path.ends_with(".pcapng") {
+            let mut writer = PcapNgWriter::new(file, None)?;
+            writer.add_interface(data_link, None)?;
+            Ok(Box::new(writer))
+        } else {
+            Ok(Box::new(PcapWriter::new(file, data_link)?))
+        }
+    }
+
+    ///There is nothing to list, any capture file can be opened.
+    fn all_interfaces(&self) -> Result<Vec<InterfaceDescription>, Error> {
+        Ok(Vec::new())
+    }
+
+    fn version(&self) -> LibraryVersion {
+        LibraryVersion::File(env!("CARGO_PKG_VERSION").into())
+    }
+}
@@ -0,0 +1,105 @@
+/*!
+Native support of capture files (classic pcap and pcapng), without any shared library.
+
+Besides the readers and writers, the module provides a `Library` whose "interfaces" are capture
+files, so that code written against the rawsock traits can run off recorded traffic.
+
+# Example
+
+```no_run
+extern crate rawsock;
+use rawsock::file;
+use rawsock::traits::{DynamicInterface, Library};
+
+fn main(){
+    let lib = file::Library::open_default_paths().unwrap();
+    let mut interf = lib.open_interface("session.pcapng").expect("Could not open capture file");
+    while let Ok(packet) = interf.receive() {
+        println!("Replayed packet: {}", packet);
+    }
+}
+```
+*/
+
+mod interface;
+mod library;
+mod pcap;
+mod pcapng;
+mod reader;
+
+pub use self::interface::Interface;
+pub use self::library::Library;
+pub use self::pcap::{PcapReader, PcapWriter};
+pub use self::pcapng::{InterfaceInfo, PcapNgReader, PcapNgWriter, Record};
+pub use self::reader::CaptureReader;
+
+use crate::{DataLink, Error};
+use std::io::{ErrorKind, Read};
+
+const LINKTYPE_RAW: u16 = 101;
+
+fn data_link_from_linktype(linktype: u16) -> DataLink {
+    match linktype {
+        // some systems wrote their DLT_RAW into files
+        LINKTYPE_RAW | 12 | 14 => DataLink::RawIp,
+        // the other link types have the same values as DLT_*
+        _ => DataLink::from_dlt(i32::from(linktype)),
+    }
+}
+
+fn linktype_from_data_link(data_link: DataLink) -> Result<u16, Error> {
+    match data_link {
+        DataLink::RawIp => Ok(LINKTYPE_RAW),
+        _ => data_link.dlt().map(|dlt| dlt as u16).ok_or_else(|| {
+            Error::CaptureFormat(format!(
+                "Cannot write {} packets to a capture file",
+                data_link
+            ))
+        }),
+    }
+}
+
+///Integers of a file in the byte order it was written in.
+#[derive(Copy, Clone)]
+struct ByteOrder {
+    big_endian: bool,
+}
+
+impl ByteOrder {
+    fn u16(self, bytes: &[u8]) -> u16 {
+        let bytes = [bytes[0], bytes[1]];
+        if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        }
+    }
+
+    fn u32(self, bytes: &[u8]) -> u32 {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+}
+
+///Fills `buf`, returning false if the file ended right before it.
+fn read_or_eof<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<bool, Error> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(truncated()),
+            Ok(n) => filled += n,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(true)
+}
+
+fn truncated() -> Error {
+    Error::CaptureFormat("The capture file is truncated".into())
+}
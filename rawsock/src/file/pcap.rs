@@ -0,0 +1,152 @@
+use super::{data_link_from_linktype, linktype_from_data_link, read_or_eof, truncated, ByteOrder};
+use crate::{traits, BorrowedPacket, DataLink, Error, Packet};
+use std::io::{Read, Write};
+use time::Timespec;
+
+const MAGIC_MICROS: u32 = 0xa1b2_c3d4;
+const MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+const VERSION_MAJOR: u16 = 2;
+const VERSION_MINOR: u16 = 4;
+const HEADER_LEN: usize = 24;
+const RECORD_HEADER_LEN: usize = 16;
+const SNAPLEN: u32 = 65536;
+
+///Reader of classic pcap files, in either byte order and with micro- or nanosecond timestamps.
+pub struct PcapReader<R> {
+    reader: R,
+    order: ByteOrder,
+    nanos: bool,
+    data_link: DataLink,
+    buffer: Vec<u8>,
+}
+
+impl<R: Read> PcapReader<R> {
+    ///Reads the file header.
+    pub fn new(mut reader: R) -> Result<Self, Error> {
+        let mut magic = [0u8; 4];
+        if !read_or_eof(&mut reader, &mut magic)? {
+            return Err(truncated());
+        }
+        Self::with_magic(reader, magic)
+    }
+
+    ///Continues reading the file header after its first four bytes.
+    pub(super) fn with_magic(mut reader: R, magic: [u8; 4]) -> Result<Self, Error> {
+        let (order, nanos) = match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+            (MAGIC_MICROS, _) => (ByteOrder { big_endian: false }, false),
+            (MAGIC_NANOS, _) => (ByteOrder { big_endian: false }, true),
+            (_, MAGIC_MICROS) => (ByteOrder { big_endian: true }, false),
+            (_, MAGIC_NANOS) => (ByteOrder { big_endian: true }, true),
+            _ => return Err(Error::CaptureFormat("Not a pcap file".into())),
+        };
+        let mut header = [0u8; HEADER_LEN - 4];
+        if !read_or_eof(&mut reader, &mut header)? {
+            return Err(truncated());
+        }
+        let major = order.u16(&header[0..]);
+        if major != VERSION_MAJOR {
+            return Err(Error::CaptureFormat(format!(
+                "Unsupported pcap version {}",
+                major
+            )));
+        }
+        // the upper bits hold the FCS length
+        let linktype = order.u32(&header[16..]) as u16;
+        Ok(PcapReader {
+            reader,
+            order,
+            nanos,
+            data_link: data_link_from_linktype(linktype),
+            buffer: Vec::new(),
+        })
+    }
+
+    ///Data link of all packets in the file.
+    pub fn data_link(&self) -> DataLink {
+        self.data_link
+    }
+
+    ///Reads the next packet, or returns `Error::EndOfFile` after the last one.
+    pub fn next_packet(&mut self) -> Result<BorrowedPacket<'_>, Error> {
+        let mut header = [0u8; RECORD_HEADER_LEN];
+        if !read_or_eof(&mut self.reader, &mut header)? {
+            return Err(Error::EndOfFile);
+        }
+        let sec = self.order.u32(&header[0..]);
+        let frac = self.order.u32(&header[4..]);
+        let caplen = self.order.u32(&header[8..]) as usize;
+        if caplen > SNAPLEN as usize * 4 {
+            return Err(Error::CaptureFormat(format!(
+                "Packet of {} bytes is too long",
+                caplen
+            )));
+        }
+        self.buffer.resize(caplen, 0);
+        if !read_or_eof(&mut self.reader, &mut self.buffer)? {
+            return Err(truncated());
+        }
+        let nsec = if self.nanos {
+            frac
+        } else {
+            frac.saturating_mul(1000)
+        };
+        if nsec >= 1_000_000_000 {
+            return Err(Error::CaptureFormat("Invalid packet timestamp".into()));
+        }
+        Ok(BorrowedPacket::new(
+            Timespec::new(sec.into(), nsec as i32),
+            &self.buffer,
+        ))
+    }
+}
+
+///Writer of classic pcap files with microsecond timestamps, which every tool can read.
+pub struct PcapWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> PcapWriter<W> {
+    ///Writes the file header for packets with the given data link.
+    pub fn new(mut writer: W, data_link: DataLink) -> Result<Self, Error> {
+        let linktype = linktype_from_data_link(data_link)?;
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(&MAGIC_MICROS.to_le_bytes());
+        header.extend_from_slice(&VERSION_MAJOR.to_le_bytes());
+        header.extend_from_slice(&VERSION_MINOR.to_le_bytes());
+        // time zone and timestamp accuracy, always zero
+        header.extend_from_slice(&[0; 8]);
+        header.extend_from_slice(&SNAPLEN.to_le_bytes());
+        header.extend_from_slice(&u32::from(linktype).to_le_bytes());
+        writer.write_all(&header)?;
+        Ok(PcapWriter { writer })
+    }
+
+    ///Appends the packet, keeping the time when it was received.
+    pub fn write_packet(&mut self, packet: &BorrowedPacket) -> Result<(), Error> {
+        let when = packet.when();
+        let mut header = [0u8; RECORD_HEADER_LEN];
+        header[0..4].copy_from_slice(&(when.sec as u32).to_le_bytes());
+        header[4..8].copy_from_slice(&((when.nsec / 1000) as u32).to_le_bytes());
+        header[8..12].copy_from_slice(&(packet.len() as u32).to_le_bytes());
+        header[12..16].copy_from_slice(&(packet.len() as u32).to_le_bytes());
+        self.writer.write_all(&header)?;
+        self.writer.write_all(packet)?;
+        Ok(())
+    }
+
+    ///Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write + Send> traits::PacketDumper for PcapWriter<W> {
+    fn dump(&mut self, packet: &BorrowedPacket) -> Result<(), Error> {
+        self.write_packet(packet)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,479 @@
+use super::{data_link_from_linktype, linktype_from_data_link, read_or_eof, truncated, ByteOrder};
+use crate::{traits, BorrowedPacket, DataLink, Error, Packet};
+use std::io::{Read, Write};
+use time::Timespec;
+
+pub(super) const SECTION_HEADER_BLOCK: u32 = 0x0a0d_0d0a;
+const INTERFACE_DESCRIPTION_BLOCK: u32 = 1;
+const OBSOLETE_PACKET_BLOCK: u32 = 2;
+const SIMPLE_PACKET_BLOCK: u32 = 3;
+const ENHANCED_PACKET_BLOCK: u32 = 6;
+
+const BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+const VERSION_MAJOR: u16 = 1;
+const VERSION_MINOR: u16 = 0;
+
+const OPT_END_OF_OPT: u16 = 0;
+const OPT_COMMENT: u16 = 1;
+const IF_NAME: u16 = 2;
+const IF_DESCRIPTION: u16 = 3;
+const IF_TSRESOL: u16 = 9;
+
+//blocks bigger than this are surely damaged, don't try to allocate them
+const MAX_BLOCK_LEN: usize = 16 * 1024 * 1024;
+const NANOSECONDS: u8 = 9;
+const MICROSECONDS: u8 = 6;
+
+///Interface described in a pcapng file.
+#[derive(Debug, Clone)]
+pub struct InterfaceInfo {
+    ///Data link of packets captured on the interface.
+    pub data_link: DataLink,
+    ///Maximum number of bytes stored from each packet, 0 when unlimited.
+    pub snaplen: u32,
+    ///Name of the interface, if recorded.
+    pub name: Option<String>,
+    ///Description of the interface, if recorded.
+    pub description: Option<String>,
+    ///Comments attached to the interface.
+    pub comments: Vec<String>,
+    tsresol: u8,
+}
+
+impl InterfaceInfo {
+    fn timespec(&self, ts: u64) -> Timespec {
+        // the high bit selects a power of two, otherwise a power of ten
+        let exp = u32::from(self.tsresol & 0x7f);
+        let per_sec = if self.tsresol & 0x80 != 0 {
+            1u128.checked_shl(exp)
+        } else {
+            10u128.checked_pow(exp)
+        }
+        .unwrap_or(1);
+        let ts = u128::from(ts);
+        let nsec = ts % per_sec * 1_000_000_000 / per_sec;
+        Timespec::new((ts / per_sec) as i64, nsec as i32)
+    }
+}
+
+///A packet read from a pcapng file, with the details stored next to it.
+pub struct Record<'a> {
+    ///Index of the interface the packet was captured on.
+    pub interface: u32,
+    ///The packet itself.
+    pub packet: BorrowedPacket<'a>,
+    ///Comments attached to the packet.
+    pub comments: Vec<String>,
+}
+
+struct Options<'a> {
+    order: ByteOrder,
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for Options<'a> {
+    type Item = (u16, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.len() < 4 {
+            return None;
+        }
+        let code = self.order.u16(self.data);
+        let len = self.order.u16(&self.data[2..]) as usize;
+        if code == OPT_END_OF_OPT || self.data.len() < 4 + len {
+            return None;
+        }
+        let value = &self.data[4..4 + len];
+        self.data = &self.data[(4 + padded(len)).min(self.data.len())..];
+        Some((code, value))
+    }
+}
+
+fn padded(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+fn option_string(value: &[u8]) -> String {
+    String::from_utf8_lossy(value).into_owned()
+}
+
+///Reader of pcapng files with any number of sections and interfaces.
+pub struct PcapNgReader<R> {
+    reader: R,
+    order: ByteOrder,
+    interfaces: Vec<InterfaceInfo>,
+    comments: Vec<String>,
+    block: Vec<u8>,
+}
+
+impl<R: Read> PcapNgReader<R> {
+    ///Reads the section header and the blocks up to the first interface description.
+    pub fn new(mut reader: R) -> Result<Self, Error> {
+        let mut block_type = [0u8; 4];
+        if !read_or_eof(&mut reader, &mut block_type)? {
+            return Err(truncated());
+        }
+        Self::with_magic(reader, block_type)
+    }
+
+    ///Continues reading the section header after its first four bytes.
+    pub(super) fn with_magic(reader: R, block_type: [u8; 4]) -> Result<Self, Error> {
+        if u32::from_le_bytes(block_type) != SECTION_HEADER_BLOCK {
+            return Err(Error::CaptureFormat("Not a pcapng file".into()));
+        }
+        let mut len = [0u8; 4];
+        let mut ret = PcapNgReader {
+            reader,
+            order: ByteOrder { big_endian: false },
+            interfaces: Vec::new(),
+            comments: Vec::new(),
+            block: Vec::new(),
+        };
+        if !read_or_eof(&mut ret.reader, &mut len)? {
+            return Err(truncated());
+        }
+        ret.read_section_body(len)?;
+        ret.read_section_header()?;
+        // the data link is known once the first interface is
+        while ret.interfaces.is_empty() {
+            match ret.read_block()? {
+                Some(INTERFACE_DESCRIPTION_BLOCK) => ret.read_interface()?,
+                Some(SECTION_HEADER_BLOCK) => ret.read_section_header()?,
+                Some(_) => {}
+                None => break,
+            }
+        }
+        Ok(ret)
+    }
+
+    ///Interfaces of the current section, indexed by `Record::interface`.
+    pub fn interfaces(&self) -> &[InterfaceInfo] {
+        &self.interfaces
+    }
+
+    ///Comments attached to the current section.
+    pub fn comments(&self) -> &[String] {
+        &self.comments
+    }
+
+    ///Data link of the first interface, which is the only one in most files.
+    pub fn data_link(&self) -> DataLink {
+        self.interfaces
+            .first()
+            .map(|i| i.data_link)
+            .unwrap_or(DataLink::Other)
+    }
+
+    ///Reads the next packet, or returns `Error::EndOfFile` after the last one.
+    pub fn next_packet(&mut self) -> Result<BorrowedPacket<'_>, Error> {
+        Ok(self.next_record()?.packet)
+    }
+
+    ///Reads the next packet with its interface and comments.
+    ///
+    /// Returns `Error::EndOfFile` after the last one.
+    pub fn next_record(&mut self) -> Result<Record<'_>, Error> {
+        loop {
+            let (interface, ts, start, caplen, options) = match self.read_block()? {
+                None => return Err(Error::EndOfFile),
+                Some(SECTION_HEADER_BLOCK) => {
+                    self.read_section_header()?;
+                    continue;
+                }
+                Some(INTERFACE_DESCRIPTION_BLOCK) => {
+                    self.read_interface()?;
+                    continue;
+                }
+                Some(ENHANCED_PACKET_BLOCK) => {
+                    let body = self.body(20)?;
+                    let ts = u64::from(self.order.u32(&body[4..])) << 32
+                        | u64::from(self.order.u32(&body[8..]));
+                    let caplen = self.order.u32(&body[12..]) as usize;
+                    (self.order.u32(body), ts, 20, caplen, 20 + padded(caplen))
+                }
+                Some(OBSOLETE_PACKET_BLOCK) => {
+                    let body = self.body(20)?;
+                    let ts = u64::from(self.order.u32(&body[4..])) << 32
+                        | u64::from(self.order.u32(&body[8..]));
+                    let caplen = self.order.u32(&body[12..]) as usize;
+                    (
+                        u32::from(self.order.u16(body)),
+                        ts,
+                        20,
+                        caplen,
+                        20 + padded(caplen),
+                    )
+                }
+                Some(SIMPLE_PACKET_BLOCK) => {
+                    let body = self.body(4)?;
+                    let len = self.order.u32(body) as usize;
+                    let snaplen = self.interface(0)?.snaplen as usize;
+                    let caplen = match snaplen {
+                        0 => len,
+                        snaplen => len.min(snaplen),
+                    }
+                    .min(body.len() - 4);
+                    (0, 0, 4, caplen, body.len())
+                }
+                Some(_) => continue,
+            };
+            let info = self.interface(interface)?;
+            let when = info.timespec(ts);
+            if self.block.len() < start + caplen {
+                return Err(truncated());
+            }
+            let comments = Options {
+                order: self.order,
+                data: &self.block[options.min(self.block.len())..],
+            }
+            .filter(|(code, _)| *code == OPT_COMMENT)
+            .map(|(_, value)| option_string(value))
+            .collect();
+            return Ok(Record {
+                interface,
+                packet: BorrowedPacket::new(when, &self.block[start..start + caplen]),
+                comments,
+            });
+        }
+    }
+
+    fn body(&self, min_len: usize) -> Result<&[u8], Error> {
+        if self.block.len() < min_len {
+            return Err(Error::CaptureFormat("Block is too short".into()));
+        }
+        Ok(&self.block)
+    }
+
+    fn interface(&self, index: u32) -> Result<&InterfaceInfo, Error> {
+        self.interfaces.get(index as usize).ok_or_else(|| {
+            Error::CaptureFormat(format!("Packet of undescribed interface {}", index))
+        })
+    }
+
+    ///Reads the body of the next block into `self.block` and returns its type.
+    fn read_block(&mut self) -> Result<Option<u32>, Error> {
+        let mut header = [0u8; 8];
+        if !read_or_eof(&mut self.reader, &mut header)? {
+            return Ok(None);
+        }
+        let block_type = self.order.u32(&header);
+        if block_type == SECTION_HEADER_BLOCK {
+            self.read_section_body([header[4], header[5], header[6], header[7]])?;
+            return Ok(Some(block_type));
+        }
+        let len = self.order.u32(&header[4..]) as usize;
+        self.read_body(len)?;
+        Ok(Some(block_type))
+    }
+
+    fn read_body(&mut self, len: usize) -> Result<(), Error> {
+        if len < 12 || len % 4 != 0 || len > MAX_BLOCK_LEN {
+            return Err(Error::CaptureFormat(format!(
+                "Invalid block length {}",
+                len
+            )));
+        }
+        self.block.resize(len - 8, 0);
+        if !read_or_eof(&mut self.reader, &mut self.block)? {
+            return Err(truncated());
+        }
+        // drop the trailing copy of the length
+        self.block.truncate(len - 12);
+        Ok(())
+    }
+
+    ///Reads the section header body, whose length is only known after the byte order magic.
+    fn read_section_body(&mut self, len: [u8; 4]) -> Result<(), Error> {
+        let mut magic = [0u8; 4];
+        if !read_or_eof(&mut self.reader, &mut magic)? {
+            return Err(truncated());
+        }
+        self.order = match u32::from_le_bytes(magic) {
+            BYTE_ORDER_MAGIC => ByteOrder { big_endian: false },
+            magic if magic.swap_bytes() == BYTE_ORDER_MAGIC => ByteOrder { big_endian: true },
+            _ => {
+                return Err(Error::CaptureFormat(
+                    "Invalid pcapng byte order magic".into(),
+                ))
+            }
+        };
+        let len = self.order.u32(&len) as usize;
+        if len < 28 {
+            return Err(Error::CaptureFormat(format!(
+                "Invalid block length {}",
+                len
+            )));
+        }
+        // the magic is already read, leave it out of the body
+        self.read_body(len - 4)
+    }
+
+    ///Starts a new section from the header body in `self.block`.
+    fn read_section_header(&mut self) -> Result<(), Error> {
+        let major = self.order.u16(&self.block);
+        if major != VERSION_MAJOR {
+            return Err(Error::CaptureFormat(format!(
+                "Unsupported pcapng version {}",
+                major
+            )));
+        }
+        let order = self.order;
+        self.interfaces.clear();
+        self.comments = Options {
+            order,
+            data: &self.block[12..],
+        }
+        .filter(|(code, _)| *code == OPT_COMMENT)
+        .map(|(_, value)| option_string(value))
+        .collect();
+        Ok(())
+    }
+
+    fn read_interface(&mut self) -> Result<(), Error> {
+        let body = self.body(8)?;
+        let mut info = InterfaceInfo {
+            data_link: data_link_from_linktype(self.order.u16(body)),
+            snaplen: self.order.u32(&body[4..]),
+            name: None,
+            description: None,
+            comments: Vec::new(),
+            tsresol: MICROSECONDS,
+        };
+        let options = Options {
+            order: self.order,
+            data: &body[8..],
+        };
+        for (code, value) in options {
+            match code {
+                OPT_COMMENT => info.comments.push(option_string(value)),
+                IF_NAME => info.name = Some(option_string(value)),
+                IF_DESCRIPTION => info.description = Some(option_string(value)),
+                IF_TSRESOL if !value.is_empty() => info.tsresol = value[0],
+                _ => {}
+            }
+        }
+        self.interfaces.push(info);
+        Ok(())
+    }
+}
+
+///Writer of pcapng files with nanosecond timestamps.
+///
+/// The file is written as a single section. Add interfaces with add_interface() before writing
+/// their packets.
+pub struct PcapNgWriter<W: Write> {
+    writer: W,
+    interfaces: u32,
+}
+
+impl<W: Write> PcapNgWriter<W> {
+    ///Writes the section header, with an optional comment.
+    pub fn new(writer: W, comment: Option<&str>) -> Result<Self, Error> {
+        let mut ret = PcapNgWriter {
+            writer,
+            interfaces: 0,
+        };
+        let mut body = Vec::new();
+        body.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        body.extend_from_slice(&VERSION_MAJOR.to_le_bytes());
+        body.extend_from_slice(&VERSION_MINOR.to_le_bytes());
+        // unknown section length
+        body.extend_from_slice(&(-1i64).to_le_bytes());
+        let options: Vec<_> = comment
+            .map(|c| (OPT_COMMENT, c.as_bytes()))
+            .into_iter()
+            .collect();
+        ret.write_block(SECTION_HEADER_BLOCK, body, &options)?;
+        Ok(ret)
+    }
+
+    ///Describes a new interface and returns the index to write its packets with.
+    pub fn add_interface(&mut self, data_link: DataLink, name: Option<&str>) -> Result<u32, Error> {
+        let linktype = linktype_from_data_link(data_link)?;
+        let mut body = Vec::new();
+        body.extend_from_slice(&linktype.to_le_bytes());
+        body.extend_from_slice(&[0; 2]);
+        // no snapshot length limit
+        body.extend_from_slice(&0u32.to_le_bytes());
+        let mut options = vec![(IF_TSRESOL, &[NANOSECONDS][..])];
+        if let Some(name) = name {
+            options.push((IF_NAME, name.as_bytes()));
+        }
+        self.write_block(INTERFACE_DESCRIPTION_BLOCK, body, &options)?;
+        self.interfaces += 1;
+        Ok(self.interfaces - 1)
+    }
+
+    ///Appends a packet captured on `interface`, with an optional comment.
+    pub fn write_packet(
+        &mut self,
+        interface: u32,
+        packet: &BorrowedPacket,
+        comment: Option<&str>,
+    ) -> Result<(), Error> {
+        if interface >= self.interfaces {
+            return Err(Error::CaptureFormat(format!(
+                "Undescribed interface {}",
+                interface
+            )));
+        }
+        let when = packet.when();
+        let ts = (when.sec as u64)
+            .wrapping_mul(1_000_000_000)
+            .wrapping_add(when.nsec as u64);
+        let mut body = Vec::with_capacity(20 + padded(packet.len()));
+        body.extend_from_slice(&interface.to_le_bytes());
+        body.extend_from_slice(&((ts >> 32) as u32).to_le_bytes());
+        body.extend_from_slice(&(ts as u32).to_le_bytes());
+        body.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        body.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        body.extend_from_slice(packet);
+        body.resize(padded(body.len()), 0);
+        let options: Vec<_> = comment
+            .map(|c| (OPT_COMMENT, c.as_bytes()))
+            .into_iter()
+            .collect();
+        self.write_block(ENHANCED_PACKET_BLOCK, body, &options)
+    }
+
+    ///Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_block(
+        &mut self,
+        block_type: u32,
+        mut body: Vec<u8>,
+        options: &[(u16, &[u8])],
+    ) -> Result<(), Error> {
+        for (code, value) in options {
+            body.extend_from_slice(&code.to_le_bytes());
+            body.extend_from_slice(&(value.len() as u16).to_le_bytes());
+            body.extend_from_slice(value);
+            body.resize(padded(body.len()), 0);
+        }
+        if !options.is_empty() {
+            body.extend_from_slice(&OPT_END_OF_OPT.to_le_bytes());
+            body.extend_from_slice(&[0; 2]);
+        }
+        let len = (body.len() + 12) as u32;
+        self.writer.write_all(&block_type.to_le_bytes())?;
+        self.writer.write_all(&len.to_le_bytes())?;
+        self.writer.write_all(&body)?;
+        self.writer.write_all(&len.to_le_bytes())?;
+        Ok(())
+    }
+}
+
+impl<W: Write + Send> traits::PacketDumper for PcapNgWriter<W> {
+    ///Writes the packet as captured on the first interface.
+    fn dump(&mut self, packet: &BorrowedPacket) -> Result<(), Error> {
+        self.write_packet(0, packet, None)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
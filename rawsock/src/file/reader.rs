@@ -0,0 +1,43 @@
+use super::pcapng::SECTION_HEADER_BLOCK;
+use super::{read_or_eof, truncated, PcapNgReader, PcapReader};
+use crate::{BorrowedPacket, DataLink, Error};
+use std::io::Read;
+
+///Reader of capture files in any of the supported formats, detected from the first bytes.
+pub enum CaptureReader<R> {
+    Pcap(PcapReader<R>),
+    PcapNg(PcapNgReader<R>),
+}
+
+impl<R: Read> CaptureReader<R> {
+    ///Detects the format and reads the file header.
+    pub fn new(mut reader: R) -> Result<Self, Error> {
+        let mut magic = [0u8; 4];
+        if !read_or_eof(&mut reader, &mut magic)? {
+            return Err(truncated());
+        }
+        if u32::from_le_bytes(magic) == SECTION_HEADER_BLOCK {
+            Ok(CaptureReader::PcapNg(PcapNgReader::with_magic(
+                reader, magic,
+            )?))
+        } else {
+            Ok(CaptureReader::Pcap(PcapReader::with_magic(reader, magic)?))
+        }
+    }
+
+    ///Data link of the packets (of the first interface for pcapng files).
+    pub fn data_link(&self) -> DataLink {
+        match self {
+            CaptureReader::Pcap(reader) => reader.data_link(),
+            CaptureReader::PcapNg(reader) => reader.data_link(),
+        }
+    }
+
+    ///Reads the next packet, or returns `Error::EndOfFile` after the last one.
+    pub fn next_packet(&mut self) -> Result<BorrowedPacket<'_>, Error> {
+        match self {
+            CaptureReader::Pcap(reader) => reader.next_packet(),
+            CaptureReader::PcapNg(reader) => reader.next_packet(),
+        }
+    }
+}
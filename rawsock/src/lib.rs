@@ -14,6 +14,8 @@ Platform-specific extensions (such as WinPcap ```pcap_sendqueue_transmit()```) a
 
 * One consistent API for all packet capturing libraries.
 * Support of pcap, wpcap (with Windows-specific optimizations), npcap and pfring
+* Native AF_PACKET sockets on Linux when none of the libraries is installed
+* Native reading and writing of pcap and pcapng capture files, which can also stand in for a library
 * Supports all main platforms: tested on Windows, Linux, Mac. Many more should work too
 * Libraries are loaded in a dynamic manner, so that the library does not have any direct
     dependency - it's going to work with whatever is available on the given platform.
@@ -78,7 +80,12 @@ extern crate time;
 #[macro_use]
 extern crate lazy_static;
 
+#[cfg(all(unix, feature = "async-tokio"))]
+mod async_interface;
+#[cfg(target_os = "linux")]
+pub mod af_packet;
 mod common;
+pub mod file;
 pub mod pcap;
 mod pcap_common;
 pub mod pfring;
@@ -87,6 +94,10 @@ mod utils;
 pub mod wpcap;
 
 pub use self::common::{
-    open_best_library, open_best_library_arc, BorrowedPacket, DataLink, Error,
-    InterfaceDescription, LibraryVersion, OwnedPacket, Packet, Stats,
+    open_best_library, open_best_library_arc, BorrowedPacket, ConnectionStatus, DataLink,
+    Direction, Endpoint, Error, Filter, FilterError, InterfaceAddress, InterfaceDescription,
+    InterfaceFlags, InterfaceOptions, LibraryVersion, OwnedPacket, Packet, Protocol, Stats,
+    TimestampPrecision,
 };
+#[cfg(all(unix, feature = "async-tokio"))]
+pub use self::async_interface::AsyncInterface;
@@ -0,0 +1,7 @@
+use super::dll::PCapDll;
+use crate::pcap_common::dumper::{self, impl_dumper_dll};
+
+impl_dumper_dll!(PCapDll);
+
+///pcap version of a capture file writer.
+pub type Dumper<'a> = dumper::Dumper<'a, PCapDll>;
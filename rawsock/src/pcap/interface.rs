@@ -1,21 +1,32 @@
 use super::dll::helpers::PCapErrBuf;
-use super::dll::{PCapDll, PCapHandle, PCapPacketHeader};
+use super::dll::{PCapDll, PCapHandle};
 use super::structs::PCapStat;
-use crate::utils::cstr_to_string;
-use crate::{traits, BorrowedPacket, DataLink, Error, Stats};
+use crate::common::SendCounters;
+use crate::utils::{cstr_to_string, last_errno, would_block};
+use crate::{
+    traits, BorrowedPacket, DataLink, Direction, Error, InterfaceOptions, Stats,
+    TimestampPrecision,
+};
 use libc::c_int;
 use std::ffi::{CStr, CString};
 use std::mem::{transmute, MaybeUninit};
+use std::ptr::{null, null_mut};
+use std::time::{Duration, Instant};
 use std::sync::Mutex;
 
-use crate::pcap_common::constants::{PCAP_EMPTY_FILTER_STR, PCAP_ERROR_BREAK, SUCCESS};
+use crate::pcap_common::constants::{
+    PCAP_EMPTY_FILTER_STR, PCAP_ERROR_BREAK, PCAP_TSTAMP_PRECISION_NANO, SUCCESS,
+};
 use crate::pcap_common::helpers::{
-    borrowed_packet_from_header, on_received_packet_dynamic, on_received_packet_static,
+    on_received_packet_dynamic, on_received_packet_static, wait_a_little,
+    LoopUser, NextEx,
 };
-use crate::pcap_common::BpfProgram;
+use crate::pcap_common::{BpfProgram, PCapDirection};
+#[cfg(unix)]
+use crate::pcap_common::helpers::wait_readable;
 
 lazy_static! {
-    static ref COMPILE_GUARD: Mutex<()> = Mutex::new(());
+    pub(super) static ref COMPILE_GUARD: Mutex<()> = Mutex::new(());
 }
 
 ///pcap version of interface.
@@ -23,6 +34,10 @@ pub struct Interface<'a> {
     handle: *const PCapHandle,
     dll: &'a PCapDll,
     datalink: DataLink,
+    nonblock: bool,
+    offline: bool,
+    nanos: bool,
+    send_counters: SendCounters,
 }
 
 unsafe impl<'a> Sync for Interface<'a> {}
@@ -30,6 +45,14 @@ unsafe impl<'a> Send for Interface<'a> {}
 
 impl<'a> Interface<'a> {
     pub fn new(name: &str, dll: &'a PCapDll) -> Result<Self, Error> {
+        Self::with_options(name, dll, &InterfaceOptions::default())
+    }
+
+    pub fn with_options(
+        name: &str,
+        dll: &'a PCapDll,
+        options: &InterfaceOptions,
+    ) -> Result<Self, Error> {
         let name = CString::new(name)?;
         let mut errbuf = PCapErrBuf::new();
         let handle = unsafe { dll.pcap_create(name.as_ptr(), errbuf.buffer()) };
@@ -51,32 +74,101 @@ impl<'a> Interface<'a> {
             dll,
             handle,
             datalink: DataLink::Other,
+            nonblock: false,
+            offline: false,
+            nanos: false,
+            send_counters: SendCounters::default(),
         };
 
-        check_err!(dll.pcap_set_snaplen(handle, 65536));
-        check_err!(dll.pcap_set_promisc(handle, 1));
-        check_err!(dll.pcap_set_timeout(handle, 1000));
+        let timeout = options.read_timeout.as_millis().min(c_int::max_value() as u128);
+        check_err!(dll.pcap_set_snaplen(handle, options.snaplen.min(c_int::max_value() as u32) as c_int));
+        check_err!(dll.pcap_set_promisc(handle, options.promiscuous as c_int));
+        check_err!(dll.pcap_set_timeout(handle, timeout as c_int));
+        if let Some(size) = options.buffer_size {
+            check_err!(dll.pcap_set_buffer_size(handle, size.min(c_int::max_value() as u32) as c_int));
+        }
+        if let Some(ref tstamp_type) = options.timestamp_type {
+            let tstamp_type = CString::new(tstamp_type.as_str())?;
+            let val = unsafe { dll.pcap_tstamp_type_name_to_val(tstamp_type.as_ptr()) };
+            if val < 0 {
+                return Err(Error::LibraryError(format!(
+                    "Unknown timestamp type {:?}",
+                    tstamp_type
+                )));
+            }
+            check_err!(dll.pcap_set_tstamp_type(handle, val));
+        }
+        if options.timestamp_precision == TimestampPrecision::Nano {
+            check_err!(dll.pcap_set_tstamp_precision(handle, PCAP_TSTAMP_PRECISION_NANO));
+            ret.nanos = true;
+        }
 
-        #[cfg(feature = "immediate_mode")]
-        ret.set_immediate_mode()?;
+        if options.immediate {
+            ret.set_immediate_mode()?;
+        }
 
         check_err!(dll.pcap_activate(handle));
 
-        let datalink = match unsafe { dll.pcap_datalink(handle) } {
-            1 => DataLink::Ethernet,
-            12 => DataLink::RawIp,
-            _ => DataLink::Other,
+        let direction = match options.direction {
+            Direction::InOut => None,
+            Direction::In => Some(PCapDirection::In),
+            Direction::Out => Some(PCapDirection::Out),
         };
-        ret.datalink = datalink;
+        if let Some(direction) = direction {
+            check_err!(dll.pcap_setdirection(handle, direction));
+        }
+
+        ret.datalink = DataLink::from_dlt(unsafe { dll.pcap_datalink(handle) });
         Ok(ret)
     }
 
+    ///Opens a capture file whose packets are then received in the order they were captured.
+    pub fn open_offline(path: &str, dll: &'a PCapDll) -> Result<Self, Error> {
+        let path = CString::new(path)?;
+        let mut errbuf = PCapErrBuf::new();
+        let handle = unsafe { dll.pcap_open_offline(path.as_ptr(), errbuf.buffer()) };
+        if handle.is_null() {
+            return Err(Error::OpeningInterface(errbuf.as_string()));
+        }
+        Ok(Interface {
+            dll,
+            handle,
+            datalink: DataLink::from_dlt(unsafe { dll.pcap_datalink(handle) }),
+            nonblock: false,
+            offline: true,
+            nanos: false,
+            send_counters: SendCounters::default(),
+        })
+    }
+
     fn last_error(&self) -> Error {
         let cerr = unsafe { self.dll.pcap_geterr(self.handle) };
         Error::LibraryError(cstr_to_string(cerr))
     }
 
-    #[cfg(feature = "immediate_mode")]
+    fn last_error_string(&self) -> String {
+        cstr_to_string(unsafe { self.dll.pcap_geterr(self.handle) })
+    }
+
+    fn next_ex(&self) -> NextEx {
+        let mut next = NextEx {
+            result: 0,
+            header: null_mut(),
+            data: null(),
+        };
+        next.result = unsafe { self.dll.pcap_next_ex(self.handle, &mut next.header, &mut next.data) };
+        next
+    }
+
+    fn setnonblock(&self, nonblock: bool) -> Result<(), Error> {
+        let mut errbuf = PCapErrBuf::new();
+        if SUCCESS == unsafe { self.dll.pcap_setnonblock(self.handle, nonblock as c_int, errbuf.buffer()) } {
+            Ok(())
+        } else {
+            Err(Error::LibraryError(errbuf.as_string()))
+        }
+    }
+
     fn set_immediate_mode(&mut self) -> Result<(), Error> {
         // TODO: libpcap >= 1.5.0 has pcap_set_immediate_mode
         if true {
@@ -112,6 +204,21 @@ impl<'a> Interface<'a> {
     }
 }
 
+impl<'a> Interface<'a> {
+    #[cfg(unix)]
+    fn wait(&self, timeout: Duration) {
+        match traits::DynamicInterface::selectable_fd(self) {
+            Some(fd) => wait_readable(fd, timeout),
+            None => wait_a_little(timeout),
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn wait(&self, timeout: Duration) {
+        wait_a_little(timeout)
+    }
+}
+
 impl<'a> Drop for Interface<'a> {
     fn drop(&mut self) {
         unsafe {
@@ -122,30 +229,72 @@ impl<'a> Drop for Interface<'a> {
 
 impl<'a> traits::DynamicInterface<'a> for Interface<'a> {
     fn send(&self, packet: &[u8]) -> Result<(), Error> {
-        if unsafe {
+        let result = if self.offline {
+            Err(Error::SendingPacket("Cannot send packets to a capture file".into()))
+        } else if unsafe {
             self.dll
                 .pcap_sendpacket(self.handle, packet.as_ptr(), packet.len() as c_int)
         } == SUCCESS
         {
             Ok(())
         } else {
+            // before anything else overwrites errno
+            let code = last_errno();
             let txt = unsafe { CStr::from_ptr(self.dll.pcap_geterr(self.handle)) }
                 .to_string_lossy()
                 .into_owned();
-            Err(Error::SendingPacket(txt))
-        }
+            if would_block(code) {
+                Err(Error::WouldBlock)
+            } else {
+                Err(Error::SendingPacket(txt))
+            }
+        };
+        self.send_counters.count(1, result)
     }
 
     fn receive(&mut self) -> Result<BorrowedPacket, Error> {
-        let mut header = MaybeUninit::<PCapPacketHeader>::uninit();
-        //TODO: replace pcap_next with pcap_next_ex to obtain more error information
-        let data = unsafe { self.dll.pcap_next(self.handle, header.as_mut_ptr()) };
-        if data.is_null() {
-            Err(Error::ReceivingPacket(
-                "Unknown error when obtaining packet".into(),
-            ))
-        } else {
-            Ok(borrowed_packet_from_header(unsafe { &header.assume_init() }, data))
+        self.next_ex().into_packet(self.nanos, || self.last_error_string())
+    }
+
+    fn receive_timeout(&mut self, timeout: Duration) -> Result<BorrowedPacket, Error> {
+        // reading a file never blocks
+        if self.offline {
+            return self.receive();
+        }
+        let deadline = Instant::now() + timeout;
+        if !self.nonblock {
+            self.setnonblock(true)?;
+        }
+        let next = loop {
+            let next = self.next_ex();
+            let now = Instant::now();
+            if next.result != 0 || now >= deadline {
+                break next;
+            }
+            self.wait(deadline - now);
+        };
+        if !self.nonblock {
+            self.setnonblock(false)?;
+        }
+        next.into_packet(self.nanos, || self.last_error_string())
+    }
+
+    fn set_nonblock(&mut self, nonblock: bool) -> Result<(), Error> {
+        if !self.offline {
+            self.setnonblock(nonblock)?;
+        }
+        self.nonblock = nonblock;
+        Ok(())
+    }
+
+    fn selectable_fd(&self) -> Option<c_int> {
+        // a regular file is always readable, there is nothing to select on
+        if self.offline {
+            return None;
+        }
+        match unsafe { self.dll.pcap_get_selectable_fd(self.handle) } {
+            -1 => None,
+            fd => Some(fd),
         }
     }
 
@@ -161,10 +310,12 @@ impl<'a> traits::DynamicInterface<'a> for Interface<'a> {
         let mut stats = MaybeUninit::<PCapStat>::uninit();
         if SUCCESS == unsafe { self.dll.pcap_stats(self.handle, stats.as_mut_ptr()) } {
             let stats = unsafe { stats.assume_init() };
-            Ok(Stats {
+            Ok(self.send_counters.fill(Stats {
                 received: stats.ps_recv as u64,
-                dropped: (stats.ps_drop + stats.ps_ifdrop) as u64,
-            })
+                dropped: stats.ps_drop as u64,
+                if_dropped: stats.ps_ifdrop as u64,
+                ..Stats::default()
+            }))
         } else {
             Err(self.last_error())
         }
@@ -174,13 +325,17 @@ impl<'a> traits::DynamicInterface<'a> for Interface<'a> {
         unsafe { self.dll.pcap_breakloop(self.handle) }
     }
 
-    fn loop_infinite_dyn(&self, callback: &dyn FnMut(&BorrowedPacket)) -> Result<(), Error> {
+    fn loop_infinite_dyn(&self, callback: &mut dyn FnMut(&BorrowedPacket)) -> Result<(), Error> {
+        let mut user = LoopUser {
+            callback,
+            nanos: self.nanos,
+        };
         let result = unsafe {
             self.dll.pcap_loop(
                 self.handle,
                 -1,
                 on_received_packet_dynamic,
-                transmute(&callback),
+                transmute(&mut user),
             )
         };
         if result == SUCCESS || result == PCAP_ERROR_BREAK {
@@ -222,12 +377,16 @@ impl<'a> traits::StaticInterface<'a> for Interface<'a> {
     where
         F: FnMut(&BorrowedPacket),
     {
+        let mut user = LoopUser {
+            callback,
+            nanos: self.nanos,
+        };
         let result = unsafe {
             self.dll.pcap_loop(
                 self.handle,
                 -1,
                 on_received_packet_static::<F>,
-                transmute(&callback),
+                transmute(&mut user),
             )
         };
         if result == SUCCESS || result == PCAP_ERROR_BREAK {
@@ -18,4 +18,7 @@ pub const PCAP_ERROR_CANTSET_TSTAMP_TYPE: c_int = -10;
 pub const PCAP_ERROR_PROMISC_PERM_DENIED: c_int = -11;
 pub const PCAP_ERROR_TSTAMP_PRECISION_NOTSUP: c_int = -12;
 
+pub const PCAP_TSTAMP_PRECISION_MICRO: c_int = 0;
+pub const PCAP_TSTAMP_PRECISION_NANO: c_int = 1;
+
 pub const PCAP_EMPTY_FILTER_STR: &'static [u8; 1] = b"\0";
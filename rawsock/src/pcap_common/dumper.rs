@@ -0,0 +1,135 @@
+use super::helpers::header_from_packet;
+use super::{PCapDumper, PCapHandle, PCapPacketHeader};
+use crate::utils::cstr_to_string;
+use crate::{traits, BorrowedPacket, DataLink, Error};
+use libc::{c_char, c_int, c_uchar};
+use std::ffi::CString;
+
+///Functions of a pcap-like library needed to write capture files.
+pub trait DumperDll: Sync {
+    unsafe fn open_dead(&self, linktype: c_int, snaplen: c_int) -> *const PCapHandle;
+    unsafe fn dump_open(&self, handle: *const PCapHandle, fname: *const c_char) -> *mut PCapDumper;
+    unsafe fn geterr(&self, handle: *const PCapHandle) -> *const c_char;
+    unsafe fn close(&self, handle: *const PCapHandle);
+    unsafe fn dump(&self, user: *mut c_uchar, h: *mut PCapPacketHeader, sp: *mut c_uchar);
+    unsafe fn dump_flush(&self, dumper: *mut PCapDumper) -> c_int;
+    unsafe fn dump_close(&self, dumper: *mut PCapDumper);
+}
+
+///Capture file writer shared by the pcap and wpcap libraries.
+pub struct Dumper<'a, D: DumperDll> {
+    handle: *const PCapHandle,
+    dumper: *mut PCapDumper,
+    dll: &'a D,
+}
+
+unsafe impl<'a, D: DumperDll> Send for Dumper<'a, D> {}
+
+impl<'a, D: DumperDll> Dumper<'a, D> {
+    pub fn new(path: &str, data_link: DataLink, dll: &'a D) -> Result<Self, Error> {
+        let path = CString::new(path)?;
+        let dlt = data_link.dlt()
+            .ok_or_else(|| Error::LibraryError(format!("Cannot write {} packets", data_link)))?;
+        let handle = unsafe { dll.open_dead(dlt, 65536) };
+        if handle.is_null() {
+            return Err(Error::LibraryError("pcap_open_dead failed".into()));
+        }
+        let dumper = unsafe { dll.dump_open(handle, path.as_ptr()) };
+        if dumper.is_null() {
+            let err = cstr_to_string(unsafe { dll.geterr(handle) });
+            unsafe { dll.close(handle) };
+            return Err(Error::LibraryError(err));
+        }
+        Ok(Dumper {
+            handle,
+            dumper,
+            dll,
+        })
+    }
+}
+
+impl<'a, D: DumperDll> Drop for Dumper<'a, D> {
+    fn drop(&mut self) {
+        unsafe {
+            self.dll.dump_close(self.dumper);
+            self.dll.close(self.handle);
+        }
+    }
+}
+
+impl<'a, D: DumperDll> traits::PacketDumper for Dumper<'a, D> {
+    fn dump(&mut self, packet: &BorrowedPacket) -> Result<(), Error> {
+        let mut header = header_from_packet(packet);
+        unsafe {
+            self.dll.dump(
+                self.dumper as *mut c_uchar,
+                &mut header,
+                packet.as_ptr() as *mut c_uchar,
+            )
+        };
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        if 0 == unsafe { self.dll.dump_flush(self.dumper) } {
+            Ok(())
+        } else {
+            Err(Error::LibraryError(
+                "Could not flush the capture file".into(),
+            ))
+        }
+    }
+}
+
+///Implements `DumperDll` for a dll wrapper that exposes the pcap dump functions.
+macro_rules! impl_dumper_dll {
+    ($dll:ty) => {
+        impl crate::pcap_common::dumper::DumperDll for $dll {
+            unsafe fn open_dead(
+                &self,
+                linktype: libc::c_int,
+                snaplen: libc::c_int,
+            ) -> *const crate::pcap_common::PCapHandle {
+                self.pcap_open_dead(linktype, snaplen)
+            }
+
+            unsafe fn dump_open(
+                &self,
+                handle: *const crate::pcap_common::PCapHandle,
+                fname: *const libc::c_char,
+            ) -> *mut crate::pcap_common::PCapDumper {
+                self.pcap_dump_open(handle, fname)
+            }
+
+            unsafe fn geterr(
+                &self,
+                handle: *const crate::pcap_common::PCapHandle,
+            ) -> *const libc::c_char {
+                self.pcap_geterr(handle)
+            }
+
+            unsafe fn close(&self, handle: *const crate::pcap_common::PCapHandle) {
+                self.pcap_close(handle)
+            }
+
+            unsafe fn dump(
+                &self,
+                user: *mut libc::c_uchar,
+                h: *mut crate::pcap_common::PCapPacketHeader,
+                sp: *mut libc::c_uchar,
+            ) {
+                self.pcap_dump(user, h, sp)
+            }
+
+            unsafe fn dump_flush(&self, dumper: *mut crate::pcap_common::PCapDumper) -> libc::c_int {
+                self.pcap_dump_flush(dumper)
+            }
+
+            unsafe fn dump_close(&self, dumper: *mut crate::pcap_common::PCapDumper) {
+                self.pcap_dump_close(dumper)
+            }
+        }
+    };
+}
+
+pub(crate) use impl_dumper_dll;
@@ -1,23 +1,52 @@
+use super::constants::PCAP_ERROR_BREAK;
 use super::structs::PCapPacketHeader;
-use crate::BorrowedPacket;
+use crate::{BorrowedPacket, Error, Filter, Packet};
 
-use libc::c_uchar;
-use std::mem::transmute;
+use libc::{c_int, c_uchar};
+use std::mem::{transmute, zeroed};
 use std::slice::from_raw_parts;
+use std::time::Duration;
 use time::Timespec;
 
+///Builds a packet from its pcap header.
+///
+/// `nanos` tells that the library was asked for nanosecond timestamps,
+/// which it then stores in the field meant for microseconds.
 pub fn borrowed_packet_from_header<'a, 'b>(
     header: &'a PCapPacketHeader,
     data: *const u8,
+    nanos: bool,
 ) -> BorrowedPacket<'b> {
+    let nsec = if nanos {
+        header.ts.tv_usec as i32
+    } else {
+        (header.ts.tv_usec * 1000) as i32
+    };
     unsafe {
         BorrowedPacket::new(
-            Timespec::new(header.ts.tv_sec as i64, (header.ts.tv_usec * 1000) as i32),
+            Timespec::new(header.ts.tv_sec as i64, nsec),
             from_raw_parts(data, header.caplen as usize),
         )
     }
 }
 
+///Builds the pcap header of a packet that is written to a capture file.
+pub fn header_from_packet(packet: &BorrowedPacket) -> PCapPacketHeader {
+    let when = packet.when();
+    let mut header: PCapPacketHeader = unsafe { zeroed() };
+    header.ts.tv_sec = when.sec as _;
+    header.ts.tv_usec = (when.nsec / 1000) as _;
+    header.caplen = packet.len() as _;
+    header.len = packet.len() as _;
+    header
+}
+
+///User data handed to pcap_loop() and back to the packet handlers.
+pub struct LoopUser<C> {
+    pub callback: C,
+    pub nanos: bool,
+}
+
 pub extern "C" fn on_received_packet_static<F>(
     user: *mut c_uchar,
     h: *const PCapPacketHeader,
@@ -25,10 +54,10 @@ pub extern "C" fn on_received_packet_static<F>(
 ) where
     F: FnMut(&BorrowedPacket),
 {
-    let callback: &mut F = unsafe { transmute(user) };
+    let user: &mut LoopUser<F> = unsafe { transmute(user) };
 
-    let packet = borrowed_packet_from_header(unsafe { &*h }, bytes);
-    callback(&packet)
+    let packet = borrowed_packet_from_header(unsafe { &*h }, bytes, user.nanos);
+    (user.callback)(&packet)
 }
 
 pub extern "C" fn on_received_packet_dynamic(
@@ -36,8 +65,59 @@ pub extern "C" fn on_received_packet_dynamic(
     h: *const PCapPacketHeader,
     bytes: *const c_uchar,
 ) {
-    let callback: &mut &mut dyn FnMut(&BorrowedPacket) = unsafe { transmute(user) };
+    let user: &mut LoopUser<&mut dyn FnMut(&BorrowedPacket)> = unsafe { transmute(user) };
+
+    let packet = borrowed_packet_from_header(unsafe { &*h }, bytes, user.nanos);
+    (user.callback)(&packet)
+}
+
+///Result of a pcap_next_ex() call, before it is turned into a packet.
+pub struct NextEx {
+    pub result: c_int,
+    pub header: *mut PCapPacketHeader,
+    pub data: *const c_uchar,
+}
+
+impl NextEx {
+    ///Converts the pcap_next_ex() outcome into a packet or the matching error.
+    ///
+    /// `last_error` is only called when the library reports an error.
+    pub fn into_packet<'b, F>(self, nanos: bool, last_error: F) -> Result<BorrowedPacket<'b>, Error>
+    where
+        F: FnOnce() -> String,
+    {
+        match self.result {
+            1 => Ok(borrowed_packet_from_header(unsafe { &*self.header }, self.data, nanos)),
+            0 => Err(Error::Timeout),
+            PCAP_ERROR_BREAK => Err(Error::EndOfFile),
+            _ => Err(Error::ReceivingPacket(last_error())),
+        }
+    }
+}
+
+///Error of a filter expression that pcap could not compile.
+///
+/// pcap does not tell where an expression is wrong, so the expression is parsed again to find out.
+pub fn filter_error(filter: &str, message: String) -> Error {
+    match filter.parse::<Filter>() {
+        Err(err) => Error::InvalidFilter(err),
+        Ok(_) => Error::LibraryError(message),
+    }
+}
+
+///Waits until `fd` is readable or `timeout` passes.
+#[cfg(unix)]
+pub fn wait_readable(fd: c_int, timeout: Duration) {
+    let mut pollfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let ms = timeout.as_millis().min(c_int::max_value() as u128) as c_int;
+    unsafe { libc::poll(&mut pollfd, 1, ms) };
+}
 
-    let packet = borrowed_packet_from_header(unsafe { &*h }, bytes);
-    callback(&packet)
+///Waits a little for packets where the library offers nothing to wait on.
+pub fn wait_a_little(timeout: Duration) {
+    std::thread::sleep(timeout.min(Duration::from_millis(1)));
 }
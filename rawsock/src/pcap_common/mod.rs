@@ -1,4 +1,5 @@
 pub mod constants;
+pub mod dumper;
 pub mod helpers;
 mod structs;
 
@@ -1,6 +1,6 @@
 use super::constants::ERRBUF_SIZE;
-use crate::common::InterfaceDescription;
-use crate::utils::cstr_to_string;
+use crate::common::{ConnectionStatus, InterfaceAddress, InterfaceDescription, InterfaceFlags};
+use crate::utils::{cstr_to_string, ip_from_sockaddr, mac_from_sockaddr};
 use libc::{c_char, c_uchar, c_uint, c_ushort, c_void, timeval};
 use std::ffi::CStr;
 use std::mem::zeroed;
@@ -44,10 +44,25 @@ pub struct PCapInterface {
     pub next: *const PCapInterface,
     pub name: *const c_char,        /* name to hand to "pcap_open_live()" */
     pub description: *const c_char, /* textual description of interface, or NULL */
-    pub addresses: *const c_void,
+    pub addresses: *const PCapAddr,
     pub flags: c_uint, /* PCAP_IF_ interface flags */
 }
 
+///Equivalent of pcap_addr_t
+#[repr(C)]
+pub struct PCapAddr {
+    pub next: *const PCapAddr,
+    pub addr: *const c_void,      /* struct sockaddr */
+    pub netmask: *const c_void,   /* struct sockaddr, or NULL */
+    pub broadaddr: *const c_void, /* struct sockaddr, or NULL */
+    pub dstaddr: *const c_void,   /* struct sockaddr, or NULL */
+}
+
+const PCAP_IF_CONNECTION_STATUS: c_uint = 0x0000_0030;
+const PCAP_IF_CONNECTION_STATUS_CONNECTED: c_uint = 0x0000_0010;
+const PCAP_IF_CONNECTION_STATUS_DISCONNECTED: c_uint = 0x0000_0020;
+const PCAP_IF_CONNECTION_STATUS_NOT_APPLICABLE: c_uint = 0x0000_0030;
+
 ///Equivalent of C struct pcap_pkthdr
 #[repr(C)]
 #[derive(Copy, Clone)]
@@ -64,7 +79,7 @@ pub struct PCapPacketHeader {
 }
 
 ///Equivalent of pcap_direction_t
-/// #[repr(u32)]
+#[repr(C)]
 pub enum PCapDirection {
     InOut = 0,
     In = 1,
@@ -78,10 +93,35 @@ pub fn interface_data_from_pcap_list(interfs: *const PCapInterface) -> Vec<Inter
     let mut interfs_descr = Vec::new();
     let mut curr = interfs;
     while !curr.is_null() {
-        let id = InterfaceDescription {
+        let flags = unsafe { (*curr).flags };
+        let mut id = InterfaceDescription {
             name: cstr_to_string(unsafe { (*curr).name }),
             description: cstr_to_string(unsafe { (*curr).description }),
+            flags: InterfaceFlags::from_bits_truncate(flags),
+            connection: match flags & PCAP_IF_CONNECTION_STATUS {
+                PCAP_IF_CONNECTION_STATUS_CONNECTED => ConnectionStatus::Connected,
+                PCAP_IF_CONNECTION_STATUS_DISCONNECTED => ConnectionStatus::Disconnected,
+                PCAP_IF_CONNECTION_STATUS_NOT_APPLICABLE => ConnectionStatus::NotApplicable,
+                _ => ConnectionStatus::Unknown,
+            },
+            ..Default::default()
         };
+        let mut addr = unsafe { (*curr).addresses };
+        while !addr.is_null() {
+            let a = unsafe { &*addr };
+            // link layer addresses come in the same list on some systems
+            if let Some(address) = ip_from_sockaddr(a.addr) {
+                id.addresses.push(InterfaceAddress {
+                    address,
+                    netmask: ip_from_sockaddr(a.netmask),
+                    broadcast: ip_from_sockaddr(a.broadaddr),
+                    destination: ip_from_sockaddr(a.dstaddr),
+                });
+            } else if let Some(mac) = mac_from_sockaddr(a.addr) {
+                id.hardware_address = Some(mac);
+            }
+            addr = a.next;
+        }
         interfs_descr.push(id);
         curr = unsafe { (*curr).next };
     }
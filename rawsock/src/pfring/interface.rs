@@ -1,42 +1,99 @@
-use super::dll::helpers::{borrowed_packet_from_header, string_from_pfring_err_code};
-use super::dll::{PFRing, PFRingDll, PFRingPacketHeader, PFRingStat, SUCCESS};
+use super::dll::helpers::{borrowed_packet_from_header, string_from_pfring_err_code, PFRingErrCode};
+use super::dll::{
+    PFRing, PFRingDll, PFRingFlags, PFRingPacketHeader, PFRingStat, PacketDirection, MAX_CAPLEN,
+    SUCCESS,
+};
+use crate::common::SendCounters;
 use crate::utils::string_from_errno;
 use crate::Error;
-use crate::{traits, BorrowedPacket, DataLink, Stats};
+use crate::{traits, BorrowedPacket, DataLink, Direction, InterfaceOptions, Stats};
 use dlopen::wrapper::Container;
 use libc::{c_int, c_uchar, c_uint};
 use std::ffi::{CStr, CString};
 use std::mem::{MaybeUninit, transmute};
+use std::time::Duration;
 
 ///pfring version of an interface.
 pub struct Interface<'a> {
     handle: *mut PFRing,
     dll: &'a Container<PFRingDll>,
+    nonblock: bool,
+    send_counters: SendCounters,
 }
 
 unsafe impl<'a> Sync for Interface<'a> {}
 unsafe impl<'a> Send for Interface<'a> {}
 
 impl<'a> Interface<'a> {
+    ///Opens the interface with the pfring defaults, without promiscuous mode and with
+    /// packets cut at 1500 bytes.
     pub fn new(name: &str, dll: &'a Container<PFRingDll>) -> Result<Self, Error> {
+        let options = InterfaceOptions {
+            snaplen: 1500,
+            promiscuous: false,
+            ..InterfaceOptions::default()
+        };
+        Self::with_options(name, dll, &options)
+    }
+
+    pub fn with_options(
+        name: &str,
+        dll: &'a Container<PFRingDll>,
+        options: &InterfaceOptions,
+    ) -> Result<Self, Error> {
         let name = CString::new(name)?;
-        let handle = unsafe { dll.pfring_open(name.as_ptr(), 1500, 0) }; //PF_RING_REENTRANT
+        let mut flags = PFRingFlags::empty();
+        flags.set(PFRingFlags::PROMISC, options.promiscuous);
+        let caplen = options.snaplen.min(MAX_CAPLEN as u32);
+        let handle = unsafe { dll.pfring_open(name.as_ptr(), caplen, flags.bits() as u32) };
         if handle.is_null() {
             return Err(Error::OpeningInterface(string_from_errno()));
         }
 
+        let direction = match options.direction {
+            Direction::InOut => PacketDirection::RxAndTx,
+            Direction::In => PacketDirection::RxOnly,
+            Direction::Out => PacketDirection::TxOnly,
+        };
+        let result = unsafe { dll.pfring_set_direction(handle, direction) };
+        if result < 0 {
+            unsafe { dll.pfring_close(handle) };
+            return Err(Error::OpeningInterface(string_from_pfring_err_code(result)));
+        }
+
         let result = unsafe { dll.pfring_enable_ring(handle) };
         if result < 0 {
             unsafe { dll.pfring_close(handle) };
             return Err(Error::OpeningInterface(string_from_pfring_err_code(result)));
         }
 
-        Ok(Self { handle, dll })
+        Ok(Self {
+            handle,
+            dll,
+            nonblock: false,
+            send_counters: SendCounters::default(),
+        })
     }
 
     fn int_to_err(&self, err: c_int) -> Error {
         Error::LibraryError(string_from_pfring_err_code(err))
     }
+
+    fn recv(&mut self, wait: bool) -> Result<BorrowedPacket, Error> {
+        let mut buf = MaybeUninit::<*mut u8>::uninit();
+        let mut header = MaybeUninit::<PFRingPacketHeader>::uninit();
+        let result = unsafe {
+            self.dll
+                .pfring_recv(self.handle, buf.as_mut_ptr(), 0, header.as_mut_ptr(), wait as u8)
+        };
+        match result {
+            1 => unsafe {
+                Ok(borrowed_packet_from_header(&header.assume_init(), buf.assume_init()))
+            },
+            0 => Err(Error::Timeout),
+            _ => Err(Error::ReceivingPacket(string_from_pfring_err_code(result))),
+        }
+    }
 }
 
 impl<'a> Drop for Interface<'a> {
@@ -51,26 +108,61 @@ impl<'a> traits::DynamicInterface<'a> for Interface<'a> {
             self.dll
                 .pfring_send(self.handle, packet.as_ptr(), packet.len() as c_uint, 0)
         };
-        if result < 0 {
+        let result = if result == PFRingErrCode::NoTxSlotsAvailable as c_int {
+            Err(Error::WouldBlock)
+        } else if result < 0 {
             Err(Error::SendingPacket(string_from_pfring_err_code(result)))
         } else {
             Ok(())
+        };
+        self.send_counters.count(1, result)
+    }
+
+    ///Queues the packets without flushing them one by one, then flushes them together.
+    fn send_batch(&self, packets: &[&[u8]]) -> Result<(), Error> {
+        for (queued, packet) in packets.iter().enumerate() {
+            let result = unsafe {
+                self.dll
+                    .pfring_send(self.handle, packet.as_ptr(), packet.len() as c_uint, 0)
+            };
+            if result < 0 {
+                // the packets behind it are not sent either
+                self.send_counters.add(queued, packets.len() - queued);
+                return Err(Error::SendingPacket(string_from_pfring_err_code(result)));
+            }
         }
+        let result = unsafe { self.dll.pfring_flush_tx_packets(self.handle) };
+        let result = if result < 0 {
+            Err(Error::SendingPacket(string_from_pfring_err_code(result)))
+        } else {
+            Ok(())
+        };
+        self.send_counters.count(packets.len(), result)
     }
 
     fn receive(&mut self) -> Result<BorrowedPacket, Error> {
-        let mut buf = MaybeUninit::<*mut u8>::uninit();
-        let mut header = MaybeUninit::<PFRingPacketHeader>::uninit();
-        let result = unsafe {
-            self.dll
-                .pfring_recv(self.handle, buf.as_mut_ptr(), 0, header.as_mut_ptr(), 1)
-        };
-        if result != 1 {
-            Err(Error::ReceivingPacket(string_from_pfring_err_code(result)))
-        } else {
-            unsafe {
-                Ok(borrowed_packet_from_header(&header.assume_init(), buf.assume_init()))
-            }
+        let wait = !self.nonblock;
+        self.recv(wait)
+    }
+
+    fn receive_timeout(&mut self, timeout: Duration) -> Result<BorrowedPacket, Error> {
+        let ms = timeout.as_millis().min(c_uint::max_value() as u128) as c_uint;
+        let result = unsafe { self.dll.pfring_poll(self.handle, ms) };
+        if result < 0 {
+            return Err(Error::ReceivingPacket(string_from_errno()));
+        }
+        self.recv(false)
+    }
+
+    fn set_nonblock(&mut self, nonblock: bool) -> Result<(), Error> {
+        self.nonblock = nonblock;
+        Ok(())
+    }
+
+    fn selectable_fd(&self) -> Option<c_int> {
+        match unsafe { self.dll.pfring_get_selectable_fd(self.handle) } {
+            fd if fd < 0 => None,
+            fd => Some(fd),
         }
     }
 
@@ -88,10 +180,12 @@ impl<'a> traits::DynamicInterface<'a> for Interface<'a> {
         let result = unsafe { self.dll.pfring_stats(self.handle, stats.as_mut_ptr()) };
         if result == SUCCESS {
             let stats = unsafe { stats.assume_init() };
-            Ok(Stats {
+            Ok(self.send_counters.fill(Stats {
                 received: stats.recv as u64,
                 dropped: stats.drop as u64,
-            })
+                shunted: stats.shunt as u64,
+                ..Stats::default()
+            }))
         } else {
             Err(self.int_to_err(result))
         }
@@ -101,12 +195,12 @@ impl<'a> traits::DynamicInterface<'a> for Interface<'a> {
         unsafe { self.dll.pfring_breakloop(self.handle) };
     }
 
-    fn loop_infinite_dyn(&self, callback: &dyn FnMut(&BorrowedPacket)) -> Result<(), Error> {
+    fn loop_infinite_dyn(&self, mut callback: &mut dyn FnMut(&BorrowedPacket)) -> Result<(), Error> {
         let result = unsafe {
             self.dll.pfring_loop(
                 self.handle,
                 on_received_packet_dynamic,
-                transmute(&callback),
+                transmute(&mut callback),
                 1,
             )
         };
@@ -167,7 +261,7 @@ extern "C" fn on_received_packet_dynamic(
 }
 
 impl<'a> traits::StaticInterface<'a> for Interface<'a> {
-    fn loop_infinite<F>(&self, callback: F) -> Result<(), Error>
+    fn loop_infinite<F>(&self, mut callback: F) -> Result<(), Error>
     where
         F: FnMut(&BorrowedPacket),
     {
@@ -175,7 +269,7 @@ impl<'a> traits::StaticInterface<'a> for Interface<'a> {
             self.dll.pfring_loop(
                 self.handle,
                 on_received_packet_static::<F>,
-                transmute(&callback),
+                transmute(&mut callback),
                 1,
             )
         };
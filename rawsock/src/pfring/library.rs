@@ -1,10 +1,15 @@
-use super::dll::PFRingDll;
+use super::dll::helpers::string_from_pfring_err_code;
+use super::dll::{PFRingBpfProgram, PFRingDll, MAX_CAPLEN};
 use super::interface::Interface;
 use super::paths::DEFAULT_PATHS;
+use crate::pcap_common::helpers::filter_error;
 use crate::utils::cstr_to_string;
-use crate::Error;
+use crate::{DataLink, Error, InterfaceOptions};
 use crate::{traits, InterfaceDescription, LibraryVersion};
 use dlopen::wrapper::Container;
+use libc::{c_char, c_uint};
+use std::ffi::CString;
+use std::mem::MaybeUninit;
 use std::sync::Arc;
 
 ///Instance of a opened pfring library.
@@ -50,6 +55,28 @@ impl traits::Library for Library {
         }
     }
 
+    fn open_interface_with_options<'a>(
+        &'a self,
+        name: &str,
+        options: &InterfaceOptions,
+    ) -> Result<Box<dyn traits::DynamicInterface<'a> + 'a>, Error> {
+        match self.open_interface_with_options(name, options) {
+            Ok(interf) => Ok(Box::new(interf) as Box<dyn traits::DynamicInterface>),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn open_interface_arc_with_options<'a>(
+        &'a self,
+        name: &str,
+        options: &InterfaceOptions,
+    ) -> Result<Arc<dyn traits::DynamicInterface<'a> + 'a>, Error> {
+        match Interface::with_options(name, &self.dll, options) {
+            Ok(interf) => Ok(Arc::new(interf) as Arc<dyn traits::DynamicInterface>),
+            Err(e) => Err(e),
+        }
+    }
+
     fn all_interfaces(&self) -> Result<Vec<InterfaceDescription>, Error> {
         let interfs = unsafe { self.dll.pfring_findalldevs() };
         let mut curr = interfs;
@@ -61,6 +88,7 @@ impl traits::Library for Library {
             let id = InterfaceDescription {
                 name: cstr_to_string(unsafe { (*curr).name }),
                 description: format!("{}, {}, {}", &system_name, &module, &sn),
+                ..Default::default()
             };
             result.push(id);
             curr = unsafe { (*curr).next };
@@ -77,12 +105,44 @@ impl traits::Library for Library {
         let release: u8 = ver as u8;
         LibraryVersion::PFRing(format!("{}.{}.{}", major, minor, release))
     }
+
+    fn compile_filter(&self, filter: &str, data_link: DataLink) -> Result<(), Error> {
+        // pfring only captures Ethernet frames and compiles filters for them
+        if data_link != DataLink::Ethernet {
+            return Err(Error::LibraryError(format!(
+                "Filters cannot be compiled for {} links",
+                data_link
+            )));
+        }
+        let cfilter = CString::new(filter)?;
+        let mut program = MaybeUninit::<PFRingBpfProgram>::uninit();
+        let result = unsafe {
+            self.dll.pfring_parse_bpf_filter(
+                cfilter.as_ptr() as *mut c_char,
+                MAX_CAPLEN as c_uint,
+                program.as_mut_ptr(),
+            )
+        };
+        if result == 0 {
+            unsafe { self.dll.pfring_free_bpf_filter(program.as_mut_ptr()) };
+            Ok(())
+        } else {
+            Err(filter_error(filter, string_from_pfring_err_code(result)))
+        }
+    }
 }
 
 impl Library {
     pub fn open_interface(&self, name: &str) -> Result<Interface, Error> {
         Interface::new(name, &self.dll)
     }
+    pub fn open_interface_with_options(
+        &self,
+        name: &str,
+        options: &InterfaceOptions,
+    ) -> Result<Interface<'_>, Error> {
+        Interface::with_options(name, &self.dll, options)
+    }
     pub fn dll(&self) -> &PFRingDll {
         &self.dll
     }
@@ -2,10 +2,15 @@
 Common traits for all libraries.
 */
 
-use crate::{BorrowedPacket, DataLink, Error, InterfaceDescription, LibraryVersion, Stats};
+use crate::{
+    BorrowedPacket, DataLink, Error, Filter, InterfaceDescription, InterfaceOptions,
+    LibraryVersion, Stats,
+};
+use libc::c_int;
 use std::ffi::{CStr, CString};
 use std::iter::IntoIterator;
 use std::sync::Arc;
+use std::time::Duration;
 
 ///Trait for structures representing an opened interface (or network card or network device)
 ///
@@ -14,9 +19,49 @@ pub trait DynamicInterface<'a>: Send + Sync {
     ///Sends a raw packet.
     fn send(&self, packet: &[u8]) -> Result<(), Error>;
 
+    ///Sends several raw packets at once.
+    ///
+    /// Libraries that can hand a whole burst to the system do so, the others send the packets
+    /// one by one. Stops at the first packet that could not be sent.
+    fn send_batch(&self, packets: &[&[u8]]) -> Result<(), Error> {
+        for packet in packets {
+            self.send(packet)?;
+        }
+        Ok(())
+    }
+
     ///Receives a raw packet.
+    ///
+    /// Returns `Error::Timeout` when the read timeout of the library expires, or at once in
+    /// non-blocking mode if no packet is ready.
     fn receive<'b>(&'b mut self) -> Result<BorrowedPacket<'b>, Error>;
 
+    ///Receives a raw packet, waiting at most `timeout` for one to arrive.
+    fn receive_timeout<'b>(&'b mut self, _timeout: Duration) -> Result<BorrowedPacket<'b>, Error> {
+        Err(Error::LibraryError(
+            "Receiving with a timeout is not supported by this library".into(),
+        ))
+    }
+
+    ///Switches non-blocking mode, in which receive() doesn't wait for packets.
+    ///
+    /// Where the library can tell, send() then fails with `Error::WouldBlock` instead of waiting
+    /// for room to send. Interfaces are blocking when opened, libraries without non-blocking mode only accept false.
+    fn set_nonblock(&mut self, nonblock: bool) -> Result<(), Error> {
+        if nonblock {
+            Err(Error::LibraryError(
+                "Non-blocking mode is not supported by this library".into(),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    ///File descriptor that becomes readable when packets arrive, if the library provides one.
+    fn selectable_fd(&self) -> Option<c_int> {
+        None
+    }
+
     ///Flushes a queue
     fn flush(&self);
 
@@ -34,7 +79,7 @@ pub trait DynamicInterface<'a>: Send + Sync {
 
     Exits when the break_loop() function is called or on error.
     */
-    fn loop_infinite_dyn(&self, callback: &dyn FnMut(&BorrowedPacket)) -> Result<(), Error>;
+    fn loop_infinite_dyn(&self, callback: &mut dyn FnMut(&BorrowedPacket)) -> Result<(), Error>;
 
     ///Set bpf filter.
     fn set_filter(&mut self, filter: &str) -> Result<(), Error> {
@@ -47,11 +92,15 @@ pub trait DynamicInterface<'a>: Send + Sync {
 
     ///Remove bpf filter.
     fn remove_filter(&mut self) -> Result<(), Error>;
+}
 
-    // fn receive_timeout<'b>(&'b mut self, timeout: Duration) -> Result<BorrowedPacket<'b>, Error>;
+///Trait for structures writing packets into a capture file.
+pub trait PacketDumper: Send {
+    ///Appends the packet to the file, keeping the time when it was received.
+    fn dump(&mut self, packet: &BorrowedPacket) -> Result<(), Error>;
 
-    //TODO
-    //receive/send with timeout
+    ///Writes buffered packets to the file.
+    fn flush(&mut self) -> Result<(), Error>;
 }
 
 /**
@@ -127,6 +176,69 @@ pub trait Library: Send + Sync {
         name: &str,
     ) -> Result<Arc<dyn DynamicInterface<'a> + 'a>, Error>;
 
+    ///Opens interface with the given capture parameters instead of the default ones.
+    ///
+    /// Libraries that cannot set them only accept the default options.
+    fn open_interface_with_options<'a>(
+        &'a self,
+        name: &str,
+        options: &InterfaceOptions,
+    ) -> Result<Box<dyn DynamicInterface<'a> + 'a>, Error> {
+        if *options != InterfaceOptions::default() {
+            return Err(Error::LibraryError(
+                "Capture options are not supported by this library".into(),
+            ));
+        }
+        self.open_interface(name)
+    }
+
+    fn open_interface_arc_with_options<'a>(
+        &'a self,
+        name: &str,
+        options: &InterfaceOptions,
+    ) -> Result<Arc<dyn DynamicInterface<'a> + 'a>, Error> {
+        if *options != InterfaceOptions::default() {
+            return Err(Error::LibraryError(
+                "Capture options are not supported by this library".into(),
+            ));
+        }
+        self.open_interface_arc(name)
+    }
+
+    ///Opens a capture file and replays its packets like an interface would receive them.
+    ///
+    /// The returned interface reports `Error::EndOfFile` after the last packet and cannot send.
+    fn open_offline<'a>(
+        &'a self,
+        _path: &str,
+    ) -> Result<Box<dyn DynamicInterface<'a> + 'a>, Error> {
+        Err(Error::LibraryError(
+            "Capture files are not supported by this library".into(),
+        ))
+    }
+
+    ///Creates (or truncates) a capture file for packets with the given data link.
+    fn open_dumper<'a>(
+        &'a self,
+        _path: &str,
+        _data_link: DataLink,
+    ) -> Result<Box<dyn PacketDumper + 'a>, Error> {
+        Err(Error::LibraryError(
+            "Capture files are not supported by this library".into(),
+        ))
+    }
+
+    /**
+    Checks that a filter expression can be set on interfaces with the given data link.
+
+    Errors point at the wrong part of the expression where that can be told. Libraries without
+    a compiler of their own accept the expressions that `Filter` parses.
+    */
+    fn compile_filter(&self, filter: &str, _data_link: DataLink) -> Result<(), Error> {
+        filter.parse::<Filter>()?;
+        Ok(())
+    }
+
     /**
     Obtains list of available network interfaces.
 
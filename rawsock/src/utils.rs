@@ -1,6 +1,10 @@
+use crate::Error;
 use errno::errno;
-use libc::{c_char, c_int, strerror};
+use libc::{c_char, c_int, c_void, strerror};
 use std::ffi::CStr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+const AF_INET: u16 = 2;
 
 pub fn cstr_to_string(txt: *const c_char) -> String {
     if txt.is_null() {
@@ -20,3 +24,112 @@ pub fn string_from_err_code(code: c_int) -> String {
     let corrected = if code < 0 { -code } else { code };
     cstr_to_string(unsafe { strerror(corrected) })
 }
+
+///Whether a send failed with `code` only because the socket is non-blocking and full.
+pub fn would_block(code: c_int) -> bool {
+    code == libc::EAGAIN || code == libc::EWOULDBLOCK
+}
+
+///Error of a send that failed with `code`, the errno read right after the call.
+#[allow(dead_code)]
+pub fn send_error(code: c_int) -> Error {
+    if would_block(code) {
+        Error::WouldBlock
+    } else {
+        Error::SendingPacket(string_from_err_code(code))
+    }
+}
+
+///The errno of the call that just failed.
+pub fn last_errno() -> c_int {
+    errno().into()
+}
+
+#[cfg(unix)]
+const AF_INET6: u16 = libc::AF_INET6 as u16;
+#[cfg(windows)]
+const AF_INET6: u16 = 23;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const AF_LINK: u16 = libc::AF_PACKET as u16;
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+const AF_LINK: u16 = libc::AF_LINK as u16;
+
+///Reads the family of a C sockaddr, which BSD systems precede with its length.
+unsafe fn sockaddr_family(addr: *const u8) -> u16 {
+    if cfg!(any(
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd"
+    )) {
+        u16::from(*addr.add(1))
+    } else {
+        (addr as *const u16).read_unaligned()
+    }
+}
+
+///Copies `out.len()` bytes found `offset` bytes into `addr`, which must all be readable.
+unsafe fn read_bytes(addr: *const u8, offset: usize, out: &mut [u8]) {
+    std::ptr::copy_nonoverlapping(addr.add(offset), out.as_mut_ptr(), out.len())
+}
+
+///Converts a C sockaddr_in or sockaddr_in6 into an IP address.
+pub fn ip_from_sockaddr(addr: *const c_void) -> Option<IpAddr> {
+    if addr.is_null() {
+        return None;
+    }
+    let addr = addr as *const u8;
+    match unsafe { sockaddr_family(addr) } {
+        AF_INET => {
+            let mut ip = [0u8; 4];
+            unsafe { read_bytes(addr, 4, &mut ip) };
+            Some(Ipv4Addr::from(ip).into())
+        }
+        AF_INET6 => {
+            let mut ip = [0u8; 16];
+            unsafe { read_bytes(addr, 8, &mut ip) };
+            Some(Ipv6Addr::from(ip).into())
+        }
+        _ => None,
+    }
+}
+
+///Extracts a MAC address from a C link layer sockaddr (sockaddr_ll or sockaddr_dl).
+pub fn mac_from_sockaddr(addr: *const c_void) -> Option<[u8; 6]> {
+    if addr.is_null() {
+        return None;
+    }
+    unsafe { link_address(addr as *const u8) }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+unsafe fn link_address(addr: *const u8) -> Option<[u8; 6]> {
+    // sll_halen, then sll_addr
+    if sockaddr_family(addr) != AF_LINK || *addr.add(11) != 6 {
+        return None;
+    }
+    let mut mac = [0u8; 6];
+    read_bytes(addr, 12, &mut mac);
+    Some(mac)
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+unsafe fn link_address(addr: *const u8) -> Option<[u8; 6]> {
+    // sdl_nlen and sdl_alen, then the name followed by the address in sdl_data
+    if sockaddr_family(addr) != AF_LINK || *addr.add(6) != 6 {
+        return None;
+    }
+    let mut mac = [0u8; 6];
+    read_bytes(addr, 8 + usize::from(*addr.add(5)), &mut mac);
+    Some(mac)
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd"
+)))]
+unsafe fn link_address(_addr: *const u8) -> Option<[u8; 6]> {
+    None
+}
@@ -9,7 +9,7 @@ pub use crate::pcap_common::{
     BpfProgram, PCapDumper, PCapHandle, PCapHandler, PCapInterface, PCapPacketHeader,
 };
 use dlopen::wrapper::WrapperApi;
-use libc::{c_char, c_int, c_long, c_uchar, c_uint, FILE};
+use libc::{c_char, c_int, c_long, c_uchar, c_uint, c_void, FILE};
 
 /// Code hat helps you use the original DLL API.
 pub mod helpers {
@@ -116,6 +116,7 @@ pub struct WPCapDll {
     pcap_file: unsafe extern "C" fn(p: *const PCapHandle) -> *mut FILE,
 
     //wpcap specific
+    pcap_getevent: unsafe extern "C" fn(p: *const PCapHandle) -> *mut c_void,
     pcap_sendqueue_destroy: unsafe extern "C" fn(queue: *mut PCapSendQueue),
     pcap_sendqueue_alloc: unsafe extern "C" fn(memsize: c_uint) -> *mut PCapSendQueue,
     pcap_sendqueue_queue: unsafe extern "C" fn(
@@ -0,0 +1,7 @@
+use super::dll::WPCapDll;
+use crate::pcap_common::dumper::{self, impl_dumper_dll};
+
+impl_dumper_dll!(WPCapDll);
+
+///wpcap version of a capture file writer.
+pub type Dumper<'a> = dumper::Dumper<'a, WPCapDll>;
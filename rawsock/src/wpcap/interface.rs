@@ -1,16 +1,20 @@
 use super::dll::helpers::PCapErrBuf;
-use super::dll::{PCapHandle, PCapPacketHeader, PCapSendQueue, WPCapDll};
+use super::dll::{PCapHandle, PCapSendQueue, WPCapDll};
 use super::structs::PCapStat;
 use crate::pcap_common::constants::{PCAP_EMPTY_FILTER_STR, PCAP_ERROR_BREAK, SUCCESS};
 use crate::pcap_common::helpers::{
-    borrowed_packet_from_header, on_received_packet_dynamic, on_received_packet_static,
+    on_received_packet_dynamic, on_received_packet_static, wait_a_little,
+    LoopUser, NextEx,
 };
-use crate::pcap_common::BpfProgram;
+use crate::pcap_common::{BpfProgram, PCapPacketHeader};
+use crate::common::SendCounters;
 use crate::utils::cstr_to_string;
-use crate::{traits, BorrowedPacket, DataLink, Error, Stats};
+use crate::{traits, BorrowedPacket, DataLink, Error, InterfaceOptions, Stats};
 use libc::{c_int, c_uint};
 use std::ffi::{CStr, CString};
-use std::mem::{transmute, MaybeUninit};
+use std::mem::{size_of, transmute, zeroed, MaybeUninit};
+use std::ptr::{null, null_mut};
+use std::time::{Duration, Instant};
 
 const QUEUE_SIZE: usize = 65536 * 8; //min 8 packets
 
@@ -19,7 +23,10 @@ pub struct Interface<'a> {
     handle: *const PCapHandle,
     dll: &'a WPCapDll,
     datalink: DataLink,
+    nonblock: bool,
+    offline: bool,
     queue: *mut PCapSendQueue,
+    send_counters: SendCounters,
 }
 
 unsafe impl<'a> Sync for Interface<'a> {}
@@ -27,14 +34,26 @@ unsafe impl<'a> Send for Interface<'a> {}
 
 impl<'a> Interface<'a> {
     pub fn new(name: &str, dll: &'a WPCapDll) -> Result<Self, Error> {
+        Self::with_options(name, dll, &InterfaceOptions::default())
+    }
+
+    ///Opens the interface with pcap_open_live(), which only takes the snapshot length,
+    /// the promiscuous mode and the read timeout. Immediate mode is set afterwards,
+    /// the remaining options are ignored.
+    pub fn with_options(
+        name: &str,
+        dll: &'a WPCapDll,
+        options: &InterfaceOptions,
+    ) -> Result<Self, Error> {
         let name = CString::new(name)?;
         let mut errbuf = PCapErrBuf::new();
+        let timeout = options.read_timeout.as_millis().min(c_uint::max_value() as u128);
         let handle = unsafe {
             dll.pcap_open_live(
                 name.as_ptr(),
-                65536, /* max packet size */
-                1,     /* promiscuous mode */
-                1000,  /* read timeout in milliseconds */
+                options.snaplen,
+                options.promiscuous as c_uint,
+                timeout as c_uint,
                 errbuf.buffer(),
             )
         };
@@ -43,31 +62,103 @@ impl<'a> Interface<'a> {
         }
         let queue = unsafe { dll.pcap_sendqueue_alloc(QUEUE_SIZE as c_uint) };
         assert!(!queue.is_null());
-        let datalink = match unsafe { dll.pcap_datalink(handle) } {
-            1 => DataLink::Ethernet,
-            12 => DataLink::RawIp,
-            _ => DataLink::Other,
-        };
+        let datalink = DataLink::from_dlt(unsafe { dll.pcap_datalink(handle) });
 
         let mut ret = Interface {
             dll,
             queue,
             handle,
             datalink,
+            nonblock: false,
+            offline: false,
+            send_counters: SendCounters::default(),
         };
 
-        #[cfg(feature = "immediate_mode")]
-        ret.set_immediate_mode()?;
+        if options.immediate {
+            ret.set_immediate_mode()?;
+        }
 
         Ok(ret)
     }
 
+    ///Opens a capture file whose packets are then received in the order they were captured.
+    pub fn open_offline(path: &str, dll: &'a WPCapDll) -> Result<Self, Error> {
+        let path = CString::new(path)?;
+        let mut errbuf = PCapErrBuf::new();
+        let handle = unsafe { dll.pcap_open_offline(path.as_ptr(), errbuf.buffer()) };
+        if handle.is_null() {
+            return Err(Error::OpeningInterface(errbuf.as_string()));
+        }
+        let queue = unsafe { dll.pcap_sendqueue_alloc(QUEUE_SIZE as c_uint) };
+        assert!(!queue.is_null());
+        Ok(Interface {
+            dll,
+            queue,
+            handle,
+            datalink: DataLink::from_dlt(unsafe { dll.pcap_datalink(handle) }),
+            nonblock: false,
+            offline: true,
+            send_counters: SendCounters::default(),
+        })
+    }
+
     fn last_error(&self) -> Error {
         let cerr = unsafe { self.dll.pcap_geterr(self.handle) };
         Error::LibraryError(cstr_to_string(cerr))
     }
 
-    #[cfg(feature = "immediate_mode")]
+    ///Transmits the packets through a send queue of their own.
+    fn transmit(&self, packets: &[&[u8]]) -> Result<(), Error> {
+        if self.offline {
+            return Err(Error::SendingPacket("Cannot send packets to a capture file".into()));
+        }
+        let size: usize = packets
+            .iter()
+            .map(|p| p.len() + size_of::<PCapPacketHeader>())
+            .sum();
+        // the shared queue is only used by flush(), batches may come from several threads
+        let queue = unsafe { self.dll.pcap_sendqueue_alloc(size as c_uint) };
+        if queue.is_null() {
+            return Err(Error::SendingPacket("Could not allocate a send queue".into()));
+        }
+        let mut header: PCapPacketHeader = unsafe { zeroed() };
+        for packet in packets {
+            header.caplen = packet.len() as c_uint;
+            header.len = packet.len() as c_uint;
+            unsafe { self.dll.pcap_sendqueue_queue(queue, &header, packet.as_ptr()) };
+        }
+        let sent = unsafe { self.dll.pcap_sendqueue_transmit(self.handle, queue, 0) };
+        unsafe { self.dll.pcap_sendqueue_destroy(queue) };
+        if sent as usize == size {
+            Ok(())
+        } else {
+            Err(Error::SendingPacket(self.last_error_string()))
+        }
+    }
+
+    fn last_error_string(&self) -> String {
+        cstr_to_string(unsafe { self.dll.pcap_geterr(self.handle) })
+    }
+
+    fn next_ex(&self) -> NextEx {
+        let mut next = NextEx {
+            result: 0,
+            header: null_mut(),
+            data: null(),
+        };
+        next.result = unsafe { self.dll.pcap_next_ex(self.handle, &mut next.header, &mut next.data) };
+        next
+    }
+
+    fn setnonblock(&self, nonblock: bool) -> Result<(), Error> {
+        let mut errbuf = PCapErrBuf::new();
+        if SUCCESS == unsafe { self.dll.pcap_setnonblock(self.handle, nonblock as c_int, errbuf.buffer()) } {
+            Ok(())
+        } else {
+            Err(Error::LibraryError(errbuf.as_string()))
+        }
+    }
+
     fn set_immediate_mode(&mut self) -> Result<(), Error> {
         if SUCCESS == unsafe { self.dll.pcap_setmintocopy(self.handle, 0) } {
             Ok(())
@@ -77,6 +168,30 @@ impl<'a> Interface<'a> {
     }
 }
 
+#[cfg(windows)]
+extern "system" {
+    fn WaitForSingleObject(handle: *mut libc::c_void, milliseconds: u32) -> u32;
+}
+
+impl<'a> Interface<'a> {
+    ///Waits for the event wpcap signals when packets arrive.
+    #[cfg(windows)]
+    fn wait(&self, timeout: Duration) {
+        let event = unsafe { self.dll.pcap_getevent(self.handle) };
+        if event.is_null() {
+            return wait_a_little(timeout);
+        }
+        // u32::MAX would be INFINITE
+        let ms = timeout.as_millis().min(u32::max_value() as u128 - 1) as u32;
+        unsafe { WaitForSingleObject(event, ms) };
+    }
+
+    #[cfg(not(windows))]
+    fn wait(&self, timeout: Duration) {
+        wait_a_little(timeout)
+    }
+}
+
 impl<'a> Drop for Interface<'a> {
     fn drop(&mut self) {
         unsafe {
@@ -88,7 +203,9 @@ impl<'a> Drop for Interface<'a> {
 
 impl<'a> traits::DynamicInterface<'a> for Interface<'a> {
     fn send(&self, packet: &[u8]) -> Result<(), Error> {
-        if unsafe {
+        let result = if self.offline {
+            Err(Error::SendingPacket("Cannot send packets to a capture file".into()))
+        } else if unsafe {
             self.dll
                 .pcap_sendpacket(self.handle, packet.as_ptr(), packet.len() as c_int)
         } == SUCCESS
@@ -99,20 +216,49 @@ impl<'a> traits::DynamicInterface<'a> for Interface<'a> {
                 .to_string_lossy()
                 .into_owned();
             Err(Error::SendingPacket(txt))
+        };
+        self.send_counters.count(1, result)
+    }
+
+    ///Queues the packets in a send queue that is transmitted at once.
+    fn send_batch(&self, packets: &[&[u8]]) -> Result<(), Error> {
+        let result = self.transmit(packets);
+        self.send_counters.count(packets.len(), result)
+    }
+
+    fn receive(&mut self) -> Result<BorrowedPacket, Error> {
+        self.next_ex().into_packet(false, || self.last_error_string())
+    }
+
+    fn receive_timeout(&mut self, timeout: Duration) -> Result<BorrowedPacket, Error> {
+        // reading a file never blocks
+        if self.offline {
+            return self.receive();
+        }
+        let deadline = Instant::now() + timeout;
+        if !self.nonblock {
+            self.setnonblock(true)?;
         }
+        let next = loop {
+            let next = self.next_ex();
+            let now = Instant::now();
+            if next.result != 0 || now >= deadline {
+                break next;
+            }
+            self.wait(deadline - now);
+        };
+        if !self.nonblock {
+            self.setnonblock(false)?;
+        }
+        next.into_packet(false, || self.last_error_string())
     }
 
-    fn receive<'b>(&mut self) -> Result<BorrowedPacket, Error> {
-        let mut header = MaybeUninit::<PCapPacketHeader>::uninit();
-        //TODO: replace pcap_next with pcap_next_ex to obtain more error information
-        let data = unsafe { self.dll.pcap_next(self.handle, header.as_mut_ptr()) };
-        if data.is_null() {
-            Err(Error::ReceivingPacket(
-                "Unknown error when obtaining packet".into(),
-            ))
-        } else {
-            Ok(borrowed_packet_from_header(unsafe { &header.assume_init() }, data))
+    fn set_nonblock(&mut self, nonblock: bool) -> Result<(), Error> {
+        if !self.offline {
+            self.setnonblock(nonblock)?;
         }
+        self.nonblock = nonblock;
+        Ok(())
     }
 
     fn flush(&self) {
@@ -135,10 +281,11 @@ impl<'a> traits::DynamicInterface<'a> for Interface<'a> {
         let mut stats = MaybeUninit::<PCapStat>::uninit();
         if SUCCESS == unsafe { self.dll.pcap_stats(self.handle, stats.as_mut_ptr()) } {
             let stats = unsafe { stats.assume_init() };
-            Ok(Stats {
+            Ok(self.send_counters.fill(Stats {
                 received: stats.ps_recv as u64,
                 dropped: stats.ps_drop as u64, //sp_ifdrop is not yet supported.
-            })
+                ..Stats::default()
+            }))
         } else {
             Err(self.last_error())
         }
@@ -148,13 +295,16 @@ impl<'a> traits::DynamicInterface<'a> for Interface<'a> {
         unsafe { self.dll.pcap_breakloop(self.handle) }
     }
 
-    fn loop_infinite_dyn(&self, callback: &dyn FnMut(&BorrowedPacket)) -> Result<(), Error> {
+    fn loop_infinite_dyn(&self, callback: &mut dyn FnMut(&BorrowedPacket)) -> Result<(), Error> {
         let result = unsafe {
             self.dll.pcap_loop(
                 self.handle,
                 -1,
                 on_received_packet_dynamic,
-                transmute(&callback),
+                transmute(&mut LoopUser {
+                    callback,
+                    nanos: false,
+                }),
             )
         };
         if result == SUCCESS || result == PCAP_ERROR_BREAK {
@@ -197,7 +347,10 @@ impl<'a> traits::StaticInterface<'a> for Interface<'a> {
                 self.handle,
                 -1,
                 on_received_packet_static::<F>,
-                transmute(&callback),
+                transmute(&mut LoopUser {
+                    callback,
+                    nanos: false,
+                }),
             )
         };
         if result == SUCCESS || result == PCAP_ERROR_BREAK {
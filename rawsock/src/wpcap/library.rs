@@ -1,14 +1,18 @@
 use super::dll::WPCapDll;
+use super::dumper::Dumper;
 use super::interface::Interface;
 use super::paths::DEFAULT_PATHS;
 use crate::common::InterfaceDescription;
 use crate::pcap_common::constants::SUCCESS;
-use crate::pcap_common::interface_data_from_pcap_list;
+use crate::pcap_common::helpers::filter_error;
+use crate::pcap_common::{interface_data_from_pcap_list, BpfProgram};
 use crate::pcap_common::{PCapErrBuf, PCapInterface};
 use crate::utils::cstr_to_string;
-use crate::Error;
+use crate::{DataLink, Error, InterfaceOptions};
 use crate::{traits, LibraryVersion};
 use dlopen::wrapper::Container;
+use std::ffi::CString;
+use std::mem::MaybeUninit;
 use std::ptr::null;
 use std::sync::Arc;
 
@@ -42,10 +46,75 @@ impl traits::Library for Library {
         }
     }
 
+    fn open_interface_arc<'a>(
+        &'a self,
+        name: &str,
+    ) -> Result<Arc<dyn traits::DynamicInterface<'a> + 'a>, Error> {
+        match Interface::new(name, &self.dll) {
+            Ok(interf) => Ok(Arc::new(interf) as Arc<dyn traits::DynamicInterface>),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn open_interface_with_options<'a>(
+        &'a self,
+        name: &str,
+        options: &InterfaceOptions,
+    ) -> Result<Box<dyn traits::DynamicInterface<'a> + 'a>, Error> {
+        match self.open_interface_with_options(name, options) {
+            Ok(interf) => Ok(Box::new(interf) as Box<dyn traits::DynamicInterface>),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn open_offline<'a>(
+        &'a self,
+        path: &str,
+    ) -> Result<Box<dyn traits::DynamicInterface<'a> + 'a>, Error> {
+        let interf = Interface::open_offline(path, &self.dll)?;
+        Ok(Box::new(interf) as Box<dyn traits::DynamicInterface>)
+    }
+
+    fn open_dumper<'a>(
+        &'a self,
+        path: &str,
+        data_link: DataLink,
+    ) -> Result<Box<dyn traits::PacketDumper + 'a>, Error> {
+        let dumper = Dumper::new(path, data_link, &self.dll)?;
+        Ok(Box::new(dumper) as Box<dyn traits::PacketDumper>)
+    }
+
     fn version(&self) -> LibraryVersion {
         LibraryVersion::WPCap(cstr_to_string(unsafe { self.dll.pcap_lib_version() }))
     }
 
+    fn compile_filter(&self, filter: &str, data_link: DataLink) -> Result<(), Error> {
+        let dlt = data_link.dlt().ok_or_else(|| {
+            Error::LibraryError(format!("Filters cannot be compiled for {} links", data_link))
+        })?;
+        let cfilter = CString::new(filter)?;
+        let handle = unsafe { self.dll.pcap_open_dead(dlt, 65536) };
+        if handle.is_null() {
+            return Err(Error::LibraryError("Could not open a capture handle".into()));
+        }
+        let mut program = MaybeUninit::<BpfProgram>::uninit();
+        let result = {
+            unsafe {
+                self.dll
+                    .pcap_compile(handle, program.as_mut_ptr(), cfilter.as_ptr(), 1, 0)
+            }
+        };
+        let result = if result == SUCCESS {
+            unsafe { self.dll.pcap_freecode(program.as_mut_ptr()) };
+            Ok(())
+        } else {
+            let message = cstr_to_string(unsafe { self.dll.pcap_geterr(handle) });
+            Err(filter_error(filter, message))
+        };
+        unsafe { self.dll.pcap_close(handle) };
+        result
+    }
+
     fn all_interfaces(&self) -> Result<Vec<InterfaceDescription>, Error> {
         let mut interfs: *const PCapInterface = null();
         let mut errbuf = PCapErrBuf::new();
@@ -58,11 +127,12 @@ impl traits::Library for Library {
         Ok(interf_datas)
     }
 
-    fn open_interface_arc<'a>(
+    fn open_interface_arc_with_options<'a>(
         &'a self,
         name: &str,
+        options: &InterfaceOptions,
     ) -> Result<Arc<dyn traits::DynamicInterface<'a> + 'a>, Error> {
-        match Interface::new(name, &self.dll) {
+        match Interface::with_options(name, &self.dll, options) {
             Ok(interf) => Ok(Arc::new(interf) as Arc<dyn traits::DynamicInterface>),
             Err(e) => Err(e),
         }
@@ -73,6 +143,19 @@ impl Library {
     pub fn open_interface(&self, name: &str) -> Result<Interface, Error> {
         Interface::new(name, &self.dll)
     }
+    pub fn open_interface_with_options(
+        &self,
+        name: &str,
+        options: &InterfaceOptions,
+    ) -> Result<Interface<'_>, Error> {
+        Interface::with_options(name, &self.dll, options)
+    }
+    pub fn open_offline(&self, path: &str) -> Result<Interface<'_>, Error> {
+        Interface::open_offline(path, &self.dll)
+    }
+    pub fn open_dumper(&self, path: &str, data_link: DataLink) -> Result<Dumper<'_>, Error> {
+        Dumper::new(path, data_link, &self.dll)
+    }
     pub fn dll(&self) -> &WPCapDll {
         &self.dll
     }
@@ -0,0 +1,140 @@
+/*
+Tests in this module open raw sockets, which requires the CAP_NET_RAW capability.
+Therefore they are disabled (ignored) by default. You can enable them by addding --ignored flag
+to your cargo testing command.
+*/
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use rawsock::af_packet;
+    use rawsock::traits::{DynamicInterface, Library};
+    use rawsock::{DataLink, Direction, InterfaceFlags, InterfaceOptions};
+    use std::net::UdpSocket;
+    use std::time::Duration;
+
+    //accepts every packet
+    const ACCEPT_ALL: &str = "1\n6 0 0 262144\n";
+
+    #[test]
+    #[ignore]
+    fn receive_on_loopback() {
+        let lib = af_packet::Library::default();
+        let interfs = lib.all_interfaces().unwrap();
+        let lo = interfs.iter().find(|i| i.name == "lo").expect("No loopback");
+        assert!(lo.flags.contains(InterfaceFlags::LOOPBACK | InterfaceFlags::UP));
+        assert!(lo.is_on_subnet_of("127.1.2.3".parse().unwrap()));
+        let mut interf = lib.open_interface("lo").expect("Could not open interface");
+        assert_eq!(interf.data_link(), DataLink::Ethernet);
+        interf.set_filter(ACCEPT_ALL).expect("Could not set filter");
+
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.send_to(b"rawsock", "127.0.0.1:9").unwrap();
+        let packet = interf
+            .receive_timeout(Duration::from_secs(1))
+            .expect("Could not receive packet");
+        assert!(packet.ends_with(b"rawsock"));
+
+        // expressions are compiled by the backend itself
+        interf.set_filter("udp dst port 9 and net 127.0.0.0/8").expect("Could not set filter");
+        socket.send_to(b"other port", "127.0.0.1:10").unwrap();
+        socket.send_to(b"rawsock filter", "127.0.0.1:9").unwrap();
+        let packet = interf
+            .receive_timeout(Duration::from_secs(1))
+            .expect("Could not receive packet");
+        assert!(packet.ends_with(b"rawsock filter"));
+        assert!(interf.set_filter("udp dst prot 9").is_err());
+    }
+
+    #[test]
+    #[ignore]
+    fn options_on_loopback() {
+        let lib = af_packet::Library::default();
+        let options = InterfaceOptions::new()
+            .snaplen(64)
+            .promiscuous(false)
+            .buffer_size(1 << 20)
+            .direction(Direction::In);
+        let mut interf = lib
+            .open_interface_with_options("lo", &options)
+            .expect("Could not open interface");
+        interf.set_filter(ACCEPT_ALL).expect("Could not set filter");
+
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.send_to(&[0x55; 200], "127.0.0.1:9").unwrap();
+        let packet = interf
+            .receive_timeout(Duration::from_secs(1))
+            .expect("Could not receive packet");
+        assert_eq!(packet.len(), 64);
+
+        let options = options.direction(Direction::Out);
+        assert!(lib.open_interface_with_options("lo", &options).is_err());
+    }
+
+    #[test]
+    #[ignore]
+    fn batch_on_loopback() {
+        let lib = af_packet::Library::default();
+        let options = InterfaceOptions::new().direction(Direction::In);
+        let mut interf = lib
+            .open_interface_with_options("lo", &options)
+            .expect("Could not open interface");
+        interf.set_filter(ACCEPT_ALL).expect("Could not set filter");
+
+        let frames: Vec<Vec<u8>> = (0..3u8)
+            .map(|i| {
+                let mut frame = vec![0xffu8; 12];
+                frame.extend_from_slice(&[0x88, 0xb5, i]);
+                frame
+            })
+            .collect();
+        let batch: Vec<&[u8]> = frames.iter().map(|f| &f[..]).collect();
+        interf.send_batch(&batch).expect("Could not send packets");
+        for frame in &frames {
+            let packet = interf
+                .receive_timeout(Duration::from_secs(1))
+                .expect("Could not receive packet");
+            assert_eq!(&packet[..], &frame[..]);
+        }
+        let stats = interf.stats().expect("Could not get stats");
+        assert_eq!((stats.sent, stats.send_errors), (3, 0));
+        assert_eq!(stats.received, 3);
+    }
+
+    #[test]
+    #[ignore]
+    fn ring_on_loopback() {
+        let lib = af_packet::Library::with_ring(af_packet::RingOptions::default());
+        let mut interf = lib.open_interface("lo").expect("Could not open interface");
+        assert!(interf.has_ring());
+        interf.set_filter(ACCEPT_ALL).expect("Could not set filter");
+
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        for _ in 0..3 {
+            socket.send_to(b"rawsock ring", "127.0.0.1:9").unwrap();
+        }
+        for _ in 0..3 {
+            let packet = interf
+                .receive_timeout(Duration::from_secs(1))
+                .expect("Could not receive packet");
+            assert!(packet.ends_with(b"rawsock ring"));
+        }
+
+        // sent through the ring, looped back into it
+        let mut frame = vec![0xffu8; 12];
+        frame.extend_from_slice(&[0x88, 0xb5]);
+        frame.extend_from_slice(b"rawsock tx ring");
+        // a block that is not full waits for the kernel's timer, which some kernels only run
+        // when more packets arrive - keep sending until one comes back
+        let found = (0..10).any(|_| {
+            interf.send(&frame).expect("Could not send packet");
+            while let Ok(packet) = interf.receive_timeout(Duration::from_millis(100)) {
+                if packet.ends_with(b"rawsock tx ring") {
+                    return true;
+                }
+            }
+            false
+        });
+        assert!(found, "Could not receive sent packet");
+        assert!(interf.send(&vec![0; 4096]).is_err());
+    }
+}
@@ -0,0 +1,210 @@
+use rawsock::file::{CaptureReader, Interface, PcapNgReader, PcapNgWriter, PcapReader, PcapWriter};
+use rawsock::traits::{DynamicInterface, Library, StaticInterface};
+use rawsock::{file, BorrowedPacket, DataLink, Error, Packet};
+use std::io::Cursor;
+use time::Timespec;
+
+/*
+Capture files are handled natively, so these tests need neither a library nor privileges.
+*/
+
+const FRAMES: [&[u8]; 3] = [&[0xff; 60], &[0x01, 0x02, 0x03], &[]];
+
+fn when(i: usize) -> Timespec {
+    Timespec::new(1_600_000_000 + i as i64, 123_456_000 + i as i32 * 1000)
+}
+
+fn pcap_file() -> Vec<u8> {
+    let mut writer = PcapWriter::new(Vec::new(), DataLink::Ethernet).unwrap();
+    for (i, frame) in FRAMES.iter().enumerate() {
+        writer
+            .write_packet(&BorrowedPacket::new(when(i), frame))
+            .unwrap();
+    }
+    writer.into_inner()
+}
+
+#[test]
+fn pcap_round_trip() {
+    let data = pcap_file();
+    let mut reader = PcapReader::new(Cursor::new(data)).unwrap();
+    assert_eq!(reader.data_link(), DataLink::Ethernet);
+    for (i, frame) in FRAMES.iter().enumerate() {
+        let packet = reader.next_packet().unwrap();
+        assert_eq!(&packet[..], *frame);
+        assert_eq!(packet.when(), when(i));
+    }
+    match reader.next_packet() {
+        Err(Error::EndOfFile) => {}
+        _ => panic!("Expected the end of the file"),
+    }
+}
+
+#[test]
+fn pcap_big_endian_nanos() {
+    let mut data = vec![0xa1, 0xb2, 0x3c, 0x4d, 0, 2, 0, 4];
+    data.extend_from_slice(&[0; 8]);
+    data.extend_from_slice(&[0, 0, 0xff, 0xff, 0, 0, 0, 101]);
+    data.extend_from_slice(&[0, 0, 0, 7, 0, 0, 0, 9, 0, 0, 0, 2, 0, 0, 0, 2, 0x45, 0x00]);
+    let mut reader = CaptureReader::new(Cursor::new(data)).unwrap();
+    assert_eq!(reader.data_link(), DataLink::RawIp);
+    let packet = reader.next_packet().unwrap();
+    assert_eq!(&packet[..], &[0x45, 0x00]);
+    assert_eq!(packet.when(), Timespec::new(7, 9));
+}
+
+#[test]
+fn pcap_truncated() {
+    let mut data = pcap_file();
+    data.truncate(data.len() - 1);
+    let mut reader = PcapReader::new(Cursor::new(data)).unwrap();
+    loop {
+        match reader.next_packet() {
+            Ok(_) => {}
+            Err(Error::CaptureFormat(_)) => break,
+            Err(e) => panic!("Expected a truncated file, got {}", e),
+        }
+    }
+}
+
+#[test]
+fn pcapng_interfaces_and_comments() {
+    let mut writer = PcapNgWriter::new(Vec::new(), Some("console session")).unwrap();
+    let eth = writer
+        .add_interface(DataLink::Ethernet, Some("eth0"))
+        .unwrap();
+    let tun = writer.add_interface(DataLink::RawIp, None).unwrap();
+    let packet = BorrowedPacket::new(when(0), FRAMES[0]);
+    writer
+        .write_packet(eth, &packet, Some("broadcast"))
+        .unwrap();
+    let packet = BorrowedPacket::new(when(1), FRAMES[1]);
+    writer.write_packet(tun, &packet, None).unwrap();
+    assert!(writer.write_packet(2, &packet, None).is_err());
+    let data = writer.into_inner();
+
+    let mut reader = PcapNgReader::new(Cursor::new(data)).unwrap();
+    assert_eq!(reader.comments(), &["console session".to_string()]);
+    assert_eq!(reader.data_link(), DataLink::Ethernet);
+    assert_eq!(reader.interfaces()[0].name.as_deref(), Some("eth0"));
+
+    let record = reader.next_record().unwrap();
+    assert_eq!(record.interface, eth);
+    assert_eq!(&record.packet[..], FRAMES[0]);
+    assert_eq!(record.packet.when(), when(0));
+    assert_eq!(record.comments, vec!["broadcast".to_string()]);
+
+    let record = reader.next_record().unwrap();
+    assert_eq!(record.interface, tun);
+    assert_eq!(&record.packet[..], FRAMES[1]);
+    assert!(record.comments.is_empty());
+    assert_eq!(reader.interfaces()[1].data_link, DataLink::RawIp);
+
+    match reader.next_record() {
+        Err(Error::EndOfFile) => {}
+        _ => panic!("Expected the end of the file"),
+    }
+}
+
+#[test]
+fn pcapng_big_endian_microseconds() {
+    let mut data = vec![
+        0x0a, 0x0d, 0x0d, 0x0a, 0, 0, 0, 28, 0x1a, 0x2b, 0x3c, 0x4d, 0, 1, 0, 0,
+    ];
+    data.extend_from_slice(&[0xff; 8]);
+    data.extend_from_slice(&[0, 0, 0, 28]);
+    // interface without if_tsresol, so in microseconds
+    data.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 20, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 20]);
+    // simple packet block, then an enhanced one
+    data.extend_from_slice(&[0, 0, 0, 3, 0, 0, 0, 20, 0, 0, 0, 3, 1, 2, 3, 0, 0, 0, 0, 20]);
+    data.extend_from_slice(&[
+        0, 0, 0, 6, 0, 0, 0, 36, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x0f, 0x42, 0x41,
+    ]);
+    data.extend_from_slice(&[0, 0, 0, 4, 0, 0, 0, 4, 9, 8, 7, 6, 0, 0, 0, 36]);
+
+    let mut reader = CaptureReader::new(Cursor::new(data)).unwrap();
+    assert_eq!(reader.data_link(), DataLink::Ethernet);
+    assert_eq!(&reader.next_packet().unwrap()[..], &[1, 2, 3]);
+    let packet = reader.next_packet().unwrap();
+    assert_eq!(&packet[..], &[9, 8, 7, 6]);
+    assert_eq!(packet.when(), Timespec::new(1, 1000));
+}
+
+#[test]
+fn replay_interface() {
+    let mut interf = Interface::from_reader(Cursor::new(pcap_file())).unwrap();
+    assert_eq!(interf.data_link(), DataLink::Ethernet);
+    assert!(interf.send(FRAMES[0]).is_err());
+
+    let mut replayed = Vec::new();
+    interf
+        .loop_infinite(|packet| replayed.push(packet.as_owned()))
+        .unwrap();
+    assert_eq!(replayed.len(), FRAMES.len());
+    assert_eq!(replayed[1].when(), when(1));
+    let stats = interf.stats().unwrap();
+    assert_eq!(stats.received, FRAMES.len() as u64);
+    assert_eq!((stats.sent, stats.send_errors), (0, 1));
+    match interf.receive() {
+        Err(Error::EndOfFile) => {}
+        _ => panic!("Expected the end of the file"),
+    }
+}
+
+#[test]
+fn break_before_replay() {
+    let interf = Interface::from_reader(Cursor::new(pcap_file())).unwrap();
+    // like pcap_breakloop(), a break before the loop starts ends it right away
+    interf.break_loop();
+    let mut replayed = 0;
+    interf.loop_infinite(|_| replayed += 1).unwrap();
+    assert_eq!(replayed, 0);
+    // and only that loop
+    interf.loop_infinite(|_| replayed += 1).unwrap();
+    assert_eq!(replayed, FRAMES.len());
+}
+
+#[test]
+fn file_library() {
+    let lib = file::Library::open_default_paths().unwrap();
+    let dir = std::env::temp_dir();
+    for name in &["rawsock-file-library.pcap", "rawsock-file-library.pcapng"] {
+        let path = dir.join(name);
+        let path = path.to_str().unwrap();
+        {
+            let mut dumper = lib.open_dumper(path, DataLink::Ethernet).unwrap();
+            for (i, frame) in FRAMES.iter().enumerate() {
+                dumper.dump(&BorrowedPacket::new(when(i), frame)).unwrap();
+            }
+            dumper.flush().unwrap();
+        }
+
+        let mut interf = lib.open_interface(path).unwrap();
+        for (i, frame) in FRAMES.iter().enumerate() {
+            let packet = interf.receive().unwrap();
+            assert_eq!(&packet[..], *frame);
+            assert_eq!(packet.when(), when(i));
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn link_types() {
+    for data_link in &[
+        DataLink::RawIp,
+        DataLink::LinuxSll,
+        DataLink::LinuxSll2,
+        DataLink::Null,
+        DataLink::Loop,
+        DataLink::Ieee80211Radio,
+    ] {
+        let writer = PcapWriter::new(Vec::new(), *data_link).unwrap();
+        let reader = PcapReader::new(Cursor::new(writer.into_inner())).unwrap();
+        assert_eq!(reader.data_link(), *data_link);
+    }
+    assert_eq!(DataLink::from_dlt(113), DataLink::LinuxSll);
+    assert_eq!(DataLink::from_dlt(DataLink::RawIp.dlt().unwrap()), DataLink::RawIp);
+    assert_eq!(DataLink::from_dlt(9999), DataLink::Other);
+    assert!(PcapWriter::new(Vec::new(), DataLink::Other).is_err());
+}
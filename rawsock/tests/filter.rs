@@ -0,0 +1,202 @@
+use rawsock::{Endpoint, Filter, Protocol};
+
+#[test]
+fn display_and_parse() {
+    let filter = Filter::net("10.13.0.0".parse().unwrap(), 16)
+        .or(Filter::Port(Some(Protocol::Udp), Endpoint::Dst, 67))
+        .and(!Filter::ether_host([2, 0, 0, 0, 0, 1]));
+    let text = "(net 10.13.0.0/16 or udp dst port 67) and not ether host 02:00:00:00:00:01";
+    assert_eq!(filter.to_string(), text);
+    assert_eq!(text.parse::<Filter>().unwrap(), filter);
+
+    for text in &[
+        "arp or ip6",
+        "src host 192.168.1.1 and tcp port 80",
+        "ether proto 34997",
+        "ether dst host ff:ff:ff:ff:ff:ff",
+        "not (icmp or rarp)",
+    ] {
+        let filter: Filter = text.parse().unwrap();
+        assert_eq!(&filter.to_string(), text);
+    }
+    // other spellings of the same filters
+    assert_eq!(
+        "!arp&&(udp||tcp)".parse::<Filter>().unwrap().to_string(),
+        "not arp and (udp or tcp)"
+    );
+    assert_eq!(
+        "ether src 02-00-00-00-00-01 or net 10.0.0.1 or ether proto 0x88b5"
+            .parse::<Filter>()
+            .unwrap()
+            .to_string(),
+        "(ether src host 02:00:00:00:00:01 or net 10.0.0.1/32) or ether proto 34997"
+    );
+}
+
+#[test]
+fn parse_errors() {
+    let cases: &[(&str, usize)] = &[
+        ("udp dst prot 67", 8),
+        ("host 10.0.0", 5),
+        ("net 10.13.0.1/16", 4),
+        ("net 10.13.0.0/33", 4),
+        ("arp udp", 4),
+        ("(arp or ip", 10),
+        ("port", 4),
+        ("ether host 02:00:00", 11),
+        ("", 0),
+        ("foo", 0),
+    ];
+    for (text, position) in cases {
+        let err = text.parse::<Filter>().unwrap_err();
+        assert_eq!(err.position(), *position, "{}: {}", text, err);
+    }
+    let err = "udp dst prot 67".parse::<Filter>().unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "expected \"port\" at position 8\nudp dst prot 67\n        ^"
+    );
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use rawsock::af_packet::{compile_program, SockFilter};
+    use rawsock::traits::Library;
+    use rawsock::{af_packet, DataLink, Error, Filter};
+
+    ///Runs a program the way the kernel does, returning whether the packet is accepted.
+    fn run(program: &[SockFilter], packet: &[u8]) -> bool {
+        let load = |offset: usize, size: usize| -> Option<u32> {
+            let bytes = packet.get(offset..offset + size)?;
+            Some(bytes.iter().fold(0, |acc, &b| acc << 8 | u32::from(b)))
+        };
+        let (mut a, mut x, mut pc) = (0u32, 0u32, 0usize);
+        loop {
+            let insn = &program[pc];
+            pc += 1;
+            let k = insn.k as usize;
+            match insn.code {
+                0x20 | 0x28 | 0x30 => {
+                    let size = [4, 2, 1][usize::from((insn.code - 0x20) / 8)];
+                    a = match load(k, size) {
+                        Some(value) => value,
+                        None => return false,
+                    }
+                }
+                0x48 => match load(x as usize + k, 2) {
+                    Some(value) => a = value,
+                    None => return false,
+                },
+                0xb1 => match load(k, 1) {
+                    Some(value) => x = (value & 0xf) * 4,
+                    None => return false,
+                },
+                0x54 => a &= insn.k,
+                0x05 => pc += k,
+                0x15 | 0x45 => {
+                    let hit = if insn.code == 0x15 { a == insn.k } else { a & insn.k != 0 };
+                    pc += usize::from(if hit { insn.jt } else { insn.jf });
+                }
+                0x06 => return insn.k != 0,
+                code => panic!("unexpected instruction {:#x}", code),
+            }
+        }
+    }
+
+    fn matches(filter: &str, data_link: DataLink, packet: &[u8]) -> bool {
+        let filter: Filter = filter.parse().unwrap();
+        run(&compile_program(&filter, data_link).unwrap(), packet)
+    }
+
+    fn ipv4(protocol: u8, src: [u8; 4], dst: [u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x45, 0, 0, 20, 0, 0, 0, 0, 64, protocol, 0, 0];
+        packet.extend_from_slice(&src);
+        packet.extend_from_slice(&dst);
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    fn ethernet(ether_type: u16, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0xff; 6];
+        frame.extend_from_slice(&[2, 0, 0, 0, 0, 1]);
+        frame.extend_from_slice(&ether_type.to_be_bytes());
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    fn udp(src: u16, dst: u16) -> Vec<u8> {
+        let mut udp = src.to_be_bytes().to_vec();
+        udp.extend_from_slice(&dst.to_be_bytes());
+        udp.extend_from_slice(&[0, 8, 0, 0]);
+        udp
+    }
+
+    #[test]
+    fn same_as_tcpdump() {
+        // tcpdump -dd arp
+        let program = compile_program(&"arp".parse().unwrap(), DataLink::Ethernet).unwrap();
+        let program: Vec<_> = program.iter().map(|i| (i.code, i.jt, i.jf, i.k)).collect();
+        assert_eq!(
+            program,
+            vec![(0x28, 0, 0, 12), (0x15, 0, 1, 0x806), (0x06, 0, 0, 262144), (0x06, 0, 0, 0)]
+        );
+    }
+
+    #[test]
+    fn ethernet_packets() {
+        let dhcp = ethernet(0x0800, &ipv4(17, [0; 4], [255; 4], &udp(68, 67)));
+        let console = ethernet(0x0800, &ipv4(6, [10, 13, 1, 2], [1, 1, 1, 1], &udp(1234, 80)));
+        let mut arp = vec![0, 1, 8, 0, 6, 4, 0, 1, 2, 0, 0, 0, 0, 1, 10, 13, 0, 1];
+        arp.extend_from_slice(&[0, 0, 0, 0, 0, 0, 10, 13, 37, 1]);
+        let arp = ethernet(0x0806, &arp);
+
+        let lan_play = "net 10.13.0.0/16 or (udp dst port 67)";
+        assert!(matches(lan_play, DataLink::Ethernet, &dhcp));
+        assert!(matches(lan_play, DataLink::Ethernet, &console));
+        assert!(matches(lan_play, DataLink::Ethernet, &arp));
+        assert!(!matches("net 10.14.0.0/16", DataLink::Ethernet, &arp));
+        assert!(matches("dst host 10.13.37.1", DataLink::Ethernet, &arp));
+        assert!(!matches("src host 10.13.37.1", DataLink::Ethernet, &arp));
+        assert!(matches("src net 10.13.0.0/16", DataLink::Ethernet, &console));
+        assert!(!matches("dst net 10.13.0.0/16", DataLink::Ethernet, &console));
+        assert!(matches("net 0.0.0.0/0", DataLink::Ethernet, &console));
+
+        assert!(matches("udp dst port 67", DataLink::Ethernet, &dhcp));
+        assert!(!matches("udp dst port 68", DataLink::Ethernet, &dhcp));
+        assert!(matches("port 68", DataLink::Ethernet, &dhcp));
+        assert!(!matches("tcp port 68", DataLink::Ethernet, &dhcp));
+        assert!(matches("tcp src port 1234", DataLink::Ethernet, &console));
+
+        assert!(matches("ether src host 02:00:00:00:00:01", DataLink::Ethernet, &arp));
+        assert!(!matches("ether dst host 02:00:00:00:00:01", DataLink::Ethernet, &arp));
+        assert!(matches("not ip and arp", DataLink::Ethernet, &arp));
+        assert!(!matches("ip6 or rarp or icmp", DataLink::Ethernet, &console));
+
+        // fragments without ports
+        let mut fragment = console.clone();
+        fragment[21] = 8;
+        assert!(!matches("tcp port 80", DataLink::Ethernet, &fragment));
+        assert!(matches("tcp", DataLink::Ethernet, &fragment));
+    }
+
+    #[test]
+    fn raw_ip_packets() {
+        let dhcp = ipv4(17, [0; 4], [255; 4], &udp(68, 67));
+        assert!(matches("ip and udp dst port 67", DataLink::RawIp, &dhcp));
+        assert!(matches("host 255.255.255.255", DataLink::RawIp, &dhcp));
+        assert!(!matches("arp or ether host 02:00:00:00:00:01", DataLink::RawIp, &dhcp));
+        assert!(!matches("ip6", DataLink::RawIp, &dhcp));
+        assert!(compile_program(&"arp".parse().unwrap(), DataLink::LinuxSll).is_err());
+    }
+
+    #[test]
+    fn compile_filter() {
+        let lib = af_packet::Library::default();
+        assert!(lib.compile_filter("1\n6 0 0 262144\n", DataLink::Ethernet).is_ok());
+        assert!(lib.compile_filter("udp dst port 67", DataLink::Ethernet).is_ok());
+        match lib.compile_filter("udp dst port 67 or", DataLink::Ethernet) {
+            Err(Error::InvalidFilter(err)) => assert_eq!(err.position(), 18),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use rawsock::traits::{DynamicInterface, Library, PacketDumper};
+use rawsock::{pcap, BorrowedPacket, DataLink, Error, Packet};
+use time::Timespec;
+
+/*
+Tests in this module need the pcap (or wpcap) library. When it is not installed they report
+that and pass, so they still run everywhere the library is available.
+*/
+
+#[cfg(unix)]
+fn open_library() -> Result<pcap::Library, Error> {
+    pcap::Library::open_default_paths()
+}
+
+#[cfg(windows)]
+fn open_library() -> Result<rawsock::wpcap::Library, Error> {
+    rawsock::wpcap::Library::open_default_paths()
+}
+
+#[test]
+fn dump_and_replay() {
+    let lib = match open_library() {
+        Ok(lib) => lib,
+        Err(err) => {
+            eprintln!("Skipping, could not open the pcap library: {}", err);
+            return;
+        }
+    };
+    let path = std::env::temp_dir().join(format!("rawsock-dump-and-replay-{}.pcap", std::process::id()));
+    let path = path.to_str().unwrap();
+    let frames: [&[u8]; 2] = [&[0xff; 60], &[0x01, 0x02, 0x03]];
+    {
+        let mut dumper = lib
+            .open_dumper(path, DataLink::Ethernet)
+            .expect("Could not create capture file");
+        for (i, frame) in frames.iter().enumerate() {
+            let packet = BorrowedPacket::new(Timespec::new(1000 + i as i64, 5000), frame);
+            dumper.dump(&packet).expect("Could not dump packet");
+        }
+    }
+
+    let mut interf = lib.open_offline(path).expect("Could not open capture file");
+    assert_eq!(interf.data_link(), DataLink::Ethernet);
+    for (i, frame) in frames.iter().enumerate() {
+        let packet = interf.receive().expect("Could not replay packet");
+        assert_eq!(&packet[..], *frame);
+        assert_eq!(packet.when(), Timespec::new(1000 + i as i64, 5000));
+    }
+    match interf.receive() {
+        Err(Error::EndOfFile) => {}
+        _ => panic!("Expected the end of the file"),
+    }
+    assert!(interf.send(frames[0]).is_err());
+    std::fs::remove_file(path).unwrap();
+}